@@ -1,9 +1,12 @@
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use serde::Deserialize;
@@ -13,6 +16,9 @@ use tracing::warn;
 
 use crate::dut::PartitionSet;
 use crate::dut::SshInfo;
+use crate::rand_util::seeded_fisher_yates_shuffle;
+use crate::shell::OutputFormat;
+use crate::shell::Shell;
 use crate::tast::run_tast_test;
 use crate::tast::TastTestExecutionType;
 
@@ -42,6 +48,75 @@ pub struct ExperimentRunMetadata {
     pub config: ExperimentConfig,
     group: usize,
     run: usize,
+    /// The full (config, group) execution order this run's cluster was
+    /// actually scheduled in, so downstream analysis can verify the
+    /// assignment instead of assuming `Schedule::Sequential`.
+    realized_order: Vec<(ExperimentConfig, usize)>,
+}
+impl ExperimentRunMetadata {
+    /// Builds metadata for a single ad-hoc run outside `abtest run`'s own
+    /// clustered scheduling, e.g. `cro3 tast run`'s DUT pool, so its result
+    /// directory is still picked up by the same `cro3_abtest_run_metadata.json`
+    /// -reading analysis pipeline as a one-run, one-group, one-cluster
+    /// experiment.
+    pub fn for_single_run(runner: ExperimentRunner, config: ExperimentConfig, run: usize) -> Self {
+        Self {
+            runner,
+            iteration: 0,
+            cluster: 0,
+            config,
+            group: 0,
+            realized_order: vec![(config, 0)],
+            run,
+        }
+    }
+
+    pub fn iteration(&self) -> usize {
+        self.iteration
+    }
+    pub fn cluster(&self) -> usize {
+        self.cluster
+    }
+    pub fn group(&self) -> usize {
+        self.group
+    }
+    pub fn run(&self) -> usize {
+        self.run
+    }
+    pub fn realized_order(&self) -> &[(ExperimentConfig, usize)] {
+        &self.realized_order
+    }
+}
+
+/// How `ExperimentRunner::run_cluster` orders the A/B group units within a
+/// cluster. The default, `Sequential`, runs every A group and then every B
+/// group, which confounds the treatment with time-order effects (thermal
+/// drift, background daemons warming up); `Random`/`Blocked` decorrelate
+/// treatment from position at the cost of a less readable log.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Schedule {
+    #[default]
+    Sequential,
+    /// A full Fisher-Yates shuffle of every unit in the cluster.
+    Random,
+    /// Permuted-block randomization: units are partitioned into consecutive
+    /// blocks of `2 * block_size`, each with exactly `block_size` A-units
+    /// and `block_size` B-units in a randomly permuted order, guaranteeing
+    /// A/B balance at every block boundary.
+    Blocked,
+}
+impl std::str::FromStr for Schedule {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sequential" => Ok(Schedule::Sequential),
+            "random" => Ok(Schedule::Random),
+            "blocked" => Ok(Schedule::Blocked),
+            other => Err(anyhow!(
+                "invalid schedule {other:?}, expected one of: sequential, random, blocked"
+            )),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -50,6 +125,121 @@ pub struct ExperimentRunParameter {
     pub group_per_cluster: usize,
     pub cluster_per_iteration: usize,
     pub num_of_iterations: usize,
+    /// How to order the A/B group units within a cluster.
+    #[serde(default)]
+    pub schedule: Schedule,
+    /// Seed for `Schedule::Random`/`Schedule::Blocked`, mixed with the
+    /// cluster index so every cluster gets its own (but still reproducible)
+    /// order. Ignored for `Schedule::Sequential`.
+    #[serde(default)]
+    pub seed: u64,
+    /// Block size `k` for `Schedule::Blocked`: each block contains `k` A
+    /// units and `k` B units. Ignored for other schedules.
+    #[serde(default = "default_block_size")]
+    pub block_size: usize,
+}
+fn default_block_size() -> usize {
+    1
+}
+
+/// Builds the realized (config, group) execution order for a cluster of
+/// `group_per_cluster` A-units and `group_per_cluster` B-units, per
+/// `schedule`.
+fn build_schedule(
+    schedule: Schedule,
+    group_per_cluster: usize,
+    block_size: usize,
+    seed: u64,
+) -> Vec<(ExperimentConfig, usize)> {
+    match schedule {
+        Schedule::Sequential => (0..group_per_cluster)
+            .map(|g| (ExperimentConfig::A, g))
+            .chain((0..group_per_cluster).map(|g| (ExperimentConfig::B, g)))
+            .collect(),
+        Schedule::Random => {
+            let mut units: Vec<(ExperimentConfig, usize)> = (0..group_per_cluster)
+                .map(|g| (ExperimentConfig::A, g))
+                .chain((0..group_per_cluster).map(|g| (ExperimentConfig::B, g)))
+                .collect();
+            seeded_fisher_yates_shuffle(&mut units, seed);
+            units
+        }
+        Schedule::Blocked => {
+            let block_size = block_size.max(1);
+            let mut schedule = Vec::with_capacity(group_per_cluster * 2);
+            let (mut next_a, mut next_b, mut block_index) = (0, 0, 0u64);
+            while next_a < group_per_cluster || next_b < group_per_cluster {
+                let a_in_block = block_size.min(group_per_cluster - next_a);
+                let b_in_block = block_size.min(group_per_cluster - next_b);
+                let mut block: Vec<ExperimentConfig> = std::iter::repeat(ExperimentConfig::A)
+                    .take(a_in_block)
+                    .chain(std::iter::repeat(ExperimentConfig::B).take(b_in_block))
+                    .collect();
+                seeded_fisher_yates_shuffle(&mut block, seed.wrapping_add(block_index));
+                block_index += 1;
+                for config in block {
+                    match config {
+                        ExperimentConfig::A => {
+                            schedule.push((ExperimentConfig::A, next_a));
+                            next_a += 1;
+                        }
+                        ExperimentConfig::B => {
+                            schedule.push((ExperimentConfig::B, next_b));
+                            next_b += 1;
+                        }
+                    }
+                }
+            }
+            schedule
+        }
+    }
+}
+
+/// Checkpoint of which `(iteration, cluster, config, group, run)` units have
+/// already completed, persisted to `results_dir/progress.json` after every
+/// successful run so a long `run_experiment` campaign can resume after a
+/// reboot or flaky hardware instead of restarting from scratch.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Progress {
+    completed: BTreeSet<(usize, usize, ExperimentConfig, usize, usize)>,
+}
+impl Progress {
+    fn path(results_dir: &Path) -> PathBuf {
+        results_dir.join("progress.json")
+    }
+    fn load(results_dir: &Path) -> Self {
+        fs::read_to_string(Self::path(results_dir))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+    fn save(&self, results_dir: &Path) -> Result<()> {
+        fs::create_dir_all(results_dir).context("Failed to create the result dir")?;
+        fs::write(Self::path(results_dir), serde_json::to_string(self)?)
+            .context("Failed to write progress.json")
+    }
+    fn is_done(
+        &self,
+        iteration: usize,
+        cluster: usize,
+        config: ExperimentConfig,
+        group: usize,
+        run: usize,
+    ) -> bool {
+        self.completed.contains(&(iteration, cluster, config, group, run))
+    }
+    fn mark_done(
+        results_dir: &Path,
+        iteration: usize,
+        cluster: usize,
+        config: ExperimentConfig,
+        group: usize,
+        run: usize,
+    ) -> Result<()> {
+        let mut progress = Self::load(results_dir);
+        progress.completed.insert((iteration, cluster, config, group, run));
+        progress.save(results_dir)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -89,7 +279,20 @@ impl ExperimentRunner {
         cluster: usize,
         config: ExperimentConfig,
         group: usize,
+        realized_order: &[(ExperimentConfig, usize)],
     ) -> Result<()> {
+        let progress = Progress::load(&self.results_dir);
+        let pending_runs: Vec<usize> = (0..self.params.run_per_group)
+            .filter(|&i| !progress.is_done(iteration, cluster, config, group, i))
+            .collect();
+        if pending_runs.is_empty() {
+            info!(
+                "group {config}-{group} (iteration {iteration}, cluster {cluster}) already \
+                 complete, skipping (resumed run)"
+            );
+            return Ok(());
+        }
+
         match config {
             ExperimentConfig::A => self.ssh.switch_partition_set(PartitionSet::A),
             ExperimentConfig::B => self.ssh.switch_partition_set(PartitionSet::B),
@@ -99,7 +302,7 @@ impl ExperimentRunner {
         }
         self.ssh.wait_online()?;
 
-        for i in 0..self.params.run_per_group {
+        for i in pending_runs {
             info!("#### run {i} with {}", self.dut_id);
             let mut result_dir = self.results_dir.clone();
             result_dir.push(format!(
@@ -115,6 +318,7 @@ impl ExperimentRunner {
                 config: config.clone(),
                 group,
                 run: i,
+                realized_order: realized_order.to_vec(),
             };
             fs::create_dir_all(&result_dir).context("Failed to create the result dir")?;
             let mut file = fs::File::create(&result_dir.join("cro3_abtest_run_metadata.json"))?;
@@ -131,17 +335,31 @@ impl ExperimentRunner {
                 )
             })
             .or(Err(anyhow!("Failed to run tast test after retries")))?;
+
+            // Stream one JSON object per completed run under --format json,
+            // so a CI caller following the process's stdout can track
+            // progress instead of waiting for the whole experiment (which
+            // may take hours) and scraping result_dir names afterwards.
+            if Shell::lock().format() == OutputFormat::Json {
+                Shell::lock().print_envelope("abtest run", true, &run_metadata);
+            }
+
+            Progress::mark_done(&self.results_dir, iteration, cluster, config, group, i)?;
         }
         Ok(())
     }
     fn run_cluster(&self, iteration: usize, cluster: usize) -> Result<()> {
-        for i in 0..self.params.group_per_cluster {
-            info!("### group A-{i}");
-            self.run_group(iteration, cluster, ExperimentConfig::A, i)?;
-        }
-        for i in 0..self.params.group_per_cluster {
-            info!("### group B-{i}");
-            self.run_group(iteration, cluster, ExperimentConfig::B, i)?;
+        let seed = self.params.seed.wrapping_add(cluster as u64);
+        let order = build_schedule(
+            self.params.schedule,
+            self.params.group_per_cluster,
+            self.params.block_size,
+            seed,
+        );
+        info!("### cluster {cluster} schedule ({:?}): {order:?}", self.params.schedule);
+        for (config, group) in &order {
+            info!("### group {config}-{group}");
+            self.run_group(iteration, cluster, *config, *group, &order)?;
         }
         Ok(())
     }
@@ -159,4 +377,28 @@ impl ExperimentRunner {
         }
         Ok(())
     }
+    /// Reconstructs an `ExperimentRunner` from the `runner` field embedded
+    /// in any existing `cro3_abtest_run_metadata.json` under
+    /// `results_dir`, so a `--resume <results_dir>` flag doesn't need the
+    /// original CLI invocation's flags replayed by hand. The returned
+    /// runner's `run_experiment`/`run_group` will skip units already
+    /// recorded in `results_dir/progress.json`.
+    pub fn resume_from(results_dir: &Path) -> Result<Self> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(results_dir)
+            .context("Failed to read the results dir")?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        entries.sort();
+        for entry in entries {
+            let Ok(text) = fs::read_to_string(entry.join("cro3_abtest_run_metadata.json")) else {
+                continue;
+            };
+            if let Ok(metadata) = serde_json::from_str::<ExperimentRunMetadata>(&text) {
+                return Ok(metadata.runner);
+            }
+        }
+        bail!("No cro3_abtest_run_metadata.json found under {results_dir:?} to resume from")
+    }
 }
@@ -4,13 +4,12 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
-use std::process::Command;
-
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 
 use crate::config::Config;
+use crate::util::shell_helpers::ProcessBuilder;
 
 const MASTER_ARC_DEV: &str = "master";
 const RVC: &str = "rvc";
@@ -40,7 +39,7 @@ pub fn setup_arc_repo(repo: &str, version: &str) -> Result<()> {
         .context("Please configure android_manifest_url")?;
     let branch = arc_version_to_branch_name(version)?;
 
-    let cmd = Command::new("repo")
+    let result = ProcessBuilder::new("repo")
         .current_dir(repo)
         .args([
             "init",
@@ -54,11 +53,10 @@ pub fn setup_arc_repo(repo: &str, version: &str) -> Result<()> {
             "--partial-clone-exclude=platform/frameworks/base",
             "--clone-filter=blob:limit=10M",
         ])
-        .spawn()
+        .stream(Some("repo init".to_string()))
+        .run()
         .context("Failed to execute repo init")?;
-
-    cmd.wait_with_output()
-        .context("Failed to wait for repo init")?;
+    result.check_status("repo init")?;
 
     Ok(())
 }
@@ -0,0 +1,148 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Content-addressed artifact cache backed by Google Storage.
+//!
+//! Mirrors the approach of ChromeOS's `get_cross.sh`: resolve the pinned
+//! SDK version from an overlay's `sdk_version.conf`, enumerate prebuilt
+//! toolchain/binpkg and image tarballs for a board, and fetch them into a
+//! cache keyed by the GS object's md5 (falling back to its generation
+//! number for objects gsutil reports no md5 for) so repeated fetches of
+//! the same content are no-ops.
+
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use regex_macro::regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cros::read_pinned_sdk_version;
+use crate::google_storage;
+use crate::util::gen_path_in_lium_dir;
+
+/// GS path of the overlay's pinned SDK version file, the same pointer
+/// `get_cross.sh` reads to decide which SDK to provision.
+const SDK_VERSION_CONF: &str = "gs://chromiumos-overlay/chromeos/binhost/host/sdk_version.conf";
+
+/// A single object returned by an artifact listing, carrying enough
+/// `gsutil stat` metadata to key a content-addressed cache entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GsArtifact {
+    pub gs_path: String,
+    pub md5: Option<String>,
+    pub generation: Option<String>,
+    pub size: Option<u64>,
+}
+impl GsArtifact {
+    pub fn file_name(&self) -> Result<String> {
+        self.gs_path
+            .rsplit('/')
+            .next()
+            .map(|s| s.to_string())
+            .context("gs path has no file name")
+    }
+    /// Content address for the cache: the object's md5 when gsutil
+    /// reports one (stable across re-uploads of identical bytes), else
+    /// its generation number (stable only for this exact object version).
+    pub fn cache_key(&self) -> Result<&str> {
+        self.md5
+            .as_deref()
+            .or(self.generation.as_deref())
+            .context("gsutil stat returned neither a md5 nor a generation")
+    }
+}
+
+/// Parses the `Hash (md5)`/`Generation`/`Content-Length` lines out of
+/// `gsutil.py stat <gs_path>` output.
+fn parse_gsutil_stat(output: &str) -> (Option<String>, Option<String>, Option<u64>) {
+    let re_md5 = regex!(r"(?i)Hash \(md5\):\s*(\S+)");
+    let re_generation = regex!(r"(?i)Generation:\s*(\S+)");
+    let re_size = regex!(r"(?i)Content-Length:\s*(\d+)");
+    let md5 = re_md5.captures(output).map(|c| c[1].to_string());
+    let generation = re_generation.captures(output).map(|c| c[1].to_string());
+    let size = re_size.captures(output).and_then(|c| c[1].parse::<u64>().ok());
+    (md5, generation, size)
+}
+
+/// Looks up metadata for a single object via `gsutil.py stat`.
+pub fn stat_artifact(gs_path: &str) -> Result<GsArtifact> {
+    let output =
+        google_storage::stat_gs_file(gs_path).with_context(|| format!("Failed to stat {gs_path}"))?;
+    let (md5, generation, size) = parse_gsutil_stat(&output);
+    Ok(GsArtifact {
+        gs_path: gs_path.to_string(),
+        md5,
+        generation,
+        size,
+    })
+}
+
+/// Lists objects matching `pattern` (a `gs://...` glob), `stat`-ing each
+/// match returned by `gsutil.py ls` so the result can be cache-keyed.
+pub fn list_artifacts(pattern: &str) -> Result<Vec<GsArtifact>> {
+    let listing = google_storage::list_gs_files(pattern)?;
+    listing
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(stat_artifact)
+        .collect()
+}
+
+fn cache_dir_for(artifact: &GsArtifact) -> Result<PathBuf> {
+    gen_path_in_lium_dir(&format!("cache/{}/.keep", artifact.cache_key()?)).map(|mut path| {
+        path.pop();
+        path
+    })
+}
+
+/// Downloads `artifact` into the content-addressed cache under
+/// `gen_path_in_lium_dir("cache/")`, reusing an already-downloaded copy
+/// keyed by the same md5/generation instead of re-fetching it.
+pub fn fetch_artifact(artifact: &GsArtifact) -> Result<PathBuf> {
+    let cache_dir = cache_dir_for(artifact)?;
+    create_dir_all(&cache_dir).context("Failed to create the artifact cache dir")?;
+    let dest = cache_dir.join(artifact.file_name()?);
+    if dest.exists() {
+        return Ok(dest);
+    }
+    google_storage::fetch_gs_file(&artifact.gs_path, &dest)
+        .with_context(|| format!("Failed to download {}", artifact.gs_path))?;
+    Ok(dest)
+}
+
+/// Resolves the SDK version currently pinned for the chromiumos-overlay,
+/// the same pointer file `get_cross.sh` reads.
+pub fn resolve_current_sdk_version() -> Result<String> {
+    let contents = google_storage::cat_gs_file(SDK_VERSION_CONF)
+        .context("Failed to read the overlay's sdk_version.conf")?;
+    read_pinned_sdk_version(&contents)
+}
+
+/// Enumerates prebuilt binpkg tarballs published for `board` at `version`
+/// (the pinned SDK version if not given), the same objects `setup_sdk`
+/// downloads, but as structured, individually-fetchable artifacts instead
+/// of an all-or-nothing sync.
+pub fn list_board_prebuilts(board: &str, version: Option<&str>) -> Result<Vec<GsArtifact>> {
+    let version = match version {
+        Some(v) => v.to_string(),
+        None => resolve_current_sdk_version()?,
+    };
+    list_artifacts(&format!(
+        "gs://chromeos-prebuilt/board/{board}/{version}/packages/*"
+    ))
+}
+
+/// Enumerates signed/test image tarballs published for `board` at
+/// `version` under `chromeos-image-archive`, e.g.
+/// `chromiumos_test_image.tar.xz`.
+pub fn list_board_images(board: &str, version: &str) -> Result<Vec<GsArtifact>> {
+    list_artifacts(&format!(
+        "gs://chromeos-image-archive/{board}-release/{version}/*.tar.xz"
+    ))
+}
@@ -17,6 +17,11 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use tracing::warn;
+
+use crate::stats::cohens_d;
+use crate::stats::mean_var;
+use crate::stats::welch_t_test;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BluebenchCycleResult {
@@ -28,6 +33,32 @@ pub struct BluebenchCycleResult {
     pub t2: Option<f64>,
     pub t3: Option<f64>,
     pub raw: Vec<f64>,
+    /// True if any sensor came within [`THROTTLE_DELTA_C`] of its `crit` or
+    /// `max` threshold while this cycle was running, i.e. a high
+    /// `converged_mean` here is plausibly explained by thermal throttling
+    /// rather than noise.
+    pub thermally_throttled: bool,
+    /// The hottest reading seen across all sensors during this cycle, if
+    /// any temperature data was available for its time window.
+    pub peak_temp_c: Option<f64>,
+}
+
+/// A cycle is flagged [`BluebenchCycleResult::thermally_throttled`] once a
+/// sensor's live reading comes within this many degrees C of its `crit` (or,
+/// absent that, `max`) threshold.
+const THROTTLE_DELTA_C: f64 = 2.0;
+
+/// One hwmon-style sensor channel's reading: generalizes the old
+/// `x86_pkg_temp`-only parsing to any `tempX`-shaped channel reported by
+/// `temp_logger`, carrying the live value alongside -- where the log line
+/// reported them -- the `max`/`crit` thresholds hwmon exposes next to
+/// `tempX_input`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SensorReading {
+    pub chip: String,
+    pub value_c: f64,
+    pub max_c: Option<f64>,
+    pub crit_c: Option<f64>,
 }
 
 lazy_static! {
@@ -44,7 +75,7 @@ pub struct BluebenchMetadata {
     pub os_release: String,
     pub bootid: String,
     pub kernel_cmdline_mitigations: String,
-    pub temperature_sensor_readouts: HashMap<String, Vec<(String, f64)>>,
+    pub temperature_sensor_readouts: HashMap<String, Vec<(String, SensorReading)>>,
     pub test_start_timestamp: String,
     pub test_end_timestamp: String,
 }
@@ -136,21 +167,65 @@ impl BluebenchMetadata {
             .trim();
         Ok(s.to_string())
     }
-    pub fn parse_temp_log_line(s: &str) -> Result<(String, HashMap<String, f64>)> {
-        let mut data: HashMap<String, f64> = HashMap::new();
+    /// Parses one `temp_logger` syslog line into a timestamp plus every
+    /// hwmon-style channel it reported. `name:value<unit>` tokens are read
+    /// the same way the old `x86_pkg_temp`-only parser did (the last
+    /// character is the unit, e.g. `47C`), folded in here; a token with no
+    /// alphabetic unit suffix is a raw hwmon millidegree reading and is
+    /// divided by 1000 to yield degrees C. `name_max`/`name_crit` tokens are
+    /// not channels of their own -- they're folded into `name`'s thresholds.
+    pub fn parse_temp_log_line(s: &str) -> Result<(String, HashMap<String, SensorReading>)> {
         let mut it = s.trim().split(' ');
         let t = it.next().context("timestamp should be there")?.to_string();
-        let it = it.skip_while(|s| !s.starts_with("x86_pkg_temp"));
-        for e in it {
+        // Skip the syslog prefix (e.g. "NOTICE temp_logger[10343]:") up to
+        // and including the temp_logger tag; what follows is a
+        // whitespace-separated list of "name:value<unit>" sensor tokens.
+        let mut it = it.skip_while(|s| !s.starts_with("temp_logger["));
+        it.next().context("temp_logger tag should be there")?;
+        let mut raw: HashMap<String, f64> = HashMap::new();
+        for e in it.filter(|s| !s.is_empty()) {
             let mut it = e.split(':');
-            let mut key = it.next().context("name should be there")?.to_string();
+            let name = it.next().context("name should be there")?.to_string();
             let value: &str = it.next().context("value should be there")?;
-            let unit = value.chars().last().context("unit should be there")?; // Assuming that the last char is unit (e.g. C, W)
-            let value = &value[..value.len() - 1];
-            let value: f64 = value.parse().context("failed to parse temp value")?;
-            key.push('_');
-            key.push(unit);
-            data.insert(key, value);
+            let last = value.chars().last().context("value should not be empty")?;
+            let value: f64 = if last.is_alphabetic() {
+                value[..value.len() - 1]
+                    .parse()
+                    .context("failed to parse sensor value")?
+            } else if name.contains("temp") || name.ends_with("_max") || name.ends_with("_crit") {
+                // A raw hwmon tempX_* value with no unit suffix: millidegrees.
+                let millidegrees: f64 = value.parse().context("failed to parse sensor value")?;
+                millidegrees / 1000.0
+            } else {
+                // Not a temperature channel (e.g. PL1's Watts without a
+                // trailing unit); keep the value as-is.
+                value.parse().context("failed to parse sensor value")?
+            };
+            raw.insert(name, value);
+        }
+        let mut thresholds: HashMap<String, (Option<f64>, Option<f64>)> = HashMap::new();
+        for (name, value) in &raw {
+            if let Some(chip) = name.strip_suffix("_crit") {
+                thresholds.entry(chip.to_string()).or_default().1 = Some(*value);
+            } else if let Some(chip) = name.strip_suffix("_max") {
+                thresholds.entry(chip.to_string()).or_default().0 = Some(*value);
+            }
+        }
+        let mut data: HashMap<String, SensorReading> = HashMap::new();
+        for (name, value) in raw {
+            if name.ends_with("_crit") || name.ends_with("_max") {
+                continue;
+            }
+            let (max_c, crit_c) = thresholds.get(&name).copied().unwrap_or((None, None));
+            data.insert(
+                name.clone(),
+                SensorReading {
+                    chip: name,
+                    value_c: value,
+                    max_c,
+                    crit_c,
+                },
+            );
         }
         Ok((t, data))
     }
@@ -159,13 +234,13 @@ impl BluebenchMetadata {
         test_name: &str,
         test_start_timestamp: &str,
         test_end_timestamp: &str,
-    ) -> Result<HashMap<String, Vec<(String, f64)>>> {
-        let mut temp_data: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+    ) -> Result<HashMap<String, Vec<(String, SensorReading)>>> {
+        let mut temp_data: HashMap<String, Vec<(String, SensorReading)>> = HashMap::new();
         let path = path.join("tests").join(test_name).join("messages.txt");
         let s = fs::read_to_string(&path).context(anyhow!("Failed to read {path:?}"))?;
-        let s: Vec<(String, HashMap<String, f64>)> = s
+        let s: Vec<(String, HashMap<String, SensorReading>)> = s
             .split('\n')
-            .filter(|s| s.contains("x86_pkg_temp"))
+            .filter(|s| s.contains("temp_logger["))
             .filter_map(|s| Self::parse_temp_log_line(s).ok())
             .collect();
         for (t, entries) in s {
@@ -173,13 +248,7 @@ impl BluebenchMetadata {
                 continue;
             }
             for (k, v) in entries {
-                if !temp_data.contains_key(&k) {
-                    temp_data.insert(k.clone(), Vec::new());
-                }
-                temp_data
-                    .get_mut(&k)
-                    .context("key should have value")?
-                    .push((t.to_string(), v));
+                temp_data.entry(k).or_default().push((t.clone(), v));
             }
         }
         Ok(temp_data)
@@ -253,7 +322,7 @@ impl Debug for BluebenchResult {
 impl BluebenchResult {
     pub fn from_path(path: &Path) -> Result<Self> {
         let test_name = "perf.TabOpenLatencyPerf";
-        let metadata = BluebenchMetadata::from_path(path, test_name, false)?;
+        let metadata = BluebenchMetadata::from_path(path, test_name, true)?;
         let result_csv = path.join("tests").join(test_name).join("bluebench_log.txt");
         if !result_csv.is_file() {
             bail!("{result_csv:?} is not a file");
@@ -275,7 +344,7 @@ impl BluebenchResult {
                 Ok(None)
             }
         };
-        let cycles: Vec<BluebenchCycleResult> = result_lines
+        let mut cycles: Vec<BluebenchCycleResult> = result_lines
             .iter()
             .map(|s| -> &str { str::trim(s) })
             .filter(|s| !s.is_empty())
@@ -305,9 +374,16 @@ impl BluebenchResult {
                     t2,
                     t3,
                     raw,
+                    thermally_throttled: false,
+                    peak_temp_c: None,
                 })
             })
             .collect::<Result<Vec<BluebenchCycleResult>>>()?;
+        Self::correlate_thermal_events(
+            &mut cycles,
+            &metadata.temperature_sensor_readouts,
+            &metadata.test_start_timestamp,
+        );
         let converged_means: Vec<f64> = cycles.iter().filter_map(|c| c.converged_mean).collect();
         let converged_mean_mean =
             converged_means.iter().sum::<f64>() / converged_means.len() as f64;
@@ -319,4 +395,148 @@ impl BluebenchResult {
             converged_mean_mean,
         })
     }
+
+    /// Walks `cycles` in order, treating each cycle's window as running from
+    /// the previous cycle's `date` (or `test_start_timestamp` for the first
+    /// cycle) up to its own `date`, and sets `peak_temp_c`/
+    /// `thermally_throttled` from any sensor readings that fall in that
+    /// window, so a high `converged_mean` can be explained by a thermal
+    /// event rather than treated as unexplained noise.
+    fn correlate_thermal_events(
+        cycles: &mut [BluebenchCycleResult],
+        sensors: &HashMap<String, Vec<(String, SensorReading)>>,
+        test_start_timestamp: &str,
+    ) {
+        let mut window_start = test_start_timestamp.to_string();
+        for cycle in cycles.iter_mut() {
+            let window_end = cycle.date.clone();
+            let mut peak: Option<f64> = None;
+            let mut throttled = false;
+            for series in sensors.values() {
+                for (t, reading) in series {
+                    if t < &window_start || &window_end < t {
+                        continue;
+                    }
+                    peak = Some(peak.map_or(reading.value_c, |p: f64| p.max(reading.value_c)));
+                    if let Some(threshold) = reading.crit_c.or(reading.max_c) {
+                        if reading.value_c >= threshold - THROTTLE_DELTA_C {
+                            throttled = true;
+                        }
+                    }
+                }
+            }
+            cycle.peak_temp_c = peak;
+            cycle.thermally_throttled = throttled;
+            window_start = window_end;
+        }
+    }
+
+    /// Runs Welch's t-test over `self`'s and `other`'s per-cycle
+    /// `converged_mean` samples, e.g. to decide whether two OS builds or two
+    /// `kernel_cmdline_mitigations` settings actually differ instead of
+    /// eyeballing `converged_mean_mean`. Bails if the two runs'
+    /// `metadata.key` (hwid/dut_id/bootid/mitigations) differ, since
+    /// comparing across configurations wouldn't be meaningful.
+    pub fn compare(&self, other: &Self, alpha: f64) -> Result<BluebenchComparisonVerdict> {
+        if self.metadata.key != other.metadata.key {
+            bail!(
+                "{:?} and {:?} are not comparable: metadata.key differs ({} vs {})",
+                self.metadata.path,
+                other.metadata.path,
+                self.metadata.key,
+                other.metadata.key
+            );
+        }
+        let samples_a: Vec<f64> = self.cycles.iter().filter_map(|c| c.converged_mean).collect();
+        let samples_b: Vec<f64> = other.cycles.iter().filter_map(|c| c.converged_mean).collect();
+        verdict_from_samples(&self.metadata.key, &samples_a, &samples_b, alpha)
+    }
+}
+
+/// Per-key Welch's t-test verdict comparing two sets of `converged_mean`
+/// samples, in the same vocabulary as tast's `AbtestMetricVerdict`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BluebenchComparisonVerdict {
+    /// The shared `BluebenchMetadata::key` (hwid/dut_id/bootid/mitigations)
+    /// the two sides were compared under.
+    pub key: String,
+    pub n_a: usize,
+    pub n_b: usize,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub welch_t: f64,
+    pub welch_df: f64,
+    pub welch_p: f64,
+    pub cohens_d: f64,
+    /// True if `welch_p` is below the caller's `alpha`.
+    pub significant: bool,
+}
+
+fn verdict_from_samples(
+    key: &str,
+    samples_a: &[f64],
+    samples_b: &[f64],
+    alpha: f64,
+) -> Result<BluebenchComparisonVerdict> {
+    if samples_a.len() < 2 || samples_b.len() < 2 {
+        bail!(
+            "{key}: at least 2 converged_mean samples are needed on each side to run Welch's \
+             t-test (got {} vs {})",
+            samples_a.len(),
+            samples_b.len()
+        );
+    }
+    let (mean_a, _) = mean_var(samples_a);
+    let (mean_b, _) = mean_var(samples_b);
+    let (welch_t, welch_df, welch_p) = welch_t_test(samples_a, samples_b);
+    Ok(BluebenchComparisonVerdict {
+        key: key.to_string(),
+        n_a: samples_a.len(),
+        n_b: samples_b.len(),
+        mean_a,
+        mean_b,
+        welch_t,
+        welch_df,
+        welch_p,
+        cohens_d: cohens_d(samples_a, samples_b),
+        significant: welch_p < alpha,
+    })
+}
+
+/// Groups `baseline` and `candidate` runs by `metadata.key` (so only
+/// genuinely comparable hwid/dut_id/bootid/kernel_cmdline_mitigations
+/// configurations are tested against each other), pools each side's
+/// per-cycle `converged_mean` samples within a key, and runs Welch's t-test
+/// between the two pools. Keys missing from either side, or with fewer than
+/// 2 samples on either side, are skipped (and logged) since Welch's test
+/// needs at least that much.
+pub fn compare_grouped(
+    baseline: &[BluebenchResult],
+    candidate: &[BluebenchResult],
+    alpha: f64,
+) -> Vec<BluebenchComparisonVerdict> {
+    fn group_by_key(results: &[BluebenchResult]) -> HashMap<String, Vec<f64>> {
+        let mut by_key: HashMap<String, Vec<f64>> = HashMap::new();
+        for r in results {
+            by_key
+                .entry(r.metadata.key.clone())
+                .or_default()
+                .extend(r.cycles.iter().filter_map(|c| c.converged_mean));
+        }
+        by_key
+    }
+    let by_key_a = group_by_key(baseline);
+    let by_key_b = group_by_key(candidate);
+    let mut verdicts = Vec::new();
+    for (key, samples_a) in &by_key_a {
+        let Some(samples_b) = by_key_b.get(key) else {
+            continue;
+        };
+        match verdict_from_samples(key, samples_a, samples_b, alpha) {
+            Ok(v) => verdicts.push(v),
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+    verdicts.sort_by(|l, r| l.key.cmp(&r.key));
+    verdicts
 }
@@ -2,26 +2,39 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
-use crate::util::gen_path_in_lium_dir;
+use crate::util::xdg_dirs::cache_path_in_lium_dir;
 use anyhow::Context;
 use anyhow::Result;
+use fs2::FileExt;
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde::Serialize;
 use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::io::Read;
-use std::io::Seek;
 use std::io::Write;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 pub struct KvCache<T: Serialize + DeserializeOwned + Sized + Clone + Debug> {
     name: &'static str,
     map: Mutex<Option<HashMap<String, T>>>,
-    file: Mutex<Option<File>>,
+    // Held open for the lifetime of the process (once opened) purely so
+    // `flock`'s advisory lock has somewhere to live; the actual cache data
+    // is read/written through `path()` directly, never through this fd, so
+    // that `sync`'s temp-file-then-rename never has to juggle a stale
+    // handle pointing at a since-replaced inode.
+    lock_file: Mutex<Option<File>>,
     //
     _value_type: PhantomData<T>,
 }
@@ -30,51 +43,67 @@ impl<T: Serialize + DeserializeOwned + Sized + Clone + Debug> KvCache<T> {
         Self {
             name,
             map: Mutex::new(None),
-            file: Mutex::new(None),
+            lock_file: Mutex::new(None),
             _value_type: PhantomData::<T>,
         }
     }
     pub fn clear(&self) -> Result<()> {
-        self.load_cache_file()?;
-        {
+        self.with_exclusive_lock(|| {
             let mut map = self.map.lock().unwrap();
-            let map = map.as_mut().unwrap();
-            map.clear();
-        }
-        self.sync()
+            map.as_mut().unwrap().clear();
+            drop(map);
+            self.sync()
+        })
+    }
+    fn path(&self) -> Result<PathBuf> {
+        cache_path_in_lium_dir(self.name).context("Failed to generate a cache file path")
+    }
+    /// A sibling lock file, distinct from the cache file itself, so holding
+    /// the advisory lock across a `sync()` (which replaces the cache file
+    /// via temp-then-rename) never requires re-locking a brand new fd.
+    fn lock_path(&self) -> Result<PathBuf> {
+        let path = self.path()?;
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(self.name);
+        Ok(path.with_file_name(format!("{file_name}.lock")))
+    }
+    /// Backs up `path` (rather than deleting it) so a transient parse
+    /// failure never throws away a user's accumulated cache.
+    fn backup_corrupt_file(path: &Path) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let backup_path = path.with_file_name(format!(
+            "{}.corrupt-{now}",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("cache")
+        ));
+        std::fs::rename(path, &backup_path)
+            .context("Failed to back up the corrupt cache file")?;
+        eprintln!("Cache file was corrupt; backed it up to {backup_path:?} and starting fresh");
+        Ok(())
     }
-    fn create_file(&self, remove: bool) -> Result<()> {
-        let path =
-            gen_path_in_lium_dir(self.name).context("Failed to generate a cache file path")?;
-        if remove {
-            std::fs::remove_file(&path).context("Failed to remove the file")?;
+    fn ensure_file_exists(&self) -> Result<()> {
+        let path = self.path()?;
+        if path.exists() {
+            return Ok(());
         }
         let mut f = OpenOptions::new()
-            .read(true)
             .write(true)
             .create(true)
-            .open(path)?;
-        if f.metadata()?.len() == 0 {
-            f.write_all(serde_json::to_string(&Map::<String, Value>::new())?.as_bytes())?;
-            f.sync_all()?;
-        }
-        let mut file = self.file.lock().expect("lock failed");
-        *file = Some(f);
+            .truncate(false)
+            .open(&path)?;
+        f.write_all(serde_json::to_string(&Map::<String, Value>::new())?.as_bytes())?;
+        f.sync_all()?;
         Ok(())
     }
     fn load_cache_file(&self) -> Result<()> {
-        let file = self.file.lock().expect("lock failed");
-        let has_file = file.is_some();
-        drop(file);
-        if !has_file {
-            self.create_file(false)?;
-        }
-        let mut file_lock = self.file.lock().expect("lock failed");
-        let file = file_lock.as_mut().expect("File is not initialized yet");
-        file.rewind()?;
+        self.ensure_file_exists()?;
+        let path = self.path()?;
         let mut json = String::new();
-        file.read_to_string(&mut json)?;
-        drop(file_lock);
+        File::open(&path)?.read_to_string(&mut json)?;
         match serde_json::from_str(&json) {
             Ok(data) => {
                 *self.map.lock().unwrap() = data;
@@ -82,39 +111,87 @@ impl<T: Serialize + DeserializeOwned + Sized + Clone + Debug> KvCache<T> {
             }
             Err(e) => {
                 eprintln!("Failed to parse the cache: {e:?}");
-                eprintln!("Creating a cache file again...");
-                self.create_file(true)?;
+                Self::backup_corrupt_file(&path)?;
+                self.ensure_file_exists()?;
                 *self.map.lock().unwrap() = Some(HashMap::new());
                 Ok(())
             }
         }
     }
+    /// Acquires the advisory exclusive lock, opening the lock file on
+    /// first use, and holds it for the duration of `f` -- which should
+    /// reload the latest on-disk state before mutating it, so a process
+    /// that raced us to the lock isn't silently overwritten.
+    fn with_exclusive_lock<R>(&self, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        {
+            let mut lock_file = self.lock_file.lock().expect("lock failed");
+            if lock_file.is_none() {
+                let lock_path = self.lock_path()?;
+                *lock_file = Some(
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .open(lock_path)
+                        .context("Failed to open the cache lock file")?,
+                );
+            }
+            lock_file
+                .as_ref()
+                .unwrap()
+                .lock_exclusive()
+                .context("Failed to acquire the cache file lock")?;
+        }
+        let result = self.load_cache_file().and_then(|_| f());
+        let lock_file = self.lock_file.lock().expect("lock failed");
+        let _ = FileExt::unlock(lock_file.as_ref().expect("lock file is not initialized yet"));
+        result
+    }
     pub fn get(&self, key: &str) -> Result<Option<T>> {
         self.load_cache_file()?;
         let mut map = self.map.lock().unwrap();
         let map = map.as_mut().unwrap();
         Ok(map.get(key).cloned())
     }
+    pub fn remove(&self, key: &str) -> Result<()> {
+        self.with_exclusive_lock(|| {
+            let mut map = self.map.lock().unwrap();
+            map.as_mut().unwrap().remove(key);
+            drop(map);
+            self.sync()
+        })
+    }
     pub fn set(&self, key: &str, value: T) -> Result<()> {
-        self.load_cache_file()?;
-        {
+        self.with_exclusive_lock(|| {
             let mut map = self.map.lock().unwrap();
-            let map = map.as_mut().unwrap();
-            map.insert(key.to_string(), value.clone());
-        }
-        self.sync()?;
+            map.as_mut().unwrap().insert(key.to_string(), value.clone());
+            drop(map);
+            self.sync()
+        })?;
         eprintln!("Cache updated. key: {}, value: {:?}", key, value);
         Ok(())
     }
+    /// Writes the in-memory map to a sibling temp file, `fsync`s it, then
+    /// atomically renames it over the real cache file, so a crash or a
+    /// concurrent reader never observes a half-written document.
     pub fn sync(&self) -> Result<()> {
-        let mut map = self.map.lock().unwrap();
-        let map = map.as_mut().unwrap();
-        let mut file = self.file.lock().expect("lock failed");
-        let file = file.as_mut().expect("File is not initialized yet");
-        file.set_len(0)?;
-        file.rewind()?;
-        file.write_all(serde_json::to_string(map)?.as_bytes())?;
-        file.sync_all().context("failed to sync backed file")
+        let map = self.map.lock().unwrap();
+        let map = map.as_ref().unwrap();
+        let path = self.path()?;
+        let tmp_path = path.with_file_name(format!(
+            "{}.tmp-{}",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("cache"),
+            std::process::id()
+        ));
+        let mut tmp = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .context("Failed to create a temp cache file")?;
+        tmp.write_all(serde_json::to_string(map)?.as_bytes())?;
+        tmp.sync_all().context("failed to sync the temp cache file")?;
+        drop(tmp);
+        std::fs::rename(&tmp_path, &path).context("Failed to atomically replace the cache file")
     }
     pub fn entries(&self) -> Result<HashMap<String, T>> {
         self.load_cache_file()?;
@@ -133,3 +210,134 @@ impl<T: Serialize + DeserializeOwned + Sized + Clone + Debug> KvCache<T> {
         }
     }
 }
+
+/// Identifies a memoized invocation for [`KvCache::get_or_compute`]: the
+/// program, args, working directory, and whichever env vars the caller
+/// considers part of its identity, plus optional fingerprints of files the
+/// computation reads. Hashing these (instead of comparing them directly)
+/// is what lets [`Memoized`] store a single stable key alongside the value.
+#[derive(Debug, Clone, Default)]
+pub struct CacheKeyInputs {
+    program: String,
+    args: Vec<String>,
+    dir: Option<String>,
+    env: Vec<(String, String)>,
+    input_files: Vec<PathBuf>,
+}
+
+impl CacheKeyInputs {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, args: I) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<String>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Fingerprints `path` (size + mtime) as part of the cache key, so a
+    /// change to the file invalidates any entry computed from it.
+    pub fn input_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.input_files.push(path.into());
+        self
+    }
+
+    /// Hashes the invocation identity plus each input file's current
+    /// fingerprint into a stable hex string. Missing input files hash as
+    /// absent rather than failing, so a compute function that tolerates a
+    /// missing file (and the next cache hit check) behaves consistently.
+    fn stable_hash(&self) -> String {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.program.hash(&mut hasher);
+        self.args.hash(&mut hasher);
+        self.dir.hash(&mut hasher);
+        self.env.hash(&mut hasher);
+        for path in &self.input_files {
+            path.hash(&mut hasher);
+            match std::fs::metadata(path) {
+                Ok(meta) => {
+                    meta.len().hash(&mut hasher);
+                    if let Ok(modified) = meta.modified() {
+                        if let Ok(age) = modified.duration_since(UNIX_EPOCH) {
+                            age.as_nanos().hash(&mut hasher);
+                        }
+                    }
+                }
+                Err(_) => "missing".hash(&mut hasher),
+            }
+        }
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// A [`KvCache`] value wrapped with the hash of the invocation that produced
+/// it and when it was computed, so [`KvCache::get_or_compute`] can tell a
+/// stale entry (changed inputs, or older than its TTL) from a fresh one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Memoized<T> {
+    hash: String,
+    timestamp_secs: u64,
+    pub value: T,
+}
+
+impl<T> Memoized<T> {
+    /// How long ago this entry was computed.
+    pub fn age(&self) -> Result<Duration> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Duration::from_secs(now.saturating_sub(self.timestamp_secs)))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Sized + Clone + Debug> KvCache<Memoized<T>> {
+    /// Looks up `key`, recomputing with `f` on a miss: the key isn't
+    /// present, `inputs`'s hash doesn't match what was stored (one of the
+    /// invocation's inputs changed), or the entry is older than `ttl` (when
+    /// given). Otherwise reuses the cached value without calling `f`.
+    pub fn get_or_compute(
+        &self,
+        key: &str,
+        inputs: &CacheKeyInputs,
+        ttl: Option<Duration>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let hash = inputs.stable_hash();
+        if let Some(entry) = self.get(key)? {
+            let stale = entry.hash != hash || matches!(ttl, Some(ttl) if entry.age()? > ttl);
+            if !stale {
+                return Ok(entry.value);
+            }
+        }
+        let value = f()?;
+        let timestamp_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system clock is before the epoch")?
+            .as_secs();
+        self.set(
+            key,
+            Memoized {
+                hash,
+                timestamp_secs,
+                value: value.clone(),
+            },
+        )?;
+        Ok(value)
+    }
+}
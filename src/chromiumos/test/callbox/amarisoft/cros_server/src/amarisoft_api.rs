@@ -5,30 +5,217 @@
 use crate::common::ServerError;
 
 use serde::de::DeserializeOwned;
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::error::Error;
-use std::{collections::HashMap, str, time};
-use tungstenite::{
-    client::IntoClientRequest,
-    connect,
-    protocol::{frame::coding::CloseCode, CloseFrame},
-    Message,
-};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{str, time};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+const AMARISOFT_WS_URL: &str = "ws://127.0.0.1:9000/";
+/// How long `AmarisoftClient::call` waits for a reply tagged with its
+/// `message_id` before giving up, e.g. if the server hangs or drops the
+/// frame on the floor.
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long `run_connection` waits before retrying after a failed connect
+/// or a socket that closed out from under it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
 struct ImeisvCache {
     imeisv: String,
     last_retrieved: std::time::Instant,
 }
 
+/// A request queued for the background connection task: the JSON-RPC-style
+/// payload (already tagged with `message_id`) and where to deliver the
+/// matching reply.
+struct PendingCall {
+    payload: Value,
+    reply: oneshot::Sender<Value>,
+}
+
+/// Long-lived async client for the Amarisoft Remote API: keeps one
+/// `tokio-tungstenite` connection open for the life of the process and
+/// multiplexes `call()`s over it by tagging each outgoing message with a
+/// monotonic `message_id` and handing the matching reply to whichever
+/// caller is waiting on that id, instead of connecting, sending, and
+/// reading two frames (assuming the 2nd is always the answer) per call.
+#[derive(Clone)]
+struct AmarisoftClient {
+    next_id: Arc<AtomicU64>,
+    to_connection: mpsc::UnboundedSender<PendingCall>,
+}
+
+impl AmarisoftClient {
+    /// Spawns the background connection task onto `handle` and returns a
+    /// client that can issue concurrent `call()`s against it -- e.g.
+    /// multiple DUTs' `ue_get` lookups no longer serialize behind one
+    /// connect/send/read round trip each.
+    fn spawn(handle: &tokio::runtime::Handle) -> AmarisoftClient {
+        let (to_connection, from_callers) = mpsc::unbounded_channel();
+        handle.spawn(run_connection(from_callers));
+        AmarisoftClient {
+            next_id: Arc::new(AtomicU64::new(1)),
+            to_connection,
+        }
+    }
+
+    /// Sends `{"message": method, "message_id": <id>, ...params}` over the
+    /// shared connection and awaits the reply tagged with the same id,
+    /// deserializing it as `T`.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Box<dyn Error>> {
+        let message_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut payload = match params {
+            Value::Object(map) => map,
+            Value::Null => Map::new(),
+            other => {
+                return Err(Box::new(ServerError(format!(
+                    "call params must be a JSON object or null, got {other}"
+                ))))
+            }
+        };
+        payload.insert("message".to_string(), Value::String(method.to_string()));
+        payload.insert("message_id".to_string(), Value::from(message_id));
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.to_connection
+            .send(PendingCall {
+                payload: Value::Object(payload),
+                reply: reply_tx,
+            })
+            .map_err(|_| {
+                Box::new(ServerError(
+                    "the Amarisoft connection task is no longer running".into(),
+                )) as Box<dyn Error>
+            })?;
+
+        let reply = timeout(CALL_TIMEOUT, reply_rx)
+            .await
+            .map_err(|_| {
+                Box::new(ServerError(format!(
+                    "{method} (message_id {message_id}) timed out after {CALL_TIMEOUT:?}"
+                ))) as Box<dyn Error>
+            })?
+            .map_err(|_| {
+                Box::new(ServerError(format!(
+                    "the Amarisoft connection task dropped the reply to {method}"
+                ))) as Box<dyn Error>
+            })?;
+
+        serde_json::from_value(reply)
+            .map_err(|_| Box::new(ServerError(format!("Failed to parse reply to {method}"))) as Box<dyn Error>)
+    }
+}
+
+/// Owns the single WebSocket connection to the Amarisoft Remote API for as
+/// long as the process runs: reconnects (after `RECONNECT_DELAY`) whenever
+/// the connect fails or the socket closes, writes each queued
+/// [`PendingCall`]'s payload as it arrives, and demultiplexes incoming
+/// frames by `message_id` to wake the right caller's oneshot. A call queued
+/// while reconnecting simply waits in `from_callers` until the next
+/// connection comes up, or times out in [`AmarisoftClient::call`] if that
+/// takes too long.
+async fn run_connection(mut from_callers: mpsc::UnboundedReceiver<PendingCall>) {
+    loop {
+        let socket = match connect().await {
+            Ok(socket) => socket,
+            Err(e) => {
+                println!("Failed to connect to the Amarisoft Remote API: {e}; retrying in {RECONNECT_DELAY:?}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        let (mut write, mut read) = socket.split();
+        let mut pending: HashMap<u64, oneshot::Sender<Value>> = HashMap::new();
+        loop {
+            tokio::select! {
+                call = from_callers.recv() => {
+                    let Some(call) = call else {
+                        return; // Every AmarisoftClient was dropped.
+                    };
+                    let Some(message_id) = call.payload.get("message_id").and_then(Value::as_u64) else {
+                        continue;
+                    };
+                    if write.send(Message::Text(call.payload.to_string())).await.is_err() {
+                        // call's caller will time out; the outer loop reconnects.
+                        break;
+                    }
+                    pending.insert(message_id, call.reply);
+                }
+                frame = read.next() => {
+                    let Some(Ok(Message::Text(text))) = frame else {
+                        break; // Socket closed or errored; reconnect.
+                    };
+                    let Ok(value) = serde_json::from_str::<Value>(&text) else {
+                        continue;
+                    };
+                    if let Some(message_id) = value.get("message_id").and_then(Value::as_u64) {
+                        if let Some(reply) = pending.remove(&message_id) {
+                            let _ = reply.send(value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Connects to the Amarisoft Remote API's WebSocket endpoint.
+async fn connect() -> Result<WsStream, Box<dyn Error>> {
+    let mut req = AMARISOFT_WS_URL.into_client_request()?;
+    req.headers_mut()
+        .insert("Origin", "croscellularserver.com".parse()?);
+    let (socket, _response) = connect_async(req).await?;
+    Ok(socket)
+}
+
 pub(crate) struct AmarisoftAPI {
     imeisv_cache: HashMap<String, ImeisvCache>,
+    // Owns the background connection task; callers of `get_imeisv`/`call`
+    // stay synchronous by `block_on`-ing against it, so `CrosWebServer`
+    // (built around tiny_http's blocking request loop) doesn't itself need
+    // to become async just to talk to the Amarisoft Remote API.
+    runtime: tokio::runtime::Runtime,
+    client: AmarisoftClient,
 }
 
 impl AmarisoftAPI {
     pub fn new() -> AmarisoftAPI {
-        return AmarisoftAPI {
+        let runtime = tokio::runtime::Runtime::new()
+            .expect("Failed to start the Amarisoft client's async runtime");
+        let client = AmarisoftClient::spawn(runtime.handle());
+        AmarisoftAPI {
             imeisv_cache: HashMap::new(),
-        };
+            runtime,
+            client,
+        }
+    }
+
+    /// Typed entry point so future MME commands don't each reimplement the
+    /// connect/send/read loop: blocks the calling thread until the reply
+    /// tagged with this call's `message_id` arrives (or it times out), then
+    /// deserializes it as `T`.
+    pub fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> std::result::Result<T, Box<dyn Error>> {
+        self.runtime.block_on(self.client.call(method, params))
     }
 
     fn extract_member<T: DeserializeOwned>(
@@ -55,9 +242,8 @@ impl AmarisoftAPI {
                 return Ok(val.imeisv.clone());
             }
         }
-        let data = Self::call_mme_remote_api(r#"{"message": "ue_get"}"#)?;
-        let ue_get = serde_json::from_str::<Value>(data.as_str())
-            .map_err(|_| Self::new_json_parse_error("ue_get", data.as_str()))?;
+        let ue_get: Value = self.call("ue_get", Value::Null)?;
+        let data = ue_get.to_string();
         for ue in Self::extract_member::<Vec<Value>>(&ue_get, "ue_list", &data)? {
             // The bearers object might not exist
             if ue.get("bearers").is_none() {
@@ -85,52 +271,6 @@ impl AmarisoftAPI {
         )));
     }
 
-    // Execute a generic command on the Amarisoft Remote API using ws.js
-    fn call_mme_remote_api(command: &str) -> std::result::Result<String, Box<dyn Error>> {
-        let mut req = "ws://127.0.0.1:9000/".into_client_request()?;
-        let headers = req.headers_mut();
-        headers.insert("Origin", "croscellularserver.com".parse()?);
-
-        let (mut socket, _response) = connect(req).expect("Can't connect");
-        socket.write_message(Message::Text(command.into())).unwrap();
-        // If the server becomes slow due to high demand from DUTs, this should be improved by making the requests async.
-        match socket.get_mut() {
-            tungstenite::stream::MaybeTlsStream::Plain(t) => {
-                t.set_read_timeout(Some(std::time::Duration::from_millis(100)))
-                    .expect("Error: cannot set read-timeout to underlying stream");
-            }
-            _ => return Err(Box::new(ServerError("Error: it is not TlsStream".into()))),
-        }
-        // This could be improved by keeping the socket opened.
-        let mut msg: String;
-        let mut counter = 0;
-        let start = std::time::Instant::now();
-        loop {
-            match socket.read_message() {
-                Ok(val) => {
-                    counter += 1;
-                    msg = val.to_string(); // replace the string. the server returns 2 values, and the last one is the one we need.
-                    if counter == 2 {
-                        let close_frame = CloseFrame {
-                            code: CloseCode::Normal,
-                            reason: Default::default(),
-                        };
-
-                        _ = socket
-                            .close(Some(close_frame))
-                            .map_err(|err| println!("Failed to close the connection:{}", err));
-                        println!("Remote API call succeeded in : {:?}", start.elapsed());
-                        return Ok(msg);
-                    }
-                }
-                Err(_) => {
-                    println!("Remote API call failed in : {:?}", start.elapsed());
-                    return Ok(String::new());
-                }
-            }
-        }
-    }
-
     fn new_json_parse_error(object_name: &str, message: &str) -> Box<dyn Error> {
         return Box::new(ServerError(format!(
             "Failed to parse {}. message:{}",
@@ -5,9 +5,11 @@
 use crate::common;
 use crate::common::ServerError;
 
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 
 pub const ENTITLEMENT_OK_CODE: u16 = common::HTTP_OK;
 pub const ENTITLEMENT_NOK_CODE: u16 = common::HTTP_ERROR_FORBIDDEN;
@@ -15,6 +17,10 @@ pub const ENTITLEMENT_ERROR_USER_NOT_ALLOWED_TO_TETHER: i32 = 1000;
 pub const ENTITLEMENT_ERROR_SYNTAX_ERROR: i32 = 1001;
 const ENTITLEMENT_ERROR_USER_NOT_RECOGNIZED: i32 = 1003;
 
+// Where the etl_result/ignore_next_etl maps are persisted, so configured
+// verdicts survive a server restart instead of resetting every time.
+const ENTITLEMENT_STATE_FILE: &str = "entitlement_state.json";
+
 pub enum EntitlementCheckResult {
     Ok,
     NotOk(i32),
@@ -25,6 +31,11 @@ pub enum EntitlementCheckResult {
 struct SetupEntitlementReturnCodeForDevice {
     imsi: String,
     code: i32,
+    // Optional glob pattern (matched with `glob::Pattern`) overriding the
+    // imeisv half of the key, e.g. "35*" to cover a whole batch of test SIMs
+    // that share an IMSI. Defaults to the requesting imeisv's exact value.
+    #[serde(default)]
+    imeisv: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -32,21 +43,69 @@ struct ResetEntitlementValueForDevice {
     imsi: String,
 }
 
+#[derive(Serialize, Deserialize, Default)]
+struct IgnoreNextEntitlementCheckForDevice {
+    #[serde(default = "default_ignore_count")]
+    count: u16,
+}
+fn default_ignore_count() -> u16 {
+    1
+}
+
+// The etl_result/ignore_next_etl maps, as written to ENTITLEMENT_STATE_FILE.
+#[derive(Serialize, Deserialize, Default)]
+struct PersistedEntitlementState {
+    etl_result: HashMap<String, i32>,
+    ignore_next_etl: HashMap<String, u16>,
+}
+
 pub(crate) struct EntitlementCheck {
-    // A `map{imsi: string, result:boolean}` containing the result to be returned for
-    // an imsi for each entitlement check.
+    // A `map{imeisv-imsi pattern: string, result: i32}` containing the result
+    // to be returned for an imeisv/imsi combination for each entitlement
+    // check. Keys may be glob patterns (see `SetupEntitlementReturnCodeForDevice`),
+    // so a batch of test SIMs sharing an IMSI can be configured with one entry.
     etl_result: HashMap<String, i32>,
     ignore_next_etl: HashMap<String, u16>,
 }
 
 impl EntitlementCheck {
     pub fn new() -> EntitlementCheck {
+        let state = Self::load_state();
         return EntitlementCheck {
-            etl_result: HashMap::new(),
-            ignore_next_etl: HashMap::new(),
+            etl_result: state.etl_result,
+            ignore_next_etl: state.ignore_next_etl,
         };
     }
 
+    fn load_state() -> PersistedEntitlementState {
+        match fs::read_to_string(ENTITLEMENT_STATE_FILE) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(_) => PersistedEntitlementState::default(),
+        }
+    }
+
+    // Serializes the current state to ENTITLEMENT_STATE_FILE, writing to a
+    // temp file and renaming it into place so a crash mid-write can't leave
+    // behind a corrupt (partially written) state file.
+    fn persist(&self) {
+        let state = PersistedEntitlementState {
+            etl_result: self.etl_result.clone(),
+            ignore_next_etl: self.ignore_next_etl.clone(),
+        };
+        if let Err(error) = Self::write_state_atomically(&state) {
+            println!("Failed to persist entitlement state: {}", error);
+        }
+    }
+
+    fn write_state_atomically(
+        state: &PersistedEntitlementState,
+    ) -> std::result::Result<(), Box<dyn Error>> {
+        let tmp_path = format!("{}.tmp", ENTITLEMENT_STATE_FILE);
+        fs::write(&tmp_path, serde_json::to_string_pretty(state)?)?;
+        fs::rename(&tmp_path, ENTITLEMENT_STATE_FILE)?;
+        Ok(())
+    }
+
     pub fn is_ignore_next_request(&mut self, imeisv: &str) -> bool {
         if let Some(val) = self.ignore_next_etl.get_mut(imeisv) {
             *val = *val - 1;
@@ -67,15 +126,10 @@ impl EntitlementCheck {
             "check_entitlement. imeisv={} imsi={} key:{}",
             imeisv, imsi, imeisv_imsi_key
         );
-        let result: i32;
-        if self.etl_result.contains_key(&imeisv_imsi_key) {
-            result = self.etl_result[&imeisv_imsi_key];
-        } else if self.etl_result.contains_key(imsi) {
-            // try falling back to the imsi only, in case the callbox API is failing.
-            result = self.etl_result[imsi];
-        } else {
-            return EntitlementCheckResult::NotOk(ENTITLEMENT_ERROR_USER_NOT_RECOGNIZED);
-        }
+        let result = match Self::resolve_entitlement(&self.etl_result, &imeisv_imsi_key, imsi) {
+            Some(result) => result,
+            None => return EntitlementCheckResult::NotOk(ENTITLEMENT_ERROR_USER_NOT_RECOGNIZED),
+        };
         if result == 0 {
             return EntitlementCheckResult::Ok;
         } else {
@@ -83,6 +137,38 @@ impl EntitlementCheck {
         }
     }
 
+    // Resolves `key` (and, as a fallback, `imsi` alone) against the
+    // configured etl_result entries, which may be exact strings or glob
+    // patterns. An exact match always wins; among glob matches, the one
+    // with the longest literal (non-wildcard) prefix wins, since it's the
+    // most specific.
+    fn resolve_entitlement(
+        etl_result: &HashMap<String, i32>,
+        key: &str,
+        imsi: &str,
+    ) -> Option<i32> {
+        if let Some(result) = etl_result.get(key) {
+            return Some(*result);
+        }
+        if let Some(result) = etl_result.get(imsi) {
+            return Some(*result);
+        }
+        let mut best: Option<(usize, i32)> = None;
+        for (pattern, result) in etl_result {
+            let Ok(compiled) = Pattern::new(pattern) else {
+                continue;
+            };
+            if !compiled.matches(key) && !compiled.matches(imsi) {
+                continue;
+            }
+            let specificity = literal_prefix_len(pattern);
+            if best.map(|(len, _)| specificity > len).unwrap_or(true) {
+                best = Some((specificity, *result));
+            }
+        }
+        best.map(|(_, result)| result)
+    }
+
     fn make_key(imeisv: &str, imsi: &str) -> String {
         return imeisv.to_owned() + "-" + imsi;
     }
@@ -92,6 +178,7 @@ impl EntitlementCheck {
         setup: SetupEntitlementReturnCodeForDevice,
         imeisv: &str,
     ) -> std::result::Result<(), Box<dyn Error>> {
+        let imeisv = setup.imeisv.as_deref().unwrap_or(imeisv);
         println!(
             "setup_entitlement_value. imeisv={} imsi={} value:{} key:{}",
             imeisv,
@@ -101,6 +188,7 @@ impl EntitlementCheck {
         );
         self.etl_result
             .insert(Self::make_key(imeisv, setup.imsi.as_str()), setup.code);
+        self.persist();
         return Ok(());
     }
 
@@ -113,6 +201,7 @@ impl EntitlementCheck {
         self.etl_result.retain(|key, _| !key.starts_with(imeisv));
         self.etl_result.remove(&setup.imsi);
         self.ignore_next_etl.remove(imeisv);
+        self.persist();
         return Ok(());
     }
 
@@ -151,11 +240,26 @@ impl EntitlementCheck {
                 }
             }
             "IgnoreNextEntitlementCheckForDevice" => {
-                // For now, we only ignore 1 request, but we could add an argument in the command to configure the number
-                self.ignore_next_etl.insert(imeisv.to_owned(), 1);
+                let count = match common::parse_value_to_json::<IgnoreNextEntitlementCheckForDevice>(
+                    setup.params,
+                ) {
+                    Ok(val) => val.count,
+                    Err(_) => default_ignore_count(),
+                };
+                self.ignore_next_etl.insert(imeisv.to_owned(), count.max(1));
+                self.persist();
                 Ok(())
             }
             _ => return Err(Box::new(ServerError("Unknown command".to_owned()))),
         }
     }
 }
+
+// The length of the longest prefix of `pattern` containing no glob
+// metacharacters, used to rank overlapping glob matches by specificity.
+fn literal_prefix_len(pattern: &str) -> usize {
+    pattern
+        .chars()
+        .take_while(|c| !matches!(c, '*' | '?' | '[' | ']'))
+        .count()
+}
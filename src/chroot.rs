@@ -5,80 +5,47 @@
 // https://developers.google.com/open-source/licenses/bsd
 
 use std::fs;
-use std::process::Command;
-use std::process::Stdio;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 
-use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
-use signal_hook::consts::SIGINT;
-use tracing::error;
 use tracing::info;
 
-use crate::util::cro3_paths::cro3_dir;
+use crate::config::Config;
 use crate::util::cro3_paths::gen_path_in_cro3_dir;
-use crate::util::shell_helpers::get_stderr;
-use crate::util::shell_helpers::get_stdout;
-use crate::util::shell_helpers::run_bash_command;
+
+mod backend;
+pub use backend::ContainerBackend;
+pub use backend::CrosSdkBackend;
+pub use backend::ExecBackend;
 
 #[derive(Debug)]
 pub struct Chroot {
     repo_path: String,
+    backend: Box<dyn ExecBackend>,
 }
 impl Chroot {
     pub fn new(repo_path: &str) -> Result<Self> {
+        Self::with_backend(repo_path, default_backend()?)
+    }
+    /// Like [`Chroot::new`], but overrides the config-selected backend,
+    /// e.g. for a `--backend` CLI flag.
+    pub fn with_backend(repo_path: &str, backend: Box<dyn ExecBackend>) -> Result<Self> {
         let chroot = Chroot {
             repo_path: repo_path.to_string(),
+            backend,
         };
-        let cro3_dir_path = cro3_dir()?;
         info!("Using Chromium OS checkout at {}", repo_path);
-        run_bash_command(
-            &format!(
-                "echo {0} /cro3 > {1} && cat {1}",
-                cro3_dir_path, "src/scripts/.local_mounts"
-            ),
-            Some(repo_path),
-        )?
-        .status
-        .exit_ok()?;
+        chroot.backend.init(&chroot.repo_path)?;
         // Remove ~/.bash_logout in chroot to avoid clearing the screen after exiting
         // Ignore error
         drop(chroot.run_bash_script_in_chroot("remove_bash_logout", "rm -f ~/.bash_logout", None));
         Ok(chroot)
     }
     pub fn exec_in_chroot(&self, args: &[&str]) -> Result<String> {
-        let mut cmd = Command::new("cros_sdk");
-        cmd.arg("--no-ns-pid")
-            .arg("--")
-            .args(args)
-            .current_dir(&self.repo_path)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-        info!("in chroot: {:?}", cmd);
-        let cmd = cmd.spawn()?;
-        let result = cmd.wait_with_output()?;
-        result
-            .status
-            .exit_ok()
-            .context(anyhow!("exec_in_chroot failed: {}", get_stderr(&result)))?;
-        let result = get_stdout(&result);
-        Ok(result)
+        self.backend.exec_in_chroot(&self.repo_path, args)
     }
     pub fn exec_in_chroot_async(&self, args: &[&str]) -> Result<async_process::Child> {
-        let mut cmd = async_process::Command::new("bash");
-        let cmd = cmd
-            .arg("-c")
-            .arg("cros_sdk --no-ns-pid -- ".to_string() + &args.join(" "))
-            .current_dir(&self.repo_path)
-            .kill_on_drop(true)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-        info!("Executing: {cmd:?} async");
-        cmd.spawn().context("exec_in_chroot_async failed")
+        self.backend.exec_in_chroot_async(&self.repo_path, args)
     }
     pub fn write_bash_script_for_chroot(&self, name: &str, script: &str) -> Result<()> {
         let dst = gen_path_in_cro3_dir(&format!("tmp/{name}.sh"))?;
@@ -100,76 +67,44 @@ impl Chroot {
         args: Option<&[&str]>,
     ) -> Result<String> {
         self.write_bash_script_for_chroot(name, script)?;
-        let mut cmd = Command::new("cros_sdk");
-        cmd.args([
-            "--no-ns-pid",
-            "--",
-            "bash",
-            "-xe",
-            &format!("/cro3/tmp/{}.sh", name),
-        ])
-        .current_dir(&self.repo_path)
-        .stdin(Stdio::piped());
-        if let Some(args) = args {
-            cmd.args(args);
-        }
-        info!("Running {name} in chroot...");
-        let run = cmd
-            .spawn()
-            .context(anyhow!("spawn failed. cmd = {cmd:?}"))?;
-
-        // Hit Ctrl-C twice to terminate cro3 immediately.
-        // Note that the Ctrl-C (SIGINT) will be sent to both the bash script
-        // in chroot and the parent cro3 process from the terminal.
-        // The bash script will (hopefully) terminates its child process but
-        // it may take a while. Since cro3 will quit immediately by default
-        // we need to setup SIGINT handlers to wait it.
-        let intr = Arc::new(AtomicBool::new(false));
-        // This will shutdown cro3 only if the 'intr' is true.
-        signal_hook::flag::register_conditional_shutdown(SIGINT, 1, Arc::clone(&intr))?;
-        // This will handle the first SIGINT to set the 'intr' flag true.
-        signal_hook::flag::register(SIGINT, Arc::clone(&intr))?;
-        // As a result, the first SIGINT set 'intr' flag true and the child bash
-        // script will be terminated (but it takes a time.)
-        // If user wants to quit immediately, send the 2nd SIGINT and it
-        // will shutdown cro3 because 'intr' is true now.
-
-        let result = run
-            .wait_with_output()
-            .context(anyhow!("wait_with_output_failed. cmd = {cmd:?}"))?;
-
-        // Even if user does not send SIGINT twice, this will return an error.
-        if intr.load(Ordering::Relaxed) {
-            return Err(anyhow!("Caught a SIGINT (Ctrl+C)"));
-        }
-        result
-            .status
-            .exit_ok()
-            .context(anyhow!("run_in_chroot failed. cmd = {cmd:?}"))?;
-        let result = get_stdout(&result);
-        Ok(result)
+        self.backend
+            .run_bash_script_in_chroot(&self.repo_path, name, args)
     }
     pub fn run_in_chroot_async(&self, script: &str) -> Result<async_process::Child> {
-        async_process::Command::new("cros_sdk")
-            .args(["--no-ns-pid", "--", "bash", "-xe", "-c", script])
-            .current_dir(&self.repo_path)
-            .kill_on_drop(true)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to launch servod")
+        self.backend.run_in_chroot_async(&self.repo_path, script)
     }
     pub fn open_chroot(&self, additional_args: &[String]) -> Result<()> {
-        let cmd = Command::new("cros_sdk")
-            .arg("--no-color")
-            .args(additional_args)
-            .current_dir(&self.repo_path)
-            .spawn()?;
-        let result = cmd.wait_with_output()?;
-        if !result.status.success() {
-            error!("cros sdk failed");
+        self.backend.open_chroot(&self.repo_path, additional_args)
+    }
+}
+
+/// Name used to select [`ContainerBackend`] via config/`--backend`.
+pub const BACKEND_NAME_CONTAINER: &str = "container";
+/// Name used to select [`CrosSdkBackend`] via config/`--backend`; also the
+/// default when nothing else is configured.
+pub const BACKEND_NAME_CROS_SDK: &str = "cros-sdk";
+
+/// Resolves a backend by the name accepted by config's `chroot_backend` key
+/// and the `--backend` CLI flag (`cros-sdk` or `container`).
+pub fn backend_by_name(name: &str) -> Result<Box<dyn ExecBackend>> {
+    match name {
+        BACKEND_NAME_CROS_SDK => Ok(Box::new(CrosSdkBackend::default())),
+        BACKEND_NAME_CONTAINER => {
+            let config = Config::read()?;
+            Ok(Box::new(ContainerBackend::new(
+                config.chroot_container_runtime().unwrap_or_else(|| "podman".to_string()),
+                config
+                    .chroot_container_image()
+                    .context("chroot_container_image must be set in config to use the container backend")?,
+            )))
         }
-        Ok(())
+        _ => anyhow::bail!("Unknown chroot backend {name:?} (expected {BACKEND_NAME_CROS_SDK:?} or {BACKEND_NAME_CONTAINER:?})"),
     }
 }
+
+fn default_backend() -> Result<Box<dyn ExecBackend>> {
+    let name = Config::read()?
+        .chroot_backend()
+        .unwrap_or_else(|| BACKEND_NAME_CROS_SDK.to_string());
+    backend_by_name(&name)
+}
@@ -0,0 +1,296 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! The actual command execution [`Chroot`](super::Chroot) delegates to.
+//! [`CrosSdkBackend`] is today's `cros_sdk --no-ns-pid -- ...` behavior;
+//! [`ContainerBackend`] runs the same generated bash scripts inside a
+//! rootless OCI container instead, for hosts that only have a container
+//! runtime rather than a full ChromiumOS SDK chroot. Both implement
+//! [`ExecBackend`] so callers (`cro3 tast`, sync, `cro3 chroot`, ...) don't
+//! need to know which one they got.
+
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use signal_hook::consts::SIGINT;
+use tracing::error;
+use tracing::info;
+
+use crate::util::cro3_paths::cro3_dir;
+use crate::util::shell_helpers::get_stderr;
+use crate::util::shell_helpers::get_stdout;
+
+/// Where `Chroot` sends command execution. Implementors own the detail of
+/// how a bash script or argv ends up running "in chroot"; `Chroot` itself
+/// only knows the repo path and which backend to ask.
+pub trait ExecBackend: std::fmt::Debug {
+    /// One-time setup for a freshly created `Chroot`, e.g. cros_sdk's
+    /// `.local_mounts` trick. Called once from `Chroot::new`.
+    fn init(&self, repo_path: &str) -> Result<()>;
+    fn exec_in_chroot(&self, repo_path: &str, args: &[&str]) -> Result<String>;
+    fn exec_in_chroot_async(&self, repo_path: &str, args: &[&str]) -> Result<async_process::Child>;
+    /// Runs the bash script `name` was already written to (by `Chroot`, at
+    /// `~/.cro3/tmp/{name}.sh`, which both backends bind-mount as
+    /// `/cro3/tmp/{name}.sh`) with the double-Ctrl-C SIGINT handling intact.
+    fn run_bash_script_in_chroot(
+        &self,
+        repo_path: &str,
+        name: &str,
+        args: Option<&[&str]>,
+    ) -> Result<String>;
+    fn run_in_chroot_async(&self, repo_path: &str, script: &str) -> Result<async_process::Child>;
+    fn open_chroot(&self, repo_path: &str, additional_args: &[String]) -> Result<()>;
+}
+
+/// Waits for `run`, honoring the "hit Ctrl-C twice to terminate cro3
+/// immediately" convention shared by both backends' `run_bash_script_in_chroot`:
+/// the first SIGINT is expected to propagate to (and be handled by) the
+/// child script, while a second one shuts cro3 down right away instead of
+/// waiting for the child to notice.
+fn wait_with_double_sigint(run: std::process::Child, cmd: &Command) -> Result<String> {
+    let intr = Arc::new(AtomicBool::new(false));
+    // This will shutdown cro3 only if the 'intr' is true.
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 1, Arc::clone(&intr))?;
+    // This will handle the first SIGINT to set the 'intr' flag true.
+    signal_hook::flag::register(SIGINT, Arc::clone(&intr))?;
+
+    let result = run
+        .wait_with_output()
+        .context(anyhow!("wait_with_output_failed. cmd = {cmd:?}"))?;
+
+    // Even if user does not send SIGINT twice, this will return an error.
+    if intr.load(Ordering::Relaxed) {
+        return Err(anyhow!("Caught a SIGINT (Ctrl+C)"));
+    }
+    result
+        .status
+        .exit_ok()
+        .context(anyhow!("run_in_chroot failed. cmd = {cmd:?}"))?;
+    Ok(get_stdout(&result))
+}
+
+/// Today's behavior: run everything via `cros_sdk --no-ns-pid -- ...` in a
+/// full ChromiumOS SDK chroot.
+#[derive(Debug, Clone, Default)]
+pub struct CrosSdkBackend {}
+impl ExecBackend for CrosSdkBackend {
+    fn init(&self, repo_path: &str) -> Result<()> {
+        let cro3_dir_path = cro3_dir()?;
+        crate::util::shell_helpers::run_bash_command(
+            &format!(
+                "echo {0} /cro3 > {1} && cat {1}",
+                cro3_dir_path, "src/scripts/.local_mounts"
+            ),
+            Some(repo_path),
+        )?
+        .status
+        .exit_ok()?;
+        Ok(())
+    }
+    fn exec_in_chroot(&self, repo_path: &str, args: &[&str]) -> Result<String> {
+        let mut cmd = Command::new("cros_sdk");
+        cmd.arg("--no-ns-pid")
+            .arg("--")
+            .args(args)
+            .current_dir(repo_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        info!("in chroot: {:?}", cmd);
+        let cmd = cmd.spawn()?;
+        let result = cmd.wait_with_output()?;
+        result
+            .status
+            .exit_ok()
+            .context(anyhow!("exec_in_chroot failed: {}", get_stderr(&result)))?;
+        Ok(get_stdout(&result))
+    }
+    fn exec_in_chroot_async(&self, repo_path: &str, args: &[&str]) -> Result<async_process::Child> {
+        let mut cmd = async_process::Command::new("bash");
+        let cmd = cmd
+            .arg("-c")
+            .arg("cros_sdk --no-ns-pid -- ".to_string() + &args.join(" "))
+            .current_dir(repo_path)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        info!("Executing: {cmd:?} async");
+        cmd.spawn().context("exec_in_chroot_async failed")
+    }
+    fn run_bash_script_in_chroot(
+        &self,
+        repo_path: &str,
+        name: &str,
+        args: Option<&[&str]>,
+    ) -> Result<String> {
+        let mut cmd = Command::new("cros_sdk");
+        cmd.args([
+            "--no-ns-pid",
+            "--",
+            "bash",
+            "-xe",
+            &format!("/cro3/tmp/{name}.sh"),
+        ])
+        .current_dir(repo_path)
+        .stdin(Stdio::piped());
+        if let Some(args) = args {
+            cmd.args(args);
+        }
+        info!("Running {name} in chroot...");
+        let run = cmd
+            .spawn()
+            .context(anyhow!("spawn failed. cmd = {cmd:?}"))?;
+        // Hit Ctrl-C twice to terminate cro3 immediately.
+        // Note that the Ctrl-C (SIGINT) will be sent to both the bash script
+        // in chroot and the parent cro3 process from the terminal.
+        // The bash script will (hopefully) terminates its child process but
+        // it may take a while. Since cro3 will quit immediately by default
+        // we need to setup SIGINT handlers to wait it.
+        wait_with_double_sigint(run, &cmd)
+    }
+    fn run_in_chroot_async(&self, repo_path: &str, script: &str) -> Result<async_process::Child> {
+        async_process::Command::new("cros_sdk")
+            .args(["--no-ns-pid", "--", "bash", "-xe", "-c", script])
+            .current_dir(repo_path)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to launch servod")
+    }
+    fn open_chroot(&self, repo_path: &str, additional_args: &[String]) -> Result<()> {
+        let cmd = Command::new("cros_sdk")
+            .arg("--no-color")
+            .args(additional_args)
+            .current_dir(repo_path)
+            .spawn()?;
+        let result = cmd.wait_with_output()?;
+        if !result.status.success() {
+            error!("cros sdk failed");
+        }
+        Ok(())
+    }
+}
+
+/// Runs the same generated bash scripts inside a rootless OCI container
+/// instead of a ChromiumOS SDK chroot, for hosts that only have a container
+/// runtime (podman/docker). Mirrors `CrosSdkBackend`'s `.local_mounts`
+/// trick by bind-mounting `~/.cro3` (where `Chroot` writes its scripts) as
+/// `/cro3`, and bind-mounts the repo itself so script-relative paths still
+/// resolve.
+#[derive(Debug, Clone)]
+pub struct ContainerBackend {
+    /// Container runtime binary, e.g. "podman" or "docker".
+    runtime: String,
+    /// Image providing the toolchain the generated scripts expect.
+    image: String,
+}
+impl ContainerBackend {
+    pub fn new(runtime: String, image: String) -> Self {
+        Self { runtime, image }
+    }
+    fn base_command(&self, repo_path: &str) -> Result<Command> {
+        let cro3_dir_path = cro3_dir()?;
+        let mut cmd = Command::new(&self.runtime);
+        cmd.arg("run")
+            .arg("--rm")
+            .arg("-i")
+            .arg("-v")
+            .arg(format!("{repo_path}:/repo"))
+            .arg("-v")
+            .arg(format!("{cro3_dir_path}:/cro3"))
+            .arg("-w")
+            .arg("/repo")
+            .arg(&self.image);
+        Ok(cmd)
+    }
+}
+impl ExecBackend for ContainerBackend {
+    fn init(&self, _repo_path: &str) -> Result<()> {
+        info!(
+            "Using {} container runtime with image {} instead of cros_sdk",
+            self.runtime, self.image
+        );
+        Ok(())
+    }
+    fn exec_in_chroot(&self, repo_path: &str, args: &[&str]) -> Result<String> {
+        let mut cmd = self.base_command(repo_path)?;
+        cmd.args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        info!("in container: {:?}", cmd);
+        let cmd = cmd.spawn()?;
+        let result = cmd.wait_with_output()?;
+        result.status.exit_ok().context(anyhow!(
+            "exec_in_chroot (container) failed: {}",
+            get_stderr(&result)
+        ))?;
+        Ok(get_stdout(&result))
+    }
+    fn exec_in_chroot_async(&self, repo_path: &str, args: &[&str]) -> Result<async_process::Child> {
+        let cmd = self.base_command(repo_path)?;
+        let mut cmd = async_process::Command::from(cmd);
+        cmd.args(args)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        info!("Executing: {cmd:?} async");
+        cmd.spawn().context("exec_in_chroot_async failed")
+    }
+    fn run_bash_script_in_chroot(
+        &self,
+        repo_path: &str,
+        name: &str,
+        args: Option<&[&str]>,
+    ) -> Result<String> {
+        let mut cmd = self.base_command(repo_path)?;
+        cmd.args(["bash", "-xe", &format!("/cro3/tmp/{name}.sh")]);
+        if let Some(args) = args {
+            cmd.args(args);
+        }
+        cmd.stdin(Stdio::piped());
+        info!("Running {name} in container...");
+        let run = cmd
+            .spawn()
+            .context(anyhow!("spawn failed. cmd = {cmd:?}"))?;
+        wait_with_double_sigint(run, &cmd)
+    }
+    fn run_in_chroot_async(&self, repo_path: &str, script: &str) -> Result<async_process::Child> {
+        let cmd = self.base_command(repo_path)?;
+        let mut cmd = async_process::Command::from(cmd);
+        cmd.args(["bash", "-xe", "-c", script])
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        cmd.spawn().context("Failed to launch servod")
+    }
+    fn open_chroot(&self, repo_path: &str, additional_args: &[String]) -> Result<()> {
+        let mut cmd = self.base_command(repo_path)?;
+        for arg in additional_args {
+            if let Some((key, value)) = arg.split_once('=') {
+                cmd.env(key, value);
+            }
+        }
+        cmd.arg("bash");
+        let cmd = cmd.spawn()?;
+        let result = cmd.wait_with_output()?;
+        if !result.status.success() {
+            error!("container shell failed");
+        }
+        Ok(())
+    }
+}
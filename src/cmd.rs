@@ -8,18 +8,26 @@ use anyhow::Result;
 use argh::FromArgs;
 
 pub mod arc;
+pub mod artifact;
+pub mod bluebench;
 pub mod build;
 pub mod chroot;
 pub mod cl;
 pub mod config;
+pub mod daemon;
 pub mod deploy;
 pub mod dut;
+pub mod firmware;
 pub mod flash;
+pub mod metrics;
 pub mod packages;
+pub mod plugin;
 pub mod servo;
 pub mod setup;
+pub mod setup_sdk;
 pub mod sync;
 pub mod tast;
+pub mod tunnel;
 pub mod version;
 pub mod vm;
 
@@ -34,27 +42,73 @@ pub struct TopLevel {
     /// https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html
     pub verbosity: Option<String>,
 
+    /// output format: "human" (default) or "json". When "json", both
+    /// successful output and errors are emitted as machine-readable JSON
+    /// instead of free text, so pipelines can parse failures too.
+    #[argh(option, default = "\"human\".to_string()")]
+    pub format: String,
+
+    /// shorthand for --format json; kept as a separate flag since it reads
+    /// better than --format json at the call site (e.g. `cro3 --json dut
+    /// list`)
+    #[argh(switch)]
+    pub json: bool,
+
+    /// suppress normal (non-error) output from the command, e.g. for use
+    /// in scripts that only care about the exit code
+    #[argh(switch)]
+    pub quiet: bool,
+
+    /// named config profile to use instead of the active one (see `cro3
+    /// config profile`), for one-off commands against another lab/checkout
+    /// without switching the persisted default. Can also be set with the
+    /// CRO3_PROFILE env var.
+    #[argh(option)]
+    pub profile: Option<String>,
+
+    /// write every #[tracing::instrument]ed span's timing to <file.json> in
+    /// Chrome Trace Event Format, viewable in chrome://tracing or Perfetto --
+    /// useful for seeing where a slow build/sync/deploy run actually spends
+    /// its time
+    #[argh(option)]
+    pub trace_output: Option<String>,
+
     #[argh(subcommand)]
     nested: Args,
 }
+impl TopLevel {
+    /// Whether JSON output was requested via either --json or --format
+    /// json.
+    pub fn json_requested(&self) -> bool {
+        self.json || self.format == "json"
+    }
+}
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 /// lium's ChromiumOS dev commands
 pub enum Args {
     Arc(arc::Args),
+    Artifact(artifact::Args),
+    Bluebench(bluebench::Args),
     Build(build::Args),
     Cl(cl::Args),
     Chroot(chroot::Args),
     Config(config::Args),
+    Daemon(daemon::Args),
     Deploy(deploy::Args),
     Dut(dut::Args),
+    Firmware(firmware::Args),
     Flash(flash::Args),
+    Metrics(metrics::Args),
     Packages(packages::Args),
+    Plugin(plugin::Args),
     Servo(servo::Args),
     Setup(setup::Args),
+    SetupSdk(setup_sdk::Args),
     Sync(sync::Args),
     Tast(tast::Args),
+    Tunnel(tunnel::Args),
     Version(version::Args),
     Vm(vm::Args),
 }
@@ -63,18 +117,26 @@ pub enum Args {
 pub fn run(args: &TopLevel) -> Result<()> {
     match &args.nested {
         Args::Arc(args) => arc::run(args),
+        Args::Artifact(args) => artifact::run(args),
+        Args::Bluebench(args) => bluebench::run(args),
         Args::Build(args) => build::run(args),
         Args::Cl(args) => cl::run(args),
         Args::Chroot(args) => chroot::run(args),
         Args::Config(args) => config::run(args),
+        Args::Daemon(args) => daemon::run(args),
         Args::Deploy(args) => deploy::run(args),
         Args::Dut(args) => dut::run(args),
+        Args::Firmware(args) => firmware::run(args),
         Args::Flash(args) => flash::run(args),
+        Args::Metrics(args) => metrics::run(args),
         Args::Packages(args) => packages::run(args),
+        Args::Plugin(args) => plugin::run(args),
         Args::Servo(args) => servo::run(args),
         Args::Setup(args) => setup::run(args),
+        Args::SetupSdk(args) => setup_sdk::run(args),
         Args::Sync(args) => sync::run(args),
         Args::Tast(args) => tast::run(args),
+        Args::Tunnel(args) => tunnel::run(args),
         Args::Version(args) => version::run(args),
         Args::Vm(args) => vm::run(args),
     }
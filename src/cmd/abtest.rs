@@ -31,19 +31,31 @@ use std::net::TcpListener;
 use std::net::TcpStream;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread::spawn;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use cro3::chroot::Chroot;
 use cro3::dut::SshInfo;
+use cro3::rand_util::seeded_fisher_yates_shuffle;
 use cro3::repo::get_cros_dir;
+use cro3::stats::mann_whitney_u;
 use lazy_static::lazy_static;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
 use rayon::prelude::*;
 use regex::Regex;
+use rustls::ServerConfig;
+use rustls::ServerConnection;
+use rustls::StreamOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use tracing::error;
@@ -52,6 +64,13 @@ use tracing::warn;
 
 use crate::cmd::tast::run_tast_test;
 
+mod formatters;
+mod router;
+
+use router::Route;
+use router::RoutePattern;
+use router::Router;
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Run / analyze performance experiments
 #[argh(subcommand, name = "abtest")]
@@ -65,6 +84,7 @@ impl Args {
         match &self.nested {
             SubCommand::Run(args) => args.run(),
             SubCommand::Analyze(args) => args.run(),
+            SubCommand::AnalyzeRuns(args) => args.run(),
         }
     }
 }
@@ -73,12 +93,41 @@ impl Args {
 enum SubCommand {
     Run(ArgsRun),
     Analyze(ArgsAnalyze),
+    AnalyzeRuns(ArgsAnalyzeRuns),
 }
 
-#[derive(Debug)]
-enum ExperimentConfig {
-    A,
-    B,
+/// One arm of an (possibly N-way) experiment: a name used both for display
+/// and as the `config` label threaded into the result key, plus the setup
+/// script that drives the DUT into that configuration.
+#[derive(Debug, Clone)]
+struct ExperimentConfigSpec {
+    name: String,
+    script_path: String,
+}
+impl std::str::FromStr for ExperimentConfigSpec {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, script_path) = s
+            .split_once('=')
+            .context(anyhow!("--script-config must be in NAME=PATH form, got {s:?}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            script_path: script_path.to_string(),
+        })
+    }
+}
+
+/// Assigns a partition set to an experiment config by its index, round
+/// robin over the two slots the hardware actually has. This lets an
+/// arbitrary number of configs be driven through the same A/B-capable
+/// boot mechanism (each config still gets its own setup script run after
+/// the partition switch).
+fn partition_for_index(index: usize) -> cro3::dut::PartitionSet {
+    if index % 2 == 0 {
+        cro3::dut::PartitionSet::Primary
+    } else {
+        cro3::dut::PartitionSet::Secondary
+    }
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -97,19 +146,18 @@ struct ArgsRun {
     #[argh(option)]
     script_init: Option<String>,
 
-    /// path to a setup script for experiment config A
-    #[argh(option)]
-    script_config_a: String,
-
-    /// path to a setup script for experiment config B
+    /// experiment config in NAME=PATH form (repeatable). Each name gets
+    /// its own setup script and its own arm in the analyzed results;
+    /// specify it two or more times for a multivariate (N-way) experiment
+    /// instead of a plain A/B one.
     #[argh(option)]
-    script_config_b: String,
+    script_config: Vec<ExperimentConfigSpec>,
 
     /// tast test identifier
     #[argh(option)]
     tast_test: String,
 
-    /// a group contains one invocation of script_config_a (or b) and some
+    /// a group contains one invocation of a config's setup script and some
     /// invocation of tast_test (for run_per_cluster times)
     #[argh(option)]
     run_per_group: Option<usize>,
@@ -135,15 +183,50 @@ struct ArgsRun {
     /// path to a dir to store the results
     #[argh(option)]
     result_dir: Option<String>,
+
+    /// seed for shuffling the A/B group execution order within a cluster.
+    /// Defaults to a value derived from --experiment-name so a given
+    /// experiment name always replays the same interleaving.
+    #[argh(option)]
+    seed: Option<u64>,
 }
 impl ArgsRun {
-    fn run_group(&self, config: ExperimentConfig, dut: &SshInfo) -> Result<()> {
+    /// Deterministic seed for this run: the user-supplied `--seed`, or one
+    /// derived from `experiment_name` so reruns of the same experiment
+    /// replay the same interleaving by default.
+    fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(|| {
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::Hash;
+            use std::hash::Hasher;
+            let mut hasher = DefaultHasher::new();
+            self.experiment_name.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+    fn run_group(
+        &self,
+        config_index: usize,
+        config: &ExperimentConfigSpec,
+        cluster: usize,
+        group: usize,
+        dut: &SshInfo,
+    ) -> Result<()> {
         let repodir = get_cros_dir(Some(&self.cros))?;
         let chroot = Chroot::new(&repodir)?;
-        match config {
-            ExperimentConfig::A => dut.switch_partition_set(cro3::dut::PartitionSet::Primary),
-            ExperimentConfig::B => dut.switch_partition_set(cro3::dut::PartitionSet::Secondary),
-        }?;
+        dut.switch_partition_set(partition_for_index(config_index))?;
+        dut.send_files(&[config.script_path.clone()], None).context(anyhow!(
+            "Failed to send the {} setup script ({}) to the DUT",
+            config.name,
+            config.script_path
+        ))?;
+        let remote_script = Path::new(&config.script_path)
+            .file_name()
+            .context("invalid --script-config path")?
+            .to_string_lossy()
+            .into_owned();
+        dut.run_cmd_stdio(&format!("sh ./{remote_script}"))
+            .context(anyhow!("Failed to run the {} setup script", config.name))?;
         if let Err(e) = dut.reboot() {
             warn!("reboot failed (ignored): {e:?}");
         }
@@ -151,28 +234,73 @@ impl ArgsRun {
 
         for i in 0..self.run_per_group.unwrap_or(20) {
             info!("#### run {i}");
+            let resultsdir = self.result_dir.as_ref().map(|result_dir| {
+                Path::new(result_dir).join(format!(
+                    "{}_c{cluster}_{}{group}_r{i}_{}",
+                    self.experiment_name,
+                    config.name,
+                    chrono::Local::now().format("%Y%m%d_%H%M%S_%f"),
+                ))
+            });
+            if let Some(resultsdir) = &resultsdir {
+                fs::create_dir_all(resultsdir).context("Failed to create the result dir")?;
+                fs::write(
+                    resultsdir.join("cro3_abtest_run_metadata.json"),
+                    serde_json::to_string(&serde_json::json!({ "config": config.name }))?,
+                )
+                .context(anyhow!("Failed to write the abtest run metadata"))?;
+            }
+            let resultsdir_arg =
+                resultsdir.map(|d| format!("-resultsdir {}", d.to_string_lossy()));
             retry::retry(retry::delay::Fixed::from_millis(500).take(3), || {
-                run_tast_test(&chroot, dut, &self.tast_test, None)
+                run_tast_test(&chroot, dut, &self.tast_test, resultsdir_arg.as_deref())
             })
             .or(Err(anyhow!("Failed to run tast test after retries")))?;
         }
         Ok(())
     }
-    fn run_cluster(&self, dut: &SshInfo) -> Result<()> {
-        for i in 0..self.group_per_cluster.unwrap_or(1) {
-            info!("### group A-{i}");
-            self.run_group(ExperimentConfig::A, dut)?;
+    fn run_cluster(&self, dut: &SshInfo, cluster: usize) -> Result<()> {
+        let group_per_cluster = self.group_per_cluster.unwrap_or(1);
+        let mut work_items: Vec<(usize, usize)> = (0..self.script_config.len())
+            .flat_map(|config_index| (0..group_per_cluster).map(move |group| (config_index, group)))
+            .collect();
+        // Mix in the cluster index so each cluster gets its own (but still
+        // reproducible) interleaving instead of repeating the same one.
+        let seed = self.seed().wrapping_add(cluster as u64);
+        seeded_fisher_yates_shuffle(&mut work_items, seed);
+        info!(
+            "cluster {cluster} realized order (seed={seed}): {:?}",
+            work_items
+                .iter()
+                .map(|(ci, g)| format!("{}{g}", self.script_config[*ci].name))
+                .collect::<Vec<_>>()
+        );
+        if let Some(result_dir) = &self.result_dir {
+            let order: Vec<String> = work_items
+                .iter()
+                .map(|(ci, g)| format!("{}{g}", self.script_config[*ci].name))
+                .collect();
+            let path = Path::new(result_dir).join(format!("cluster{cluster}_realized_order.json"));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).ok();
+            }
+            fs::write(&path, serde_json::to_string(&order)?)
+                .context(anyhow!("Failed to write {path:?}"))?;
         }
-        for i in 0..self.group_per_cluster.unwrap_or(1) {
-            info!("### group A-{i}");
-            self.run_group(ExperimentConfig::B, dut)?;
+        for (config_index, group) in work_items {
+            let config = &self.script_config[config_index];
+            info!("### group {}-{group}", config.name);
+            self.run_group(config_index, config, cluster, group, dut)?;
         }
         Ok(())
     }
     fn run_iteration(&self, dut: &SshInfo) -> Result<()> {
+        if self.script_config.len() < 2 {
+            bail!("Please specify --script-config NAME=PATH at least twice (e.g. A and B)");
+        }
         for i in 0..self.cluster_per_iteration.unwrap_or(1000) {
             info!("## cluster {i}");
-            self.run_cluster(dut)?;
+            self.run_cluster(dut, i)?;
         }
         Ok(())
     }
@@ -228,9 +356,27 @@ struct ArgsAnalyze {
     #[argh(option, default = "8080")]
     port: u16,
 
+    /// path to a PEM certificate chain; serve HTTPS instead of plain HTTP
+    /// (requires --tls-key, and binds beyond localhost so remote lab
+    /// machines can reach the dashboard)
+    #[argh(option)]
+    tls_cert: Option<String>,
+    /// path to the PEM private key matching --tls-cert
+    #[argh(option)]
+    tls_key: Option<String>,
+
     /// list DUT information from the specified results
     #[argh(switch)]
     list_duts: bool,
+
+    /// emit a machine-readable report instead of data.csv/*.csv: "junit" or
+    /// "json"
+    #[argh(option)]
+    format: Option<String>,
+    /// regression threshold (as a fraction of effect size / converged_mean,
+    /// e.g. 0.05 for 5%) above which a junit testcase is marked as failed
+    #[argh(option, default = "0.05")]
+    regression_threshold: f64,
 }
 impl ArgsAnalyze {
     fn run(&self) -> Result<()> {
@@ -240,10 +386,46 @@ impl ArgsAnalyze {
                 .test_name
                 .as_ref()
                 .context("--test-name should be specified")?;
+            if let Some(format) = &self.format {
+                let results = collect_candidates(self)?;
+                let results = analyze_all(results, test_name, self.hwid.as_deref());
+                let comparisons = compare_configs(&results);
+                match format.as_str() {
+                    "junit" => {
+                        print!(
+                            "{}",
+                            formatters::to_junit_xml(
+                                &results,
+                                &comparisons,
+                                self.regression_threshold
+                            )
+                        );
+                    }
+                    "json" => {
+                        print!("{}", formatters::to_json(&results, &comparisons)?);
+                    }
+                    other => bail!("Unknown --format {other:?}, expected junit or json"),
+                }
+                return Ok(());
+            }
             generate(self, test_name)?;
         }
         if self.serve {
-            listen_http(self.port)?;
+            let tls_config = match (&self.tls_cert, &self.tls_key) {
+                (Some(cert), Some(key)) => Some(load_tls_config(cert, key)?),
+                (None, None) => None,
+                _ => bail!("--tls-cert and --tls-key must be specified together"),
+            };
+            if let Some(test_name) = &self.test_name {
+                *API_CONFIG.lock().unwrap() = Some(ApiConfig {
+                    cros: self.cros.clone(),
+                    results_dir: self.results_dir.clone(),
+                    test_name: test_name.clone(),
+                });
+            } else {
+                warn!("--test-name not given; /api/results will be unavailable");
+            }
+            listen_http(self.port, tls_config)?;
         }
         if self.list_duts {
             let test_name = self
@@ -264,7 +446,66 @@ impl ArgsAnalyze {
     }
 }
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// Post-hoc Welch/Mann-Whitney comparison of ExperimentRunner-produced A/B
+/// tast results (see `cro3::abtest::ExperimentRunner`), distinct from the
+/// bluebench-oriented `analyze` subcommand above.
+#[argh(subcommand, name = "analyze-runs")]
+struct ArgsAnalyzeRuns {
+    /// results dir containing cro3_abtest_run_metadata.json files (as
+    /// written by `ExperimentRunner::run_group`)
+    #[argh(positional)]
+    results_dir: String,
+    /// tast test name whose results-chart.json metrics should be compared
+    #[argh(option)]
+    test_name: String,
+    /// significance level for the compact verdict table
+    #[argh(option, default = "0.05")]
+    alpha: f64,
+}
+impl ArgsAnalyzeRuns {
+    fn run(&self) -> Result<()> {
+        let results = cro3::tast::collect_results(None, Some(&self.results_dir), None, None)?;
+        let results: Vec<_> = results
+            .into_iter()
+            .filter(|r| r.result_json_item.name == self.test_name)
+            .collect();
+        let verdicts = cro3::tast::compare_abtest_results(&results, self.alpha);
+        if verdicts.is_empty() {
+            bail!(
+                "No comparable metric found under {:?} for test {:?} (need >=2 units per arm \
+                 with abtest metadata and results-chart.json)",
+                self.results_dir,
+                self.test_name
+            );
+        }
+        println!(
+            "{:<32} {:>5} {:>5} {:>12} {:>12} {:>9} {:>9} {:>9} {:>9} {:>8}",
+            "metric", "n_a", "n_b", "mean_a", "mean_b", "welch_p", "mw_p", "cohens_d", "ci_diff",
+            "verdict"
+        );
+        for v in &verdicts {
+            println!(
+                "{:<32} {:>5} {:>5} {:>12.4} {:>12.4} {:>9.4} {:>9.4} {:>9.3} [{:>.3},{:>.3}] {:>8}",
+                v.metric,
+                v.n_a,
+                v.n_b,
+                v.mean_a,
+                v.mean_b,
+                v.welch_p,
+                v.mann_whitney_p,
+                v.cohens_d,
+                v.mean_diff_ci_low,
+                v.mean_diff_ci_high,
+                if v.significant { "DIFFERS" } else { "-" }
+            );
+        }
+        Ok(())
+    }
+}
+
 const HTTP_RESPONSE_HEADER_200_OK: &str = r#"HTTP/1.1 200 OK"#;
+const HTTP_RESPONSE_HEADER_206_PARTIAL_CONTENT: &str = r#"HTTP/1.1 206 Partial Content"#;
 const HTTP_RESPONSE_HEADER_404_NOT_FOUND: &str = r#"HTTP/1.1 404 NOT FOUND"#;
 const HTTP_RESPONSE_HEADER_KEEP_ALIVE: &str = r#"Keep-Alive: timeout=5, max=100"#;
 const HTTP_RESPONSE_HEADER_HTML_UTF8: &str = r#"Content-Type: text/html; charset=UTF-8"#;
@@ -285,8 +526,22 @@ struct BluebenchCycleResult {
     raw: Vec<f64>,
 }
 
+/// The subset of `ArgsAnalyze` needed to serve `/api/results` queries:
+/// where to look for tast results and which test's metrics to parse.
+/// Captured once at `--serve` startup, since a request handler otherwise
+/// has no access to the original CLI args.
+struct ApiConfig {
+    cros: Option<String>,
+    results_dir: Option<String>,
+    test_name: String,
+}
+
 lazy_static! {
-    static ref RE_CSV_PATH: Regex = Regex::new(r"^/[A-Za-z0-9_.]+.csv$").unwrap();
+    /// Live `/ws` connections to push new result rows to as they land.
+    static ref WS_CLIENTS: Mutex<Vec<TcpStream>> = Mutex::new(Vec::new());
+    /// Set by `ArgsAnalyze::run` before `listen_http` starts, if
+    /// `--test-name` was given alongside `--serve`.
+    static ref API_CONFIG: Mutex<Option<ApiConfig>> = Mutex::new(None);
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -513,6 +768,64 @@ struct BluebenchResult {
     last_result_date: String,
     converged_mean_mean: f64,
     cycles: Vec<BluebenchCycleResult>,
+    experiment_config: Option<String>,
+    /// Set when the package temperature exceeded [`THERMAL_CEILING_C`] or
+    /// PL1 sagged below [`PL1_NOMINAL_W`] for a sustained window during the
+    /// test, meaning `converged_mean_mean` likely reflects thermal
+    /// throttling rather than the configuration under test.
+    thermally_compromised: bool,
+}
+
+/// Default package-temperature ceiling (in Celsius) above which a run is
+/// flagged as thermally compromised.
+const THERMAL_CEILING_C: f64 = 95.0;
+/// Nominal PL1 power budget (in Watts); a sustained drop below this
+/// fraction of nominal indicates the SoC throttled its power limit.
+const PL1_NOMINAL_W: f64 = 15.0;
+const PL1_SUSTAINED_DROP_FRACTION: f64 = 0.9;
+/// Number of consecutive below-nominal PL1 samples required to flag a
+/// "sustained" drop, as opposed to a single noisy reading.
+const PL1_SUSTAINED_SAMPLE_COUNT: usize = 3;
+
+/// Scans the (already test-window-filtered) temperature readouts and
+/// returns whether the run looks thermally compromised.
+fn is_thermally_compromised(metadata: &BluebenchMetadata) -> bool {
+    if let Some(pkg_temp) = metadata.temperature_sensor_readouts.get("x86_pkg_temp_C") {
+        if pkg_temp.iter().any(|(_, v)| *v > THERMAL_CEILING_C) {
+            return true;
+        }
+    }
+    if let Some(pl1) = metadata.temperature_sensor_readouts.get("PL1_W") {
+        let threshold = PL1_NOMINAL_W * PL1_SUSTAINED_DROP_FRACTION;
+        let mut consecutive_low = 0;
+        for (_, v) in pl1 {
+            if *v < threshold {
+                consecutive_low += 1;
+                if consecutive_low >= PL1_SUSTAINED_SAMPLE_COUNT {
+                    return true;
+                }
+            } else {
+                consecutive_low = 0;
+            }
+        }
+    }
+    false
+}
+
+/// Shape written by `ArgsRun::run_group` (and, for the legacy A/B-only
+/// path, `cro3::abtest::ExperimentRunner::run_group`) next to the tast
+/// results. Only the `config` label is read here; any other fields in the
+/// metadata file are ignored.
+#[derive(Deserialize)]
+struct RunConfigMetadata {
+    config: String,
+}
+
+/// Reads the experiment arm recorded next to the tast results, if any.
+fn read_experiment_config(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(path.join("cro3_abtest_run_metadata.json")).ok()?;
+    let metadata: RunConfigMetadata = serde_json::from_str(&text).ok()?;
+    Some(metadata.config)
 }
 
 fn analyze_one_result(
@@ -586,12 +899,19 @@ fn analyze_one_result(
     let converged_means: Vec<f64> = cycles.iter().filter_map(|c| c.converged_mean).collect();
     let converged_mean_mean = converged_means.iter().sum::<f64>() / converged_means.len() as f64;
     let last_result_date = cycles.last().unwrap().date.clone();
+    let experiment_config = read_experiment_config(path);
+    let thermally_compromised = is_thermally_compromised(&metadata);
+    if thermally_compromised {
+        warn!("{path:?}: thermally compromised, converged_mean_mean may be unreliable");
+    }
     info!("parse done: {:?} {:?}", t0.elapsed(), path);
     Ok(BluebenchResult {
         metadata,
         last_result_date,
         cycles,
         converged_mean_mean,
+        experiment_config,
+        thermally_compromised,
     })
 }
 
@@ -700,6 +1020,45 @@ fn write_temp_csv(
     Ok(())
 }
 
+/// Extracts `(timestamp, value, config key)` tuples for `metric` from
+/// `results`, the same rows [`write_latency_csv`] and [`write_temp_csv`]
+/// write to `data.csv` and the per-sensor CSVs. `"converged_mean_mean"`
+/// selects the benchmark's own metric; anything else is looked up in
+/// [`BluebenchMetadata::temperature_sensor_readouts`].
+fn extract_metric_points(results: &[BluebenchResult], metric: &str) -> Vec<(String, f64, String)> {
+    let mut data: Vec<(String, f64, String)> = if metric == "converged_mean_mean" {
+        results
+            .iter()
+            .map(|r| {
+                (
+                    r.last_result_date.to_string(),
+                    r.converged_mean_mean,
+                    r.metadata.key.to_string(),
+                )
+            })
+            .collect()
+    } else {
+        results
+            .iter()
+            .flat_map(|r| {
+                let k = &r.metadata.key;
+                r.metadata
+                    .temperature_sensor_readouts
+                    .get(metric)
+                    .map(|e| {
+                        e.iter()
+                            .map(|(t, v)| (t.clone(), *v, k.clone()))
+                            .collect::<Vec<(String, f64, String)>>()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect()
+    };
+    data.sort_by(|l, r| l.0.cmp(&r.0));
+    data.dedup();
+    data
+}
+
 fn result_key_order(results: &[BluebenchResult]) -> HashMap<String, usize> {
     let mut result_keys = BTreeSet::<String>::new();
     let mut result_key_counts = HashMap::<String, usize>::new();
@@ -718,34 +1077,215 @@ fn result_key_order(results: &[BluebenchResult]) -> HashMap<String, usize> {
     result_key_order
 }
 
+fn median(values: &[f64]) -> f64 {
+    let mut v = values.to_vec();
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = v.len();
+    if n == 0 {
+        f64::NAN
+    } else if n % 2 == 1 {
+        v[n / 2]
+    } else {
+        (v[n / 2 - 1] + v[n / 2]) / 2.0
+    }
+}
+
+/// Bootstrap confidence interval for the difference of medians (B - A).
+/// Returns `(effect_size, ci_low, ci_high)`.
+fn bootstrap_median_ci(a: &[f64], b: &[f64], iterations: usize) -> (f64, f64, f64) {
+    let mut rng = thread_rng();
+    let mut diffs: Vec<f64> = (0..iterations)
+        .map(|_| {
+            let resample_a: Vec<f64> = (0..a.len())
+                .map(|_| *a.choose(&mut rng).unwrap())
+                .collect();
+            let resample_b: Vec<f64> = (0..b.len())
+                .map(|_| *b.choose(&mut rng).unwrap())
+                .collect();
+            median(&resample_b) - median(&resample_a)
+        })
+        .collect();
+    diffs.sort_by(|l, r| l.partial_cmp(r).unwrap());
+    let pct = |p: f64| -> f64 {
+        let idx = ((diffs.len() - 1) as f64 * p).round() as usize;
+        diffs[idx]
+    };
+    let effect_size = median(b) - median(a);
+    (effect_size, pct(0.025), pct(0.975))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ComparisonResult {
+    key: String,
+    hwid: String,
+    config_a: String,
+    config_b: String,
+    n_a: usize,
+    n_b: usize,
+    mean_a: f64,
+    u_statistic: f64,
+    p_value: f64,
+    effect_size_median_diff: f64,
+    ci_low: f64,
+    ci_high: f64,
+}
+
+/// Groups results by `metadata.key` and then by the (arbitrary, possibly
+/// N-way) `experiment_config` label, computing a Mann-Whitney U test plus a
+/// bootstrap CI for the difference of medians for every pair of arms
+/// present under that key.
+fn compare_configs(results: &[BluebenchResult]) -> Vec<ComparisonResult> {
+    let mut by_key: HashMap<String, (String, HashMap<String, Vec<f64>>)> = HashMap::new();
+    for r in results {
+        let Some(config) = &r.experiment_config else {
+            continue;
+        };
+        let entry = by_key
+            .entry(r.metadata.key.clone())
+            .or_insert_with(|| (r.metadata.hwid.clone(), HashMap::new()));
+        entry
+            .1
+            .entry(config.clone())
+            .or_default()
+            .push(r.converged_mean_mean);
+    }
+    let mut comparisons = Vec::new();
+    for (key, (hwid, arms)) in &by_key {
+        let mut names: Vec<&String> = arms.keys().collect();
+        names.sort();
+        for i in 0..names.len() {
+            for j in (i + 1)..names.len() {
+                let a = &arms[names[i]];
+                let b = &arms[names[j]];
+                if a.is_empty() || b.is_empty() {
+                    continue;
+                }
+                let (u_statistic, p_value) = mann_whitney_u(a, b);
+                let (effect_size_median_diff, ci_low, ci_high) = bootstrap_median_ci(a, b, 10000);
+                let mean_a = a.iter().sum::<f64>() / a.len() as f64;
+                comparisons.push(ComparisonResult {
+                    key: key.clone(),
+                    hwid: hwid.clone(),
+                    config_a: names[i].clone(),
+                    config_b: names[j].clone(),
+                    n_a: a.len(),
+                    n_b: b.len(),
+                    mean_a,
+                    u_statistic,
+                    p_value,
+                    effect_size_median_diff,
+                    ci_low,
+                    ci_high,
+                });
+            }
+        }
+    }
+    comparisons.sort_by(|l, r| {
+        (l.key.as_str(), l.config_a.as_str(), l.config_b.as_str())
+            .cmp(&(r.key.as_str(), r.config_a.as_str(), r.config_b.as_str()))
+    });
+    comparisons
+}
+
+fn write_comparisons(comparisons: &[ComparisonResult]) -> Result<()> {
+    let mut csv_file = fs::File::create("comparisons.csv")?;
+    writeln!(
+        csv_file,
+        "key,hwid,config_a,config_b,n_a,n_b,mean_a,u_statistic,p_value,effect_size_median_diff,\
+         ci_low,ci_high"
+    )?;
+    for c in comparisons {
+        writeln!(
+            csv_file,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            c.key,
+            c.hwid,
+            c.config_a,
+            c.config_b,
+            c.n_a,
+            c.n_b,
+            c.mean_a,
+            c.u_statistic,
+            c.p_value,
+            c.effect_size_median_diff,
+            c.ci_low,
+            c.ci_high
+        )?;
+    }
+    info!("Generated comparisons.csv");
+    let mut json_file = fs::File::create("comparisons.json")?;
+    write!(json_file, "{}", serde_json::to_string(comparisons)?)?;
+    info!("Generated comparisons.json");
+    Ok(())
+}
+
 fn write_results(results: Vec<BluebenchResult>) -> Result<()> {
     info!(
         "{} succesfull test results in the specified range",
         results.len()
     );
-    let result_key_order = result_key_order(&results);
-    write_latency_csv(&results, &result_key_order)?;
+    let (compromised, clean): (Vec<BluebenchResult>, Vec<BluebenchResult>) =
+        results.into_iter().partition(|r| r.thermally_compromised);
+    if !compromised.is_empty() {
+        warn!(
+            "{} results are thermally compromised and excluded from data.csv/comparisons; see \
+             data_thermal_flagged.csv",
+            compromised.len()
+        );
+    }
+    let result_key_order = result_key_order(&clean);
+    write_latency_csv(&clean, &result_key_order)?;
     write_temp_csv(
-        &results,
+        &clean,
         &result_key_order,
         "x86_pkg_temp_C",
         "x86_pkg_temp.csv",
     )?;
-    write_temp_csv(&results, &result_key_order, "TSR0_C", "tsr0_temp.csv")?;
-    write_temp_csv(&results, &result_key_order, "TSR1_C", "tsr1_temp.csv")?;
-    write_temp_csv(&results, &result_key_order, "TSR2_C", "tsr2_temp.csv")?;
-    write_temp_csv(&results, &result_key_order, "TSR3_C", "tsr3_temp.csv")?;
+    write_temp_csv(&clean, &result_key_order, "TSR0_C", "tsr0_temp.csv")?;
+    write_temp_csv(&clean, &result_key_order, "TSR1_C", "tsr1_temp.csv")?;
+    write_temp_csv(&clean, &result_key_order, "TSR2_C", "tsr2_temp.csv")?;
+    write_temp_csv(&clean, &result_key_order, "TSR3_C", "tsr3_temp.csv")?;
     write_temp_csv(
-        &results,
+        &clean,
         &result_key_order,
         "TCPU_PCI_C",
         "tcpu_pci_temp.csv",
     )?;
+    write_thermally_flagged_csv(&compromised)?;
+    let comparisons = compare_configs(&clean);
+    write_comparisons(&comparisons)?;
     Ok(())
 }
 
-fn collect_candidates(args: &ArgsAnalyze) -> Result<Vec<PathBuf>> {
-    let results_dir = match (&args.cros, &args.results_dir) {
+/// Writes out the results that were excluded from `data.csv` for looking
+/// thermally throttled, so they remain visible rather than silently
+/// vanishing from the analysis.
+fn write_thermally_flagged_csv(compromised: &[BluebenchResult]) -> Result<()> {
+    let mut csv_file = fs::File::create("data_thermal_flagged.csv")?;
+    writeln!(csv_file, "t,key,converged_mean_mean")?;
+    for r in compromised {
+        writeln!(
+            csv_file,
+            "{},{},{}",
+            r.last_result_date, r.metadata.key, r.converged_mean_mean
+        )?;
+    }
+    info!("Generated data_thermal_flagged.csv");
+    Ok(())
+}
+
+/// Lists and date-filters candidate result directories under `cros`'s tast
+/// output (or `results_dir` directly), bounded to `[start, end)` by
+/// directory name. Shared by [`collect_candidates`] (CLI) and
+/// [`serve_api_results`] (the `/api/results` query params), so both see
+/// the same filtering rules.
+fn collect_candidates_in(
+    cros: Option<&str>,
+    results_dir: Option<&str>,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let results_dir = match (cros, results_dir) {
         (Some(cros), None) => {
             let cros = Path::new(cros);
             if !cros.is_dir() {
@@ -767,9 +1307,9 @@ fn collect_candidates(args: &ArgsAnalyze) -> Result<Vec<PathBuf>> {
         .collect();
     results.sort();
     info!("{} test results found", results.len());
-    let start = args.start.clone().unwrap_or("0".to_string());
+    let start = start.unwrap_or("0").to_string();
     let start = OsStr::new(&start);
-    let end = args.end.clone().unwrap_or("9".to_string());
+    let end = end.unwrap_or("9").to_string();
     let end = OsStr::new(&end);
     let results: Vec<PathBuf> = results
         .iter()
@@ -787,6 +1327,15 @@ fn collect_candidates(args: &ArgsAnalyze) -> Result<Vec<PathBuf>> {
     Ok(results)
 }
 
+fn collect_candidates(args: &ArgsAnalyze) -> Result<Vec<PathBuf>> {
+    collect_candidates_in(
+        args.cros.as_deref(),
+        args.results_dir.as_deref(),
+        args.start.as_deref(),
+        args.end.as_deref(),
+    )
+}
+
 fn dump_result(result: &BluebenchResult) -> Result<()> {
     info!("{:?} {:?}", result.metadata, result.converged_mean_mean);
     Ok(())
@@ -806,7 +1355,7 @@ fn generate(args: &ArgsAnalyze, test_name: &str) -> Result<()> {
     Ok(())
 }
 
-#[derive(PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Debug, Hash, PartialOrd, Ord, Serialize)]
 struct HardwareInfo {
     hwid: String,
     serial_number: String,
@@ -856,112 +1405,599 @@ fn hwid_and_info_map(
     Ok(dict)
 }
 
-fn handle_write(stream: &TcpStream, path: &str) -> Result<()> {
-    let mut res = BufWriter::new(stream);
-    match path {
-        // Bundled files
-        "/" | "/index.html" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
-            writeln!(res)?;
-            res.write_all(include_bytes!("../../assets/index.html"))?;
+/// GUID appended to the client's `Sec-WebSocket-Key` before hashing, fixed
+/// by RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Minimal SHA-1 (RFC 3174), only needed here to compute the
+/// `Sec-WebSocket-Accept` handshake response.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let bit_len = (data.len() as u64) * 8;
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
         }
-        "/index.js" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_JS_UTF8)?;
-            writeln!(res)?;
-            res.write_all(include_bytes!("../../assets/index.js"))?;
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
         }
-        "/index.css" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_CSS_UTF8)?;
-            writeln!(res)?;
-            res.write_all(include_bytes!("../../assets/index.css"))?;
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | ((!b) & d), 0x5A827999u32),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
         }
-        "/third_party/dygraph.js" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_JS_UTF8)?;
-            writeln!(res)?;
-            res.write_all(include_bytes!("../../third_party/dygraph.js"))?;
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+    let mut out = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// Computes the `Sec-WebSocket-Accept` header value for a client's
+/// `Sec-WebSocket-Key`, per the RFC 6455 handshake.
+fn websocket_accept_key(client_key: &str) -> String {
+    STANDARD.encode(sha1(format!("{client_key}{WEBSOCKET_GUID}").as_bytes()))
+}
+
+/// Extracts the `Sec-WebSocket-Key` header from a request's headers, if the
+/// request is asking to upgrade to a WebSocket connection.
+fn parse_websocket_upgrade(headers: &HashMap<String, String>) -> Option<String> {
+    let upgrade = headers.get("upgrade")?;
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return None;
+    }
+    Some(headers.get("sec-websocket-key")?.trim().to_string())
+}
+
+/// Frames `payload` as a single unmasked WebSocket text message (opcode
+/// 0x1), per RFC 6455 section 5.2. Server-to-client frames must not be
+/// masked.
+fn websocket_text_frame(payload: &str) -> Vec<u8> {
+    let payload = payload.as_bytes();
+    let mut frame = vec![0x81u8]; // FIN + text opcode
+    match payload.len() {
+        len if len <= 125 => frame.push(len as u8),
+        len if len <= 0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
         }
-        "/third_party/synchronizer.js" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_JS_UTF8)?;
-            writeln!(res)?;
-            res.write_all(include_bytes!("../../third_party/synchronizer.js"))?;
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
         }
-        "/third_party/dygraph.css" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_CSS_UTF8)?;
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Performs the RFC 6455 handshake on `stream` and, on success, registers
+/// it in [`WS_CLIENTS`] so [`broadcast_new_rows`] can push new result rows
+/// to it.
+fn handle_websocket_upgrade(stream: &TcpStream, client_key: &str) -> Result<()> {
+    let accept = websocket_accept_key(client_key);
+    let mut res = BufWriter::new(stream);
+    writeln!(res, "HTTP/1.1 101 Switching Protocols")?;
+    writeln!(res, "Upgrade: websocket")?;
+    writeln!(res, "Connection: Upgrade")?;
+    writeln!(res, "Sec-WebSocket-Accept: {accept}")?;
+    writeln!(res)?;
+    res.flush().context("Failed to flush the websocket handshake")?;
+    let client = stream.try_clone().context("Failed to clone the websocket stream")?;
+    WS_CLIENTS.lock().unwrap().push(client);
+    Ok(())
+}
+
+/// Watches `data.csv` for newly appended lines and pushes each one as a
+/// WebSocket text frame to every connection in [`WS_CLIENTS`], so a
+/// `/ws`-subscribed dygraph page updates live instead of polling
+/// `data.json`. Dead connections are dropped on the first failed write.
+fn broadcast_new_rows() {
+    let mut last_line_count = 0usize;
+    loop {
+        std::thread::sleep(Duration::from_secs(2));
+        let Ok(data) = fs::read_to_string("data.csv") else {
+            continue;
+        };
+        let lines: Vec<&str> = data.lines().collect();
+        if lines.len() <= last_line_count {
+            continue;
+        }
+        let new_lines = &lines[last_line_count..];
+        last_line_count = lines.len();
+        let mut clients = WS_CLIENTS.lock().unwrap();
+        clients.retain_mut(|client| {
+            for line in new_lines {
+                let frame = websocket_text_frame(line);
+                if client.write_all(&frame).is_err() {
+                    return false;
+                }
+            }
+            true
+        });
+    }
+}
+
+/// A parsed `Range: bytes=start-end` request header. `end` is inclusive and
+/// `None` means "until EOF", per RFC 7233.
+#[derive(Debug, Clone, Copy)]
+struct ByteRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+/// Extracts the `Range` header (if any) from a request's headers. Only the
+/// single `bytes=start-end` form is supported; anything else is ignored so
+/// the caller falls back to a full response.
+fn parse_range_header(headers: &HashMap<String, String>) -> Option<ByteRange> {
+    let spec = headers.get("range")?.trim().strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let start: u64 = start.trim().parse().ok()?;
+    let end = end.trim();
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(end.parse().ok()?)
+    };
+    Some(ByteRange { start, end })
+}
+
+/// Writes a bundled static asset as a 200 response with `content_type`.
+fn serve_asset(res: &mut dyn Write, content_type: &str, bytes: &'static [u8]) -> Result<()> {
+    writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
+    writeln!(res, "{content_type}")?;
+    writeln!(res)?;
+    res.write_all(bytes)?;
+    Ok(())
+}
+
+fn serve_index(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(res, HTTP_RESPONSE_HEADER_HTML_UTF8, include_bytes!("../../assets/index.html"))
+}
+fn serve_index_js(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(res, HTTP_RESPONSE_HEADER_JS_UTF8, include_bytes!("../../assets/index.js"))
+}
+fn serve_index_css(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(res, HTTP_RESPONSE_HEADER_CSS_UTF8, include_bytes!("../../assets/index.css"))
+}
+fn serve_dygraph_js(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(res, HTTP_RESPONSE_HEADER_JS_UTF8, include_bytes!("../../third_party/dygraph.js"))
+}
+fn serve_synchronizer_js(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(
+        res,
+        HTTP_RESPONSE_HEADER_JS_UTF8,
+        include_bytes!("../../third_party/synchronizer.js"),
+    )
+}
+fn serve_dygraph_css(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_asset(res, HTTP_RESPONSE_HEADER_CSS_UTF8, include_bytes!("../../third_party/dygraph.css"))
+}
+
+/// Serves a whole-file JSON response by reading `path` fresh on every
+/// request (these files are small and rewritten often by `abtest run`).
+fn serve_json_file(res: &mut dyn Write, path: &str) -> Result<()> {
+    writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
+    writeln!(res, "{}", HTTP_RESPONSE_HEADER_JSON_UTF8)?;
+    writeln!(res)?;
+    write!(res, "{}", fs::read_to_string(path)?.as_str())?;
+    Ok(())
+}
+fn serve_data_json(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_json_file(res, "data.json")
+}
+fn serve_comparisons_json(res: &mut dyn Write, _req: &Request, _captures: &[String]) -> Result<()> {
+    serve_json_file(res, "comparisons.json")
+}
+
+/// Response body for `/api/results`: the `(t, value, key)` points matching
+/// the query, plus the hardware info for each distinct hwid among them.
+#[derive(Serialize)]
+struct ApiResultsResponse {
+    points: Vec<(String, f64, String)>,
+    hardware_info: Vec<HardwareInfo>,
+}
+
+/// Serves `GET /api/results?hwid=...&start=...&end=...&metric=...`: reruns
+/// [`collect_candidates_in`] and [`analyze_all`] on demand with the query's
+/// filters, rather than reading one of the seven fixed CSVs [`write_results`]
+/// pre-generates. `start`/`end`/`hwid` behave exactly as the `abtest
+/// analyze` flags of the same name; `metric` defaults to
+/// `converged_mean_mean` and otherwise names a
+/// `temperature_sensor_readouts` key (e.g. `x86_pkg_temp_C`).
+fn serve_api_results(res: &mut dyn Write, req: &Request, _captures: &[String]) -> Result<()> {
+    let config = API_CONFIG.lock().unwrap();
+    let config = config
+        .as_ref()
+        .context("/api/results requires --serve to be started with --test-name")?;
+    let hwid = req.query.get("hwid").map(String::as_str);
+    let metric = req.query.get("metric").map(String::as_str).unwrap_or("converged_mean_mean");
+    let candidates = collect_candidates_in(
+        config.cros.as_deref(),
+        config.results_dir.as_deref(),
+        req.query.get("start").map(String::as_str),
+        req.query.get("end").map(String::as_str),
+    )?;
+    let results = analyze_all(candidates, &config.test_name, hwid);
+    let points = extract_metric_points(&results, metric);
+
+    let mut seen_hwids = HashSet::new();
+    let mut hardware_info = Vec::new();
+    for r in &results {
+        if seen_hwids.insert(r.metadata.hwid.clone()) {
+            hardware_info
+                .push(HardwareInfo::parse(Path::new(&r.metadata.path), &config.test_name)?);
+        }
+    }
+
+    let body = serde_json::to_string(&ApiResultsResponse { points, hardware_info })?;
+    writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
+    writeln!(res, "{}", HTTP_RESPONSE_HEADER_JSON_UTF8)?;
+    writeln!(res)?;
+    write!(res, "{body}")?;
+    Ok(())
+}
+
+/// Serves a `*.csv` file captured by [`RE_CSV_PATH_SEGMENT`], honoring a
+/// `Range: bytes=` request header with a 206 response.
+fn serve_csv(res: &mut dyn Write, req: &Request, captures: &[String]) -> Result<()> {
+    let filename = captures.first().context("CSV route matched without a filename capture")?;
+    let range = parse_range_header(&req.headers);
+    let data = fs::read_to_string(filename)?;
+    let data = data.as_bytes();
+    match range {
+        Some(range) if !data.is_empty() => {
+            let last = data.len() as u64 - 1;
+            let end = range.end.unwrap_or(last).min(last);
+            let start = range.start.min(end);
+            let slice = &data[start as usize..=end as usize];
+            writeln!(res, "{}", HTTP_RESPONSE_HEADER_206_PARTIAL_CONTENT)?;
+            writeln!(res, "{}", HTTP_RESPONSE_HEADER_KEEP_ALIVE)?;
+            writeln!(res, "{}", HTTP_RESPONSE_HEADER_CSV_UTF8)?;
+            writeln!(res, "Accept-Ranges: bytes")?;
+            writeln!(res, "Content-Range: bytes {start}-{end}/{}", data.len())?;
+            writeln!(res, "Content-Length: {}", slice.len())?;
             writeln!(res)?;
-            res.write_all(include_bytes!("../../third_party/dygraph.css"))?;
+            info!("path = {:?}: Content-Range: bytes {start}-{end}/{}", req.path, data.len());
+            res.write_all(slice)?;
         }
-        // Data from the local path
-        path if RE_CSV_PATH.is_match(path) => {
-            let data = fs::read_to_string(&path[1..])?;
-            let data = data.as_bytes();
+        _ => {
             writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
             writeln!(res, "{}", HTTP_RESPONSE_HEADER_KEEP_ALIVE)?;
             writeln!(res, "{}", HTTP_RESPONSE_HEADER_CSV_UTF8)?;
+            writeln!(res, "Accept-Ranges: bytes")?;
             writeln!(res, "Content-Length: {}", data.len())?;
             writeln!(res)?;
-            info!("path = {path:?}: Content length: {}", data.len());
+            info!("path = {:?}: Content length: {}", req.path, data.len());
             res.write_all(data)?;
         }
-        "/data.json" => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_200_OK)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_JSON_UTF8)?;
-            writeln!(res)?;
-            write!(res, "{}", fs::read_to_string("data.json")?.as_str())?;
+    }
+    Ok(())
+}
+
+lazy_static! {
+    /// Captures the CSV filename (without the leading `/`) so [`serve_csv`]
+    /// can read it straight from the current directory.
+    static ref RE_CSV_PATH_SEGMENT: Regex = Regex::new(r"^/([A-Za-z0-9_.]+\.csv)$").unwrap();
+
+    /// The abtest HTTP server's route table: bundled static assets, the CSV
+    /// data files, and the JSON summaries, tried top to bottom with a
+    /// single 404 fallthrough in [`handle_write`]. New endpoints (an
+    /// `/api/results` query, per-hwid summaries, more sensor CSVs) are
+    /// added here rather than in a hand-written `match`.
+    static ref ROUTER: Router = Router::new(vec![
+        Route { method: "GET", pattern: RoutePattern::Exact("/"), handler: serve_index },
+        Route { method: "GET", pattern: RoutePattern::Exact("/index.html"), handler: serve_index },
+        Route { method: "GET", pattern: RoutePattern::Exact("/index.js"), handler: serve_index_js },
+        Route { method: "GET", pattern: RoutePattern::Exact("/index.css"), handler: serve_index_css },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Exact("/third_party/dygraph.js"),
+            handler: serve_dygraph_js,
+        },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Exact("/third_party/synchronizer.js"),
+            handler: serve_synchronizer_js,
+        },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Exact("/third_party/dygraph.css"),
+            handler: serve_dygraph_css,
+        },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Regex(RE_CSV_PATH_SEGMENT.clone()),
+            handler: serve_csv,
+        },
+        Route { method: "GET", pattern: RoutePattern::Exact("/data.json"), handler: serve_data_json },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Exact("/comparisons.json"),
+            handler: serve_comparisons_json,
+        },
+        Route {
+            method: "GET",
+            pattern: RoutePattern::Exact("/api/results"),
+            handler: serve_api_results,
+        },
+    ]);
+}
+
+fn handle_write<S: Write>(stream: &mut S, req: &Request) -> Result<()> {
+    let mut res = BufWriter::new(stream);
+    if !ROUTER.dispatch(&mut res, req)? {
+        writeln!(res, "{}", HTTP_RESPONSE_HEADER_404_NOT_FOUND)?;
+        writeln!(res, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
+        writeln!(res)?;
+        writeln!(res, "404 Not Found")?;
+    }
+    res.flush().context("Failed to flash the response")
+}
+
+/// A parsed HTTP/1.x request line plus headers and (if any) body.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    /// The request target's path, with any `?query` stripped (see
+    /// [`Request::query`]).
+    path: String,
+    version: String,
+    /// Header names are lowercased so callers can look them up
+    /// case-insensitively, per RFC 7230.
+    headers: HashMap<String, String>,
+    /// The `?key=value&...` portion of the request target, percent-decoded
+    /// and split into a map.
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+}
+impl Request {
+    /// Whether the connection should stay open for another request after
+    /// this one, per the HTTP/1.0 vs. HTTP/1.1 keep-alive defaults.
+    fn keep_alive(&self) -> bool {
+        match self.headers.get("connection").map(|v| v.to_ascii_lowercase()) {
+            Some(v) if v == "close" => false,
+            Some(v) if v == "keep-alive" => true,
+            _ => self.version == "HTTP/1.1",
         }
-        _ => {
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_404_NOT_FOUND)?;
-            writeln!(res, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
-            writeln!(res)?;
-            writeln!(res, "404 Not Found")?;
+    }
+}
+
+/// Reads and parses a single HTTP request from `stream`: loops until the
+/// `\r\n\r\n` header terminator is seen (to tolerate header blocks larger
+/// than one `read()`), parses the request line and headers, then reads
+/// exactly `Content-Length` body bytes if present. Returns `Ok(None)` on a
+/// clean EOF (the keep-alive peer closed the connection between requests).
+fn read_request<S: Read>(stream: &mut S) -> Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let header_end = loop {
+        if let Some(pos) = find_subslice(&buf, b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let mut chunk = [0u8; 4096];
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            return if buf.is_empty() {
+                Ok(None)
+            } else {
+                Err(anyhow!("Connection closed mid-request"))
+            };
         }
+        buf.extend_from_slice(&chunk[..len]);
     };
-    res.flush().context("Failed to flash the response")
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().context("Missing request line")?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next().context("Missing method")?.to_string();
+    let target = parts.next().context("Missing path")?;
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query_string(query)),
+        None => (target.to_string(), HashMap::new()),
+    };
+    let version = parts.next().unwrap_or("HTTP/1.0").to_string();
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = buf.split_off(header_end);
+    while body.len() < content_length {
+        let mut chunk = [0u8; 4096];
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            bail!("Connection closed while reading the request body");
+        }
+        body.extend_from_slice(&chunk[..len]);
+    }
+    body.truncate(content_length);
+
+    let req = Request {
+        method,
+        path,
+        version,
+        headers,
+        query,
+        body,
+    };
+    info!(
+        "{} {} {} ({} body bytes)",
+        req.method,
+        req.path,
+        req.version,
+        req.body.len()
+    );
+    Ok(Some(req))
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Decodes `%XX` percent-escapes in a query string component. `+` is left
+/// as a literal plus since these values come from a JS `fetch()` URL, not
+/// a submitted HTML form.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) = u8::from_str_radix(&s[i + 1..i + 3], 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
 }
 
-/// Returns requested path
-fn handle_read(mut stream: &TcpStream) -> Result<String> {
-    let mut buf = [0u8; 4096];
-    let len = stream.read(&mut buf)?;
-    let req = String::from_utf8_lossy(&buf[..len]);
-    let path = req
-        .split(' ')
-        .map(str::to_string)
-        .nth(1)
-        .context("Path should be specified");
-    info!("path = {path:?}");
-    path
+/// Parses a `key=value&key2=value2` query string (without the leading
+/// `?`) into a percent-decoded map.
+fn parse_query_string(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
 }
 
 fn handle_client(mut stream: TcpStream) -> Result<()> {
     stream.set_nodelay(true)?;
-    let path = handle_read(&stream)?;
-    if handle_write(&stream, &path).is_err() {
-        writeln!(stream, "{}", HTTP_RESPONSE_HEADER_404_NOT_FOUND)?;
-        writeln!(stream, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
-        writeln!(stream)?;
-        writeln!(stream, "404 Not Found")?;
-    }
-    stream.flush()?;
-    stream.set_read_timeout(Some(std::time::Duration::from_millis(1000)))?;
-    let _ = stream.read(&mut [0; 128]);
-    // No need to handle the error, but read is needed for reliable transfer...
-    Ok(())
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    loop {
+        let Some(req) = read_request(&mut stream)? else {
+            return Ok(());
+        };
+        if req.path == "/ws" {
+            let Some(ws_key) = parse_websocket_upgrade(&req.headers) else {
+                bail!("/ws requires a WebSocket upgrade request");
+            };
+            // The connection now belongs to broadcast_new_rows(); don't
+            // keep looping to serve further requests on it.
+            return handle_websocket_upgrade(&stream, &ws_key);
+        }
+        let keep_alive = req.keep_alive();
+        if handle_write(&mut stream, &req).is_err() {
+            writeln!(stream, "{}", HTTP_RESPONSE_HEADER_404_NOT_FOUND)?;
+            writeln!(stream, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
+            writeln!(stream)?;
+            writeln!(stream, "404 Not Found")?;
+        }
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Loads a PEM certificate chain and private key into a rustls server
+/// config for [`handle_tls_client`].
+fn load_tls_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>> {
+    let cert_file = fs::File::open(cert_path).context("Failed to open --tls-cert")?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(cert_file))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("Failed to parse the TLS certificate chain")?;
+    let key_file = fs::File::open(key_path).context("Failed to open --tls-key")?;
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(key_file))
+        .context("Failed to parse the TLS private key")?
+        .context("No private key found in --tls-key")?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Failed to build the TLS server config")?;
+    Ok(Arc::new(config))
+}
+
+/// Same request/response loop as [`handle_client`], but over a TLS session
+/// instead of plain TCP. `/ws` isn't supported on this path yet, since
+/// [`WS_CLIENTS`]/[`broadcast_new_rows`] push to raw `TcpStream`s.
+fn handle_tls_client(mut stream: TcpStream, tls_config: Arc<ServerConfig>) -> Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let conn = ServerConnection::new(tls_config).context("Failed to start the TLS session")?;
+    let mut tls = StreamOwned::new(conn, stream);
+    loop {
+        let Some(req) = read_request(&mut tls)? else {
+            return Ok(());
+        };
+        if req.path == "/ws" {
+            bail!("/ws over TLS isn't supported; connect in plaintext for live updates");
+        }
+        let keep_alive = req.keep_alive();
+        if handle_write(&mut tls, &req).is_err() {
+            writeln!(tls, "{}", HTTP_RESPONSE_HEADER_404_NOT_FOUND)?;
+            writeln!(tls, "{}", HTTP_RESPONSE_HEADER_HTML_UTF8)?;
+            writeln!(tls)?;
+            writeln!(tls, "404 Not Found")?;
+        }
+        if !keep_alive {
+            return Ok(());
+        }
+    }
 }
 
-fn listen_http(port: u16) -> Result<()> {
-    let listener = TcpListener::bind(format!("127.0.0.1:{port}")).unwrap();
-    info!("Listening on port {port}");
+fn listen_http(port: u16, tls_config: Option<Arc<ServerConfig>>) -> Result<()> {
+    // TLS is opt-in via --tls-cert/--tls-key, and is the only case where we
+    // bind beyond localhost (per the --tls-cert help text).
+    let bind_addr = if tls_config.is_some() { "0.0.0.0" } else { "127.0.0.1" };
+    let listener = TcpListener::bind(format!("{bind_addr}:{port}")).unwrap();
+    info!("Listening on port {port} (tls={})", tls_config.is_some());
+    spawn(broadcast_new_rows);
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                spawn(|| {
-                    if let Err(e) = handle_client(stream) {
+                let tls_config = tls_config.clone();
+                spawn(move || {
+                    let result = match tls_config {
+                        Some(tls_config) => handle_tls_client(stream, tls_config),
+                        None => handle_client(stream),
+                    };
+                    if let Err(e) = result {
                         error!("handle_client failed: {e:?}");
                     }
                 });
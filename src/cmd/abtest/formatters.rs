@@ -0,0 +1,88 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! CI-consumable report formatters for `cro3 abtest analyze --format`.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::BluebenchResult;
+use super::ComparisonResult;
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Serializes the results plus the per-key comparison verdict into a JUnit
+/// XML `<testsuites>` document: one `<testcase>` per hwid/config, with a
+/// `<failure>` emitted when the regression (effect size as a fraction of the
+/// baseline) crosses `regression_threshold`.
+pub fn to_junit_xml(
+    results: &[BluebenchResult],
+    comparisons: &[ComparisonResult],
+    regression_threshold: f64,
+) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites tests=\"{}\">\n",
+        results.len() + comparisons.len()
+    ));
+    out.push_str("  <testsuite name=\"cro3.abtest.results\">\n");
+    for r in results {
+        out.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"0\" />\n",
+            xml_escape(&r.metadata.hwid),
+            xml_escape(&r.metadata.key),
+        ));
+    }
+    out.push_str("  </testsuite>\n");
+    out.push_str("  <testsuite name=\"cro3.abtest.comparisons\">\n");
+    for c in comparisons {
+        let regression_fraction = if c.mean_a.abs() > f64::EPSILON {
+            c.effect_size_median_diff.abs() / c.mean_a.abs()
+        } else {
+            0.0
+        };
+        let is_regression = c.p_value < 0.05 && regression_fraction >= regression_threshold;
+        out.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"0\">\n",
+            xml_escape(&c.hwid),
+            xml_escape(&c.key),
+        ));
+        if is_regression {
+            out.push_str(&format!(
+                "      <failure message=\"regression detected\">p={:.4} \
+                 effect_size={:.4} ci=[{:.4},{:.4}]</failure>\n",
+                c.p_value, c.effect_size_median_diff, c.ci_low, c.ci_high
+            ));
+        }
+        out.push_str("    </testcase>\n");
+    }
+    out.push_str("  </testsuite>\n");
+    out.push_str("</testsuites>\n");
+    out
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    results: &'a [BluebenchResult],
+    comparisons: &'a [ComparisonResult],
+}
+
+/// Serializes the results plus the comparison verdict into a single JSON
+/// object, mirroring `BluebenchResult` with the computed comparison fields
+/// alongside it.
+pub fn to_json(results: &[BluebenchResult], comparisons: &[ComparisonResult]) -> Result<String> {
+    let report = JsonReport {
+        results,
+        comparisons,
+    };
+    Ok(serde_json::to_string_pretty(&report)?)
+}
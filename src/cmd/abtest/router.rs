@@ -0,0 +1,84 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! A tiny router for the abtest HTTP server: an ordered table of
+//! `(method, path pattern) -> handler` entries, matched top to bottom with
+//! the first hit winning. This replaces a hand-written `match` in
+//! `handle_write` so new endpoints (another bundled asset, another CSV
+//! pattern, a JSON query) can be added by appending a [`Route`] instead of
+//! editing one monolithic function.
+
+use std::io::Write;
+
+use anyhow::Result;
+use regex::Regex;
+
+use super::Request;
+
+/// How a [`Route`]'s path is matched against an incoming request.
+pub(super) enum RoutePattern {
+    /// Matches the path verbatim.
+    Exact(&'static str),
+    /// Matches via regex; capture groups (if any) are passed to the
+    /// handler as path segments.
+    Regex(Regex),
+}
+
+/// A route handler. Receives the captured path segments (empty for
+/// [`RoutePattern::Exact`] routes, or for a regex with no capture groups).
+pub(super) type Handler = fn(&mut dyn Write, &Request, &[String]) -> Result<()>;
+
+/// One entry in a [`Router`]'s table.
+pub(super) struct Route {
+    pub method: &'static str,
+    pub pattern: RoutePattern,
+    pub handler: Handler,
+}
+
+impl Route {
+    /// Returns the captured path segments if this route matches `method`
+    /// and `path`, or `None` otherwise.
+    fn matches(&self, method: &str, path: &str) -> Option<Vec<String>> {
+        if self.method != method {
+            return None;
+        }
+        match &self.pattern {
+            RoutePattern::Exact(want) => (*want == path).then(Vec::new),
+            RoutePattern::Regex(re) => re.captures(path).map(|caps| {
+                caps.iter()
+                    .skip(1)
+                    .map(|m| m.map(|m| m.as_str().to_string()).unwrap_or_default())
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// An ordered table of [`Route`]s, tried top to bottom. The caller is
+/// expected to serve a 404 itself when [`Router::dispatch`] returns
+/// `Ok(false)`, keeping the single fallthrough in one place.
+pub(super) struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Self { routes }
+    }
+
+    /// Looks up and invokes the handler for `req`. Returns `Ok(true)` if a
+    /// route matched (even if the handler itself returned an error), or
+    /// `Ok(false)` if no route matched.
+    pub fn dispatch(&self, out: &mut dyn Write, req: &Request) -> Result<bool> {
+        for route in &self.routes {
+            if let Some(captures) = route.matches(&req.method, &req.path) {
+                (route.handler)(out, req, &captures)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
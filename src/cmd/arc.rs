@@ -7,14 +7,29 @@
 //! ## ARC (Android Runtime on Chrome) related utilities
 //! This feature is mainly for the internal developers.
 
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
+use std::process::Stdio;
 
+use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::chroot::Chroot;
 use cro3::cros::ensure_testing_rsa_is_there;
 use cro3::dut::SshInfo;
 use cro3::repo::get_cros_dir;
+use glob::Pattern;
+use regex_macro::regex;
 use tracing::error;
 use tracing::info;
 
@@ -178,6 +193,29 @@ pub struct ArgsLogcat {
     /// target DUT
     #[argh(option)]
     dut: String,
+
+    /// persist captured logs under this directory instead of just piping
+    /// logcat straight to the terminal.
+    #[argh(option)]
+    out: Option<String>,
+
+    /// demultiplex captured logs into one file per PID under --out, instead
+    /// of a single merged stream.
+    #[argh(switch)]
+    split_by_pid: bool,
+
+    /// rotate a captured file once it exceeds this many bytes, keeping a
+    /// bounded number of rotated segments. Requires --out.
+    #[argh(option)]
+    rotate: Option<u64>,
+
+    /// only capture lines at or above this priority (one of V/D/I/W/E/F/S).
+    #[argh(option)]
+    priority: Option<String>,
+
+    /// only capture lines whose tag matches this glob.
+    #[argh(option)]
+    tag: Option<String>,
 }
 fn run_logcat(args: &ArgsLogcat) -> Result<()> {
     let remote = SshInfo::new(&args.dut)?;
@@ -185,6 +223,196 @@ fn run_logcat(args: &ArgsLogcat) -> Result<()> {
     if !devices.contains("localhost:22") {
         remote.run_cmd_piped(&["adb", "connect", "localhost:22"])?;
     }
-    remote.run_cmd_piped(&["adb", "logcat"])?;
+    match &args.out {
+        None => remote.run_cmd_piped(&["adb", "logcat"]),
+        Some(out_dir) => capture_logcat(&remote, args, Path::new(out_dir)),
+    }
+}
+
+/// How many most-recent lines (across all processes, since logcat's
+/// `threadtime` stream is one shared ring buffer before we demux it) are
+/// kept around to backfill a just-created per-process file, so the first
+/// few lines of context aren't lost to having been read before that
+/// process's file existed.
+const BACKFILL_LINES: usize = 200;
+/// How many rotated segments `capture_logcat` keeps per file before the
+/// oldest is discarded.
+const MAX_ROTATED_SEGMENTS: u32 = 5;
+
+/// The fields of an `adb logcat -v threadtime` line this module demuxes
+/// and filters on: `MM-DD HH:MM:SS.mmm  PID  TID  PRIORITY  TAG: message`.
+struct ThreadtimeLine<'a> {
+    pid: &'a str,
+    priority: &'a str,
+    tag: &'a str,
+}
+fn parse_threadtime_line(line: &str) -> Option<ThreadtimeLine<'_>> {
+    let re = regex!(r"^\S+\s+\S+\s+(\d+)\s+\d+\s+([A-Z])\s+([^:]*):");
+    let caps = re.captures(line)?;
+    Some(ThreadtimeLine {
+        pid: caps.get(1)?.as_str(),
+        priority: caps.get(2)?.as_str(),
+        tag: caps.get(3)?.as_str(),
+    })
+}
+fn priority_rank(priority: &str) -> Result<u8> {
+    match priority {
+        "V" => Ok(0),
+        "D" => Ok(1),
+        "I" => Ok(2),
+        "W" => Ok(3),
+        "E" => Ok(4),
+        "F" => Ok(5),
+        "S" => Ok(6),
+        _ => bail!("Unknown logcat priority {priority:?}, expected one of V/D/I/W/E/F/S"),
+    }
+}
+
+/// A small shared-buffer ring of the most recently seen lines, kept so a
+/// newly opened per-process file can be backfilled with recent context
+/// instead of starting empty.
+struct RingBuffer {
+    lines: VecDeque<String>,
+    cap: usize,
+}
+impl RingBuffer {
+    fn new(cap: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(cap),
+            cap,
+        }
+    }
+    fn push(&mut self, line: String) {
+        if self.lines.len() == self.cap {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+    fn iter(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+}
+
+/// An append-only capture file that rotates itself out to `name.log.N`
+/// once it exceeds `rotate_bytes`, keeping at most `MAX_ROTATED_SEGMENTS`
+/// old segments.
+struct RotatingWriter {
+    path: PathBuf,
+    rotate_bytes: Option<u64>,
+    bytes_written: u64,
+    file: File,
+}
+impl RotatingWriter {
+    fn open(path: PathBuf, rotate_bytes: Option<u64>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {path:?}"))?;
+        let bytes_written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            rotate_bytes,
+            bytes_written,
+            file,
+        })
+    }
+    fn segment_path(&self, n: u32) -> PathBuf {
+        PathBuf::from(format!("{}.{n}", self.path.display()))
+    }
+    fn rotate(&mut self) -> Result<()> {
+        let _ = fs::remove_file(self.segment_path(MAX_ROTATED_SEGMENTS));
+        for n in (1..MAX_ROTATED_SEGMENTS).rev() {
+            let from = self.segment_path(n);
+            if from.exists() {
+                fs::rename(&from, self.segment_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.segment_path(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to recreate {:?} after rotation", self.path))?;
+        self.bytes_written = 0;
+        Ok(())
+    }
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        if let Some(limit) = self.rotate_bytes {
+            if self.bytes_written >= limit {
+                self.rotate()?;
+            }
+        }
+        writeln!(self.file, "{line}")?;
+        self.bytes_written += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Captures `adb logcat -v threadtime` into files under `out_dir`,
+/// demultiplexed by PID when `--split-by-pid` is set (one merged
+/// `logcat.log` otherwise), filtered by `--priority`/`--tag`, and rotated
+/// per `--rotate`.
+fn capture_logcat(remote: &SshInfo, args: &ArgsLogcat, out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir).context("Failed to create --out directory")?;
+    let min_priority = args.priority.as_deref().map(priority_rank).transpose()?;
+    let tag_filter = args
+        .tag
+        .as_deref()
+        .map(Pattern::new)
+        .transpose()
+        .context("Invalid --tag glob")?;
+
+    let mut ssh_cmd = remote.ssh_cmd(None)?;
+    let mut child = ssh_cmd
+        .args(["adb", "logcat", "-v", "threadtime"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to start adb logcat")?;
+    let stdout = child.stdout.take().context("adb logcat stdout was None")?;
+
+    let mut writers: HashMap<String, RotatingWriter> = HashMap::new();
+    let mut backfill = RingBuffer::new(BACKFILL_LINES);
+
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("Failed to read a logcat line")?;
+        let parsed = parse_threadtime_line(&line);
+
+        if let Some(parsed) = &parsed {
+            if let Some(min_priority) = min_priority {
+                if priority_rank(parsed.priority)? < min_priority {
+                    backfill.push(line);
+                    continue;
+                }
+            }
+            if let Some(filter) = &tag_filter {
+                if !filter.matches(parsed.tag.trim()) {
+                    backfill.push(line);
+                    continue;
+                }
+            }
+        }
+
+        let key = match (&parsed, args.split_by_pid) {
+            (Some(parsed), true) => parsed.pid.to_string(),
+            _ => "logcat".to_string(),
+        };
+        if !writers.contains_key(&key) {
+            let mut writer =
+                RotatingWriter::open(out_dir.join(format!("{key}.log")), args.rotate)?;
+            for backlog_line in backfill.iter() {
+                writer.write_line(backlog_line)?;
+            }
+            writers.insert(key.clone(), writer);
+        }
+        writers.get_mut(&key).unwrap().write_line(&line)?;
+        backfill.push(line);
+    }
+
+    let status = child.wait().context("Failed to wait for adb logcat")?;
+    if !status.success() {
+        error!("adb logcat exited with {status:?}");
+    }
     Ok(())
 }
@@ -0,0 +1,88 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! ## Inspect and fetch prebuilt artifacts from Google Storage
+//! ```
+//! # List prebuilt binpkgs for a board at the pinned SDK version
+//! cro3 artifact ls --board eve
+//! # Download one into the content-addressed cache and print its local path
+//! cro3 artifact fetch gs://chromeos-prebuilt/board/eve/R120-15000.0.0/packages/foo.tbz2
+//! ```
+
+use anyhow::bail;
+use anyhow::Result;
+use argh::FromArgs;
+use cro3::artifact::fetch_artifact;
+use cro3::artifact::list_artifacts;
+use cro3::artifact::list_board_prebuilts;
+use cro3::artifact::stat_artifact;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// inspect and fetch prebuilt artifacts from Google Storage
+#[argh(subcommand, name = "artifact")]
+pub struct Args {
+    #[argh(subcommand)]
+    nested: SubCommand,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommand {
+    Ls(ArgsLs),
+    Fetch(ArgsFetch),
+}
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    match &args.nested {
+        SubCommand::Ls(args) => run_ls(args),
+        SubCommand::Fetch(args) => run_fetch(args),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// list artifacts matching a gs:// glob, or a board's prebuilt binpkgs
+#[argh(subcommand, name = "ls")]
+struct ArgsLs {
+    /// board to enumerate prebuilt binpkgs for (mutually exclusive with a positional pattern)
+    #[argh(option)]
+    board: Option<String>,
+    /// SDK version to look up --board's prebuilts for (defaults to the pinned version)
+    #[argh(option)]
+    version: Option<String>,
+    /// a gs:// glob pattern to list directly, instead of --board
+    #[argh(positional)]
+    pattern: Option<String>,
+}
+fn run_ls(args: &ArgsLs) -> Result<()> {
+    let artifacts = match (&args.pattern, &args.board) {
+        (Some(pattern), None) => list_artifacts(pattern)?,
+        (None, Some(board)) => list_board_prebuilts(board, args.version.as_deref())?,
+        _ => bail!("Please specify either a gs:// pattern or --board"),
+    };
+    for a in &artifacts {
+        println!(
+            "{}\t{}\t{}",
+            a.gs_path,
+            a.size.map(|s| s.to_string()).unwrap_or_default(),
+            a.cache_key().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// download a single gs:// object into the content-addressed cache
+#[argh(subcommand, name = "fetch")]
+struct ArgsFetch {
+    /// gs:// path of the object to fetch
+    #[argh(positional)]
+    gs_path: String,
+}
+fn run_fetch(args: &ArgsFetch) -> Result<()> {
+    let artifact = stat_artifact(&args.gs_path)?;
+    let path = fetch_artifact(&artifact)?;
+    println!("{}", path.display());
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// Copyright 2026 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! ## Comparing bluebench runs
+//! ```
+//! # Decide whether a candidate build regressed vs a baseline, per hwid/
+//! # dut_id/bootid/mitigations configuration
+//! cro3 bluebench compare --baseline /data/results/old_a --baseline /data/results/old_b \
+//!     --candidate /data/results/new_a --candidate /data/results/new_b
+//! ```
+
+use std::path::Path;
+
+use anyhow::Result;
+use argh::FromArgs;
+use cro3::bluebench::compare_grouped;
+use cro3::bluebench::BluebenchComparisonVerdict;
+use cro3::bluebench::BluebenchResult;
+use cro3::shell::OutputFormat;
+use cro3::shell::Shell;
+use cro3::sh_println;
+use tracing::warn;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// compare bluebench run results
+#[argh(subcommand, name = "bluebench")]
+pub struct Args {
+    #[argh(subcommand)]
+    nested: SubCommand,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommand {
+    Compare(ArgsCompare),
+}
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    match &args.nested {
+        SubCommand::Compare(args) => run_compare(args),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// run a Welch's t-test over two sets of bluebench results' per-cycle
+/// converged_mean samples, grouped by hwid/dut_id/bootid/mitigations so
+/// only comparable configurations are tested against each other
+#[argh(subcommand, name = "compare")]
+pub struct ArgsCompare {
+    /// a baseline bluebench result directory; repeat for more than one run
+    #[argh(option)]
+    baseline: Vec<String>,
+    /// a candidate bluebench result directory to compare against the
+    /// baseline; repeat for more than one run
+    #[argh(option)]
+    candidate: Vec<String>,
+    /// significance threshold for the "significant" verdict
+    #[argh(option, default = "0.05")]
+    alpha: f64,
+}
+fn run_compare(args: &ArgsCompare) -> Result<()> {
+    let baseline = load_results(&args.baseline);
+    let candidate = load_results(&args.candidate);
+    let verdicts = compare_grouped(&baseline, &candidate, args.alpha);
+    if Shell::lock().format() == OutputFormat::Json {
+        Shell::lock().print_envelope("bluebench compare", true, &verdicts);
+        return Ok(());
+    }
+    if verdicts.is_empty() {
+        sh_println!("No comparable (same hwid/dut_id/bootid/mitigations) results with enough samples were found");
+        return Ok(());
+    }
+    sh_println!(
+        "{:50} {:>4} {:>4} {:>12} {:>12} {:>8} {:>8} {:>10} {:>11}",
+        "key", "n_a", "n_b", "mean_a", "mean_b", "t", "df", "p", "significant"
+    );
+    for v in &verdicts {
+        print_verdict_row(v);
+    }
+    Ok(())
+}
+
+fn print_verdict_row(v: &BluebenchComparisonVerdict) {
+    sh_println!(
+        "{:50} {:>4} {:>4} {:>12.3} {:>12.3} {:>8.3} {:>8.2} {:>10.4} {:>11}",
+        v.key,
+        v.n_a,
+        v.n_b,
+        v.mean_a,
+        v.mean_b,
+        v.welch_t,
+        v.welch_df,
+        v.welch_p,
+        v.significant
+    );
+}
+
+/// Parses every path into a [`BluebenchResult`], skipping (and logging) any
+/// that fail to parse instead of failing the whole comparison -- a single
+/// malformed/incomplete result directory shouldn't block comparing the rest.
+fn load_results(paths: &[String]) -> Vec<BluebenchResult> {
+    paths
+        .iter()
+        .filter_map(|p| match BluebenchResult::from_path(Path::new(p)) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                warn!("{p}: failed to load bluebench result: {e:#}");
+                None
+            }
+        })
+        .collect()
+}
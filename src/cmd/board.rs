@@ -10,6 +10,9 @@ use argh::FromArgs;
 use glob::Pattern;
 use lium::cache::KvCache;
 use lium::repo::get_repo_dir;
+use lium::sh_println;
+use lium::shell::OutputFormat;
+use lium::shell::Shell;
 use lium::util::shell_helpers::run_bash_command;
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -53,7 +56,7 @@ pub struct ArgsList {
     repo: Option<String>,
 }
 
-fn print_cached_boards(filter: &Pattern) -> Result<()> {
+fn filtered_cached_boards(filter: &Pattern) -> Result<Vec<String>> {
     let boards = BOARD_CACHE.entries()?;
     if boards.is_empty() {
         bail!("No cache found");
@@ -61,13 +64,9 @@ fn print_cached_boards(filter: &Pattern) -> Result<()> {
 
     let mut boards: Vec<String> = boards.into_keys().collect();
     boards.sort();
+    boards.retain(|board| filter.matches(board));
 
-    for board in boards {
-        if filter.matches(&board) {
-            println!("{board}");
-        }
-    }
-    Ok(())
+    Ok(boards)
 }
 
 fn update_cached_boards(repodir: &str) -> Result<()> {
@@ -91,5 +90,14 @@ fn run_board_list(args: &ArgsList) -> Result<()> {
         update_cached_boards(&get_repo_dir(&args.cros)?)?;
     }
 
-    print_cached_boards(&filter)
+    let boards = filtered_cached_boards(&filter)?;
+    match Shell::lock().format() {
+        OutputFormat::Json => Shell::lock().print_envelope("board list", true, &boards),
+        OutputFormat::Human => {
+            for board in &boards {
+                sh_println!("{board}");
+            }
+            Ok(())
+        }
+    }
 }
@@ -11,12 +11,20 @@
 //! ```
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::chroot::Chroot;
+use cro3::config::Config;
 use cro3::repo::get_cros_dir;
+use cro3::sh_println;
+use cro3::shell::OutputFormat;
+use cro3::shell::Shell;
+use serde::Serialize;
 use tracing::info;
 
+const DEFAULT_USE_FLAGS: &str = "chrome_internal -cros-debug pcserial";
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// build package(s)
 #[argh(subcommand, name = "build")]
@@ -25,9 +33,9 @@ pub struct Args {
     #[argh(option)]
     cros: Option<String>,
 
-    /// target board
+    /// target board (falls back to `default_board` in config if omitted)
     #[argh(option)]
-    board: String,
+    board: Option<String>,
 
     /// packages to build (or workon, for a full build)
     #[argh(positional)]
@@ -41,24 +49,78 @@ pub struct Args {
     #[argh(switch)]
     keep_workon: bool,
 
-    /// USE flags to be used, space separated
-    #[argh(
-        option,
-        default = "String::from(\"chrome_internal -cros-debug pcserial\")"
-    )]
-    use_flags: String,
+    /// USE flags to be used, space separated (falls back to
+    /// `default_use_flags` in config, then to a built-in default, if
+    /// omitted)
+    #[argh(option)]
+    use_flags: Option<String>,
 
     /// do full build (build_packages + build_image)
     #[argh(switch)]
     full: bool,
 
+    /// print the result as JSON (no effect on --full/--packages behavior)
+    #[argh(switch)]
+    json: bool,
+
     #[argh(option, hidden_help)]
     repo: Option<String>,
 }
-#[tracing::instrument(level = "trace")]
+/// A machine-readable summary of one `build` invocation, for `--format
+/// json`/`--json` output: the caller-facing fields a CI wrapper actually
+/// needs (did it work, which board) plus the failure message rather than a
+/// bare anyhow one-liner.
+#[derive(Debug, Serialize)]
+struct BuildResult {
+    status: &'static str,
+    board: String,
+    error: Option<String>,
+}
+
+/// Resolves `--board`, falling back to config's `default_board` so users
+/// stop retyping `--board brya` on every invocation.
+fn resolve_board(args: &Args) -> Result<String> {
+    if let Some(board) = &args.board {
+        return Ok(board.clone());
+    }
+    Config::read()?.default_board().context(
+        "--board was not given and no default_board is set; run `cro3 config wizard` or `cro3 \
+         config set default_board <board>`",
+    )
+}
+
+/// Resolves `--use-flags`, falling back to config's `default_use_flags`,
+/// then to the same built-in default this command has always used.
+fn resolve_use_flags(args: &Args) -> Result<String> {
+    if let Some(use_flags) = &args.use_flags {
+        return Ok(use_flags.clone());
+    }
+    if let Some(use_flags) = Config::read()?.default_use_flags() {
+        return Ok(use_flags);
+    }
+    Ok(DEFAULT_USE_FLAGS.to_string())
+}
+
 pub fn run(args: &Args) -> Result<()> {
-    let board = &args.board;
-    let use_flags = &args.use_flags;
+    let result = run_build(args);
+    if args.json || Shell::lock().format() == OutputFormat::Json {
+        let data = BuildResult {
+            status: if result.is_ok() { "ok" } else { "error" },
+            board: resolve_board(args).unwrap_or_default(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+        };
+        match Shell::lock().format() {
+            OutputFormat::Json => Shell::lock().print_envelope("build", result.is_ok(), &data),
+            OutputFormat::Human => sh_println!("{}", serde_json::to_string_pretty(&data)?),
+        };
+    }
+    result
+}
+
+#[tracing::instrument(level = "trace")]
+fn run_build(args: &Args) -> Result<()> {
+    let board = &resolve_board(args)?;
+    let use_flags = &resolve_use_flags(args)?;
     let chroot = Chroot::new(&get_cros_dir(args.cros.as_deref())?)?;
     if !args.skip_setup {
         chroot.run_bash_script_in_chroot(
@@ -6,6 +6,7 @@
 
 use anyhow::Result;
 use argh::FromArgs;
+use lium::chroot::backend_by_name;
 use lium::chroot::Chroot;
 use lium::dut::SshInfo;
 use lium::repo::get_cros_dir;
@@ -27,6 +28,11 @@ pub struct Args {
     #[argh(option)]
     cmd: Option<String>,
 
+    /// execution backend to use instead of the one from config:
+    /// "cros-sdk" (default) or "container"
+    #[argh(option)]
+    backend: Option<String>,
+
     #[argh(option, hidden_help)]
     repo: Option<String>,
 }
@@ -42,7 +48,10 @@ pub fn run(args: &Args) -> Result<()> {
     if let Some(board) = &args.board {
         additional_args.push(format!("BOARD={board}"));
     }
-    let chroot = Chroot::new(&repo)?;
+    let chroot = match &args.backend {
+        Some(backend) => Chroot::with_backend(&repo, backend_by_name(backend)?)?,
+        None => Chroot::new(&repo)?,
+    };
     if let Some(cmd) = &args.cmd {
         let mut script = String::new();
         for l in additional_args {
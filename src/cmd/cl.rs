@@ -2,13 +2,29 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use lazy_static::lazy_static;
 use lium::chroot::Chroot;
 use lium::repo::get_repo_dir;
+use lium::shell::OutputFormat;
+use lium::shell::Shell;
+use lium::util::gen_path_in_lium_dir;
+use lium::util::shell_helpers::get_stderr;
+use lium::util::shell_helpers::get_stdout;
+use lium::util::shell_helpers::run_bash_command;
 use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::error;
+use tracing::info;
 
 lazy_static! {
     static ref RE_GERRIT_CL: Regex = Regex::new(r"^(?P<cl>[0-9]+)/(?P<patchset>[0-9+])$").unwrap();
@@ -25,15 +41,36 @@ pub struct Args {
 #[argh(subcommand)]
 enum SubCommand {
     Pick(ArgsPick),
+    Sync(ArgsSync),
+}
+/// A machine-readable summary of one `cl` invocation, for `--format json`
+/// output: which subcommand ran and whether it worked, with the failure
+/// message rendered as structured JSON rather than a bare anyhow one-liner.
+#[derive(Debug, Serialize)]
+struct ClResult {
+    status: &'static str,
+    action: &'static str,
+    error: Option<String>,
 }
+
 pub fn run(args: &Args) -> Result<()> {
-    match &args.nested {
-        SubCommand::Pick(args) => run_pick(args),
+    let (action, result) = match &args.nested {
+        SubCommand::Pick(args) => ("pick", run_pick(args)),
+        SubCommand::Sync(args) => ("sync", run_sync(args)),
+    };
+    if Shell::lock().format() == OutputFormat::Json {
+        let data = ClResult {
+            status: if result.is_ok() { "ok" } else { "error" },
+            action,
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+        };
+        Shell::lock().print_envelope("cl", result.is_ok(), &data);
     }
+    result
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
-/// cherry-pick a CL
+/// cherry-pick one or more CLs
 #[argh(subcommand, name = "pick")]
 pub struct ArgsPick {
     /// target cros repo dir
@@ -44,37 +81,337 @@ pub struct ArgsPick {
     #[argh(option)]
     dir: String,
 
-    /// CLs to checkout (e.g. "4196467", "4196467/2")
+    /// also cherry-pick each CL's unmerged ancestors in its Gerrit relation
+    /// chain, oldest first, before the CL itself
+    #[argh(switch)]
+    with_deps: bool,
+
+    /// platform tag recorded in the patch manifest for this pick, used to
+    /// filter which records `cl sync --platform` replays
+    #[argh(option, default = "\"cros\".to_string()")]
+    platform: String,
+
+    /// CLs to checkout (e.g. "4196467/2"); multiple may be given
     #[argh(positional)]
-    cl: String,
+    cl: Vec<String>,
 }
 fn run_pick(args: &ArgsPick) -> Result<()> {
-    let capture = RE_GERRIT_CL
-        .captures(&args.cl)
-        .context("Invalid CL id. please specify patchset number as well (like '1234/5').")?;
-    let cl = &capture["cl"];
-    let cl_suffix = &cl[cl.len() - 2..];
-    let patchset = &capture["patchset"];
+    if args.cl.is_empty() {
+        bail!("at least one CL must be specified (e.g. '4196467/2')");
+    }
     let dir = &args.dir;
-    let chroot = Chroot::new(&get_repo_dir(&args.repo)?)?;
+    let repo = get_repo_dir(&args.repo)?;
+    let chroot = Chroot::new(&repo)?;
+
+    // Recorded so a failed pick can be rolled back to exactly where the
+    // tree started, rather than left with some CLs applied and others not.
+    let pre_pick_head = chroot
+        .run_bash_script_in_chroot(
+            "cl_pick_head",
+            &format!("cd ~/chromiumos/{dir} && git rev-parse HEAD"),
+            None,
+        )?
+        .trim()
+        .to_string();
+
+    for spec in &args.cl {
+        let (cl, patchset) = parse_cl_patchset(spec)?;
+        let chain = if args.with_deps {
+            resolve_relation_chain(&cl, &patchset)?
+        } else {
+            vec![resolve_chain_entry(&cl, &patchset)?]
+        };
+        let branch = format!("cl-{cl}_ps-{patchset}");
+        chroot.run_bash_script_in_chroot(
+            "cl_pick_branch",
+            &format!("cd ~/chromiumos/{dir} && git checkout -b {branch} {pre_pick_head}"),
+            None,
+        )?;
+        for entry in &chain {
+            info!("Cherry-picking {}/{}", entry.cl, entry.patchset);
+            if let Err(error) = pick_one(&chroot, dir, entry) {
+                error!(
+                    "Cherry-pick of {}/{} failed, rolling back: {error:#}",
+                    entry.cl, entry.patchset
+                );
+                chroot.run_bash_script_in_chroot(
+                    "cl_pick_rollback",
+                    &format!(
+                        r###"
+cd ~/chromiumos/{dir}
+git cherry-pick --abort || true
+git checkout {pre_pick_head}
+git branch -D {branch} || true
+"###
+                    ),
+                    None,
+                )?;
+                return Err(error);
+            }
+            append_to_manifest(
+                &repo,
+                PatchRecord {
+                    cl: entry.cl.clone(),
+                    patchset: entry.patchset.clone(),
+                    project: entry.project.clone(),
+                    rel_paths: vec![dir.clone()],
+                    platforms: vec![args.platform.clone()],
+                },
+            )?;
+        }
+        info!("{branch} now has {cl}/{patchset} applied");
+    }
+    Ok(())
+}
+
+/// One CL resolved from a Gerrit relation chain, carrying everything
+/// `pick_one` needs to fetch and cherry-pick it directly (which project it
+/// lives in, and the ref/revision of the patchset to fetch) without
+/// shelling out to `repo info` to guess the project.
+struct ChainEntry {
+    cl: String,
+    patchset: String,
+    project: String,
+    git_ref: String,
+    revision: String,
+}
+
+/// Cherry-picks `entry` into `dir`, skipping it if `entry.revision` is
+/// already an ancestor of the branch (e.g. it landed, or an earlier run
+/// already applied it), and deriving the fetch URL from `entry.project`
+/// rather than a single hardcoded repo.
+fn pick_one(chroot: &Chroot, dir: &str, entry: &ChainEntry) -> Result<()> {
+    let already_applied = chroot
+        .run_bash_script_in_chroot(
+            "cl_pick_check_ancestor",
+            &format!(
+                r###"
+cd ~/chromiumos/{dir}
+git merge-base --is-ancestor {revision} HEAD && echo already_applied || echo not_applied
+"###,
+                revision = entry.revision,
+            ),
+            None,
+        )?
+        .trim()
+        == "already_applied";
+    if already_applied {
+        info!(
+            "{}/{} ({}) is already present in the branch, skipping",
+            entry.cl, entry.patchset, entry.revision
+        );
+        return Ok(());
+    }
+    let project_url = format!("https://chromium.googlesource.com/{}", entry.project);
     chroot.run_bash_script_in_chroot(
         "checkout",
         &format!(
             r###"
-cd ~/chromiumos
-cd {dir}
-export PROJ=`repo info . | grep -e 'Project:' | cut -d ' ' -f 2`
-echo "PROJ=${{PROJ}}"
-git fetch https://chromium.googlesource.com/${{PROJ}} \
-  refs/changes/{cl_suffix}/{cl}/{patchset}
-git cherry-pick FETCH_HEAD || git cherry-pick --abort
+cd ~/chromiumos/{dir}
+git fetch {project_url} {git_ref}
+git cherry-pick FETCH_HEAD
 "###,
+            git_ref = entry.git_ref,
         ),
         None,
     )?;
     Ok(())
 }
 
+/// One cherry-pick recorded by `cl pick`, so `cl sync` can replay it into
+/// another checkout later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PatchRecord {
+    cl: String,
+    patchset: String,
+    project: String,
+    /// Checkout-relative dirs the patch was applied in (just the one `cl
+    /// pick --dir` was given, today).
+    rel_paths: Vec<String>,
+    /// Tree kinds this patch should be replayed into, e.g. ["cros"] or
+    /// ["cros", "arc"].
+    platforms: Vec<String>,
+}
+
+/// A stable, filesystem-safe identifier for `repo`'s checkout, used to name
+/// its patch manifest file.
+fn checkout_id(repo: &str) -> String {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hash;
+    use std::hash::Hasher;
+    let canonical = std::fs::canonicalize(repo)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| repo.to_string());
+    let mut hasher = DefaultHasher::new();
+    canonical.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn manifest_path(repo: &str) -> Result<PathBuf> {
+    gen_path_in_lium_dir(&format!("patches/{}.json", checkout_id(repo)))
+}
+
+fn load_manifest(repo: &str) -> Result<Vec<PatchRecord>> {
+    let path = manifest_path(repo)?;
+    match std::fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s).context("Failed to parse patch manifest"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("Failed to read patch manifest"),
+    }
+}
+
+fn append_to_manifest(repo: &str, record: PatchRecord) -> Result<()> {
+    let mut records = load_manifest(repo)?;
+    records.push(record);
+    std::fs::write(manifest_path(repo)?, serde_json::to_string_pretty(&records)?)
+        .context("Failed to write patch manifest")
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// replay another checkout's recorded cherry-picks into this one
+#[argh(subcommand, name = "sync")]
+pub struct ArgsSync {
+    /// checkout whose patch manifest to read from
+    #[argh(option)]
+    from: String,
+    /// checkout to replay the cherry-picks into
+    #[argh(option)]
+    to: String,
+    /// only replay records tagged with this platform (e.g. "cros" or "arc")
+    #[argh(option)]
+    platform: String,
+}
+fn run_sync(args: &ArgsSync) -> Result<()> {
+    let records = load_manifest(&args.from)?;
+    let chroot = Chroot::new(&args.to)?;
+    for record in records
+        .iter()
+        .filter(|r| r.platforms.iter().any(|p| p == &args.platform))
+    {
+        let Some(dir) = record.rel_paths.first() else {
+            continue;
+        };
+        if !Path::new(&args.to).join(dir).is_dir() {
+            info!(
+                "Skipping {}/{}: project dir {dir:?} not present in {}",
+                record.cl, record.patchset, args.to
+            );
+            continue;
+        }
+        info!("Replaying {}/{} into {dir}", record.cl, record.patchset);
+        let entry = resolve_chain_entry(&record.cl, &record.patchset)?;
+        pick_one(&chroot, dir, &entry)?;
+    }
+    Ok(())
+}
+
+fn parse_cl_patchset(spec: &str) -> Result<(String, String)> {
+    let capture = RE_GERRIT_CL
+        .captures(spec)
+        .context("Invalid CL id. please specify patchset number as well (like '1234/5').")?;
+    Ok((capture["cl"].to_string(), capture["patchset"].to_string()))
+}
+
+#[derive(Deserialize)]
+struct RelatedChangeAndCommitInfo {
+    #[serde(rename = "_change_number")]
+    change_number: Option<u64>,
+    #[serde(rename = "_revision_number")]
+    revision_number: Option<u64>,
+    status: Option<String>,
+}
+#[derive(Deserialize)]
+struct RelatedChangesInfo {
+    changes: Vec<RelatedChangeAndCommitInfo>,
+}
+
+/// The subset of Gerrit's `RevisionInfo` this client needs: the patchset
+/// number and the ref to `git fetch` it from.
+#[derive(Deserialize)]
+struct GerritRevisionInfo {
+    #[serde(rename = "_number")]
+    number: u64,
+    #[serde(rename = "ref")]
+    git_ref: String,
+}
+/// The subset of Gerrit's `ChangeInfo` this client needs, fetched with
+/// `o=ALL_REVISIONS` so every patchset's ref is available, keyed by the
+/// patchset's commit SHA.
+#[derive(Deserialize)]
+struct GerritChangeDetail {
+    project: String,
+    revisions: HashMap<String, GerritRevisionInfo>,
+}
+
+/// Gerrit's JSON endpoints prefix every response with `)]}'` to defend
+/// against cross-site script inclusion; strip it off before parsing.
+fn strip_gerrit_xssi_prefix(body: &str) -> &str {
+    body.strip_prefix(")]}'").unwrap_or(body).trim_start()
+}
+
+fn gerrit_get(path: &str) -> Result<String> {
+    let url = format!("https://chromium-review.googlesource.com/changes/{path}");
+    let output =
+        run_bash_command(&format!("curl -sf {url}"), None).context("Failed to run curl")?;
+    output
+        .status
+        .exit_ok()
+        .context(anyhow!("curl {url} failed: {}", get_stderr(&output)))?;
+    Ok(strip_gerrit_xssi_prefix(&get_stdout(&output)).to_string())
+}
+
+/// Resolves `cl`/`patchset` into a [`ChainEntry`] carrying its project and
+/// the ref/revision of that exact patchset.
+fn resolve_chain_entry(cl: &str, patchset: &str) -> Result<ChainEntry> {
+    let body = gerrit_get(&format!("{cl}/detail?o=ALL_REVISIONS"))?;
+    let detail: GerritChangeDetail =
+        serde_json::from_str(&body).context("Failed to parse Gerrit change detail response")?;
+    let ps_number: u64 = patchset
+        .parse()
+        .with_context(|| format!("Invalid patchset number: {patchset}"))?;
+    let (revision, info) = detail
+        .revisions
+        .into_iter()
+        .find(|(_, info)| info.number == ps_number)
+        .with_context(|| format!("Patchset {patchset} not found for CL {cl}"))?;
+    Ok(ChainEntry {
+        cl: cl.to_string(),
+        patchset: patchset.to_string(),
+        project: detail.project,
+        git_ref: info.git_ref,
+        revision,
+    })
+}
+
+/// Resolves `cl`/`patchset`'s Gerrit relation chain, oldest ancestor first
+/// with `cl`/`patchset` itself last, so no CL is applied before its
+/// ancestors. Already-merged/abandoned ancestors are skipped since they're
+/// expected to already be present.
+fn resolve_relation_chain(cl: &str, patchset: &str) -> Result<Vec<ChainEntry>> {
+    let body = gerrit_get(&format!("{cl}/revisions/{patchset}/related"))?;
+    let info: RelatedChangesInfo =
+        serde_json::from_str(&body).context("Failed to parse Gerrit related-changes response")?;
+    // Gerrit returns newest descendant first, oldest ancestor last; reverse
+    // to pick ancestors before their descendants.
+    let mut chain: Vec<(String, String)> = info
+        .changes
+        .into_iter()
+        .rev()
+        .filter(|c| !matches!(c.status.as_deref(), Some("MERGED") | Some("ABANDONED")))
+        .filter_map(|c| match (c.change_number, c.revision_number) {
+            (Some(cl), Some(ps)) => Some((cl.to_string(), ps.to_string())),
+            _ => None,
+        })
+        .collect();
+    if chain.is_empty() {
+        chain.push((cl.to_string(), patchset.to_string()));
+    }
+    chain
+        .into_iter()
+        .map(|(cl, ps)| resolve_chain_entry(&cl, &ps))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,4 +421,9 @@ mod tests {
         assert_eq!(&RE_GERRIT_CL.captures("1234/5").unwrap()["cl"], "1234");
         assert_eq!(&RE_GERRIT_CL.captures("1234/5").unwrap()["patchset"], "5");
     }
+    #[test]
+    fn strips_gerrit_xssi_prefix() {
+        assert_eq!(strip_gerrit_xssi_prefix(")]}'\n{\"a\":1}"), "{\"a\":1}");
+        assert_eq!(strip_gerrit_xssi_prefix("{\"a\":1}"), "{\"a\":1}");
+    }
 }
@@ -4,10 +4,16 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+use std::path::Path;
+use std::path::PathBuf;
+
 use anyhow::bail;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::config::Config;
+use cro3::sh_println;
+use cro3::shell::OutputFormat;
+use cro3::shell::Shell;
 use serde_json::json;
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -23,6 +29,10 @@ enum SubCommand {
     Set(ArgsSet),
     Show(ArgsShow),
     Clear(ArgsClear),
+    Profile(ArgsProfile),
+    Export(ArgsExport),
+    Import(ArgsImport),
+    Wizard(ArgsWizard),
 }
 #[tracing::instrument(level = "trace")]
 pub fn run(args: &Args) -> Result<()> {
@@ -30,6 +40,10 @@ pub fn run(args: &Args) -> Result<()> {
         SubCommand::Clear(args) => run_clear(args),
         SubCommand::Set(args) => run_set(args),
         SubCommand::Show(args) => run_show(args),
+        SubCommand::Profile(args) => run_profile(args),
+        SubCommand::Export(args) => run_export(args),
+        SubCommand::Import(args) => run_import(args),
+        SubCommand::Wizard(args) => run_wizard(args),
     }
 }
 
@@ -40,10 +54,18 @@ pub struct ArgsClear {
     /// key of a config
     #[argh(positional)]
     key: String,
+    /// clear it from the per-checkout layer (`.cro3/config.json`) instead
+    /// of the active profile
+    #[argh(switch)]
+    local: bool,
 }
 fn run_clear(args: &ArgsClear) -> Result<()> {
     let key = args.key.as_str();
-    let mut config = Config::read()?;
+    let mut config = if args.local {
+        Config::read_checkout_layer()?
+    } else {
+        Config::read_profile(&Config::active_profile_name())?
+    };
     config.clear(key)
 }
 
@@ -57,11 +79,20 @@ pub struct ArgsSet {
     /// value of a config
     #[argh(positional)]
     values: Vec<String>,
+    /// set it in the per-checkout layer (`.cro3/config.json`, discovered by
+    /// walking up from the current directory) instead of the active
+    /// profile, so it only applies within this checkout
+    #[argh(switch)]
+    local: bool,
 }
 fn run_set(args: &ArgsSet) -> Result<()> {
     let key = args.key.as_str();
     let values = &args.values;
-    let mut config = Config::read()?;
+    let mut config = if args.local {
+        Config::read_checkout_layer()?
+    } else {
+        Config::read_profile(&Config::active_profile_name())?
+    };
     config.set(key, values.as_slice())
 }
 
@@ -75,16 +106,211 @@ pub struct ArgsShow {
 }
 fn run_show(args: &ArgsShow) -> Result<()> {
     let config = Config::read()?;
+    let format = Shell::lock().format();
 
     if let Some(key) = &args.key {
         let value = match json!(&config).get(key) {
-            Some(v) => v.to_string(),
+            Some(v) => v.clone(),
             None => bail!("Failed to get a config value of {key}"),
         };
-        println!("{}", value);
+        match format {
+            OutputFormat::Json => Shell::lock().print_envelope("config show", true, &value),
+            OutputFormat::Human => sh_println!("{value}"),
+        }
     } else {
-        println!("{}", serde_json::to_string_pretty(&config)?);
+        match format {
+            OutputFormat::Json => Shell::lock().print_envelope("config show", true, &config),
+            OutputFormat::Human => {
+                sh_println!("# profile: {}", config.profile_name());
+                sh_println!("{}", serde_json::to_string_pretty(&config)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Create, switch between, list, or delete named config profiles, so you
+/// can keep separate environments (different DUT labs, checkouts, tast
+/// bundles) and switch between them without editing config by hand.
+#[argh(subcommand, name = "profile")]
+pub struct ArgsProfile {
+    #[argh(subcommand)]
+    nested: SubCommandProfile,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommandProfile {
+    Create(ArgsProfileCreate),
+    Use(ArgsProfileUse),
+    List(ArgsProfileList),
+    Delete(ArgsProfileDelete),
+}
+fn run_profile(args: &ArgsProfile) -> Result<()> {
+    match &args.nested {
+        SubCommandProfile::Create(args) => Config::create_profile(&args.name),
+        SubCommandProfile::Use(args) => Config::use_profile(&args.name),
+        SubCommandProfile::List(_) => run_profile_list(),
+        SubCommandProfile::Delete(args) => Config::delete_profile(&args.name),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Create a new, empty config profile
+#[argh(subcommand, name = "create")]
+pub struct ArgsProfileCreate {
+    /// name of the profile to create
+    #[argh(positional)]
+    name: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Make a profile the active one for future invocations
+#[argh(subcommand, name = "use")]
+pub struct ArgsProfileUse {
+    /// name of the profile to switch to
+    #[argh(positional)]
+    name: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// List known config profiles
+#[argh(subcommand, name = "list")]
+pub struct ArgsProfileList {}
+fn run_profile_list() -> Result<()> {
+    let profiles = Config::list_profiles()?;
+    let active = Config::active_profile_name();
+    match Shell::lock().format() {
+        OutputFormat::Json => Shell::lock().print_envelope("config profile list", true, &profiles),
+        OutputFormat::Human => {
+            for profile in &profiles {
+                if *profile == active {
+                    sh_println!("* {profile}");
+                } else {
+                    sh_println!("  {profile}");
+                }
+            }
+        }
     }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Delete a config profile
+#[argh(subcommand, name = "delete")]
+pub struct ArgsProfileDelete {
+    /// name of the profile to delete
+    #[argh(positional)]
+    name: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Export the active profile as a TOML document
+#[argh(subcommand, name = "export")]
+pub struct ArgsExport {
+    /// file to write the exported config to
+    #[argh(positional)]
+    file: PathBuf,
+}
+fn run_export(args: &ArgsExport) -> Result<()> {
+    let config = Config::read()?;
+    config.export_to_file(&args.file)
+}
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// Import a TOML document exported with `config export` into a profile
+#[argh(subcommand, name = "import")]
+pub struct ArgsImport {
+    /// file previously written by `config export`
+    #[argh(positional)]
+    file: PathBuf,
+    /// profile to import into (defaults to the active profile)
+    #[argh(option)]
+    profile: Option<String>,
+}
+fn run_import(args: &ArgsImport) -> Result<()> {
+    let profile = args
+        .profile
+        .clone()
+        .unwrap_or_else(Config::active_profile_name);
+    Config::import_from_file(&args.file, &profile)?;
     Ok(())
 }
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// interactively prompt for commonly-needed defaults (cros checkout dir,
+/// board, USE flags, servo serial) and write them to the active profile, so
+/// `cro3 build`/`cro3 servo` stop needing the same flags retyped every time
+#[argh(subcommand, name = "wizard")]
+pub struct ArgsWizard {}
+fn run_wizard(_args: &ArgsWizard) -> Result<()> {
+    let mut config = Config::read_profile(&Config::active_profile_name())?;
+    sh_println!(
+        "Setting up cro3 defaults for profile {:?}. Press enter to leave a value unchanged.",
+        config.profile_name()
+    );
+
+    if let Some(value) = prompt(
+        "Default cros checkout dir",
+        config.default_cros_checkout().as_deref(),
+        |dir| {
+            if Path::new(dir).is_dir() {
+                Ok(())
+            } else {
+                bail!("{dir:?} is not a directory")
+            }
+        },
+    )? {
+        config.set("default_cros_checkout", &[value])?;
+    }
+    if let Some(value) = prompt("Default board (e.g. brya)", config.default_board().as_deref(), |_| Ok(()))? {
+        config.set("default_board", &[value])?;
+    }
+    if let Some(value) = prompt(
+        "Preferred USE flags",
+        config.default_use_flags().as_deref(),
+        |_| Ok(()),
+    )? {
+        config.set("default_use_flags", &[value])?;
+    }
+    if let Some(value) = prompt(
+        "Default servo serial",
+        config.default_servo_serial().as_deref(),
+        |_| Ok(()),
+    )? {
+        config.set("default_servo_serial", &[value])?;
+    }
+    sh_println!("Done. Run `cro3 config show` to review.");
+    Ok(())
+}
+
+/// Prompts `label` on stdout (showing `current` if any), reads one line
+/// from stdin, and returns `Ok(None)` if left blank (value unchanged) or
+/// `Ok(Some(input))` after `validate` accepts the trimmed input.
+fn prompt(
+    label: &str,
+    current: Option<&str>,
+    validate: impl Fn(&str) -> Result<()>,
+) -> Result<Option<String>> {
+    use std::io::Write;
+    loop {
+        match current {
+            Some(current) => print!("{label} [{current}]: "),
+            None => print!("{label}: "),
+        }
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match validate(line) {
+            Ok(()) => return Ok(Some(line.to_string())),
+            Err(e) => sh_println!("{e:#}, try again"),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use argh::FromArgs;
+use futures::executor::block_on;
+use lium::daemon_client::socket_path;
+use lium::daemon_client::DaemonRequest;
+use lium::daemon_client::DaemonResponse;
+use lium::daemon_client::Hello;
+use lium::daemon_client::PROTOCOL_VERSION;
+use lium::dut::DutInfo;
+use lium::dut::MonitoredDut;
+use lium::dut::SshInfo;
+use tracing::error;
+use tracing::info;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// manage the long-running DUT connection daemon
+#[argh(subcommand, name = "daemon")]
+pub struct Args {
+    #[argh(subcommand)]
+    nested: SubCommand,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommand {
+    Start(ArgsStart),
+}
+pub fn run(args: &Args) -> Result<()> {
+    match &args.nested {
+        SubCommand::Start(args) => run_start(args),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// start the DUT manager daemon in the foreground, listening on a local
+/// Unix socket. It periodically refreshes DutInfo for every DUT it is
+/// asked about, and serves list/info requests from its in-memory cache so
+/// multiple concurrent `lium` invocations can share one connection per DUT.
+#[argh(subcommand, name = "start")]
+struct ArgsStart {
+    /// how often (in seconds) to refresh cached DutInfo in the background
+    #[argh(option, default = "60")]
+    refresh_interval_secs: u64,
+}
+
+#[derive(Default)]
+struct State {
+    info_cache: Mutex<HashMap<String, HashMap<String, String>>>,
+    /// DUT id -> (local port, forwarding child). The child is killed when
+    /// this map drops its entry (`kill_on_drop(true)`, set by
+    /// `start_port_forwarding`), so a forward lives exactly as long as the
+    /// daemon does.
+    forwards: Mutex<HashMap<String, (u16, async_process::Child)>>,
+    /// DUT id -> daemon-owned `MonitoredDut`, populated by `MonitorAdd` and
+    /// kept alive (and reconnected) by `run_start`'s background poll loop
+    /// instead of dying with the CLI invocation that requested it.
+    monitored: Mutex<HashMap<String, MonitoredDut>>,
+}
+
+fn handle_request(state: &'static State, req: DaemonRequest) -> DaemonResponse {
+    match req {
+        DaemonRequest::Info { dut } => match DutInfo::new(&dut) {
+            Ok(info) => {
+                let snapshot = info.info().clone();
+                state.info_cache.lock().unwrap().insert(info.id().to_string(), snapshot.clone());
+                DaemonResponse::Info(snapshot)
+            }
+            Err(e) => DaemonResponse::Error(format!("{e:#}")),
+        },
+        DaemonRequest::List => {
+            let info_cache = state.info_cache.lock().unwrap();
+            DaemonResponse::List(info_cache.values().cloned().collect())
+        }
+        DaemonRequest::Run { dut, cmd } => {
+            let args: Vec<&str> = cmd.iter().map(String::as_str).collect();
+            match SshInfo::new(&dut).and_then(|ssh| ssh.run_cmd_captured(&args)) {
+                Ok((code, stdout, stderr)) => DaemonResponse::Run { code, stdout, stderr },
+                Err(e) => DaemonResponse::Error(format!("{e:#}")),
+            }
+        }
+        DaemonRequest::Forward { dut, port_first, port_last } => {
+            if let Some((port, _)) = state.forwards.lock().unwrap().get(&dut) {
+                return DaemonResponse::Forward { port: *port };
+            }
+            let ssh = match SshInfo::new(&dut) {
+                Ok(ssh) => ssh,
+                Err(e) => return DaemonResponse::Error(format!("{e:#}")),
+            };
+            // Deliberately not holding `forwards`'s lock while SSH sets the
+            // forward up: that's the slow part, and serializing it across
+            // unrelated DUTs would make every forward request wait on
+            // whichever one happened to get there first.
+            let (child, port) = match block_on(ssh.start_ssh_forwarding_in_range(port_first..=port_last)) {
+                Ok(result) => result,
+                Err(e) => return DaemonResponse::Error(format!("{e:#}")),
+            };
+            // A racing request may have set up its own forward for the same
+            // DUT in the meantime; keep whichever one got inserted first
+            // and let the loser's child be dropped (and killed) here.
+            let port = state.forwards.lock().unwrap().entry(dut).or_insert((port, child)).0;
+            DaemonResponse::Forward { port }
+        }
+        DaemonRequest::MonitorAdd { dut, port } => match MonitoredDut::new(&dut, port) {
+            Ok(monitored) => {
+                state.monitored.lock().unwrap().insert(dut, monitored);
+                DaemonResponse::MonitorAdded
+            }
+            Err(e) => DaemonResponse::Error(format!("{e:#}")),
+        },
+        DaemonRequest::MonitorRemove { dut } => {
+            // Dropping the entry kills its forwarding child, same as
+            // removing a `forwards` entry above.
+            state.monitored.lock().unwrap().remove(&dut);
+            DaemonResponse::MonitorRemoved
+        }
+        DaemonRequest::MonitorList => {
+            let monitored = state.monitored.lock().unwrap();
+            DaemonResponse::MonitorList(monitored.keys().cloned().collect())
+        }
+        DaemonRequest::MonitorStatus => {
+            let mut monitored = state.monitored.lock().unwrap();
+            let statuses = monitored
+                .values_mut()
+                .filter_map(|m| match m.status_json() {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        error!("Failed to poll a monitored DUT's status: {e:#}");
+                        None
+                    }
+                })
+                .collect();
+            DaemonResponse::MonitorStatus(statuses)
+        }
+    }
+}
+
+fn handshake(stream: &mut UnixStream) -> Result<bool> {
+    let mut line = String::new();
+    BufReader::new(&*stream).read_line(&mut line)?;
+    let their_version = serde_json::from_str::<Hello>(&line)?.version;
+    let mut reply = serde_json::to_string(&Hello {
+        version: PROTOCOL_VERSION,
+    })?;
+    reply.push('\n');
+    stream.write_all(reply.as_bytes())?;
+    Ok(their_version == PROTOCOL_VERSION)
+}
+
+fn run_start(args: &ArgsStart) -> Result<()> {
+    let path = socket_path()?;
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    info!("lium daemon listening on {path}");
+    let state: &'static State = Box::leak(Box::<State>::default());
+
+    // Periodically refresh every DUT the daemon has been asked about, so
+    // repeated `list`/`info` queries are served from a reasonably fresh
+    // cache instead of going stale forever.
+    {
+        let refresh_interval = Duration::from_secs(args.refresh_interval_secs);
+        thread::spawn(move || loop {
+            thread::sleep(refresh_interval);
+            let dut_ids: Vec<String> = state.info_cache.lock().unwrap().keys().cloned().collect();
+            for dut_id in dut_ids {
+                if let Ok(info) = DutInfo::new(&dut_id) {
+                    state.info_cache.lock().unwrap().insert(dut_id, info.info().clone());
+                }
+            }
+        });
+    }
+
+    // Drive every monitored DUT's get_status/reconnect loop even when no
+    // client is actively polling `MonitorStatus`, so a forward reconnects
+    // (and its dead child is reaped) promptly after it drops rather than
+    // only on the next status query.
+    {
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(5));
+            let mut monitored = state.monitored.lock().unwrap();
+            for (dut, m) in monitored.iter_mut() {
+                if let Err(e) = m.get_status() {
+                    error!("Failed to poll monitored DUT {dut}: {e:#}");
+                }
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        thread::spawn(move || -> Result<()> {
+            if !handshake(&mut stream)? {
+                return Ok(());
+            }
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+            let req: DaemonRequest = serde_json::from_str(&line)?;
+            let response = handle_request(state, req);
+            let mut out = serde_json::to_string(&response)?;
+            out.push('\n');
+            stream.write_all(out.as_bytes())?;
+            Ok(())
+        });
+    }
+    Ok(())
+}
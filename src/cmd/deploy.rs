@@ -10,17 +10,21 @@
 //! ```
 
 use std::cmp::Ordering;
+use std::path::Path;
 
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::chroot::Chroot;
 use cro3::cros::ensure_testing_rsa_is_there;
 use cro3::dut::SshInfo;
 use cro3::repo::get_cros_dir;
+use cro3::vm::LocalVm;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use tracing::info;
+use tracing::warn;
 
 static RE_CROS_KERNEL: Lazy<Regex> = Lazy::new(|| Regex::new("chromeos-kernel-").unwrap());
 
@@ -32,9 +36,23 @@ pub struct Args {
     #[argh(option)]
     cros: Option<String>,
 
-    /// a DUT identifier (e.g. 127.0.0.1, localhost:2222)
+    /// a DUT identifier (e.g. 127.0.0.1, localhost:2222). Mutually
+    /// exclusive with --vm.
     #[argh(option)]
-    dut: String,
+    dut: Option<String>,
+
+    /// deploy to a locally-launched crosvm guest instead of a physical DUT;
+    /// requires --vm-kernel and --vm-rootfs
+    #[argh(switch)]
+    vm: bool,
+
+    /// kernel image to boot the VM with (required with --vm)
+    #[argh(option)]
+    vm_kernel: Option<String>,
+
+    /// rootfs image to boot the VM with (required with --vm)
+    #[argh(option)]
+    vm_rootfs: Option<String>,
 
     /// packages to deploy
     #[argh(positional)]
@@ -48,6 +66,11 @@ pub struct Args {
     #[argh(switch)]
     ab_update: bool,
 
+    /// abort the whole deploy if any requested package isn't available for
+    /// the DUT's board, instead of skipping it with a warning
+    #[argh(switch)]
+    strict: bool,
+
     #[argh(option, hidden_help)]
     repo: Option<String>,
 }
@@ -56,16 +79,37 @@ pub struct Args {
 pub fn run(args: &Args) -> Result<()> {
     ensure_testing_rsa_is_there()?;
 
-    let target = SshInfo::new(&args.dut)?.into_forwarded()?;
+    // Kept alive for the rest of `run()` so the guest is torn down (via
+    // Drop) only once we're done deploying to it.
+    let _vm;
+    let dut_ssh = if args.vm {
+        let kernel = args
+            .vm_kernel
+            .as_ref()
+            .context("--vm-kernel is required with --vm")?;
+        let rootfs = args
+            .vm_rootfs
+            .as_ref()
+            .context("--vm-rootfs is required with --vm")?;
+        _vm = Some(LocalVm::launch(Path::new(kernel), Path::new(rootfs))?);
+        _vm.as_ref().unwrap().ssh()?
+    } else {
+        let dut = args.dut.as_ref().context("Please specify --dut or --vm")?;
+        SshInfo::new(dut)?
+    };
+
+    let target = dut_ssh.into_forwarded()?;
     info!("Target DUT is {:?}", target);
 
     let board = target.get_board()?;
-    let packages_str = args.packages.join(" ");
     let chroot = Chroot::new(&get_cros_dir(&args.cros)?)?;
 
-    let kernel_pkg = extract_kernel_pkg(&args.packages)?;
+    let packages = filter_deployable_packages(&chroot, &board, &args.packages, args.strict)?;
+    let packages_str = packages.join(" ");
 
-    cros_workon_user_packages(&chroot, &board, &args.packages, &packages_str, &target)?;
+    let kernel_pkg = extract_kernel_pkg(&packages)?;
+
+    cros_workon_user_packages(&chroot, &board, &packages, &packages_str, &target)?;
 
     if kernel_pkg.is_some() {
         chroot.run_bash_script_in_chroot(
@@ -110,6 +154,37 @@ fn extract_kernel_pkg(packages: &[String]) -> Result<Option<String>> {
     }
 }
 
+fn is_package_applicable(chroot: &Chroot, board: &str, pkg: &str) -> bool {
+    chroot
+        .exec_in_chroot(&[&format!("equery-{board}"), "which", pkg])
+        .is_ok()
+}
+
+/// Partitions `packages` into those actually available for `board` (i.e.
+/// `equery-<board> which` can find an ebuild for them) and those that
+/// aren't, so a package list spanning multiple boards doesn't have to
+/// abort the whole deploy. Packages that get skipped are reported via
+/// `warn!`, unless `strict` is set, in which case any skip is a hard error
+/// (the pre-existing fail-fast behavior).
+fn filter_deployable_packages(
+    chroot: &Chroot,
+    board: &str,
+    packages: &[String],
+    strict: bool,
+) -> Result<Vec<String>> {
+    let (deployable, skipped): (Vec<String>, Vec<String>) = packages
+        .iter()
+        .cloned()
+        .partition(|pkg| is_package_applicable(chroot, board, pkg));
+    if !skipped.is_empty() {
+        if strict {
+            bail!("The following packages are not available for board {board}: {skipped:?}");
+        }
+        warn!("Skipping packages not available for board {board}: {skipped:?}");
+    }
+    Ok(deployable)
+}
+
 fn cros_workon_user_packages(
     chroot: &Chroot,
     board: &str,
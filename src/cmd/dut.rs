@@ -8,8 +8,13 @@ use std::collections::HashMap;
 use std::env::current_exe;
 use std::fs::read_to_string;
 use std::io::stdout;
+use std::io::BufRead;
 use std::io::Read;
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Mutex;
 use std::thread;
 use std::time;
 
@@ -18,19 +23,31 @@ use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
+use chrono::Local;
 use lazy_static::lazy_static;
 use lium::chroot::Chroot;
 use lium::cros;
+use lium::dut::discover_arp_neighbors;
 use lium::dut::discover_local_nodes;
 use lium::dut::fetch_dut_info_in_parallel;
+use lium::dut::ControlMaster;
+use lium::dut::DISCOVER_PROTOCOL_VERSION;
 use lium::dut::DutInfo;
+use lium::dut::LogSource;
 use lium::dut::MonitoredDut;
+use lium::lsp_proxy;
+use lium::daemon_client;
+use lium::daemon_client::DaemonRequest;
+use lium::daemon_client::DaemonResponse;
+use lium::dut_db::DutDb;
+use lium::mdns;
 use lium::dut::SshInfo;
 use lium::dut::SSH_CACHE;
 use lium::repo::get_repo_dir;
 use lium::servo::get_cr50_attached_to_servo;
 use lium::servo::LocalServo;
 use lium::servo::ServoList;
+use lium::util::is_json_format;
 use rayon::prelude::*;
 use termion::screen::IntoAlternateScreen;
 use tracing::error;
@@ -48,32 +65,42 @@ pub struct Args {
 #[argh(subcommand)]
 enum SubCommand {
     ArcInfo(ArgsArcInfo),
+    Connect(ArgsDutConnect),
+    Console(ArgsDutConsole),
     Discover(ArgsDiscover),
     Do(ArgsDutDo),
     Info(ArgsDutInfo),
     KernelConfig(ArgsDutKernelConfig),
     List(ArgsDutList),
+    Logs(ArgsDutLogs),
+    Lsp(ArgsDutLsp),
     Shell(ArgsDutShell),
     Monitor(ArgsDutMonitor),
     Pull(ArgsPull),
     Push(ArgsPush),
     Setup(ArgsSetup),
+    Verify(ArgsDutVerify),
     Vnc(ArgsVnc),
 }
 #[tracing::instrument(level = "trace")]
 pub fn run(args: &Args) -> Result<()> {
     match &args.nested {
         SubCommand::ArcInfo(args) => run_arc_info(args),
+        SubCommand::Connect(args) => run_dut_connect(args),
+        SubCommand::Console(args) => run_dut_console(args),
         SubCommand::Discover(args) => run_discover(args),
         SubCommand::Do(args) => run_dut_do(args),
         SubCommand::Info(args) => run_dut_info(args),
         SubCommand::KernelConfig(args) => run_dut_kernel_config(args),
         SubCommand::List(args) => run_dut_list(args),
+        SubCommand::Logs(args) => run_dut_logs(args),
+        SubCommand::Lsp(args) => run_dut_lsp(args),
         SubCommand::Shell(args) => run_dut_shell(args),
         SubCommand::Monitor(args) => run_dut_monitor(args),
         SubCommand::Pull(args) => run_dut_pull(args),
         SubCommand::Push(args) => run_dut_push(args),
         SubCommand::Setup(args) => run_setup(args),
+        SubCommand::Verify(args) => run_dut_verify(args),
         SubCommand::Vnc(args) => run_dut_vnc(args),
     }
 }
@@ -126,6 +153,36 @@ fn run_dut_push(args: &ArgsPush) -> Result<()> {
     target.send_files(&args.files, args.dest.as_ref())
 }
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// Bridge a local editor's LSP client to a language server running on a
+/// DUT, rewriting file:// URIs between the host checkout and the DUT's
+/// on-device source tree
+#[argh(subcommand, name = "lsp")]
+struct ArgsDutLsp {
+    /// DUT to run the language server on
+    #[argh(option)]
+    dut: String,
+
+    /// language server command to run on the DUT, e.g. "clangd" or
+    /// "rust-analyzer"
+    #[argh(option)]
+    cmd: String,
+
+    /// path to the checkout root on this host, as the editor sees it
+    #[argh(option)]
+    local_root: String,
+
+    /// path to the same checkout root on the DUT, as the language server
+    /// sees it
+    #[argh(option)]
+    remote_root: String,
+}
+fn run_dut_lsp(args: &ArgsDutLsp) -> Result<()> {
+    cros::ensure_testing_rsa_is_there()?;
+    let ssh = SshInfo::new(&args.dut)?;
+    lsp_proxy::run_proxy(&ssh, &args.cmd, &args.local_root, &args.remote_root)
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Open Vnc from DUT
 #[argh(subcommand, name = "vnc")]
@@ -168,6 +225,43 @@ fn run_dut_vnc(args: &ArgsVnc) -> Result<()> {
     }
 }
 #[derive(FromArgs, PartialEq, Debug)]
+/// pre-warm a ControlMaster connection to a DUT so the following commands
+/// become sub-100ms channel opens instead of fresh SSH handshakes
+#[argh(subcommand, name = "connect")]
+struct ArgsDutConnect {
+    /// a DUT identifier
+    #[argh(positional)]
+    dut: String,
+
+    /// keep the master connection open in the foreground until interrupted,
+    /// instead of just warming it up and exiting
+    #[argh(switch)]
+    persist: bool,
+}
+fn run_dut_connect(args: &ArgsDutConnect) -> Result<()> {
+    cros::ensure_testing_rsa_is_there()?;
+    let target = SshInfo::new(&args.dut)?;
+    if !args.persist {
+        target.prewarm_control_master()?;
+        info!(
+            "ControlMaster connection to {} is up at {}",
+            args.dut,
+            target.host_and_port()
+        );
+        return Ok(());
+    }
+    let _master: ControlMaster = target.connect_persistent()?;
+    info!(
+        "ControlMaster connection to {} is up at {}",
+        args.dut,
+        target.host_and_port()
+    );
+    warn!("Holding the ControlMaster connection open. Press Ctrl-C to disconnect.");
+    loop {
+        thread::sleep(time::Duration::from_secs(5));
+    }
+}
+#[derive(FromArgs, PartialEq, Debug)]
 /// open a SSH monitor
 #[argh(subcommand, name = "monitor")]
 struct ArgsDutMonitor {
@@ -176,16 +270,44 @@ struct ArgsDutMonitor {
     duts: Vec<String>,
 }
 
+/// Hands `args.duts` off to the `lium daemon`'s own `MonitoredDut` pool (so
+/// the forwards persist and auto-reconnect after this process exits), then
+/// polls the daemon for status lines to render -- a thin client over the
+/// `MonitorAdd`/`MonitorStatus` daemon protocol rather than holding the
+/// `MonitoredDut`s itself.
 fn run_dut_monitor(args: &ArgsDutMonitor) -> Result<()> {
     cros::ensure_testing_rsa_is_there()?;
-    let mut targets: Vec<MonitoredDut> = Vec::new();
+    daemon_client::ensure_running()?;
     let mut port = 4022;
-
     for dut in &args.duts {
-        targets.push(MonitoredDut::new(dut, port)?);
+        match daemon_client::query(&DaemonRequest::MonitorAdd {
+            dut: dut.clone(),
+            port,
+        })? {
+            DaemonResponse::MonitorAdded => {}
+            DaemonResponse::Error(e) => bail!("Failed to start monitoring {dut}: {e}"),
+            other => bail!("Unexpected daemon response to MonitorAdd: {other:?}"),
+        }
         port += 1;
     }
 
+    // `--format json` is meant for piping into a dashboard, so it skips the
+    // alternate-screen redraw and just emits one JSON array per poll.
+    if is_json_format() {
+        loop {
+            match daemon_client::query(&DaemonRequest::MonitorStatus)? {
+                DaemonResponse::MonitorStatus(statuses) => {
+                    println!("{}", serde_json::to_string_pretty(&statuses)?);
+                }
+                DaemonResponse::Error(e) => {
+                    println!("{}", serde_json::json!({"error": e}));
+                }
+                other => bail!("Unexpected daemon response to MonitorStatus: {other:?}"),
+            }
+            thread::sleep(time::Duration::from_secs(5))
+        }
+    }
+
     let mut screen = stdout().into_alternate_screen().unwrap();
     loop {
         // Draw headers.
@@ -197,8 +319,14 @@ fn run_dut_monitor(args: &ArgsDutMonitor) -> Result<()> {
         )?;
         println!("{}", MonitoredDut::get_status_header());
 
-        for target in targets.iter_mut() {
-            println!("{}", target.get_status()?);
+        match daemon_client::query(&DaemonRequest::MonitorStatus)? {
+            DaemonResponse::MonitorStatus(statuses) => {
+                for status in statuses {
+                    println!("{}", status.to_line());
+                }
+            }
+            DaemonResponse::Error(e) => bail!("Failed to query monitor status: {e}"),
+            other => bail!("Unexpected daemon response to MonitorStatus: {other:?}"),
         }
 
         thread::sleep(time::Duration::from_secs(5))
@@ -209,7 +337,9 @@ fn run_dut_monitor(args: &ArgsDutMonitor) -> Result<()> {
 /// open a SSH shell
 #[argh(subcommand, name = "shell")]
 struct ArgsDutShell {
-    /// a DUT identifier (e.g. 127.0.0.1, localhost:2222)
+    /// a DUT identifier, or a comma-separated list of them, to run the
+    /// command on in parallel (e.g. 127.0.0.1,localhost:2222). A list is
+    /// only valid together with a non-interactive command.
     #[argh(option)]
     dut: String,
 
@@ -222,9 +352,8 @@ struct ArgsDutShell {
     #[argh(positional)]
     args: Vec<String>,
 }
-fn run_dut_shell(args: &ArgsDutShell) -> Result<()> {
-    cros::ensure_testing_rsa_is_there()?;
-    let target = &SshInfo::new(&args.dut)?;
+fn run_dut_shell_one(dut: &str, args: &ArgsDutShell) -> Result<()> {
+    let target = &SshInfo::new(dut)?;
     if args.autologin {
         target.run_autologin()?;
     }
@@ -234,6 +363,34 @@ fn run_dut_shell(args: &ArgsDutShell) -> Result<()> {
         target.run_cmd_piped(&args.args)
     }
 }
+fn run_dut_shell(args: &ArgsDutShell) -> Result<()> {
+    cros::ensure_testing_rsa_is_there()?;
+    let duts = split_dut_list(&args.dut);
+    if duts.len() == 1 {
+        return run_dut_shell_one(duts[0], args);
+    }
+    if args.args.is_empty() {
+        bail!("An interactive shell can only be opened for a single --dut.");
+    }
+    let results: Vec<(String, Result<()>)> = duts
+        .par_iter()
+        .map(|dut| (dut.to_string(), run_dut_shell_one(dut, args)))
+        .collect();
+    let mut any_failed = false;
+    for (dut, result) in &results {
+        match result {
+            Ok(()) => println!("PASS: {dut}"),
+            Err(e) => {
+                any_failed = true;
+                println!("FAIL: {dut}: {e:#}");
+            }
+        }
+    }
+    if any_failed {
+        bail!("One or more DUTs failed. See the summary above.");
+    }
+    Ok(())
+}
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// get the kernel configuration from the DUT
@@ -252,7 +409,22 @@ fn run_dut_kernel_config(args: &ArgsDutKernelConfig) -> Result<()> {
 }
 
 type DutAction = Box<fn(&SshInfo) -> Result<()>>;
+
+lazy_static! {
+    /// Records the boot_id observed right before a `reboot` action was
+    /// issued for a given DUT, so a following `wait_online` action can prove
+    /// that the machine actually went down and came back instead of
+    /// returning success against the pre-reboot kernel.
+    static ref BOOT_ID_BEFORE_REBOOT: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
 fn do_reboot(s: &SshInfo) -> Result<()> {
+    if let Ok(boot_id) = s.boot_id() {
+        BOOT_ID_BEFORE_REBOOT
+            .lock()
+            .unwrap()
+            .insert(s.host_and_port(), boot_id);
+    }
     s.run_cmd_piped(&["reboot; exit"])
 }
 
@@ -295,12 +467,12 @@ fn do_switch_to_secondary(s: &SshInfo) -> Result<()> {
     switch_partition_set(s, PartitionSet::Secondary)
 }
 fn do_wait_online(s: &SshInfo) -> Result<()> {
-    for _ in 0..100 {
-        if s.run_cmd_piped(&["echo ok"]).is_ok() {
-            return Ok(());
-        }
+    let boot_id_before_reboot = BOOT_ID_BEFORE_REBOOT.lock().unwrap().remove(&s.host_and_port());
+    match boot_id_before_reboot {
+        Some(boot_id) => s.wait_for_reboot(&boot_id, time::Duration::from_secs(300)),
+        // No preceding `reboot` action was observed, so simply wait for SSH.
+        None => s.wait_online(),
     }
-    bail!("do_wait_online timed out")
 }
 fn do_login(s: &SshInfo) -> Result<()> {
     s.run_autologin()
@@ -531,6 +703,150 @@ fn check_dev_gbb_flags(dut: &DutInfo) -> Result<()> {
     Ok(())
 }
 
+/// The result of a single named health probe run by `dut verify`.
+#[derive(Debug, Clone, serde::Serialize)]
+struct ProbeReport {
+    name: String,
+    passed: bool,
+    detail: String,
+}
+fn probe_report(name: &str, result: Result<String>) -> ProbeReport {
+    match result {
+        Ok(detail) => ProbeReport {
+            name: name.to_string(),
+            passed: true,
+            detail,
+        },
+        Err(e) => ProbeReport {
+            name: name.to_string(),
+            passed: false,
+            detail: format!("{e:#}"),
+        },
+    }
+}
+fn probe_ssh(ssh: &SshInfo) -> Result<String> {
+    ssh.run_cmd_piped(&["echo ok"])?;
+    Ok("SSH reachable".to_string())
+}
+fn probe_gbb_flags(ssh: &SshInfo) -> Result<String> {
+    let info = DutInfo::fetch_keys(ssh, &vec!["gbb_flags"])?;
+    let gbb_flags = info.get("gbb_flags").context("gbb_flags is not set")?;
+    let parsed = u64::from_str_radix(gbb_flags.trim_start_matches("0x"), 16)
+        .context("failed to parse gbb_flags")?;
+    if parsed & 0x19 == 0x19 {
+        Ok(format!("GBB flags {gbb_flags} are set for development"))
+    } else {
+        bail!("GBB flags {gbb_flags} are not set for development (expect bit 0x19 set)")
+    }
+}
+fn probe_partition_layout(ssh: &SshInfo) -> Result<String> {
+    let info = ssh.get_partnum_info()?;
+    for key in ["kern_a", "kern_b", "root_a", "root_b"] {
+        info.get(key).context(format!("{key} not found"))?;
+    }
+    Ok(format!("Partition layout looks sane: {info:?}"))
+}
+fn probe_kernel_config(ssh: &SshInfo) -> Result<String> {
+    let config = ssh.get_host_kernel_config()?;
+    if config.trim().is_empty() {
+        bail!("kernel config was empty");
+    }
+    Ok(format!("kernel config present ({} bytes)", config.len()))
+}
+fn probe_ccd_open(cr50: &LocalServo) -> Result<String> {
+    if is_ccd_opened(cr50)? {
+        Ok("CCD is Opened".to_string())
+    } else {
+        bail!("CCD is Locked")
+    }
+}
+
+/// List of named probes `dut verify` can run. Each probe is independent and
+/// can be selected individually, mirroring the ad-hoc boolean flags
+/// `run_setup` used to have.
+const VERIFY_PROBES: [&str; 5] = [
+    "ssh",
+    "gbb_flags",
+    "partition_layout",
+    "kernel_config",
+    "ccd_open",
+];
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// run a battery of DUT health probes and report pass/fail per probe
+#[argh(subcommand, name = "verify")]
+struct ArgsDutVerify {
+    /// a DUT identifier (e.g. 127.0.0.1, localhost:2222)
+    #[argh(option)]
+    dut: String,
+    /// servo serial, needed for the ccd_open probe (autodetected if only one
+    /// Servo is attached)
+    #[argh(option)]
+    serial: Option<String>,
+    /// probes to run (--list-probes to see available options). runs all
+    /// probes by default.
+    #[argh(positional)]
+    probes: Vec<String>,
+    /// list available probes
+    #[argh(switch)]
+    list_probes: bool,
+    /// print the report as JSON instead of human-readable text
+    #[argh(switch)]
+    json: bool,
+}
+fn run_dut_verify(args: &ArgsDutVerify) -> Result<()> {
+    if args.list_probes {
+        println!("{}", VERIFY_PROBES.join(" "));
+        return Ok(());
+    }
+    let probes: Vec<&str> = if args.probes.is_empty() {
+        VERIFY_PROBES.to_vec()
+    } else {
+        args.probes.iter().map(|s| s.as_str()).collect()
+    };
+    let unknown: Vec<&&str> = probes.iter().filter(|p| !VERIFY_PROBES.contains(p)).collect();
+    if !unknown.is_empty() {
+        bail!("Unknown probe: {unknown:?}. See `lium dut verify --list-probes`.");
+    }
+    let ssh = SshInfo::new(&args.dut)?;
+    let reports: Vec<ProbeReport> = probes
+        .iter()
+        .map(|&name| {
+            probe_report(
+                name,
+                match name {
+                    "ssh" => probe_ssh(&ssh),
+                    "gbb_flags" => probe_gbb_flags(&ssh),
+                    "partition_layout" => probe_partition_layout(&ssh),
+                    "kernel_config" => probe_kernel_config(&ssh),
+                    "ccd_open" => find_servo(&args.serial).and_then(|s| probe_ccd_open(&s)),
+                    _ => unreachable!(),
+                },
+            )
+        })
+        .collect();
+    let all_passed = reports.iter().all(|r| r.passed);
+    if args.json {
+        println!(
+            "{}",
+            serde_json::json!({"dut": args.dut, "passed": all_passed, "probes": reports})
+        );
+    } else {
+        for r in &reports {
+            println!(
+                "{}: {:<16} {}",
+                if r.passed { "PASS" } else { "FAIL" },
+                r.name,
+                r.detail
+            );
+        }
+    }
+    if !all_passed {
+        bail!("One or more probes failed. See the report above.");
+    }
+    Ok(())
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Make DUTs connected via Servo ready for development
 /// "Ready for development" means:
@@ -570,11 +886,13 @@ struct ArgsSetup {
     #[argh(switch)]
     check_ssh: bool,
 }
-fn run_setup(args: &ArgsSetup) -> Result<()> {
-    let repo = get_repo_dir(&args.repo)?;
-    let servo = if let Some(serial) = &args.serial {
+/// Resolves the Servo to use: the one with the given serial, or the sole
+/// attached Servo if there is exactly one and no serial was given.
+fn find_servo(serial: &Option<String>) -> Result<LocalServo> {
+    if let Some(serial) = serial {
         let list = ServoList::discover()?;
-        list.find_by_serial(serial)
+        Ok(list
+            .find_by_serial(serial)
             .context(format!(
                 "
         Servo {serial} not found.
@@ -583,7 +901,7 @@ fn run_setup(args: &ArgsSetup) -> Result<()> {
         `lium servo list` may be helpful.
         "
             ))?
-            .clone()
+            .clone())
     } else {
         let list = ServoList::discover()?;
         let list: Vec<LocalServo> = list
@@ -598,12 +916,132 @@ fn run_setup(args: &ArgsSetup) -> Result<()> {
                  be helpful."
             ));
         }
-        list.first()
+        Ok(list
+            .first()
             .context(
                 "Servo is not connected. Run `lium servo list` to check if Servo is connected.",
             )?
-            .clone()
+            .clone())
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// stream a Servo-attached UART console to stdout, optionally tee-ing it to a
+/// log file. Useful when the DUT is not SSH-reachable (bringup, kernel
+/// panics, failed firmware flashes).
+#[argh(subcommand, name = "console")]
+struct ArgsDutConsole {
+    /// servo serial (autodetected if only one Servo is attached)
+    #[argh(option)]
+    serial: Option<String>,
+    /// which console to attach to
+    #[argh(option, default = "\"ap\".to_string()")]
+    channel: String,
+    /// tee the console output to this file, timestamping each line
+    #[argh(option)]
+    log_file: Option<String>,
+}
+fn run_dut_console(args: &ArgsDutConsole) -> Result<()> {
+    let servo = find_servo(&args.serial)?;
+    let tty_type = match args.channel.as_str() {
+        "ap" => "DUT UART",
+        "ec" => "EC",
+        "cr50" => "Shell",
+        other => bail!("Unknown --channel {other:?}. Expected one of: ap, ec, cr50."),
+    };
+    let tty_path = servo.tty_path(tty_type)?;
+    info!("Streaming {tty_type} console from {tty_path}...");
+    let mut log_file = args
+        .log_file
+        .as_ref()
+        .map(std::fs::File::create)
+        .transpose()
+        .context("Failed to create --log-file")?;
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(format!("stty -F {tty_path} 115200 raw -echo; exec cat {tty_path}"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to attach to the console tty")?;
+    let stdout = child.stdout.take().context("console stdout was None")?;
+    for line in std::io::BufReader::new(stdout).lines() {
+        let line = line.context("Failed to read from console tty")?;
+        println!("{line}");
+        if let Some(log_file) = &mut log_file {
+            writeln!(log_file, "[{}] {line}", chrono::Local::now())?;
+        }
+    }
+    child.wait().context("console child process failed")?;
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// stream one or more DUTs' logs to stdout, reconnecting across
+/// reboots/transient SSH drops without losing or duplicating the recent
+/// window. Lines from each DUT are prefixed with its identifier so that
+/// streams from multiple DUTs can be tailed concurrently and stay
+/// distinguishable when interleaved.
+#[argh(subcommand, name = "logs")]
+struct ArgsDutLogs {
+    /// DUT identifiers to stream logs from (e.g. 127.0.0.1, localhost:2222)
+    #[argh(positional, greedy)]
+    duts: Vec<String>,
+    /// which log to stream: "messages" (/var/log/messages, default),
+    /// "dmesg", or "logcat" (ARC container/VM logs)
+    #[argh(option, default = "\"messages\".to_string()")]
+    source: String,
+    /// number of lines of backlog to replay on (re)connect
+    #[argh(option, default = "200")]
+    lines: u32,
+    /// also append every line to this file
+    #[argh(option)]
+    log_file: Option<String>,
+}
+fn run_dut_logs(args: &ArgsDutLogs) -> Result<()> {
+    if args.duts.is_empty() {
+        bail!("At least one DUT identifier is required");
+    }
+    let source = match args.source.as_str() {
+        "messages" => LogSource::Messages,
+        "dmesg" => LogSource::Dmesg,
+        "logcat" => LogSource::Logcat,
+        other => bail!("Unknown --source {other:?}. Expected one of: messages, dmesg, logcat."),
     };
+    let log_file = args.log_file.as_ref().map(PathBuf::from);
+    if args.duts.len() == 1 {
+        return stream_dut_logs(args.duts[0].clone(), source, args.lines, log_file);
+    }
+    // Fan out: each DUT gets its own reconnect-forever tail (owned by
+    // `SshInfo::stream_logs`), and their already-prefixed lines interleave
+    // on stdout as they arrive.
+    let handles: Vec<_> = args
+        .duts
+        .iter()
+        .map(|dut| {
+            let dut = dut.clone();
+            let lines = args.lines;
+            let log_file = log_file.clone();
+            thread::spawn(move || stream_dut_logs(dut, source, lines, log_file))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("log streaming thread panicked")?;
+    }
+    Ok(())
+}
+/// Prints `source` logs from `dut` to stdout forever, prefixed with `dut`'s
+/// identifier, via `SshInfo::stream_logs`.
+fn stream_dut_logs(dut: String, source: LogSource, lines: u32, log_file: Option<PathBuf>) -> Result<()> {
+    let ssh = SshInfo::new(&dut)?;
+    for line in ssh.stream_logs(source, lines, log_file) {
+        println!("{}: {}", line.dut_key, line.text);
+    }
+    Ok(())
+}
+
+fn run_setup(args: &ArgsSetup) -> Result<()> {
+    let repo = get_repo_dir(&args.repo)?;
+    let servo = find_servo(&args.serial)?;
     info!("Using {} {} as Servo", servo.product(), servo.serial());
     let cr50 = get_cr50_attached_to_servo(&servo)?;
     info!("Using {} {} as Cr50", cr50.product(), cr50.serial());
@@ -631,11 +1069,19 @@ fn run_setup(args: &ArgsSetup) -> Result<()> {
     Ok(())
 }
 
+/// Splits a `--dut` argument into the individual DUT identifiers it names.
+/// Accepts either a single DUT, or a comma-separated list so a fleet of
+/// identical boards can be driven in one invocation.
+fn split_dut_list(dut: &str) -> Vec<&str> {
+    dut.split(',').map(str::trim).filter(|s| !s.is_empty()).collect()
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// send actions
 #[argh(subcommand, name = "do")]
 struct ArgsDutDo {
-    /// a DUT identifier (e.g. 127.0.0.1, localhost:2222)
+    /// a DUT identifier, or a comma-separated list of them (e.g.
+    /// 127.0.0.1,localhost:2222) to run the actions on in parallel
     #[argh(option)]
     dut: Option<String>,
     /// actions to do (--list-actions to see available options)
@@ -645,6 +1091,17 @@ struct ArgsDutDo {
     #[argh(switch)]
     list_actions: bool,
 }
+fn run_dut_do_one(dut: &str, actions: &[String]) -> Result<()> {
+    let dut = &SshInfo::new(dut)?;
+    let resolved: Vec<&DutAction> = actions
+        .iter()
+        .flat_map(|s| DUT_ACTIONS.get(s.as_str()))
+        .collect();
+    for (name, f) in actions.iter().zip(resolved.iter()) {
+        f(dut).context(anyhow!("DUT action: {name}"))?;
+    }
+    Ok(())
+}
 fn run_dut_do(args: &ArgsDutDo) -> Result<()> {
     cros::ensure_testing_rsa_is_there()?;
     if args.list_actions {
@@ -669,24 +1126,50 @@ fn run_dut_do(args: &ArgsDutDo) -> Result<()> {
              actions."
         ));
     }
-    let dut = &SshInfo::new(args.dut.as_ref().context(anyhow!("Please specify --dut"))?)?;
-    let actions: Vec<&DutAction> = args
-        .actions
-        .iter()
-        .flat_map(|s| DUT_ACTIONS.get(s.as_str()))
+    let duts = split_dut_list(args.dut.as_ref().context(anyhow!("Please specify --dut"))?);
+    if duts.len() == 1 {
+        return run_dut_do_one(duts[0], &args.actions);
+    }
+    let results: Vec<(String, Result<()>)> = duts
+        .par_iter()
+        .map(|dut| (dut.to_string(), run_dut_do_one(dut, &args.actions)))
         .collect();
-    let actions: Vec<(&String, &&DutAction)> = args.actions.iter().zip(actions.iter()).collect();
-    for (name, f) in actions {
-        f(dut).context(anyhow!("DUT action: {name}"))?;
+    let mut any_failed = false;
+    for (dut, result) in &results {
+        match result {
+            Ok(()) => println!("PASS: {dut}"),
+            Err(e) => {
+                any_failed = true;
+                println!("FAIL: {dut}: {e:#}");
+            }
+        }
+    }
+    if any_failed {
+        bail!("One or more DUTs failed. See the summary above.");
     }
     Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum DutStatus {
+    /// Reachable over SSH right now.
     Online,
+    /// Was reachable at some point, but not reachable over SSH right now
+    /// (and not recently enough to be considered `Stale` instead).
     Offline,
+    /// The cached address now resolves to a different `dut_id`.
     AddressReused,
+    /// The DUT was last confirmed online longer ago than its state's
+    /// max-age allows, so a cached "online" result can no longer be
+    /// trusted without re-checking.
+    Stale,
+}
+impl DutStatus {
+    /// How long a DUT may go unconfirmed before `Online` should be
+    /// downgraded to `Stale` rather than reported as ground truth. Lab
+    /// devices reboot into recovery/fastboot far more often than they
+    /// change address, so this is intentionally short.
+    const MAX_AGE: std::time::Duration = std::time::Duration::from_secs(600);
 }
 #[derive(FromArgs, PartialEq, Debug)]
 /// list all cached DUTs
@@ -715,8 +1198,25 @@ struct ArgsDutList {
     /// update the DUT list and show their status
     #[argh(switch)]
     update: bool,
+
+    /// show the recorded address history for a given dut_id instead of
+    /// listing all DUTs
+    #[argh(option)]
+    show_history: Option<String>,
+
+    /// only list DUTs last recorded in the inventory at or after this
+    /// RFC3339 timestamp
+    #[argh(option)]
+    since: Option<String>,
 }
 fn run_dut_list(args: &ArgsDutList) -> Result<()> {
+    if let Some(dut_id) = &args.show_history {
+        let db = DutDb::open()?;
+        for entry in db.history(dut_id)? {
+            println!("{:32} {:15} seen {} .. {}", entry.dut_id, entry.address, entry.first_seen, entry.last_seen);
+        }
+        return Ok(());
+    }
     if args.clear {
         return SSH_CACHE.clear();
     }
@@ -747,6 +1247,7 @@ fn run_dut_list(args: &ArgsDutList) -> Result<()> {
             "Checking status of {} DUTs. It will take a minute...",
             duts.len()
         );
+        let last_seen = DutDb::open().ok().and_then(|db| db.last_seen_all().ok());
         let duts: Vec<(String, DutStatus, SshInfo)> = duts
             .par_iter()
             .map(|e| {
@@ -758,6 +1259,17 @@ fn run_dut_list(args: &ArgsDutList) -> Result<()> {
                     } else {
                         DutStatus::AddressReused
                     }
+                } else if last_seen
+                    .as_ref()
+                    .and_then(|m| m.get(id))
+                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                    .map(|t| {
+                        Local::now().signed_duration_since(t).to_std().unwrap_or_default()
+                            > DutStatus::MAX_AGE
+                    })
+                    .unwrap_or(false)
+                {
+                    DutStatus::Stale
                 } else {
                     DutStatus::Offline
                 };
@@ -778,21 +1290,56 @@ fn run_dut_list(args: &ArgsDutList) -> Result<()> {
         } else {
             (Vec::new(), duts)
         };
-        for dut in duts {
-            println!("{:32} {:13} {:?}", dut.0, &format!("{:?}", dut.1), dut.2);
+        if is_json_format() {
+            let rows: Vec<_> = duts
+                .iter()
+                .map(|d| serde_json::json!({"id": d.0, "status": format!("{:?}", d.1), "ssh": d.2}))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        } else {
+            for dut in &duts {
+                println!("{:32} {:13} {:?}", dut.0, &format!("{:?}", dut.1), dut.2);
+            }
         }
         if !duts_to_be_removed.is_empty() {
-            println!("\nFollowing DUTs are removed: ");
+            if !is_json_format() {
+                println!("\nFollowing DUTs are removed: ");
+            }
             for dut in duts_to_be_removed {
-                println!("{:32} {:13} {:?}", dut.0, &format!("{:?}", dut.1), dut.2);
+                if !is_json_format() {
+                    println!("{:32} {:13} {:?}", dut.0, &format!("{:?}", dut.1), dut.2);
+                }
                 SSH_CACHE.remove(&dut.0)?;
             }
         }
         return Ok(());
     }
     // List cached DUTs
+    let last_seen = if args.since.is_some() {
+        Some(DutDb::open()?.last_seen_all()?)
+    } else {
+        None
+    };
+    let mut matched: Vec<(&String, &SshInfo)> = Vec::new();
     for it in duts.iter() {
-        println!("{:32} {}", it.0, serde_json::to_string(it.1)?);
+        if let (Some(since), Some(last_seen)) = (&args.since, &last_seen) {
+            match last_seen.get(it.0) {
+                Some(seen) if seen >= since => {}
+                _ => continue,
+            }
+        }
+        matched.push((it.0, it.1));
+    }
+    if is_json_format() {
+        let rows: Vec<_> = matched
+            .iter()
+            .map(|(id, ssh)| serde_json::json!({"id": id, "ssh": ssh}))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else {
+        for (id, ssh) in matched {
+            println!("{:32} {}", id, serde_json::to_string(ssh)?);
+        }
     }
     Ok(())
 }
@@ -817,8 +1364,50 @@ fn run_dut_info(args: &ArgsDutInfo) -> Result<()> {
     } else {
         args.keys.iter().map(|s| s.as_str()).collect()
     };
+    if is_json_format() {
+        // Unlike the plain output below, this doesn't abort on the first
+        // failing attribute: each requested key gets its own success/error
+        // entry, so a CI pipeline scripting `cro3 dut info --format json`
+        // can still act on the attributes that were fetched successfully.
+        let ssh = SshInfo::new(dut)?;
+        let values = DutInfo::fetch_keys_partial(&ssh, &keys)?;
+        let attrs: Vec<serde_json::Value> = keys
+            .iter()
+            .map(|&k| match values.get(k) {
+                Some(Ok(v)) => serde_json::json!({"key": k, "value": v}),
+                Some(Err(e)) => serde_json::json!({"key": k, "error": e}),
+                None => serde_json::json!({"key": k, "error": "key was not fetched"}),
+            })
+            .collect();
+        let dut_id = values.get("dut_id").and_then(|v| v.as_ref().ok()).cloned();
+        let result = serde_json::json!({
+            "dut_id": dut_id,
+            "host_and_port": ssh.host_and_port(),
+            "attrs": attrs,
+        });
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+    // If a `lium daemon start` is running, prefer its already-connected,
+    // periodically-refreshed cache over reconnecting from scratch.
+    if daemon_client::is_running() {
+        match daemon_client::query(&DaemonRequest::Info { dut: dut.clone() }) {
+            Ok(DaemonResponse::Info(info)) => {
+                println!("{}", serde_json::to_string(&info)?);
+                return Ok(());
+            }
+            Ok(DaemonResponse::Error(e)) => warn!("daemon returned an error, falling back to direct SSH: {e}"),
+            Ok(DaemonResponse::List(_) | DaemonResponse::Run { .. } | DaemonResponse::Forward { .. }) => {}
+            Err(e) => warn!("Failed to query daemon, falling back to direct SSH: {e:#}"),
+        }
+    }
     let ssh = SshInfo::new(dut)?;
     let info = DutInfo::fetch_keys(&ssh, &keys)?;
+    if let Ok(dut_info) = DutInfo::new(dut) {
+        if let Err(e) = DutDb::open().and_then(|db| db.record(&dut_info)) {
+            warn!("Failed to record DUT history: {e:#}");
+        }
+    }
     let result = serde_json::to_string(&info)?;
     println!("{}", result);
     Ok(())
@@ -839,6 +1428,12 @@ pub struct ArgsDiscover {
     /// path to a list of DUT_IDs to scan.
     #[argh(option)]
     target_list: Option<String>,
+    /// discovery method to use: "sweep" (ping6 subnet sweep, default),
+    /// "mdns" (listen for DUTs advertising over multicast DNS), "arp" (read
+    /// the kernel's neighbor table instead of probing), or "all" to combine
+    /// every method ("both" is kept as an alias for "all" for compatibility)
+    #[argh(option, default = "\"sweep\".to_string()")]
+    method: String,
     /// additional attributes to retrieve
     #[argh(positional, greedy)]
     extra_attr: Vec<String>,
@@ -849,11 +1444,22 @@ pub fn run_discover(args: &ArgsDiscover) -> Result<()> {
         let lium_path = current_exe()?;
         info!("lium executable path: {:?}", lium_path);
         let remote = SshInfo::new(remote)?;
-        remote.send_files(
-            &[lium_path.to_string_lossy().to_string()],
-            Some(&"~/".to_string()),
-        )?;
-        let mut cmd = "~/lium dut discover".to_string();
+        let remote_lium = remote.ensure_remote_lium(&lium_path.to_string_lossy())?;
+        let capabilities: serde_json::Value =
+            serde_json::from_str(&remote.run_cmd_stdio(&format!("{remote_lium} version --json"))?)
+                .context("Failed to parse remote lium's capabilities record")?;
+        let remote_protocol_version = capabilities
+            .get("discover_protocol_version")
+            .and_then(|v| v.as_u64());
+        if remote_protocol_version != Some(DISCOVER_PROTOCOL_VERSION as u64) {
+            bail!(
+                "Remote lium speaks discover protocol {:?}, but this lium expects {}. \
+                 Refusing to trust its output; re-run after updating the remote binary.",
+                remote_protocol_version,
+                DISCOVER_PROTOCOL_VERSION
+            );
+        }
+        let mut cmd = format!("{remote_lium} dut discover");
         for ea in &args.extra_attr {
             cmd += " ";
             cmd += ea;
@@ -877,14 +1483,69 @@ pub fn run_discover(args: &ArgsDiscover) -> Result<()> {
             .map(str::to_string)
             .collect())
     } else {
-        discover_local_nodes(args.interface.to_owned())
+        let all = args.method == "both" || args.method == "all";
+        let mut addrs = Vec::new();
+        if args.method == "sweep" || all {
+            addrs.extend(discover_local_nodes(args.interface.to_owned())?);
+        }
+        if args.method == "arp" || all {
+            addrs.extend(discover_arp_neighbors(args.interface.to_owned())?);
+        }
+        if args.method == "mdns" || all {
+            for dut in mdns::discover(std::time::Duration::from_secs(5))? {
+                // A mDNS responder that advertised its model+serial lets us
+                // know this DUT's eventual dut_id before we've even opened
+                // an SSH connection to it; seed SSH_CACHE with that now so
+                // anything racing the upcoming probe already sees it.
+                if let Some(dut_id) = dut.dut_id() {
+                    for addr in &dut.addresses {
+                        if let Err(e) = lium::dut::seed_ssh_cache(addr, &dut_id) {
+                            info!("Failed to pre-cache {addr} as {dut_id}: {e:#}");
+                        }
+                    }
+                }
+                addrs.extend(dut.addresses);
+            }
+        }
+        if !["sweep", "mdns", "arp", "both", "all"].contains(&args.method.as_str()) {
+            bail!(
+                "Unknown --method {:?}. Expected sweep, mdns, arp, or all.",
+                args.method
+            );
+        }
+        addrs.sort();
+        addrs.dedup();
+        Ok(addrs)
     }?;
     info!("Found {} candidates. Checking...", addrs.len());
     let duts = fetch_dut_info_in_parallel(&addrs, &args.extra_attr)?;
     info!("Discovery completed with {} DUTs", duts.len());
-    let duts: Vec<HashMap<String, String>> = duts.iter().map(|e| e.info().to_owned()).collect();
-    let dut_list = serde_json::to_string_pretty(&duts)?;
-    println!("{}", dut_list);
+    if let Ok(db) = DutDb::open() {
+        for dut in &duts {
+            if let Err(e) = db.record(dut) {
+                warn!("Failed to record DUT history for {}: {e:#}", dut.id());
+            }
+        }
+    }
+    if is_json_format() {
+        let duts: Vec<HashMap<String, String>> = duts.iter().map(|e| e.info().to_owned()).collect();
+        println!("{}", serde_json::to_string_pretty(&duts)?);
+    } else {
+        println!(
+            "{:<20}\t{:<10}\t{:<15}\t{}",
+            "DUT_ID", "BOARD", "MODEL", "LAST_SEEN"
+        );
+        for dut in &duts {
+            let info = dut.info();
+            println!(
+                "{:<20}\t{:<10}\t{:<15}\t{}",
+                dut.id(),
+                info.get("board").map(String::as_str).unwrap_or("?"),
+                info.get("model").map(String::as_str).unwrap_or("?"),
+                info.get("timestamp").map(String::as_str).unwrap_or("?"),
+            );
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,426 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! ## Manage AP/EC firmware on a DUT (flashrom wrapper)
+//! ```
+//! # Read the AP firmware off a DUT into a local file
+//! cro3 firmware read --dut ${DUT} --programmer internal image.bin
+//! # Write a new AP firmware image to a DUT and verify it took
+//! cro3 firmware write --dut ${DUT} --programmer internal image.bin
+//! # Check the current write-protect status
+//! cro3 firmware wp --dut ${DUT}
+//! ```
+
+use std::fs::read;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use argh::FromArgs;
+use cro3::cros::ensure_testing_rsa_is_there;
+use cro3::dut::DutInfo;
+use cro3::dut::SshInfo;
+use cro3::util::shell_helpers::get_stderr;
+use cro3::util::shell_helpers::get_stdout;
+use regex::Regex;
+use tracing::error;
+use tracing::info;
+
+/// Scratch dir for images flashrom reads/writes on the DUT side, when
+/// running against a remote target.
+const REMOTE_WORKDIR: &str = "/tmp/cro3_firmware";
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// manage AP/EC firmware via flashrom
+#[argh(subcommand, name = "firmware")]
+pub struct Args {
+    #[argh(subcommand)]
+    nested: SubCommand,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommand {
+    Read(ArgsRead),
+    Write(ArgsWrite),
+    Erase(ArgsErase),
+    Verify(ArgsVerify),
+    Wp(ArgsWp),
+}
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    match &args.nested {
+        SubCommand::Read(args) => run_read(args),
+        SubCommand::Write(args) => run_write(args),
+        SubCommand::Erase(args) => run_erase(args),
+        SubCommand::Verify(args) => run_verify(args),
+        SubCommand::Wp(args) => run_wp(args),
+    }
+}
+
+/// Outcome of a single flashrom invocation, emitted via `tracing` so a
+/// caller scraping logs can tell which chip and region a pass/fail
+/// applies to without re-parsing flashrom's own output.
+#[derive(Debug)]
+struct FirmwareOpResult {
+    operation: &'static str,
+    chip: Option<String>,
+    region: Option<String>,
+    success: bool,
+}
+impl FirmwareOpResult {
+    fn log(&self) {
+        let region = self.region.as_deref().unwrap_or("whole chip");
+        let chip = self.chip.as_deref().unwrap_or("unknown");
+        if self.success {
+            info!(operation = self.operation, chip, region, "flashrom operation succeeded");
+        } else {
+            error!(operation = self.operation, chip, region, "flashrom operation failed");
+        }
+    }
+}
+
+/// Extracts the chip name flashrom prints while probing, e.g.
+/// `Found Winbond flash chip "W25Q128.V" (16384 kB, SPI) on ...`.
+fn parse_chip_name(output: &str) -> Option<String> {
+    let re = Regex::new(r#"Found .* flash chip "([^"]+)""#).ok()?;
+    re.captures(output).map(|c| c[1].to_string())
+}
+
+/// Where to run flashrom: on the local machine, or over SSH on a DUT cro3
+/// already knows how to reach. `write`/`verify` need an image on the
+/// target's own filesystem, so this also handles staging files to and
+/// from a remote target.
+enum FlashromTarget {
+    Local,
+    Remote(SshInfo),
+}
+impl FlashromTarget {
+    fn resolve(dut: &Option<String>) -> Result<Self> {
+        match dut {
+            Some(dut) => {
+                ensure_testing_rsa_is_there()?;
+                Ok(Self::Remote(DutInfo::new(dut)?.ssh().clone()))
+            }
+            None => Ok(Self::Local),
+        }
+    }
+    fn combine_output(output: &Output) -> String {
+        format!("{}\n{}", get_stdout(output), get_stderr(output))
+    }
+    /// Runs `flashrom <flashrom_args>`, returning whether it succeeded,
+    /// its combined stdout/stderr, and any chip name parsed out of it.
+    fn run(&self, flashrom_args: &[&str]) -> Result<(bool, String, Option<String>)> {
+        let (success, output) = match self {
+            Self::Local => {
+                let output = Command::new("flashrom").args(flashrom_args).output()?;
+                (output.status.success(), Self::combine_output(&output))
+            }
+            Self::Remote(ssh) => {
+                let mut cmd = vec!["flashrom"];
+                cmd.extend(flashrom_args);
+                let (code, stdout, stderr) = ssh.run_cmd_captured(&cmd)?;
+                (code == 0, format!("{stdout}\n{stderr}"))
+            }
+        };
+        let chip = parse_chip_name(&output);
+        Ok((success, output, chip))
+    }
+    fn remote_path(&self, name: &str) -> String {
+        format!("{REMOTE_WORKDIR}/{name}")
+    }
+    /// Makes `local_file` available at the path flashrom should write
+    /// from: a no-op locally, or an scp push into a scratch dir on the
+    /// DUT.
+    fn stage_input(&self, local_file: &Path) -> Result<String> {
+        match self {
+            Self::Local => Ok(local_file.to_string_lossy().to_string()),
+            Self::Remote(ssh) => {
+                ssh.run_cmd_piped(&[&format!("mkdir -p {REMOTE_WORKDIR}")])?;
+                let dest = self.remote_path("write.bin");
+                ssh.send_files(&[local_file.to_string_lossy().to_string()], Some(&dest))?;
+                Ok(dest)
+            }
+        }
+    }
+    /// The path flashrom should read into: a no-op locally, or a scratch
+    /// path on the DUT that `fetch_output` later pulls back.
+    fn stage_output(&self, remote_name: &str, local_file: &Path) -> Result<String> {
+        match self {
+            Self::Local => Ok(local_file.to_string_lossy().to_string()),
+            Self::Remote(ssh) => {
+                ssh.run_cmd_piped(&[&format!("mkdir -p {REMOTE_WORKDIR}")])?;
+                Ok(self.remote_path(remote_name))
+            }
+        }
+    }
+    fn fetch_output(&self, remote_name: &str, local_file: &Path) -> Result<()> {
+        if let Self::Remote(ssh) = self {
+            ssh.get_files(
+                &[self.remote_path(remote_name)],
+                Some(&local_file.to_string_lossy().to_string()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the chip (or `region` of it) into `local_dest`, staging through
+/// the DUT's scratch dir when `target` is remote.
+fn dump_chip(
+    target: &FlashromTarget,
+    programmer: &str,
+    region: &Option<String>,
+    remote_name: &str,
+    local_dest: &Path,
+) -> Result<(bool, Option<String>)> {
+    let read_path = target.stage_output(remote_name, local_dest)?;
+    let mut flashrom_args = vec!["-p", programmer, "-r", read_path.as_str()];
+    if let Some(region) = region {
+        flashrom_args.extend(["-i", region.as_str()]);
+    }
+    let (success, output, chip) = target.run(&flashrom_args)?;
+    info!("{output}");
+    if success {
+        target.fetch_output(remote_name, local_dest)?;
+    }
+    Ok((success, chip))
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// read firmware into a local file
+#[argh(subcommand, name = "read")]
+struct ArgsRead {
+    /// DUT to read from (reads locally if omitted)
+    #[argh(option)]
+    dut: Option<String>,
+    /// flashrom programmer spec (e.g. internal, ec, raiden_debug_spi:target=AP)
+    #[argh(option, default = "String::from(\"internal\")")]
+    programmer: String,
+    /// flash region to read (e.g. RW_SECTION_A); reads the whole chip if omitted
+    #[argh(option)]
+    region: Option<String>,
+    /// local path to write the image to
+    #[argh(positional)]
+    image: String,
+}
+fn run_read(args: &ArgsRead) -> Result<()> {
+    let target = FlashromTarget::resolve(&args.dut)?;
+    let image = Path::new(&args.image);
+    let (success, chip) = dump_chip(&target, &args.programmer, &args.region, "read.bin", image)?;
+    FirmwareOpResult {
+        operation: "read",
+        chip,
+        region: args.region.clone(),
+        success,
+    }
+    .log();
+    if !success {
+        bail!("flashrom read failed");
+    }
+    info!("Read firmware into {image:?}");
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// write a local image to the chip, then read it back to verify
+#[argh(subcommand, name = "write")]
+struct ArgsWrite {
+    /// DUT to write to (writes locally if omitted)
+    #[argh(option)]
+    dut: Option<String>,
+    /// flashrom programmer spec
+    #[argh(option, default = "String::from(\"internal\")")]
+    programmer: String,
+    /// flash region to write (e.g. RW_SECTION_A); writes the whole chip if omitted
+    #[argh(option)]
+    region: Option<String>,
+    /// local path of the image to write
+    #[argh(positional)]
+    image: String,
+}
+fn run_write(args: &ArgsWrite) -> Result<()> {
+    let target = FlashromTarget::resolve(&args.dut)?;
+    let image = Path::new(&args.image);
+    let write_path = target.stage_input(image)?;
+    let mut flashrom_args = vec!["-p", args.programmer.as_str(), "-w", write_path.as_str()];
+    if let Some(region) = &args.region {
+        flashrom_args.extend(["-i", region.as_str()]);
+    }
+    let (success, output, chip) = target.run(&flashrom_args)?;
+    info!("{output}");
+    if !success {
+        FirmwareOpResult {
+            operation: "write",
+            chip,
+            region: args.region.clone(),
+            success,
+        }
+        .log();
+        bail!("flashrom write failed");
+    }
+
+    // Read back what's now on the chip and diff it against the image we
+    // just wrote, the same check the flashrom E2E tester runs after a write.
+    let readback = tempfile::NamedTempFile::new().context("Failed to create a readback tempfile")?;
+    let (read_ok, _) = dump_chip(&target, &args.programmer, &args.region, "verify.bin", readback.path())?;
+    let matches = read_ok && read(readback.path())? == read(image)?;
+    FirmwareOpResult {
+        operation: "write",
+        chip,
+        region: args.region.clone(),
+        success: matches,
+    }
+    .log();
+    if !matches {
+        bail!("Write succeeded but read-back verification failed");
+    }
+    info!("Wrote and verified {image:?}");
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// erase the flash chip (or a region of it)
+#[argh(subcommand, name = "erase")]
+struct ArgsErase {
+    /// DUT to erase (erases locally if omitted)
+    #[argh(option)]
+    dut: Option<String>,
+    /// flashrom programmer spec
+    #[argh(option, default = "String::from(\"internal\")")]
+    programmer: String,
+    /// flash region to erase; erases the whole chip if omitted
+    #[argh(option)]
+    region: Option<String>,
+}
+fn run_erase(args: &ArgsErase) -> Result<()> {
+    let target = FlashromTarget::resolve(&args.dut)?;
+    let mut flashrom_args = vec!["-p", args.programmer.as_str(), "-E"];
+    if let Some(region) = &args.region {
+        flashrom_args.extend(["-i", region.as_str()]);
+    }
+    let (success, output, chip) = target.run(&flashrom_args)?;
+    info!("{output}");
+    FirmwareOpResult {
+        operation: "erase",
+        chip,
+        region: args.region.clone(),
+        success,
+    }
+    .log();
+    if !success {
+        bail!("flashrom erase failed");
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// diff the chip's contents against a reference image
+#[argh(subcommand, name = "verify")]
+struct ArgsVerify {
+    /// DUT to read from (reads locally if omitted)
+    #[argh(option)]
+    dut: Option<String>,
+    /// flashrom programmer spec
+    #[argh(option, default = "String::from(\"internal\")")]
+    programmer: String,
+    /// flash region to verify; verifies the whole chip if omitted
+    #[argh(option)]
+    region: Option<String>,
+    /// local reference image to diff the chip's contents against
+    #[argh(positional)]
+    image: String,
+}
+fn run_verify(args: &ArgsVerify) -> Result<()> {
+    let target = FlashromTarget::resolve(&args.dut)?;
+    let reference = Path::new(&args.image);
+    let readback = tempfile::NamedTempFile::new().context("Failed to create a readback tempfile")?;
+    let (read_ok, chip) = dump_chip(&target, &args.programmer, &args.region, "verify.bin", readback.path())?;
+    let matches = read_ok && read(readback.path())? == read(reference)?;
+    FirmwareOpResult {
+        operation: "verify",
+        chip,
+        region: args.region.clone(),
+        success: matches,
+    }
+    .log();
+    if !matches {
+        bail!("Chip contents do not match {reference:?}");
+    }
+    info!("Chip contents match {reference:?}");
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// read or change the write-protect range/status
+#[argh(subcommand, name = "wp")]
+struct ArgsWp {
+    /// DUT to operate on (operates locally if omitted)
+    #[argh(option)]
+    dut: Option<String>,
+    /// flashrom programmer spec
+    #[argh(option, default = "String::from(\"internal\")")]
+    programmer: String,
+    /// enable write-protect (mutually exclusive with --disable)
+    #[argh(switch)]
+    enable: bool,
+    /// disable write-protect (mutually exclusive with --enable)
+    #[argh(switch)]
+    disable: bool,
+    /// write-protect range as "start,length" (e.g. "0x00000000,0x00001000"), used with --enable
+    #[argh(option)]
+    range: Option<String>,
+}
+fn run_wp(args: &ArgsWp) -> Result<()> {
+    let target = FlashromTarget::resolve(&args.dut)?;
+    if args.enable && args.disable {
+        bail!("--enable and --disable are mutually exclusive");
+    }
+    if args.enable || args.disable {
+        let mut flashrom_args = vec!["-p", args.programmer.as_str()];
+        if let Some(range) = &args.range {
+            flashrom_args.extend(["--wp-range", range.as_str()]);
+        }
+        flashrom_args.push(if args.enable { "--wp-enable" } else { "--wp-disable" });
+        let (success, output, chip) = target.run(&flashrom_args)?;
+        info!("{output}");
+        if !success {
+            FirmwareOpResult {
+                operation: "wp",
+                chip,
+                region: None,
+                success,
+            }
+            .log();
+            bail!("Setting write-protect failed");
+        }
+    }
+
+    // Always confirm via --wp-status, whether or not we just changed
+    // anything, so `cro3 firmware wp --dut $DUT` with no flags is a
+    // read-only status check.
+    let (success, output, chip) = target.run(&["-p", args.programmer.as_str(), "--wp-status"])?;
+    info!("{output}");
+    FirmwareOpResult {
+        operation: "wp",
+        chip,
+        region: None,
+        success,
+    }
+    .log();
+    if !success {
+        bail!("Reading write-protect status failed");
+    }
+    if args.enable || args.disable {
+        let reports_enabled = output.contains("is enabled");
+        if reports_enabled != args.enable {
+            bail!("--wp-status does not confirm the requested write-protect change");
+        }
+    }
+    Ok(())
+}
@@ -12,19 +12,31 @@
 //! cro3 flash --cros ${CROS} --usb --board ${BOARD}
 //! ```
 
+use std::fs::create_dir_all;
 use std::process::Command;
 
 use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
+use cro3::artifact;
+use cro3::cache::KvCache;
+use cro3::config::Config;
+use cro3::container::run_in_container;
 use cro3::cros::ensure_testing_rsa_is_there;
 use cro3::cros::lookup_full_version;
 use cro3::dut::DutInfo;
+use cro3::dut::SshInfo;
 use cro3::repo::get_cros_dir;
+use cro3::verity::regenerate_image_verity;
+use cro3::verity::VerityParams;
 use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 
 fn get_board_from_dut(dut: &str) -> Result<String> {
     let dut = DutInfo::new(dut)?;
@@ -77,6 +89,182 @@ fn determine_board_to_flash(
     }
 }
 
+/// Records enough to resume or roll back a `--verify`-ed flash across a
+/// reboot: which DUT and board it targeted, the version that was running
+/// before the flash (the rollback target), and the version it was flashed
+/// to (what a successful boot should report). An entry here means the
+/// verify is still pending; it is removed once the DUT is confirmed to
+/// have booted into `target_version`, or once a rollback completes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingFlash {
+    board: String,
+    previous_version: String,
+    target_version: String,
+}
+static FLASH_VERIFY_CACHE: KvCache<PendingFlash> = KvCache::new("flash_verify_state");
+
+/// Extracts the leading version number (e.g. "15183.0.0") from a
+/// CHROMEOS_RELEASE_DESCRIPTION string such as
+/// "15183.0.0 (Official Build ...) dev-channel board test".
+fn version_from_release(release: &str) -> Result<String> {
+    release
+        .split_whitespace()
+        .next()
+        .map(|s| s.to_string())
+        .context("Failed to parse a version out of the release string")
+}
+
+fn flash_to_destination(
+    repo: &std::path::Path,
+    destination: &str,
+    image_path: &str,
+    enable_rootfs_verification: bool,
+    board: &str,
+    container_image: Option<&str>,
+) -> Result<()> {
+    let mut cmd_args: Vec<&str> =
+        Vec::from(["flash", "--clobber-stateful", "--clear-tpm-owner", "-vvv"]);
+    if !enable_rootfs_verification {
+        cmd_args.push("--disable-rootfs-verification");
+    }
+    cmd_args.push(destination);
+    cmd_args.push(image_path);
+
+    if let Some(container_image) = container_image {
+        let command = format!("cros {}", cmd_args.join(" "));
+        return run_in_container(container_image, &repo.to_string_lossy(), board, &command);
+    }
+
+    let cmd = Command::new("cros")
+        .current_dir(repo)
+        .args(cmd_args)
+        .spawn()?;
+    let result = cmd.wait_with_output()?;
+    if !result.status.success() {
+        bail!("cros flash failed");
+    }
+    Ok(())
+}
+
+/// Waits for `dut` to come back over SSH and checks that it booted into
+/// `target_version` (skipped for the floating `latest*` aliases, which
+/// have no fixed version to compare against). On any failure, re-flashes
+/// `previous_version` as an automatic rollback so the DUT is never left
+/// on a half-verified image.
+fn verify_and_rollback_on_failure(
+    repo: &std::path::Path,
+    dut: &str,
+    board: &str,
+    previous_version: &str,
+    target_version: &str,
+    container_image: Option<&str>,
+) -> Result<()> {
+    let key = dut.to_string();
+    let health = SshInfo::new(dut)
+        .context("failed to create SshInfo")
+        .and_then(|ssh| ssh.wait_online())
+        .context("DUT did not come back online after flashing")
+        .and_then(|()| DutInfo::new(dut).context("failed to read back DUT info after flashing"))
+        .and_then(|info| {
+            if target_version.starts_with("latest") {
+                return Ok(());
+            }
+            let release = info
+                .info()
+                .get("release")
+                .context("Failed to read the release version from the DUT")?;
+            let booted_version = version_from_release(release)?;
+            if booted_version != target_version {
+                bail!(
+                    "DUT booted {booted_version} but {target_version} was requested",
+                );
+            }
+            Ok(())
+        });
+    match health {
+        Ok(()) => {
+            info!("Verified that the DUT booted {target_version}");
+            FLASH_VERIFY_CACHE.remove(&key)?;
+            Ok(())
+        }
+        Err(e) => {
+            error!("Post-flash verification failed: {e:#}; rolling back to {previous_version}");
+            let destination = {
+                let dut = &DutInfo::new(dut)?;
+                dut.ssh().into_forwarded()?.host_and_port().to_string()
+            };
+            let rollback_image = format!("xBuddy://remote/{board}/{previous_version}/test");
+            flash_to_destination(repo, &destination, &rollback_image, false, board, container_image)
+                .context("Rollback flash also failed; DUT may be left in an unknown state")?;
+            FLASH_VERIFY_CACHE.remove(&key)?;
+            bail!("Flash verification failed, rolled back to {previous_version}: {e:#}")
+        }
+    }
+}
+
+/// Tarball name published for each flash variant under
+/// `chromeos-image-archive/<board>-release/<version>/`.
+fn image_tarball_name(variant: &str) -> &'static str {
+    match variant {
+        "recovery" => "recovery_image.tar.xz",
+        _ => "chromiumos_test_image.tar.xz",
+    }
+}
+
+/// Fetches and extracts `variant`'s image tarball for `board`/`version`
+/// via the artifact cache (see `cro3 artifact`), instead of handing an
+/// xBuddy URL to `cros flash` and trusting it to resolve the download
+/// itself. Returns the path to the extracted raw image.
+fn fetch_image_via_artifact_cache(board: &str, version: &str, variant: &str) -> Result<String> {
+    let tarball_name = image_tarball_name(variant);
+    let artifacts = artifact::list_board_images(board, version)?;
+    let tarball = artifacts
+        .into_iter()
+        .find(|a| a.gs_path.ends_with(tarball_name))
+        .with_context(|| format!("{tarball_name} not found for {board} at {version}"))?;
+    let tarball_path = artifact::fetch_artifact(&tarball)?;
+    let extract_dir = tarball_path
+        .parent()
+        .context("cached tarball has no parent dir")?
+        .join("extracted");
+    let image_name = tarball_name.trim_end_matches(".tar.xz");
+    let image_path = extract_dir.join(format!("{image_name}.bin"));
+    if !image_path.exists() {
+        create_dir_all(&extract_dir).context("Failed to create the image extraction dir")?;
+        let status = Command::new("tar")
+            .arg("xf")
+            .arg(&tarball_path)
+            .arg("-C")
+            .arg(&extract_dir)
+            .status()
+            .context("Failed to run tar")?;
+        if !status.success() {
+            bail!("Failed to extract {}", tarball_path.display());
+        }
+    }
+    Ok(image_path.to_string_lossy().to_string())
+}
+
+/// Builds `VerityParams` out of whichever `--verity-*` flags were given,
+/// or `None` if none were, so a plain `cro3 flash` run doesn't pay for
+/// regenerating a hash tree it doesn't need.
+fn verity_params_from_args(args: &Args) -> Option<VerityParams> {
+    if args.verity_salt.is_none()
+        && args.verity_hash_alg.is_none()
+        && args.verity_data_block_size.is_none()
+    {
+        return None;
+    }
+    let defaults = VerityParams::default();
+    Some(VerityParams {
+        salt: args.verity_salt.clone(),
+        hash_alg: args.verity_hash_alg.clone().unwrap_or(defaults.hash_alg),
+        data_block_size: args
+            .verity_data_block_size
+            .unwrap_or(defaults.data_block_size),
+    })
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// flash image
 #[argh(subcommand, name = "flash")]
@@ -109,6 +297,13 @@ pub struct Args {
     #[argh(switch)]
     use_local_image: bool,
 
+    /// fetch the image tarball directly via the artifact cache (see
+    /// `cro3 artifact`) instead of always delegating the download to
+    /// `cros flash`'s own xBuddy resolution; mutually exclusive with
+    /// --use-local-image
+    #[argh(switch)]
+    use_gs_cache: bool,
+
     /// flash recovery image (default: flash test image)
     #[argh(switch)]
     recovery: bool,
@@ -117,15 +312,108 @@ pub struct Args {
     #[argh(switch)]
     enable_rootfs_verification: bool,
 
+    /// custom dm-verity salt (hex) to regenerate the rootfs hash tree
+    /// with, for a locally-built or recovery image (requires --image,
+    /// --use-local-image, or --use-gs-cache)
+    #[argh(option)]
+    verity_salt: Option<String>,
+
+    /// dm-verity hash algorithm to regenerate the rootfs hash tree with:
+    /// sha1 or sha256 (default: sha256)
+    #[argh(option)]
+    verity_hash_alg: Option<String>,
+
+    /// dm-verity data block size, in bytes, to regenerate the rootfs hash
+    /// tree with (default: 4096)
+    #[argh(option)]
+    verity_data_block_size: Option<u32>,
+
+    /// after flashing to a --dut, wait for it to boot and confirm it is
+    /// running the requested version, automatically rolling back to the
+    /// previously installed version on failure
+    #[argh(switch)]
+    verify: bool,
+
+    /// run `cros flash` inside a container instead of requiring a local
+    /// chroot. Requires an image providing `cros flash` (see
+    /// --container-image or `cro3 config set chroot-container-image`).
+    #[argh(switch)]
+    container: bool,
+
+    /// container image to flash from (defaults to the configured
+    /// chroot-container-image). Implies --container.
+    #[argh(option)]
+    container_image: Option<String>,
+
     #[argh(option, hidden_help)]
     repo: Option<String>,
 }
+
+/// Resolves which container image to flash from, if either --container or
+/// --container-image was given.
+fn resolve_container_image(args: &Args) -> Result<Option<String>> {
+    if !args.container && args.container_image.is_none() {
+        return Ok(None);
+    }
+    let image = args
+        .container_image
+        .clone()
+        .or_else(|| Config::read().ok().and_then(|c| c.chroot_container_image()))
+        .context(
+            "--container requires an image; pass --container-image or set \
+             chroot-container-image in the config",
+        )?;
+    Ok(Some(image))
+}
 #[tracing::instrument(level = "trace")]
 pub fn run(args: &Args) -> Result<()> {
+    if args.use_gs_cache && args.use_local_image {
+        bail!("--use-gs-cache cannot be combined with --use-local-image");
+    }
+    if let Some(hash_alg) = &args.verity_hash_alg {
+        if hash_alg != "sha1" && hash_alg != "sha256" {
+            bail!("--verity-hash-alg must be sha1 or sha256, got {hash_alg}");
+        }
+    }
+    if args.verify && args.image.is_some() {
+        // The verify/auto-rollback safety net needs a resolved board and
+        // target build version to confirm the DUT booted into, which a raw
+        // --image file doesn't carry -- bail loudly instead of silently
+        // skipping verification.
+        bail!(
+            "--verify cannot be combined with --image: there is no build version to confirm the \
+             DUT booted into. Use --board/--version (optionally with --use-local-image or \
+             --use-gs-cache) instead."
+        );
+    }
+
     // repo path is needed since cros flash outside chroot only works within the
     // cros checkout
     let repo = &get_cros_dir(&args.cros)?;
+    let container_image = resolve_container_image(args)?;
+
+    // If a verify from a previous, interrupted invocation is still pending for
+    // this DUT, resume straight into verification instead of flashing again.
+    if args.verify {
+        if let Some(dut) = &args.dut {
+            if let Some(pending) = FLASH_VERIFY_CACHE.get(dut)? {
+                warn!(
+                    "Resuming a pending flash verification for {dut} (target: {})",
+                    pending.target_version
+                );
+                return verify_and_rollback_on_failure(
+                    repo,
+                    dut,
+                    &pending.board,
+                    &pending.previous_version,
+                    &pending.target_version,
+                    container_image.as_deref(),
+                );
+            }
+        }
+    }
 
+    let mut board_and_version: Option<(String, String)> = None;
     let image_path = if let Some(image) = &args.image {
         // If --image is specified, use the local file
         image.clone()
@@ -155,9 +443,28 @@ pub fn run(args: &Args) -> Result<()> {
             ));
         }
         let variant = if args.recovery { "recovery" } else { "test" };
-        format!("xBuddy://{host}/{board_to_flash}/{version}/{variant}")
+        board_and_version = Some((board_to_flash.clone(), version.clone()));
+        if args.use_gs_cache {
+            fetch_image_via_artifact_cache(&board_to_flash, &version, variant)?
+        } else {
+            format!("xBuddy://{host}/{board_to_flash}/{version}/{variant}")
+        }
     };
 
+    if let Some(verity_params) = verity_params_from_args(args) {
+        if image_path.starts_with("xBuddy://") {
+            bail!(
+                "--verity-* options require a local image (--image, --use-local-image, or \
+                 --use-gs-cache)"
+            );
+        }
+        let hash_tree = regenerate_image_verity(std::path::Path::new(&image_path), &verity_params)?;
+        info!(
+            "Regenerated rootfs verity hash tree: root_hexdigest={}",
+            hash_tree.root_hexdigest
+        );
+    }
+
     // Determine a destination
     let destination = match (&args.dut, args.usb) {
         (Some(dut), false) => {
@@ -169,21 +476,63 @@ pub fn run(args: &Args) -> Result<()> {
         _ => bail!("Please specify either --dut or --usb"),
     };
 
-    let mut cmd_args: Vec<&str> =
-        Vec::from(["flash", "--clobber-stateful", "--clear-tpm-owner", "-vvv"]);
-    if !args.enable_rootfs_verification {
-        cmd_args.push("--disable-rootfs-verification");
+    // When verifying, the rollback target is whatever the DUT is running right
+    // now, recorded before we overwrite it.
+    let previous_version = if args.verify {
+        match &args.dut {
+            Some(dut) => Some(version_from_release(
+                DutInfo::new(dut)?
+                    .info()
+                    .get("release")
+                    .context("Failed to read the release version from the DUT")?,
+            )?),
+            None => None,
+        }
+    } else {
+        None
+    };
+
+    let board_for_container = board_and_version
+        .as_ref()
+        .map(|(board, _)| board.as_str())
+        .unwrap_or("");
+    if let Err(e) = flash_to_destination(
+        repo,
+        &destination,
+        &image_path,
+        args.enable_rootfs_verification,
+        board_for_container,
+        container_image.as_deref(),
+    ) {
+        error!("cros sdk failed: {e:#}");
+        if !args.verify {
+            return Ok(());
+        }
+        return Err(e);
     }
-    cmd_args.push(&destination);
-    cmd_args.push(&image_path);
 
-    let cmd = Command::new("cros")
-        .current_dir(repo)
-        .args(cmd_args)
-        .spawn()?;
-    let result = cmd.wait_with_output()?;
-    if !result.status.success() {
-        error!("cros sdk failed");
+    if let (true, Some(dut), Some((board, target_version)), Some(previous_version)) = (
+        args.verify,
+        &args.dut,
+        &board_and_version,
+        &previous_version,
+    ) {
+        FLASH_VERIFY_CACHE.set(
+            dut,
+            PendingFlash {
+                board: board.clone(),
+                previous_version: previous_version.clone(),
+                target_version: target_version.clone(),
+            },
+        )?;
+        verify_and_rollback_on_failure(
+            repo,
+            dut,
+            board,
+            previous_version,
+            target_version,
+            container_image.as_deref(),
+        )?;
     }
     Ok(())
 }
@@ -0,0 +1,57 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use anyhow::Result;
+use argh::FromArgs;
+use cro3::metrics::read_metric_history;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// inspect the Tast metrics time series `cro3 tast analyze` records
+#[argh(subcommand, name = "metrics")]
+pub struct Args {
+    #[argh(subcommand)]
+    nested: SubCommand,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SubCommand {
+    History(ArgsHistory),
+}
+
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    match &args.nested {
+        SubCommand::History(args) => args.run(),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// print the mean/change_percent trend recorded for one metric key (as
+/// emitted by `cro3 tast analyze`, e.g.
+/// `experiment_name/model/perf.TabOpenLatencyPerf.TabOpenLatency.`)
+#[argh(subcommand, name = "history")]
+pub struct ArgsHistory {
+    #[argh(positional)]
+    metric_key: String,
+}
+impl ArgsHistory {
+    fn run(&self) -> Result<()> {
+        let history = read_metric_history(&self.metric_key)?;
+        if history.is_empty() {
+            println!("No metrics recorded yet for {:?}", self.metric_key);
+            return Ok(());
+        }
+        println!("{:<12} {:>12} {:>10} {:>10} {:>10}", "timestamp", "mean", "stddev", "p", "change%");
+        for r in history {
+            println!(
+                "{:<12} {:>12.3} {:>10.3} {:>10.6} {:>+9.2}%",
+                r.timestamp, r.mean, r.stddev, r.p, r.change_percent
+            );
+        }
+        Ok(())
+    }
+}
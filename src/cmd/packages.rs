@@ -2,11 +2,15 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+use std::time::Duration;
+
 use anyhow::bail;
 use anyhow::Result;
 use argh::FromArgs;
 use glob::Pattern;
+use lium::cache::CacheKeyInputs;
 use lium::cache::KvCache;
+use lium::cache::Memoized;
 use lium::chroot::Chroot;
 use lium::repo::get_repo_dir;
 
@@ -18,8 +22,12 @@ pub struct Args {
     nested: SubCommand,
 }
 
-pub static PACKAGE_CACHE: KvCache<Vec<String>> = KvCache::new("package_cache");
+pub static PACKAGE_CACHE: KvCache<Memoized<Vec<String>>> = KvCache::new("package_cache");
 static DEFAULT_BOARD: &str = "host";
+/// `cros workon list --all` is cheap but not free; a cache entry older than
+/// this is treated as a miss even if `--build-target`/`--host` hasn't
+/// changed, so an ebuild marked workon since the last run is still noticed.
+const PACKAGE_CACHE_TTL: Duration = Duration::from_secs(3600);
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
@@ -55,8 +63,11 @@ pub struct ArgsList {
 }
 
 fn print_cached_packages(filter: &Pattern, board: &str) -> Result<()> {
-    if let Ok(Some(packages)) = PACKAGE_CACHE.get(board) {
-        for t in &packages {
+    if let Ok(Some(entry)) = PACKAGE_CACHE.get(board) {
+        if let Ok(age) = entry.age() {
+            eprintln!("(cached {}s ago)", age.as_secs());
+        }
+        for t in &entry.value {
             if filter.matches(t) {
                 println!("{t}");
             }
@@ -72,10 +83,14 @@ fn update_cached_packages(repodir: &str, board: &str) -> Result<()> {
     } else {
         format!("--build-target={}", board)
     };
-    let chroot = Chroot::new(repodir)?;
-    let list = chroot.exec_in_chroot(&["cros", "workon", &boardopt, "list", "--all"])?;
-    let packages: Vec<String> = list.lines().map(|s| s.to_string()).collect::<Vec<_>>();
-    PACKAGE_CACHE.set(board, packages)?;
+    let inputs = CacheKeyInputs::new("cros")
+        .args(["workon", &boardopt, "list", "--all"])
+        .dir(repodir);
+    PACKAGE_CACHE.get_or_compute(board, &inputs, Some(PACKAGE_CACHE_TTL), || {
+        let chroot = Chroot::new(repodir)?;
+        let list = chroot.exec_in_chroot(&["cros", "workon", &boardopt, "list", "--all"])?;
+        Ok(list.lines().map(|s| s.to_string()).collect::<Vec<_>>())
+    })?;
     Ok(())
 }
 
@@ -0,0 +1,59 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use anyhow::Result;
+use argh::FromArgs;
+use lium::config::Config;
+use lium::plugin;
+use lium::repo::get_repo_dir;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// run a subcommand provided by an external plugin under ~/.lium/plugins/
+#[argh(subcommand, name = "plugin")]
+pub struct Args {
+    /// list the discovered plugins instead of running one
+    #[argh(switch)]
+    list: bool,
+
+    /// name of the plugin, followed by whatever args it expects
+    #[argh(positional)]
+    command: Vec<String>,
+}
+
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    if args.list || args.command.is_empty() {
+        for plugin in plugin::discover_plugins()? {
+            println!("{:<16} {}", plugin.signature.name, plugin.signature.description);
+        }
+        return Ok(());
+    }
+
+    let plugin = plugin::find_plugin(&args.command[0])?;
+    let status = plugin::run_plugin(&plugin, args.command[1..].to_vec(), plugin_env())?;
+    if !status.success() {
+        bail!("Plugin {:?} exited with {status}", args.command[0]);
+    }
+    Ok(())
+}
+
+/// A handful of config values and the repo dir, passed through to plugins
+/// as part of the `run` request's environment -- not the whole `Config`,
+/// since most of it is irrelevant to an external plugin.
+fn plugin_env() -> HashMap<String, String> {
+    let mut env = HashMap::new();
+    if let Ok(repo_dir) = get_repo_dir(&None) {
+        env.insert("repo_dir".to_string(), repo_dir);
+    }
+    if let Ok(config) = Config::read() {
+        env.insert("profile".to_string(), config.profile_name().to_string());
+        if let Some(checkout) = config.default_cros_checkout() {
+            env.insert("default_cros_checkout".to_string(), checkout);
+        }
+    }
+    env
+}
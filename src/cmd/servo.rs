@@ -17,24 +17,77 @@
 //! sudo `which cro3` servo reset
 //! ```
 
+use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
 use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
 
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
+use chrono::Local;
 use cro3::chroot::Chroot;
+use cro3::config::Config;
 use cro3::repo::get_cros_dir;
+use cro3::servo::get_cr50_attached_to_servo;
 use cro3::servo::get_servo_attached_to_cr50;
+use cro3::servo::reap_orphaned_servod;
 use cro3::servo::reset_devices;
+use cro3::servo::servo_user_config_get;
+use cro3::servo::servo_user_config_set;
+use cro3::servo::servod_status;
+use cro3::servo::stop_all_servod;
+use cro3::servo::stop_servod;
+use cro3::servo::CachedServoInfo;
 use cro3::servo::LocalServo;
 use cro3::servo::ServoList;
 use cro3::servo::ServodConnection;
+use cro3::servo::SERVO_CACHE;
+use cro3::servo_daemon;
+use cro3::servo_daemon::ServodDaemonRequest;
+use cro3::servo_daemon::ServodDaemonResponse;
+use cro3::sh_println;
+use cro3::shell::OutputFormat;
+use cro3::shell::Shell;
 use cro3::util::cro3_paths::cro3_dir;
 use cro3::util::cro3_paths::gen_path_in_cro3_dir;
 use cro3::util::shell_helpers::run_bash_command;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tracing::error;
 use tracing::info;
+use tracing::warn;
+
+lazy_static! {
+    // e.g. "generated challenge:\n\nA0B1 2C3D ... 9Z8Y\n\n"
+    static ref RE_RMA_AUTH_CHALLENGE: Regex =
+        Regex::new(r"(?P<challenge>[0-9A-Za-z ]{20,})").unwrap();
+}
+
+/// Resolves `--serial`, falling back to config's `default_servo_serial` so
+/// a single-servo setup stops needing it retyped on every invocation.
+fn resolve_serial(explicit: &Option<String>) -> Result<String> {
+    if let Some(serial) = explicit {
+        return Ok(serial.clone());
+    }
+    Config::read()?.default_servo_serial().context(
+        "--serial was not given and no default_servo_serial is set; run `cro3 config wizard` or \
+         `cro3 config set default_servo_serial <serial>`",
+    )
+}
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// control Servo
@@ -46,24 +99,96 @@ pub struct Args {
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 enum SubCommand {
+    CacheClear(ArgsCacheClear),
+    CacheRead(ArgsCacheRead),
+    CacheWrite(ArgsCacheWrite),
     Control(ArgsControl),
+    Daemon(ArgsDaemon),
+    Flash(ArgsFlash),
     Get(ArgsGet),
     List(ArgsList),
     Kill(ArgsKill),
+    Monitor(ArgsMonitor),
     Reset(ArgsReset),
+    RmaOpen(ArgsRmaOpen),
+    Set(ArgsSet),
     Shell(ArgsShell),
     Show(ArgsShow),
+    Status(ArgsStatus),
+    Stop(ArgsStop),
 }
 #[tracing::instrument(level = "trace")]
 pub fn run(args: &Args) -> Result<()> {
     match &args.nested {
+        SubCommand::CacheClear(args) => run_cache_clear(args),
+        SubCommand::CacheRead(args) => run_cache_read(args),
+        SubCommand::CacheWrite(args) => run_cache_write(args),
         SubCommand::Control(args) => run_control(args),
+        SubCommand::Daemon(args) => run_daemon(args),
+        SubCommand::Flash(args) => run_flash(args),
         SubCommand::Get(args) => run_get(args),
         SubCommand::List(args) => run_list(args),
         SubCommand::Kill(args) => run_kill(args),
+        SubCommand::Monitor(args) => run_monitor(args),
         SubCommand::Reset(args) => run_reset(args),
+        SubCommand::RmaOpen(args) => run_rma_open(args),
+        SubCommand::Set(args) => run_set(args),
         SubCommand::Shell(args) => run_shell(args),
         SubCommand::Show(args) => run_show(args),
+        SubCommand::Status(args) => run_status(args),
+        SubCommand::Stop(args) => run_stop(args),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// read the persistent discovery cache entry for a servo serial
+#[argh(subcommand, name = "cache-read")]
+pub struct ArgsCacheRead {
+    /// servo serial
+    #[argh(positional)]
+    serial: String,
+}
+fn run_cache_read(args: &ArgsCacheRead) -> Result<()> {
+    match SERVO_CACHE.get(&args.serial)? {
+        Some(info) => println!("{}", serde_json::to_string_pretty(&info)?),
+        None => println!("No cache entry for {}", args.serial),
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// write (upsert) the persistent discovery cache entry for a servo serial
+#[argh(subcommand, name = "cache-write")]
+pub struct ArgsCacheWrite {
+    /// servo serial
+    #[argh(positional)]
+    serial: String,
+    /// MAC address to store
+    #[argh(option)]
+    mac_addr: Option<String>,
+    /// EC version string to store
+    #[argh(option)]
+    ec_version: Option<String>,
+}
+fn run_cache_write(args: &ArgsCacheWrite) -> Result<()> {
+    let list = ServoList::discover()?;
+    let usb_sysfs_path = list.find_by_serial(&args.serial)?.usb_sysfs_path().to_string();
+    let info = CachedServoInfo::new(usb_sysfs_path, args.mac_addr.clone(), args.ec_version.clone());
+    SERVO_CACHE.set(&args.serial, info)
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// clear the persistent discovery cache, for one serial or entirely
+#[argh(subcommand, name = "cache-clear")]
+pub struct ArgsCacheClear {
+    /// servo serial to clear (clears the whole cache if omitted)
+    #[argh(positional)]
+    serial: Option<String>,
+}
+fn run_cache_clear(args: &ArgsCacheClear) -> Result<()> {
+    match &args.serial {
+        Some(serial) => SERVO_CACHE.remove(serial),
+        None => SERVO_CACHE.clear(),
     }
 }
 
@@ -71,17 +196,18 @@ pub fn run(args: &Args) -> Result<()> {
 /// get servo attributes
 #[argh(subcommand, name = "get")]
 pub struct ArgsGet {
-    /// servo serial
+    /// servo serial (falls back to `default_servo_serial` in config)
     #[argh(option)]
-    serial: String,
+    serial: Option<String>,
 
     /// name of attribute
     #[argh(positional)]
     key: String,
 }
 pub fn run_get(args: &ArgsGet) -> Result<()> {
+    let serial = resolve_serial(&args.serial)?;
     let list = ServoList::discover()?;
-    let s = list.find_by_serial(&args.serial)?;
+    let s = list.find_by_serial(&serial)?;
     let s = get_servo_attached_to_cr50(s)?;
     match args.key.as_str() {
         "ipv6_addr" => {
@@ -110,9 +236,52 @@ pub fn run_get(args: &ArgsGet) -> Result<()> {
             let repo = get_cros_dir(None)?;
             println!("{:#X}", s.read_gbb_flags(&repo)?);
         }
-        key => {
-            bail!("attribute {key} is not defined");
+        key => match servo_user_config_get(&serial, key)? {
+            Some(value) => println!("{value}"),
+            None => bail!("attribute {key} is not defined"),
+        },
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// write servo attributes, symmetric with `get`
+#[argh(subcommand, name = "set")]
+pub struct ArgsSet {
+    /// servo serial (falls back to `default_servo_serial` in config)
+    #[argh(option)]
+    serial: Option<String>,
+
+    /// name of attribute
+    #[argh(positional)]
+    key: String,
+
+    /// value to write
+    #[argh(positional)]
+    value: String,
+}
+pub fn run_set(args: &ArgsSet) -> Result<()> {
+    let serial = resolve_serial(&args.serial)?;
+    match args.key.as_str() {
+        "gbb_flags" => {
+            let repo = get_cros_dir(None)?;
+            let list = ServoList::discover()?;
+            let s = list.find_by_serial(&serial)?;
+            let s = get_servo_attached_to_cr50(s)?;
+            let flags = args
+                .value
+                .strip_prefix("0x")
+                .or_else(|| args.value.strip_prefix("0X"))
+                .map(|hex| u64::from_str_radix(hex, 16))
+                .unwrap_or_else(|| u64::from_str_radix(&args.value, 16))
+                .context("gbb_flags value must be a hex number, e.g. 0x18F3 or 18F3")?;
+            s.write_gbb_flags(&repo, flags)?;
         }
+        // Anything not backed by live hardware falls through to the
+        // per-serial user config store -- e.g. a preferred tty_type, a
+        // board override, or a servod port -- which `get` reads back from
+        // the same place.
+        key => servo_user_config_set(&serial, key, &args.value)?,
     }
     Ok(())
 }
@@ -137,6 +306,11 @@ pub struct ArgsList {
     #[argh(switch)]
     slow: bool,
 
+    /// retrieve additional info too, but reuse the persistent on-disk
+    /// cache (keyed by serial) instead of re-probing every console
+    #[argh(switch)]
+    cached: bool,
+
     /// display space-separated Servo serials on one line (stable)
     #[argh(switch)]
     serials: bool,
@@ -145,8 +319,37 @@ pub struct ArgsList {
     #[argh(switch)]
     json: bool,
 }
+/// A machine-readable view of one discovered servo, for `--json`/`--format
+/// json` output: the bits `servo control`/`servo shell` actually key off
+/// (serial, tty paths) plus whether a servod is already managing it.
+#[derive(Debug, Serialize)]
+struct ServoListEntry {
+    product: String,
+    serial: String,
+    usb_sysfs_path: String,
+    tty_list: std::collections::BTreeMap<String, String>,
+    mac_addr: Option<String>,
+    ec_version: Option<String>,
+    servod_running: bool,
+}
+impl ServoListEntry {
+    fn from(s: &LocalServo) -> Self {
+        Self {
+            product: s.product().to_string(),
+            serial: s.serial().to_string(),
+            usb_sysfs_path: s.usb_sysfs_path().to_string(),
+            tty_list: s.tty_list().clone(),
+            mac_addr: s.cached_mac_addr().map(str::to_string),
+            ec_version: s.cached_ec_version().map(str::to_string),
+            servod_running: ServodConnection::from_serial(s.serial()).is_ok(),
+        }
+    }
+}
+
 pub fn run_list(args: &ArgsList) -> Result<()> {
-    let list = if args.slow {
+    let list = if args.cached {
+        ServoList::discover_cached()?
+    } else if args.slow {
         ServoList::discover_slow()?
     } else {
         ServoList::discover()?
@@ -160,8 +363,12 @@ pub fn run_list(args: &ArgsList) -> Result<()> {
         println!("{}", keys.join(" "));
         return Ok(());
     }
-    if args.json {
-        println!("{}", list);
+    if args.json || Shell::lock().format() == OutputFormat::Json {
+        let entries: Vec<ServoListEntry> = list.devices().iter().map(ServoListEntry::from).collect();
+        match Shell::lock().format() {
+            OutputFormat::Json => Shell::lock().print_envelope("servo list", true, &entries),
+            OutputFormat::Human => sh_println!("{}", serde_json::to_string_pretty(&entries)?),
+        };
         return Ok(());
     }
     println!("product         serial                          usb_sysfs_path");
@@ -184,9 +391,10 @@ pub struct ArgsControl {
     /// path to chromiumos source checkout
     #[argh(option)]
     cros: String,
-    /// a servo serial number. To list available servos, run `cro3 servo list`
+    /// a servo serial number (falls back to `default_servo_serial` in
+    /// config). To list available servos, run `cro3 servo list`
     #[argh(option)]
-    serial: String,
+    serial: Option<String>,
     /// arguments to pass to dut_control command
     #[argh(positional)]
     args: Vec<String>,
@@ -195,14 +403,329 @@ pub struct ArgsControl {
     repo: Option<String>,
 }
 pub fn run_control(args: &ArgsControl) -> Result<()> {
+    let serial = resolve_serial(&args.serial)?;
+    // If a `servo daemon start` is running, prefer it over spawning our own
+    // servod: it already owns (and keeps alive) a servod per serial, so
+    // concurrent `servo control` invocations stop fighting over
+    // `pkill -f servod`.
+    if servo_daemon::is_running() {
+        match servo_daemon::query(&ServodDaemonRequest::DutControl {
+            serial: serial.clone(),
+            args: args.args.clone(),
+        }) {
+            Ok(ServodDaemonResponse::Output(output)) => {
+                println!("{output}");
+                return Ok(());
+            }
+            Ok(ServodDaemonResponse::Error(e)) => {
+                warn!("servod daemon returned an error, falling back to direct servod: {e}")
+            }
+            Err(e) => warn!("Failed to query servod daemon, falling back to direct servod: {e:#}"),
+        }
+    }
     let chroot = Chroot::new(&args.cros)?;
-    let servod = ServodConnection::from_serial(&args.serial)
-        .or_else(|_| LocalServo::from_serial(&args.serial)?.start_servod(&chroot))?;
+    let servod = ServodConnection::from_serial(&serial)
+        .or_else(|_| LocalServo::from_serial(&serial)?.start_servod(&chroot))?;
     let output = servod.run_dut_control(&chroot, &args.args)?;
     println!("{}", output);
     Ok(())
 }
 
+/// Parses `dut_control`'s `name:value`-per-line output into a map, e.g. a
+/// `ppvar_vbat_mw:1234` line becomes `"ppvar_vbat_mw" -> "1234"`. Lines that
+/// don't contain a `:` (blank lines, stray warnings) are skipped rather than
+/// treated as an error, since `dut_control` output is not itself guaranteed
+/// to be warning-free.
+fn parse_dut_control_output(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// poll power/rail (or any dut_control) telemetry at a fixed interval,
+/// emitting one JSON Lines record per poll
+#[argh(subcommand, name = "monitor")]
+pub struct ArgsMonitor {
+    /// path to chromiumos source checkout
+    #[argh(option)]
+    cros: String,
+    /// a servo serial number (falls back to `default_servo_serial` in
+    /// config). To list available servos, run `cro3 servo list`
+    #[argh(option)]
+    serial: Option<String>,
+    /// dut_control measurement names to poll, e.g. ppvar_vbat_mw
+    #[argh(positional)]
+    controls: Vec<String>,
+    /// poll interval in seconds
+    #[argh(option, default = "1.0")]
+    interval: f64,
+    /// stop after this many seconds (runs until interrupted if omitted)
+    #[argh(option)]
+    duration: Option<f64>,
+    /// file name to append JSON Lines records to, under
+    /// `gen_path_in_cro3_dir("servo_monitor_logs/")` (prints to stdout if
+    /// omitted)
+    #[argh(option)]
+    output: Option<String>,
+
+    #[argh(option, hidden_help)]
+    repo: Option<String>,
+}
+pub fn run_monitor(args: &ArgsMonitor) -> Result<()> {
+    if args.controls.is_empty() {
+        bail!("at least one dut_control measurement name must be given");
+    }
+    let serial = resolve_serial(&args.serial)?;
+    let chroot = Chroot::new(&args.cros)?;
+    let use_daemon = servo_daemon::is_running();
+    let servod = if use_daemon {
+        None
+    } else {
+        Some(
+            ServodConnection::from_serial(&serial)
+                .or_else(|_| LocalServo::from_serial(&serial)?.start_servod(&chroot))?,
+        )
+    };
+    let mut out = args
+        .output
+        .as_ref()
+        .map(|name| -> Result<_> {
+            let path = gen_path_in_cro3_dir(&format!("servo_monitor_logs/{name}"))?;
+            Ok(OpenOptions::new().create(true).append(true).open(path)?)
+        })
+        .transpose()?;
+    let start = Instant::now();
+    loop {
+        let output = if use_daemon {
+            match servo_daemon::query(&ServodDaemonRequest::DutControl {
+                serial: serial.clone(),
+                args: args.controls.clone(),
+            }) {
+                Ok(ServodDaemonResponse::Output(output)) => output,
+                Ok(ServodDaemonResponse::Error(e)) => bail!("servod daemon returned an error: {e}"),
+                Err(e) => return Err(e).context("Failed to query servod daemon"),
+            }
+        } else {
+            servod
+                .as_ref()
+                .context("servod connection should be present when not using the daemon")?
+                .run_dut_control(&chroot, &args.controls)?
+        };
+        let mut record = parse_dut_control_output(&output)
+            .into_iter()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect::<serde_json::Map<_, _>>();
+        record.insert(
+            "timestamp".to_string(),
+            serde_json::Value::String(Local::now().to_rfc3339()),
+        );
+        let line = serde_json::to_string(&record)?;
+        match &mut out {
+            Some(file) => writeln!(file, "{line}")?,
+            None => println!("{line}"),
+        }
+        if let Some(duration) = args.duration {
+            if start.elapsed().as_secs_f64() >= duration {
+                break;
+            }
+        }
+        sleep(Duration::from_secs_f64(args.interval));
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// manage the long-running servod daemon
+#[argh(subcommand, name = "daemon")]
+pub struct ArgsDaemon {
+    #[argh(subcommand)]
+    nested: DaemonSubCommand,
+}
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum DaemonSubCommand {
+    Start(ArgsDaemonStart),
+}
+fn run_daemon(args: &ArgsDaemon) -> Result<()> {
+    match &args.nested {
+        DaemonSubCommand::Start(args) => run_daemon_start(args),
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// start the servod manager daemon in the foreground, listening on a local
+/// Unix socket. It keeps one servod alive per serial it is asked to serve,
+/// watches for servo connect/disconnect events, and auto-restarts servod
+/// when a previously-served device reappears.
+#[argh(subcommand, name = "start")]
+struct ArgsDaemonStart {
+    /// path to chromiumos source checkout used to launch servod
+    #[argh(option)]
+    cros: String,
+    /// how often (in seconds) to poll for servo connect/disconnect events
+    #[argh(option, default = "5")]
+    poll_interval_secs: u64,
+}
+
+#[derive(Default)]
+struct DaemonState {
+    /// Serials any client has asked the daemon to serve at least once. The
+    /// watcher thread only auto-restarts servod for serials in this set
+    /// when they reappear, instead of for every device plugged into the
+    /// host.
+    managed_serials: Mutex<std::collections::HashSet<String>>,
+}
+
+fn ensure_servod(chroot: &Chroot, serial: &str) -> Result<ServodConnection> {
+    if let Ok(conn) = ServodConnection::from_serial(serial) {
+        return Ok(conn);
+    }
+    info!("servod for {serial} is not running (or has died); starting it...");
+    let servo = ServoList::discover()?.find_by_serial(serial)?.clone();
+    servo.start_servod(chroot)
+}
+
+fn handle_daemon_request(
+    chroot: &Chroot,
+    state: &DaemonState,
+    req: ServodDaemonRequest,
+) -> ServodDaemonResponse {
+    match req {
+        ServodDaemonRequest::DutControl { serial, args } => {
+            state.managed_serials.lock().unwrap().insert(serial.clone());
+            match ensure_servod(chroot, &serial).and_then(|servod| servod.run_dut_control(chroot, &args)) {
+                Ok(output) => ServodDaemonResponse::Output(output),
+                Err(e) => ServodDaemonResponse::Error(format!("{e:#}")),
+            }
+        }
+        ServodDaemonRequest::Shell { serial, tty_type, cmd } => {
+            state.managed_serials.lock().unwrap().insert(serial.clone());
+            let result = ServoList::discover()
+                .and_then(|list| list.find_by_serial(&serial).map(|s| s.clone()))
+                .and_then(|s| s.run_cmd(&tty_type, &cmd));
+            match result {
+                Ok(output) => ServodDaemonResponse::Output(output),
+                Err(e) => ServodDaemonResponse::Error(format!("{e:#}")),
+            }
+        }
+    }
+}
+
+fn daemon_handshake(stream: &mut UnixStream) -> Result<bool> {
+    let mut line = String::new();
+    BufReader::new(&*stream).read_line(&mut line)?;
+    let their_version = serde_json::from_str::<servo_daemon::Hello>(&line)?.version;
+    let mut reply = serde_json::to_string(&servo_daemon::Hello {
+        version: servo_daemon::PROTOCOL_VERSION,
+    })?;
+    reply.push('\n');
+    stream.write_all(reply.as_bytes())?;
+    Ok(their_version == servo_daemon::PROTOCOL_VERSION)
+}
+
+/// Polls `ServoList::discover()` every `poll_interval` and, for any serial
+/// this daemon has previously been asked to serve (`state.managed_serials`)
+/// that just reappeared after being absent, proactively restarts its
+/// servod instead of waiting for the next client request to notice.
+fn watch_for_reconnects(state: &'static DaemonState, chroot: std::sync::Arc<Chroot>, poll_interval: Duration) {
+    let mut previously_present = std::collections::HashSet::new();
+    loop {
+        thread::sleep(poll_interval);
+        let present: std::collections::HashSet<String> = match ServoList::discover() {
+            Ok(list) => list.devices().iter().map(|s| s.serial().to_string()).collect(),
+            Err(e) => {
+                warn!("servo discovery failed while watching for reconnects: {e:#}");
+                continue;
+            }
+        };
+        let managed = state.managed_serials.lock().unwrap().clone();
+        for serial in managed.intersection(&present) {
+            if !previously_present.contains(serial) {
+                info!("{serial} reappeared; restarting its servod");
+                if let Err(e) = ensure_servod(&chroot, serial) {
+                    warn!("failed to restart servod for {serial}: {e:#}");
+                }
+            }
+        }
+        for serial in previously_present.difference(&present) {
+            if managed.contains(serial) {
+                warn!("{serial} disconnected");
+            }
+        }
+        previously_present = present;
+    }
+}
+
+fn run_daemon_start(args: &ArgsDaemonStart) -> Result<()> {
+    let chroot = std::sync::Arc::new(Chroot::new(&args.cros)?);
+    let path = servo_daemon::socket_path()?;
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    info!("servod daemon listening on {path}");
+    let state: &'static DaemonState = Box::leak(Box::<DaemonState>::default());
+
+    {
+        let chroot = chroot.clone();
+        let poll_interval = Duration::from_secs(args.poll_interval_secs);
+        thread::spawn(move || watch_for_reconnects(state, chroot, poll_interval));
+    }
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let chroot = chroot.clone();
+        thread::spawn(move || -> Result<()> {
+            if !daemon_handshake(&mut stream)? {
+                return Ok(());
+            }
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+            let req: ServodDaemonRequest = serde_json::from_str(&line)?;
+            let response = handle_daemon_request(&chroot, state, req);
+            let mut out = serde_json::to_string(&response)?;
+            out.push('\n');
+            stream.write_all(out.as_bytes())?;
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// flash EC/AP firmware over a Servo console, block by block
+#[argh(subcommand, name = "flash")]
+pub struct ArgsFlash {
+    /// servo serial
+    #[argh(option)]
+    serial: String,
+    /// tty type to flash through (e.g. EC, Firmware update)
+    #[argh(option, default = "String::from(\"Firmware update\")")]
+    tty_type: String,
+    /// path to the firmware image to flash
+    #[argh(option)]
+    image: String,
+    /// block size in bytes for each transfer chunk
+    #[argh(option, default = "4096")]
+    block_size: usize,
+}
+fn run_flash(args: &ArgsFlash) -> Result<()> {
+    let list = ServoList::discover()?;
+    let s = list.find_by_serial(&args.serial)?;
+    s.flash_firmware(
+        &args.tty_type,
+        std::path::Path::new(&args.image),
+        args.block_size,
+    )
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Kill all servods
 #[argh(subcommand, name = "kill")]
@@ -217,6 +740,116 @@ pub fn run_kill(_args: &ArgsKill) -> Result<()> {
     Ok(())
 }
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// drive the Cr50/Ti50 RMA-open factory-unlock handshake over its console tty
+#[argh(subcommand, name = "rma-open")]
+pub struct ArgsRmaOpen {
+    /// servo serial (attached Cr50/Ti50 is looked up automatically)
+    #[argh(option)]
+    serial: String,
+    /// also disable AP/EC write-protect once CCD is opened
+    #[argh(switch)]
+    disable_wp: bool,
+}
+fn cr50_ccd_is_open(cr50: &LocalServo) -> Result<bool> {
+    let ccd_state = cr50.run_cmd("Shell", "ccd")?;
+    let ccd_state = ccd_state
+        .split('\n')
+        .rev()
+        .find(|line| line.starts_with("State: "))
+        .context("Could not detect CCD state")?
+        .trim();
+    match ccd_state {
+        "State: Locked" => Ok(false),
+        "State: Opened" => Ok(true),
+        other => bail!("Unexpected ccd state: {other}"),
+    }
+}
+/// Ask the configured authorization endpoint (or, failing that, the
+/// operator) to exchange `challenge` for an 8-character rma_auth authcode.
+fn resolve_rma_auth_code(challenge: &str) -> Result<String> {
+    if let Some(endpoint) = Config::read()?.rma_auth_endpoint() {
+        info!("Requesting rma_auth authcode from {endpoint}...");
+        let cmd = format!(
+            "curl -sf -X POST -d 'challenge={challenge}' {endpoint}",
+            challenge = challenge,
+            endpoint = endpoint
+        );
+        let output = run_bash_command(&cmd, None)?;
+        output
+            .status
+            .exit_ok()
+            .context(anyhow!("rma_auth_endpoint request failed"))?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        info!(
+            "Visit https://chromeos.google.com/partner/console/cr50reset?challenge={challenge} \
+             to get the unlock code (for Googlers, go/rma-auth has more details), then paste it \
+             below:"
+        );
+        let mut authcode = String::new();
+        std::io::stdin()
+            .read_line(&mut authcode)
+            .context("Failed to read authcode from stdin")?;
+        Ok(authcode.trim().to_string())
+    }
+}
+/// Re-discovers the Cr50/Ti50 attached to `servo_serial` after it reboots
+/// (e.g. as a side effect of `rma_auth <code>`), retrying since the tty
+/// takes a moment to come back.
+fn rediscover_cr50(servo_serial: &str) -> Result<LocalServo> {
+    for attempt in 0..10 {
+        sleep(Duration::from_secs(2));
+        let list = ServoList::discover();
+        if let Ok(list) = list {
+            if let Ok(servo) = list.find_by_serial(servo_serial) {
+                if let Ok(cr50) = get_cr50_attached_to_servo(&servo) {
+                    return Ok(cr50);
+                }
+            }
+        }
+        warn!("Cr50 console not back yet after reboot (attempt {attempt})...");
+    }
+    bail!("Cr50 did not come back after rma_auth reboot")
+}
+pub fn run_rma_open(args: &ArgsRmaOpen) -> Result<()> {
+    let list = ServoList::discover()?;
+    let servo = list.find_by_serial(&args.serial)?;
+    let cr50 = get_cr50_attached_to_servo(&servo)?;
+    if !cr50.is_cr50() {
+        bail!("{} is not a Cr50/Ti50", cr50.serial());
+    }
+    if cr50_ccd_is_open(&cr50)? {
+        info!("CCD is already open on {}", cr50.serial());
+        return Ok(());
+    }
+    info!("Requesting an rma_auth challenge...");
+    let response = cr50.run_cmd("Shell", "rma_auth")?;
+    let challenge = RE_RMA_AUTH_CHALLENGE
+        .captures(&response)
+        .map(|c| c["challenge"].split_whitespace().collect::<String>())
+        .context(anyhow!(
+            "Could not find an rma_auth challenge in the console output: {response}"
+        ))?;
+    let authcode = resolve_rma_auth_code(&challenge)?;
+    if authcode.len() != 8 {
+        bail!("Expected an 8-character authcode, got {authcode:?}");
+    }
+    // `rma_auth <authcode>` reboots the Cr50, so the console tty goes away
+    // and we need to re-discover it before issuing any further commands.
+    cr50.run_cmd("Shell", &format!("rma_auth {authcode}"))?;
+    let cr50 = rediscover_cr50(&args.serial)?;
+    if !cr50_ccd_is_open(&cr50)? {
+        bail!("CCD is still not open after rma_auth");
+    }
+    info!("CCD is now open on {}", cr50.serial());
+    if args.disable_wp {
+        cr50.run_cmd("Shell", "wp disable")?;
+        info!("Write-protect disabled on {}", cr50.serial());
+    }
+    Ok(())
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// run shell command
 #[argh(subcommand, name = "shell")]
@@ -224,31 +857,106 @@ pub struct ArgsShell {
     /// print the tty path (e.g. /dev/ttyUSB0) for the shell
     #[argh(switch)]
     print_tty_path: bool,
-    /// DUT serial number (e.g. 09803057-8C65B668) to use
+    /// DUT serial number (e.g. 09803057-8C65B668) to use (falls back to
+    /// `default_servo_serial` in config)
     #[argh(option)]
-    serial: String,
-    /// tty type (e.g. EC, I2C, AP EC upgrade, AP, Shell, Firmware upgrade, ...)
-    #[argh(option, default = "String::from(\"Shell\")")]
-    tty_type: String,
+    serial: Option<String>,
+    /// tty type (e.g. EC, I2C, AP EC upgrade, AP, Shell, Firmware upgrade,
+    /// ...). Falls back to a `tty_type` stashed via `cro3 servo set
+    /// <serial> tty_type <type>`, then to "Shell".
+    #[argh(option)]
+    tty_type: Option<String>,
     /// command to execute
     #[argh(option)]
     cmd: Option<String>,
+    /// when running interactively (no --cmd), also log the whole session
+    /// to this file name under `gen_path_in_cro3_dir("servo_shell_logs/")`
+    #[argh(option)]
+    log: Option<String>,
+    /// serial baud rate for the interactive console
+    #[argh(option, default = "115200")]
+    baud: u32,
+    /// the byte that ends an interactive console session: a literal
+    /// character, "C-a"/"^a" control notation, or a "0xNN" hex byte.
+    /// Defaults to Ctrl-D.
+    #[argh(option, default = "\"C-d\".to_string()")]
+    escape: String,
 }
 fn run_shell(args: &ArgsShell) -> Result<()> {
+    let serial = resolve_serial(&args.serial)?;
+    let tty_type = args
+        .tty_type
+        .clone()
+        .or(servo_user_config_get(&serial, "tty_type")?)
+        .unwrap_or_else(|| "Shell".to_string());
+    if let Some(cmd) = &args.cmd {
+        if servo_daemon::is_running() {
+            match servo_daemon::query(&ServodDaemonRequest::Shell {
+                serial: serial.clone(),
+                tty_type: tty_type.clone(),
+                cmd: cmd.clone(),
+            }) {
+                Ok(ServodDaemonResponse::Output(output)) => {
+                    info!("{}", output);
+                    return Ok(());
+                }
+                Ok(ServodDaemonResponse::Error(e)) => {
+                    warn!("servod daemon returned an error, falling back to direct console: {e}")
+                }
+                Err(e) => warn!("Failed to query servod daemon, falling back to direct console: {e:#}"),
+            }
+        }
+    }
     let list = ServoList::discover()?;
-    let s = list.find_by_serial(&args.serial)?;
+    let s = list.find_by_serial(&serial)?;
     if args.print_tty_path {
-        info!("{}", s.tty_path(&args.tty_type)?);
+        info!("{}", s.tty_path(&tty_type)?);
         Ok(())
     } else if let Some(cmd) = &args.cmd {
-        let ccd_state = s.run_cmd(&args.tty_type, cmd)?;
+        let ccd_state = s.run_cmd(&tty_type, cmd)?;
         info!("{}", ccd_state);
         Ok(())
     } else {
-        bail!("invalid args. please check --help.")
+        run_shell_interactive(args, s, &tty_type)
     }
 }
 
+/// Parses `--escape` into the single byte socat's `escape=` option expects:
+/// a `0xNN` hex byte, `C-x`/`^x` control notation (`x`'s control code), or a
+/// literal character taken as-is.
+fn parse_escape_key(s: &str) -> Result<u8> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u8::from_str_radix(hex, 16).context("--escape hex byte must be 0x00-0xff");
+    }
+    if let Some(letter) = s.strip_prefix("C-").or_else(|| s.strip_prefix("^")) {
+        let c = letter
+            .chars()
+            .next()
+            .context("--escape control notation needs a letter, e.g. C-a or ^a")?
+            .to_ascii_uppercase();
+        if !c.is_ascii_uppercase() {
+            bail!("--escape control notation needs a letter, e.g. C-a or ^a");
+        }
+        return Ok(c as u8 - b'A' + 1);
+    }
+    Ok(s.chars().next().context("--escape must not be empty")? as u8)
+}
+
+/// Opens a genuinely persistent, bidirectional console session against
+/// `servo`'s `tty_type`, via [`LocalServo::open_interactive_shell`], instead
+/// of re-spawning `socat` for every command. If `--log` is given, the whole
+/// session is also recorded to a file under
+/// `gen_path_in_cro3_dir("servo_shell_logs/")`.
+fn run_shell_interactive(args: &ArgsShell, servo: &LocalServo, tty_type: &str) -> Result<()> {
+    let log_path = args
+        .log
+        .as_ref()
+        .map(|name| gen_path_in_cro3_dir(&format!("servo_shell_logs/{name}")))
+        .transpose()?;
+    let escape_byte = parse_escape_key(&args.escape)?;
+    servo.open_interactive_shell(tty_type, log_path.as_deref(), args.baud, escape_byte)
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// show info related to a Servo
 #[argh(subcommand, name = "show")]
@@ -275,3 +983,61 @@ fn run_show(args: &ArgsShow) -> Result<()> {
     }
     Ok(())
 }
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// list servod instances `start_servod` has launched, live or dead
+#[argh(subcommand, name = "status")]
+pub struct ArgsStatus {
+    /// print in JSON format
+    #[argh(switch)]
+    json: bool,
+}
+fn run_status(args: &ArgsStatus) -> Result<()> {
+    let entries = servod_status()?;
+    if args.json || Shell::lock().format() == OutputFormat::Json {
+        match Shell::lock().format() {
+            OutputFormat::Json => Shell::lock().print_envelope("servo status", true, &entries),
+            OutputFormat::Human => sh_println!("{}", serde_json::to_string_pretty(&entries)?),
+        };
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No servod instances are tracked");
+        return Ok(());
+    }
+    println!("serial                          pid     port    alive");
+    for e in &entries {
+        println!("{:32}{:8}{:8}{}", e.serial, e.pid, e.port, e.alive);
+    }
+    Ok(())
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// stop a tracked servod (or all of them), and reap any orphaned/zombie
+/// servod processes left behind by a crashed `cro3` run
+#[argh(subcommand, name = "stop")]
+pub struct ArgsStop {
+    /// servo serial to stop
+    #[argh(option)]
+    serial: Option<String>,
+    /// stop every tracked servod
+    #[argh(switch)]
+    all: bool,
+}
+fn run_stop(args: &ArgsStop) -> Result<()> {
+    let stopped = if args.all {
+        stop_all_servod()?
+    } else {
+        let serial = resolve_serial(&args.serial)?;
+        stop_servod(&serial)?;
+        vec![serial]
+    };
+    for serial in &stopped {
+        info!("Stopped servod for {serial}");
+    }
+    let reaped = reap_orphaned_servod()?;
+    for serial in &reaped {
+        info!("Reaped an orphaned/zombie servod for {serial}");
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! ## Provision a pinned SDK + board prebuilts without a full `repo sync`
+//! ```
+//! cro3 setup-sdk --board eve
+//! cro3 setup-sdk --board eve --version 15000.0.0
+//! ```
+
+use std::fs::read_to_string;
+
+use anyhow::Context;
+use anyhow::Result;
+use argh::FromArgs;
+use cro3::cros::setup_sdk;
+use cro3::repo::get_cros_dir;
+use tracing::info;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// provision a pinned SDK and board prebuilts, without a full `repo sync`
+#[argh(subcommand, name = "setup-sdk")]
+pub struct Args {
+    /// target BOARD to fetch prebuilt binpkgs for
+    #[argh(option)]
+    board: String,
+
+    /// SDK version to provision (default: read from the overlay's
+    /// sdk_version.conf)
+    #[argh(option)]
+    version: Option<String>,
+
+    /// target cros repo dir, used to locate the overlay's sdk_version.conf
+    /// when --version is omitted
+    #[argh(option)]
+    cros: Option<String>,
+
+    #[argh(option, hidden_help)]
+    repo: Option<String>,
+}
+
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    let sdk_version_conf = if args.version.is_none() {
+        let repo = get_cros_dir(&args.cros)?;
+        let path = format!(
+            "{repo}/src/third_party/chromiumos-overlay/chromeos/config/sdk_version.conf"
+        );
+        read_to_string(&path).with_context(|| format!("Failed to read {path}"))?
+    } else {
+        String::new()
+    };
+
+    info!("Provisioning SDK for board {}...", args.board);
+    setup_sdk(&sdk_version_conf, &args.board, args.version.as_deref())?;
+    info!("SDK and {} prebuilts are ready.", args.board);
+    Ok(())
+}
@@ -17,10 +17,13 @@ use std::fs;
 use std::path::Path;
 
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::arc::lookup_arc_version;
 use cro3::arc::setup_arc_repo;
+use cro3::config::Config;
+use cro3::container::run_in_container;
 use cro3::cros::lookup_full_version;
 use cro3::cros::setup_cros_repo;
 use cro3::repo::get_cros_dir_unchecked;
@@ -65,6 +68,49 @@ pub struct Args {
 
     #[argh(option, hidden_help)]
     repo: Option<String>,
+
+    /// run `repo sync` inside a container instead of on the host, for
+    /// machines that can't host a ChromiumOS chroot. Requires an image
+    /// providing `repo` (see --container-image or `cro3 config set
+    /// chroot-container-image`).
+    #[argh(switch)]
+    container: bool,
+
+    /// container image to sync in (defaults to the configured
+    /// chroot-container-image). Implies --container.
+    #[argh(option)]
+    container_image: Option<String>,
+}
+
+/// Resolves which container image to sync in, if either --container or
+/// --container-image was given.
+fn resolve_container_image(args: &Args) -> Result<Option<String>> {
+    if !args.container && args.container_image.is_none() {
+        return Ok(None);
+    }
+    let image = args
+        .container_image
+        .clone()
+        .or_else(|| Config::read().ok().and_then(|c| c.chroot_container_image()))
+        .context(
+            "--container requires an image; pass --container-image or set \
+             chroot-container-image in the config",
+        )?;
+    Ok(Some(image))
+}
+
+/// Runs `repo sync` either directly on the host or, if `container_image` is
+/// set, inside that container image via [`run_in_container`]. The
+/// container path is a simpler one-shot `repo sync`; it does not retry the
+/// per-repo `--force` recovery `repo_sync` does on the host.
+fn sync_repo(repo: &str, force: bool, verbose: bool, container_image: Option<&str>) -> Result<()> {
+    match container_image {
+        Some(image) => {
+            let command = format!("repo sync -j$(nproc){}", if force { " --force-sync" } else { "" });
+            run_in_container(image, repo, "", &command)
+        }
+        None => repo_sync(repo, force, verbose),
+    }
 }
 
 #[tracing::instrument(level = "trace")]
@@ -97,12 +143,14 @@ pub fn run(args: &Args) -> Result<()> {
 
     prepare_repo_paths(&repo, is_cros)?;
 
+    let container_image = resolve_container_image(args)?;
+
     // If we are using another repo as reference for rapid cloning, so make sure
     // that one is synced.
     let reference = get_reference_repo(&args.reference)?;
     if let Some(reference) = &reference {
         warn!("Updating the mirror at {reference}...");
-        repo_sync(reference, args.force, args.verbose)?;
+        sync_repo(reference, args.force, args.verbose, container_image.as_deref())?;
     }
 
     if is_cros {
@@ -111,7 +159,7 @@ pub fn run(args: &Args) -> Result<()> {
         setup_arc_repo(&repo, &version)?;
     }
 
-    repo_sync(&repo, args.force, args.verbose)
+    sync_repo(&repo, args.force, args.verbose, container_image.as_deref())
 }
 
 /// Extract a appropriate version name from a argument.
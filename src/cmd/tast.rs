@@ -4,9 +4,16 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use anyhow::anyhow;
 use anyhow::bail;
@@ -14,21 +21,37 @@ use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
 use cro3::abtest::ExperimentConfig;
+use cro3::abtest::ExperimentRunMetadata;
+use cro3::abtest::ExperimentRunParameter;
+use cro3::abtest::ExperimentRunner;
+use cro3::abtest::Schedule;
 use cro3::chroot::Chroot;
 use cro3::config::Config;
 use cro3::dut::SshInfo;
 use cro3::repo::get_cros_dir;
+use cro3::sh_println;
+use cro3::shell::OutputFormat;
+use cro3::shell::Shell;
+use cro3::tast::collect_cached_tests;
+use cro3::metrics::record_metrics;
+use cro3::tast::collect_new_results;
 use cro3::tast::collect_results;
-use cro3::tast::print_cached_tests;
 use cro3::tast::run_tast_test;
 use cro3::tast::save_result_metadata_json;
 use cro3::tast::update_cached_tests;
+use cro3::tast::write_html_report;
 use cro3::tast::TastAnalyzerInputJson;
+use cro3::tast::to_json_report;
+use cro3::tast::to_junit_report;
 use cro3::tast::TastAnalyzerOutput;
+use cro3::tast::TastAnalyzerOutputAnalysisLine;
+use cro3::tast::TastCiReportEntry;
+use cro3::tast::TastAnalyzerReportEntry;
+use cro3::tast::TastBundleType;
 use cro3::tast::TastResultMetadata;
 use cro3::tast::TastTestExecutionType;
-use cro3::util::shell_helpers::get_stdout;
-use cro3::util::shell_helpers::run_bash_command;
+use cro3::s3_storage::S3Bucket;
+use cro3::util::cro3_paths::gen_path_in_cro3_dir;
 use glob::Pattern;
 use hashbrown::HashMap;
 use rayon::prelude::*;
@@ -49,6 +72,15 @@ struct ComparativeAnalysisMetadata {
 struct ComparativeAnalysisMetadataSeries {
     tast_analyzer_input_json: PathBuf,
     variant_description: String,
+    /// Each result's `bluebench_result.converged_mean_mean`, for the native
+    /// A/B comparison in `TastAnalyzerOutput::compute` -- kept alongside
+    /// `tast_analyzer_input_json` rather than replacing it, since the JSON
+    /// is still useful as a saved artifact.
+    values: Vec<f64>,
+    /// Distinct `kernel_cmdline_mitigations` values seen across this arm's
+    /// DUTs, deduped. More than one entry means the DUTs didn't all boot
+    /// with identical mitigation-relevant args.
+    mitigations: Vec<String>,
 }
 impl Display for ComparativeAnalysisMetadataSeries {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -86,6 +118,10 @@ impl ComparativeAnalysisMetadataSeries {
                 mitigations.first().map(|s| s.as_str().trim()).unwrap_or("")
             );
         }
+        let values: Vec<f64> = v
+            .iter()
+            .map(|e| e.invocation.bluebench_result.as_ref().unwrap().converged_mean_mean)
+            .collect();
         let t = TastAnalyzerInputJson::from_results(&v)?;
         let name = format!("{}_{}", k.replace('/', "_"), cfg);
         save_result_metadata_json(&v, Some(&name)).context(anyhow!("Failed to save {}", name))?;
@@ -95,8 +131,33 @@ impl ComparativeAnalysisMetadataSeries {
         Ok(Self {
             tast_analyzer_input_json: path,
             variant_description: mitigations.join(" ").trim().replace('\n', " ").to_string(),
+            values,
+            mitigations,
         })
     }
+
+    /// The arm's representative mitigations string for cross-arm diffing:
+    /// its only distinct value, or the first of several if the DUTs
+    /// disagreed (already flagged by the `warn!` in [`Self::from`]).
+    fn representative_mitigations(&self) -> &str {
+        self.mitigations.first().map(|s| s.as_str()).unwrap_or("")
+    }
+}
+
+/// Prints the token-level [`cro3::linux::diff_mitigations`] between `a` and
+/// `b`'s representative mitigations strings, turning the opaque
+/// `variant_description` pair into an actionable "A had X, B had Y"
+/// report.
+fn print_mitigations_diff(a: &ComparativeAnalysisMetadataSeries, b: &ComparativeAnalysisMetadataSeries) {
+    let diff = cro3::linux::diff_mitigations(a.representative_mitigations(), b.representative_mitigations());
+    if diff.is_empty() {
+        println!("  cmdline: identical mitigation-relevant args");
+        return;
+    }
+    println!("  cmdline diff (A -> B):");
+    for d in diff {
+        println!("    {d}");
+    }
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -158,16 +219,58 @@ pub struct ArgsAnalyze {
     #[argh(switch)]
     sample: bool,
 
-    /// tast-analyzer path
-    #[argh(option)]
-    tast_analyzer: Option<String>,
-
     /// experiment name filter
     #[argh(option)]
     experiment_name: Option<String>,
+
+    /// instead of scanning results-dir once and exiting, keep polling it
+    /// for newly-completed test invocations and re-emit the analysis as
+    /// they arrive. Useful during a long overnight A/B run, to see rolling
+    /// statistical results instead of waiting for the whole campaign to
+    /// finish.
+    #[argh(switch)]
+    watch: bool,
+
+    /// how often (in seconds) to poll results-dir for new invocations in
+    /// --watch mode
+    #[argh(option, default = "10")]
+    watch_interval_secs: u64,
+
+    /// write a self-contained Plotly HTML report comparing the A/B
+    /// distribution of every metric to this path, instead of (or in
+    /// addition to) the console summary
+    #[argh(option)]
+    html_report: Option<PathBuf>,
+
+    /// label of a stored historical baseline to diff this run's B arm
+    /// against (e.g. "last-week"), using the same significance machinery
+    /// as the A/B comparison; the --start/--end window already selects
+    /// which runs are "this run". Also persists this run as the new
+    /// baseline under the same label, so the next `--baseline <name>` run
+    /// compares against it in turn.
+    #[argh(option)]
+    baseline: Option<String>,
+
+    /// max acceptable |%change| vs --baseline before a metric is flagged
+    /// as regressed (same units as the printed %change)
+    #[argh(option, default = "5.0")]
+    baseline_regression_threshold: f64,
+
+    /// write a machine-readable report (see --format) to this path, so a CI
+    /// job can gate on "did any metric regress" instead of scraping the
+    /// console output
+    #[argh(option)]
+    report: Option<PathBuf>,
+
+    /// format for --report: "json" or "junit"
+    #[argh(option, default = "\"json\".to_string()")]
+    format: String,
 }
 impl ArgsAnalyze {
     fn run(&self) -> Result<()> {
+        if self.watch {
+            return self.run_watch();
+        }
         let results = collect_results(
             self.cros.as_deref(),
             self.results_dir.as_deref(),
@@ -183,68 +286,134 @@ impl ArgsAnalyze {
                 info!("Sample (last): {result:#?}");
             }
         }
+        self.analyze(results)
+    }
+
+    /// Polls `collect_new_results` every `watch_interval_secs`, accumulating
+    /// every newly-ingested invocation into a running set and re-running
+    /// the same analysis as `run` on the whole set so far, until the user
+    /// interrupts it (e.g. Ctrl-C).
+    fn run_watch(&self) -> Result<()> {
+        let mut seen = HashSet::new();
+        let mut all_results: Vec<TastResultMetadata> = Vec::new();
+        loop {
+            let new_results = collect_new_results(
+                self.cros.as_deref(),
+                self.results_dir.as_deref(),
+                self.start.as_deref(),
+                self.end.as_deref(),
+                &mut seen,
+            )?;
+            if !new_results.is_empty() {
+                info!(
+                    "{} new test invocations ingested ({} total)",
+                    new_results.len(),
+                    all_results.len() + new_results.len()
+                );
+                all_results.extend(new_results);
+                self.analyze(all_results.clone())?;
+            }
+            thread::sleep(Duration::from_secs(self.watch_interval_secs));
+        }
+    }
+
+    fn analyze(&self, results: Vec<TastResultMetadata>) -> Result<()> {
         let results = parse_cro3_abtest_results(results, self.experiment_name.as_deref())?;
         show_experiments_in_results(&results)?;
         let experiments = parse_bluebench_results(results)?;
-        if let Some(tast_analyzer) = &self.tast_analyzer {
-            info!("Using tast-analyzer at: {tast_analyzer}");
-            let tast_analyzer = Path::new(tast_analyzer);
-            if !tast_analyzer.is_dir() || !tast_analyzer.join("analyzer/__init__.py").is_file() {
-                bail!(
-                    "It looks like the directory is not a tast-analyzer. Please check and try \
-                     again"
-                );
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut report_entries = Vec::new();
+        let mut ci_report_entries = Vec::new();
+        for (k, v) in experiments {
+            println!("{k}: ");
+            println!("  A: {}", v.a.variant_description);
+            println!("  B: {}", v.b.variant_description);
+            print_mitigations_diff(&v.a, &v.b);
+            if let Some(label) = &self.baseline {
+                self.check_and_update_baseline(&k, &v.b.values, timestamp, label);
             }
-            for (k, v) in experiments {
-                println!("{k}: ");
-                println!("  A: {}", v.a.variant_description);
-                println!("  B: {}", v.b.variant_description);
-                let results = run_tast_analyzer(
-                    tast_analyzer,
-                    &v.a.tast_analyzer_input_json,
-                    &v.b.tast_analyzer_input_json,
-                )?;
-                if results.is_empty() {
-                    println!("  no statistical significance");
-                } else {
-                    for e in results {
-                        println!("{e}");
+            match TastAnalyzerOutput::compute(
+                k.clone(),
+                "milliseconds",
+                "milliseconds",
+                &v.a.values,
+                &v.b.values,
+            ) {
+                Ok(e) => {
+                    println!("{e}");
+                    let board = k.rsplit('/').next();
+                    if let Err(err) = record_metrics(std::slice::from_ref(&e), board, None, timestamp) {
+                        warn!("failed to record metrics for {k}: {err:#}");
+                    }
+                    if self.report.is_some() {
+                        ci_report_entries.push(TastCiReportEntry {
+                            key: k.clone(),
+                            variant_a: v.a.variant_description.clone(),
+                            variant_b: v.b.variant_description.clone(),
+                            regressed: e.analysis.significant && e.analysis.change_percent > 0.0,
+                            output: e.clone(),
+                        });
+                    }
+                    if self.html_report.is_some() {
+                        report_entries.push(TastAnalyzerReportEntry {
+                            output: e,
+                            samples_a: v.a.values,
+                            samples_b: v.b.values,
+                        });
                     }
                 }
-                println!();
+                Err(e) => println!("  no statistical significance ({e:#})"),
             }
-        } else {
-            info!("To compare the results statistically, run:");
-            info!(
-                "PYTHONPATH=$TAST_ANALYZER python3 -m analyzer.run print-results --compare \
-                 out/$RESULT_A_JSON out/$RESULT_B_JSON"
-            );
-            info!("Note: TAST_ANALYZER can be downloaded from: https://chromium.googlesource.com/chromiumos/platform/tast-tests/");
-            info!(
-                "and please specify the absolute path of tools/tast-analyzer/ in the repo above \
-                 as TAST_ANALYZER"
-            );
+            println!();
+        }
+        if let Some(path) = &self.html_report {
+            write_html_report(&report_entries, path)?;
+            info!("wrote HTML report to {}", path.display());
+        }
+        if let Some(path) = &self.report {
+            let rendered = match self.format.as_str() {
+                "json" => to_json_report(&ci_report_entries)?,
+                "junit" => to_junit_report(&ci_report_entries),
+                other => bail!("Unknown --format {other:?}, expected json or junit"),
+            };
+            std::fs::write(path, rendered)?;
+            info!("wrote {} report to {}", self.format, path.display());
         }
         Ok(())
     }
-}
 
-fn run_tast_analyzer(
-    tast_analyzer: &Path,
-    input_a: &Path,
-    input_b: &Path,
-) -> Result<Vec<TastAnalyzerOutput>> {
-    let result = run_bash_command(
-        &format!(
-            r#"
-    PYTHONPATH={tast_analyzer:?} python3 -m analyzer.run print-results --compare {input_a:?} {input_b:?}
-        "#
-        ),
-        None,
-    )?;
-    let result = get_stdout(&result);
-    let result = TastAnalyzerOutput::from(&result)?;
-    Ok(result)
+    /// Diffs `values` (this run's samples for experiment `k`) against
+    /// whatever's stored under `label`, flagging a regression when the
+    /// difference is both statistically significant and its |%change|
+    /// crosses `baseline_regression_threshold`; then persists `values` as
+    /// the new baseline under the same label for next time.
+    fn check_and_update_baseline(&self, k: &str, values: &[f64], timestamp: u64, label: &str) {
+        match cro3::tast::load_baseline(k, label) {
+            Ok(Some(baseline)) => match TastAnalyzerOutputAnalysisLine::compute(&baseline.values, values) {
+                Ok(vs_baseline) => {
+                    let regressed = vs_baseline.significant
+                        && vs_baseline.change_percent.abs() >= self.baseline_regression_threshold;
+                    println!(
+                        "  vs baseline {label:?} ({} samples from {}): {:+.2}% change, p={:.4}{}",
+                        baseline.values.len(),
+                        baseline.timestamp,
+                        vs_baseline.change_percent,
+                        vs_baseline.p,
+                        if regressed { " [REGRESSED]" } else { "" }
+                    );
+                }
+                Err(e) => println!("  vs baseline {label:?}: unavailable ({e:#})"),
+            },
+            Ok(None) => println!("  vs baseline {label:?}: no stored baseline yet"),
+            Err(e) => warn!("failed to read baseline {label:?} for {k}: {e:#}"),
+        }
+        if let Err(e) = cro3::tast::save_baseline(k, label, values.to_vec(), timestamp) {
+            warn!("failed to persist baseline {label:?} for {k}: {e:#}");
+        }
+    }
 }
 
 fn parse_cro3_abtest_results(
@@ -353,6 +522,24 @@ fn parse_bluebench_results(
     Ok(experiments)
 }
 
+/// Hashes every file that ends up in a tastpack (the bundle binaries,
+/// `remote_test_runner`, `tast`, and the `data` dir) as they currently
+/// stand installed in the chroot, printing a single sha256 hex digest. Run
+/// both before building (to check the cache against whatever's already
+/// installed) and after (to key the upload), so a re-run against unchanged
+/// sources produces the same digest either way.
+const TASTPACK_DIGEST_SCRIPT: &str = r#"
+{
+  for f in /usr/bin/remote_test_runner /usr/bin/tast; do
+    [ -f "$f" ] && sha256sum "$f"
+  done
+  [ -d /usr/libexec/tast/bundles ] && \
+    find /usr/libexec/tast/bundles -type f -print0 | sort -z | xargs -0 -r sha256sum
+  [ -d /usr/share/tast/data ] && \
+    find /usr/share/tast/data -type f -print0 | sort -z | xargs -0 -r sha256sum
+} | sha256sum | cut -d' ' -f1
+"#;
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Generate a portable tast execution package
 #[argh(subcommand, name = "build")]
@@ -360,12 +547,63 @@ pub struct ArgsBuild {
     /// cros source dir
     #[argh(option)]
     cros: Option<String>,
+
+    /// S3-compatible endpoint URL for the tastpack cache (falls back to
+    /// `tast_cache_endpoint` in config, then to AWS S3's own default
+    /// endpoint, if omitted); has no effect unless a bucket is configured
+    /// via `cro3 config set tast_cache_bucket <bucket>`
+    #[argh(option)]
+    cache_url: Option<String>,
+
+    /// skip the tastpack cache entirely, even if a bucket is configured
+    #[argh(switch)]
+    no_cache: bool,
 }
 impl ArgsBuild {
+    /// Resolves the S3-compatible cache bucket from config, unless
+    /// `--no-cache` was given or no bucket is configured (in which case
+    /// caching is simply skipped and `run` always builds).
+    fn resolve_cache(&self) -> Result<Option<S3Bucket>> {
+        if self.no_cache {
+            return Ok(None);
+        }
+        let config = Config::read()?;
+        let Some(bucket) = config.tast_cache_bucket() else {
+            return Ok(None);
+        };
+        Ok(Some(S3Bucket {
+            bucket,
+            endpoint: self.cache_url.clone().or_else(|| config.tast_cache_endpoint()),
+            access_key_id: config.tast_cache_access_key_id(),
+            secret_access_key: config.tast_cache_secret_access_key(),
+        }))
+    }
+
     fn run(&self) -> Result<()> {
         let cros = get_cros_dir(self.cros.as_deref())?;
         let chroot = Chroot::new(&cros)?;
-        chroot.run_bash_script_in_chroot(
+        let cache = self.resolve_cache()?;
+
+        if let Some(cache) = &cache {
+            let digest = chroot
+                .run_bash_script_in_chroot("tastpack_digest", TASTPACK_DIGEST_SCRIPT, None)?
+                .trim()
+                .to_string();
+            if cache.exists(&digest)? {
+                info!("tastpack cache hit for digest {digest}, downloading instead of building");
+                let archive = gen_path_in_cro3_dir(&format!("tastpack_cache/{digest}.tar.gz"))?;
+                cache.download(&digest, &archive)?;
+                sh_println!(
+                    "Downloaded a cached tastpack archive to {}; extract it and run \
+                     `./run_tast.sh <DUT> <test>` from inside.",
+                    archive.display()
+                );
+                return Ok(());
+            }
+            info!("tastpack cache miss for digest {digest}, building");
+        }
+
+        let stdout = chroot.run_bash_script_in_chroot(
             "generate_tast_archive",
             r#"
 # First, emerge the required packages.
@@ -400,9 +638,33 @@ echo "${TASTPACK_PATH_OUTSIDE}"
 echo ""
 echo "Move into the dir and run something like this to run Tast tests:"
 echo "./run_tast.sh \${DUT} meta.RemotePass"
+echo "TASTPACK_PATH_OUTSIDE=${TASTPACK_PATH_OUTSIDE}"
 "#,
             None,
         )?;
+
+        if let Some(cache) = &cache {
+            let digest = chroot
+                .run_bash_script_in_chroot("tastpack_digest", TASTPACK_DIGEST_SCRIPT, None)?
+                .trim()
+                .to_string();
+            let path_outside = stdout
+                .lines()
+                .find_map(|l| l.strip_prefix("TASTPACK_PATH_OUTSIDE="))
+                .context("build script did not report TASTPACK_PATH_OUTSIDE")?;
+            let archive = gen_path_in_cro3_dir(&format!("tastpack_cache/{digest}.tar.gz"))?;
+            let tar_status = std::process::Command::new("tar")
+                .args(["czf"])
+                .arg(&archive)
+                .args(["-C", path_outside, "."])
+                .status()
+                .context("Failed to run tar")?;
+            if !tar_status.success() {
+                bail!("tar czf {} exited with {tar_status}", archive.display());
+            }
+            cache.upload(&archive, &digest)?;
+            info!("uploaded the tastpack archive to the cache under digest {digest}");
+        }
         Ok(())
     }
 }
@@ -452,7 +714,15 @@ fn run_tast_list(args: &ArgsList) -> Result<()> {
         update_cached_tests(&bundles, dut, &get_cros_dir(args.cros.as_deref())?)?;
     }
 
-    print_cached_tests(&filter, &bundles)?;
+    let tests = collect_cached_tests(&filter, &bundles)?;
+    match Shell::lock().format() {
+        OutputFormat::Json => Shell::lock().print_envelope("tast list", true, &tests),
+        OutputFormat::Human => {
+            for t in &tests {
+                sh_println!("{t}");
+            }
+        }
+    }
 
     Ok(())
 }
@@ -469,14 +739,57 @@ pub struct ArgsRun {
     #[argh(option)]
     tastpack: Option<String>,
 
-    /// target DUT
+    /// target DUT, optionally tagged with an A/B experiment-config
+    /// assignment in DUT[=a|b] form (e.g. "dut1" or "dut1=a"); repeatable
+    /// to build a pool that's run concurrently. Combined with any DUTs
+    /// listed via --dut-file
     #[argh(option)]
-    dut: String,
+    dut: Vec<DutPoolEntry>,
+
+    /// path to a newline-separated host-list file of DUT[=a|b] entries
+    /// (same form as --dut, one per line; blank lines and `#`-comments are
+    /// ignored), merged with any --dut given directly
+    #[argh(option)]
+    dut_file: Option<String>,
+
+    /// max DUTs to run the test against concurrently
+    #[argh(option, default = "4")]
+    jobs: usize,
+
+    /// base directory for per-DUT `-resultsdir` output (default:
+    /// out/tast_run_<unix timestamp>); each DUT gets its own subdirectory
+    /// named after it
+    #[argh(option)]
+    results_dir: Option<PathBuf>,
+
+    /// experiment name to stamp into each DUT's
+    /// `cro3_abtest_run_metadata.json`, for DUTs given an `=a`/`=b` config
+    /// in --dut/--dut-file, so the whole comparative pipeline (`cro3 tast
+    /// analyze`) can be kicked off against this pool's results in one run
+    #[argh(option)]
+    experiment_name: Option<String>,
 
     /// test options (e.g. "-var ...")
     #[argh(option)]
     option: Option<String>,
 
+    /// which class of bundle to run: a "local" bundle on the DUT itself
+    /// (default), or a "remote" bundle that runs on the host and drives the
+    /// DUT (and --companion-dut/--servo) over the network, e.g. for
+    /// firmware tests
+    #[argh(option, default = "TastBundleType::Local")]
+    bundle: TastBundleType,
+
+    /// companion DUT to pass to a remote bundle as `-companiondut=<value>`
+    /// (e.g. "cd1:192.168.1.2"), only meaningful with --bundle remote
+    #[argh(option)]
+    companion_dut: Option<String>,
+
+    /// servo to pass to a remote bundle as `-var=servo=<value>` (e.g.
+    /// "localhost:9999"), only meaningful with --bundle remote
+    #[argh(option)]
+    servo: Option<String>,
+
     /// test name or pattern
     #[argh(positional)]
     tests: String,
@@ -490,11 +803,147 @@ impl ArgsRun {
             self.cros.as_deref(),
             self.tastpack.as_deref(),
         )?;
+        let mut pool = self.dut.clone();
+        if let Some(path) = &self.dut_file {
+            pool.extend(read_dut_pool_file(path)?);
+        }
+        if pool.is_empty() {
+            bail!("At least one --dut or --dut-file entry is required");
+        }
+        let results_dir = self.results_dir.clone().unwrap_or_else(|| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            PathBuf::from("out").join(format!("tast_run_{timestamp}"))
+        });
+
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs.max(1))
+            .build()
+            .context("Failed to build the DUT pool thread pool")?;
+        let failures: Vec<(String, anyhow::Error)> = thread_pool.install(|| {
+            pool.par_iter()
+                .filter_map(|entry| {
+                    self.run_one(&tast, entry, &results_dir)
+                        .err()
+                        .map(|e| (entry.dut.clone(), e))
+                })
+                .collect()
+        });
+
+        if !failures.is_empty() {
+            let summary = failures
+                .iter()
+                .map(|(dut, e)| format!("{dut}: {e:#}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            bail!("{}/{} DUTs failed: {summary}", failures.len(), pool.len());
+        }
+        Ok(())
+    }
+
+    /// Runs `tests` against a single pool entry: creates its per-DUT result
+    /// dir, stamps `cro3_abtest_run_metadata.json` into it when `entry` was
+    /// given an A/B config, then runs the test with `-resultsdir` pointed at
+    /// it so the result lands in an isolated, analyzable directory.
+    fn run_one(
+        &self,
+        tast: &TastTestExecutionType,
+        entry: &DutPoolEntry,
+        results_dir: &Path,
+    ) -> Result<()> {
+        let dut_result_dir = results_dir.join(sanitize_for_path(&entry.dut));
+        std::fs::create_dir_all(&dut_result_dir)
+            .with_context(|| format!("Failed to create {dut_result_dir:?}"))?;
+        let ssh = SshInfo::new(&entry.dut)?;
+        if let Some(config) = entry.config {
+            let experiment_name = self
+                .experiment_name
+                .clone()
+                .context("--experiment-name is required when a --dut entry has an =a/=b config")?;
+            let runner = ExperimentRunner::new(
+                tast.clone(),
+                experiment_name,
+                ssh.clone(),
+                entry.dut.clone(),
+                ExperimentRunParameter {
+                    run_per_group: 1,
+                    group_per_cluster: 1,
+                    cluster_per_iteration: 1,
+                    num_of_iterations: 1,
+                    schedule: Schedule::Sequential,
+                    seed: 0,
+                    block_size: 1,
+                },
+                self.tests.clone(),
+                dut_result_dir.clone(),
+            );
+            let metadata = ExperimentRunMetadata::for_single_run(runner, config, 0);
+            let mut file = std::fs::File::create(dut_result_dir.join("cro3_abtest_run_metadata.json"))?;
+            write!(file, "{}", serde_json::to_string(&metadata)?)?;
+        }
+        let resultsdir_flag = format!("-resultsdir {}", dut_result_dir.to_string_lossy());
+        let option = match &self.option {
+            Some(option) => format!("{option} {resultsdir_flag}"),
+            None => resultsdir_flag,
+        };
         run_tast_test(
-            &SshInfo::new(&self.dut)?,
-            &tast,
+            &ssh,
+            tast,
             &self.tests,
-            self.option.as_deref(),
+            Some(&option),
+            self.bundle,
+            self.companion_dut.as_deref(),
+            self.servo.as_deref(),
         )
     }
 }
+
+/// A single `--dut`/`--dut-file` pool entry: the DUT identifier, optionally
+/// tagged with an A/B experiment-config assignment in `DUT=a`/`DUT=b` form.
+#[derive(Debug, Clone, PartialEq)]
+struct DutPoolEntry {
+    dut: String,
+    config: Option<ExperimentConfig>,
+}
+impl std::str::FromStr for DutPoolEntry {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((dut, config)) => Ok(Self {
+                dut: dut.to_string(),
+                config: Some(match config.to_ascii_lowercase().as_str() {
+                    "a" => ExperimentConfig::A,
+                    "b" => ExperimentConfig::B,
+                    other => bail!("Unknown experiment config {other:?} in --dut, expected `a` or `b`"),
+                }),
+            }),
+            None => Ok(Self {
+                dut: s.to_string(),
+                config: None,
+            }),
+        }
+    }
+}
+
+/// Reads a `--dut-file` host-list: one `DUT[=a|b]` entry per line, with
+/// blank lines and `#`-comments ignored.
+fn read_dut_pool_file(path: &str) -> Result<Vec<DutPoolEntry>> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read --dut-file {path:?}"))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(DutPoolEntry::from_str)
+        .collect()
+}
+
+/// Replaces characters that aren't safe in a directory name (e.g. the `:`
+/// in `dut.local:22`) with `_`, so a DUT identifier can be used as its
+/// per-DUT result subdirectory name.
+fn sanitize_for_path(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
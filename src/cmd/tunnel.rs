@@ -0,0 +1,50 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use anyhow::Context;
+use anyhow::Result;
+use argh::FromArgs;
+use futures::executor::block_on;
+use lium::dut::SshInfo;
+use tracing::info;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// forward a local port to a port on a DUT or a locally-launched crosvm
+/// guest, the same role `ssh -L` plays today but without having to craft
+/// the invocation by hand for every target
+#[argh(subcommand, name = "tunnel")]
+pub struct Args {
+    /// the DUT to reach (hostname/SSH alias, as accepted everywhere else
+    /// `--dut` is), or a crosvm guest already reachable at e.g.
+    /// `127.0.0.1:<port>` as printed by `cro3 vm start`
+    #[argh(positional)]
+    target: String,
+
+    /// port the target service listens on
+    #[argh(positional)]
+    remote_port: u16,
+
+    /// local port to listen on (defaults to the same as --remote-port)
+    #[argh(option)]
+    local_port: Option<u16>,
+}
+
+#[tracing::instrument(level = "trace")]
+pub fn run(args: &Args) -> Result<()> {
+    let ssh = SshInfo::new(&args.target)?;
+    let local_port = args.local_port.unwrap_or(args.remote_port);
+
+    info!(
+        "Forwarding 127.0.0.1:{local_port} -> {}:{} on {}. Press Ctrl-C to stop.",
+        "127.0.0.1", args.remote_port, args.target
+    );
+    // `-L` multiplexes every concurrent connection to the local port over
+    // this one already-authenticated SSH channel, so there's nothing extra
+    // to do here to support more than one client at a time.
+    let forward = ssh.start_port_forwarding(local_port, args.remote_port, "sleep infinity")?;
+    block_on(forward.status()).context("Failed to wait for the tunnel")?;
+    Ok(())
+}
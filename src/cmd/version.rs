@@ -6,16 +6,48 @@
 
 use anyhow::Result;
 use argh::FromArgs;
+use lium::dut::DISCOVER_PROTOCOL_VERSION;
+use lium::shell::OutputFormat;
+use lium::shell::Shell;
+use serde::Serialize;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// display version info
 #[argh(subcommand, name = "version")]
-pub struct Args {}
+pub struct Args {
+    /// print a machine-readable capabilities record instead of the plain
+    /// version string, so callers like `dut discover --remote` can
+    /// negotiate protocol compatibility before trusting this binary's
+    /// output.
+    #[argh(switch)]
+    json: bool,
+}
+
+/// The generic `--json`/`-format json` envelope's `data` payload for
+/// `version`, as opposed to `--json`'s capabilities record above (that one
+/// is a stable negotiation protocol on its own; this is just this
+/// command's bit of the shared envelope).
+#[derive(Serialize)]
+struct VersionInfo {
+    name: &'static str,
+    version: &'static str,
+}
 
 #[tracing::instrument(level = "trace")]
-pub fn run(_args: &Args) -> Result<()> {
-    println!("lium v{VERSION}");
+pub fn run(args: &Args) -> Result<()> {
+    if args.json {
+        let capabilities = serde_json::json!({
+            "version": VERSION,
+            "discover_protocol_version": DISCOVER_PROTOCOL_VERSION,
+        });
+        println!("{}", serde_json::to_string(&capabilities)?);
+    } else if Shell::lock().format() == OutputFormat::Json {
+        let info = VersionInfo { name: "lium", version: VERSION };
+        Shell::lock().print_envelope("version", true, &info);
+    } else {
+        lium::sh_println!("lium v{VERSION}");
+    }
     Ok(())
 }
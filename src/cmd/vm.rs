@@ -6,15 +6,20 @@
 
 use std::env;
 use std::path::Path;
+use std::process::Child;
 use std::process::Command;
+use std::process::Stdio;
 use std::string::ToString;
+use std::time::Instant;
 
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use argh::FromArgs;
+use lium::cache::KvCache;
 use lium::config::Config;
+use lium::dut::SshInfo;
 use lium::util::shell_helpers::run_bash_command;
 use regex_macro::regex;
 use strum_macros::Display;
@@ -22,6 +27,12 @@ use tracing::error;
 use tracing::info;
 use whoami;
 
+static ANDROID_BUILD_ID_CACHE: KvCache<String> = KvCache::new("android_build_id_cache");
+
+/// The localhost port betty.sh forwards the guest's SSH to, the same port
+/// `lium dut shell --dut localhost:9222` connects to after `vm start`.
+const BETTY_SSH_PORT: u16 = 9222;
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// create a virtual machine
 #[argh(subcommand, name = "vm")]
@@ -38,6 +49,9 @@ enum SubCommand {
 
     #[strum(serialize = "start")]
     Start(ArgsStart),
+
+    #[strum(serialize = "test")]
+    Test(ArgsTest),
 }
 
 #[tracing::instrument(level = "trace")]
@@ -50,6 +64,7 @@ pub fn run(args: &Args) -> Result<()> {
     match &args.nested {
         SubCommand::Setup(args) => run_setup(args),
         SubCommand::Start(args) => run_start(args),
+        SubCommand::Test(args) => run_test(args),
     }
 }
 
@@ -66,80 +81,223 @@ pub struct ArgsSetup {
     /// --extra-args "options".
     #[argh(option)]
     extra_args: Option<String>,
-}
 
-fn run_setup(args: &ArgsSetup) -> Result<()> {
-    let dir = find_betty_script(&args.arc)?;
+    /// re-run every step even if it looks already satisfied. It is false by
+    /// default.
+    #[argh(switch)]
+    force: bool,
 
-    info!("Updating packages...");
-    let mut update_package = Command::new("sudo")
-        .args(["apt", "update"])
-        .spawn()
-        .context("Failed to execute sudo apt update")?;
-    update_package
-        .wait()
-        .context("Failed to wait for updating packages")?;
+    /// comma-separated list of steps to skip (--list-steps to see available
+    /// options).
+    #[argh(option)]
+    skip: Option<String>,
 
-    info!("Enabling KVM...");
-    enable_kvm()?;
+    /// list available setup steps and exit.
+    #[argh(switch)]
+    list_steps: bool,
 
-    info!("Installing python packages...");
-    let mut install_python_package = Command::new("sudo")
-        .args(["apt", "install", "python3-pip", "python3-venv"])
-        .spawn()
-        .context("Failed to install python packages")?;
-    install_python_package
-        .wait()
-        .context("Failed to wait for installing python packages")?;
+    /// steps to run (--list-steps to see available options). runs all
+    /// steps by default.
+    #[argh(positional)]
+    only: Vec<String>,
+}
 
-    info!("Running betty.sh setup...");
-    let options = args.extra_args.clone().unwrap_or_else(|| String::from(""));
-    run_betty_cmd(&dir, SubCommand::Setup(args.clone()), &[&options])?;
+/// List of named steps `vm setup` can run. Each is independently
+/// idempotency-checked and selectable, mirroring `dut verify`'s
+/// `VERIFY_PROBES`.
+const SETUP_STEPS: [&str; 4] = ["packages", "kvm", "betty", "gcloud_auth"];
 
-    info!("Running gcloud auth login...");
-    let mut gcloud_auth = Command::new("gcloud")
-        .args(["auth", "login"])
-        .spawn()
-        .context("Failed to run gcloud login gcloud")?;
-    gcloud_auth
-        .wait()
-        .context("Failed to wait for gcloud auth login")?;
+fn run_setup(args: &ArgsSetup) -> Result<()> {
+    if args.list_steps {
+        println!("{}", SETUP_STEPS.join(" "));
+        return Ok(());
+    }
+    let steps: Vec<&str> = if args.only.is_empty() {
+        SETUP_STEPS.to_vec()
+    } else {
+        args.only.iter().map(String::as_str).collect()
+    };
+    let unknown: Vec<&&str> = steps.iter().filter(|s| !SETUP_STEPS.contains(s)).collect();
+    if !unknown.is_empty() {
+        bail!("Unknown step: {unknown:?}. See `lium vm setup --list-steps`.");
+    }
+    let skip: Vec<&str> = args
+        .skip
+        .as_deref()
+        .map(|s| s.split(',').collect())
+        .unwrap_or_default();
+
+    let dir = find_betty_script(&args.arc)?;
+    for &step in &steps {
+        if skip.contains(&step) {
+            info!("Skipping {step:?} (--skip)");
+            continue;
+        }
+        if !args.force && is_setup_step_done(step, args).unwrap_or(false) {
+            info!("{step:?} already done, skipping (use --force to re-run)");
+            continue;
+        }
+        info!("Running {step:?}...");
+        run_setup_step(step, args, &dir)?;
+    }
 
     Ok(())
 }
 
+/// Probes whether `step` is already satisfied, so `run_setup` can skip it
+/// instead of re-installing packages or re-prompting for auth every time.
+/// Errors (e.g. the probe command itself isn't installed yet) are treated
+/// as "not done" by the caller rather than failing setup outright.
+fn is_setup_step_done(step: &str, _args: &ArgsSetup) -> Result<bool> {
+    match step {
+        "packages" => Ok(run_bash_command("dpkg -s python3-pip python3-venv", None)?
+            .status
+            .success()),
+        "kvm" => {
+            let username = whoami::username();
+            Ok(run_bash_command(
+                &format!(
+                    "[[ -e /dev/kvm ]] && id -nG {username} | grep -qw kvm && getfacl /dev/kvm \
+                     2>/dev/null | grep -q \"user:{username}:rw\""
+                ),
+                None,
+            )?
+            .status
+            .success())
+        }
+        // betty.sh's own setup doesn't expose a cheap readiness signal, so
+        // it's always (re-)run unless explicitly --skip'd.
+        "betty" => Ok(false),
+        "gcloud_auth" => {
+            let config = Config::read()?;
+            match config.is_internal_auth_valid() {
+                Ok(cmd) => Ok(run_bash_command(&cmd, None)?.status.success()),
+                Err(_) => Ok(false),
+            }
+        }
+        _ => unreachable!("unknown setup step {step:?}"),
+    }
+}
+
+fn run_setup_step(step: &str, args: &ArgsSetup, dir: &str) -> Result<()> {
+    match step {
+        "packages" => {
+            info!("Updating packages...");
+            let mut update_package = Command::new("sudo")
+                .args(["apt", "update"])
+                .spawn()
+                .context("Failed to execute sudo apt update")?;
+            update_package
+                .wait()
+                .context("Failed to wait for updating packages")?;
+
+            info!("Installing python packages...");
+            let mut install_python_package = Command::new("sudo")
+                .args(["apt", "install", "python3-pip", "python3-venv"])
+                .spawn()
+                .context("Failed to install python packages")?;
+            install_python_package
+                .wait()
+                .context("Failed to wait for installing python packages")?;
+            Ok(())
+        }
+        "kvm" => {
+            info!("Enabling KVM...");
+            enable_kvm()
+        }
+        "betty" => {
+            info!("Running betty.sh setup...");
+            let options = args.extra_args.clone().unwrap_or_else(|| String::from(""));
+            run_betty_cmd(dir, SubCommand::Setup(args.clone()), &[&options])
+        }
+        "gcloud_auth" => {
+            info!("Running gcloud auth login...");
+            let mut gcloud_auth = Command::new("gcloud")
+                .args(["auth", "login"])
+                .spawn()
+                .context("Failed to run gcloud login gcloud")?;
+            gcloud_auth
+                .wait()
+                .context("Failed to wait for gcloud auth login")?;
+            Ok(())
+        }
+        _ => unreachable!("unknown setup step {step:?}"),
+    }
+}
+
+/// The host CPU architecture, as needed to pick qemu's package name and
+/// how to detect/enable virtualization extensions. `uname -m` reports
+/// `aarch64` for 64-bit ARM; normalized here the same way
+/// `SshInfo::get_arch` normalizes a DUT's `uname -m`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum HostArch {
+    X86_64,
+    Arm64,
+}
+fn host_arch() -> Result<HostArch> {
+    let uname = run_bash_command("uname -m", None)?;
+    uname.status.exit_ok().context("Failed to run `uname -m`")?;
+    match String::from_utf8(uname.stdout)?.trim() {
+        "aarch64" | "arm64" => Ok(HostArch::Arm64),
+        _ => Ok(HostArch::X86_64),
+    }
+}
+
 fn enable_kvm() -> Result<()> {
+    let arch = host_arch()?;
+
     info!("Installing kvm support...");
+    let qemu_package = match arch {
+        HostArch::X86_64 => "qemu-system-x86",
+        HostArch::Arm64 => "qemu-system-arm",
+    };
     let mut install_kvm_support = Command::new("sudo")
-        .args(["apt-get", "install", "qemu-system-x86"])
+        .args(["apt-get", "install", qemu_package])
         .spawn()
         .context("Failed to install kvm support")?;
     install_kvm_support
         .wait()
         .context("Failed to wait for installing kvm support")?;
 
-    let is_intel = run_bash_command("grep vmx /proc/cpuinfo", None)?
-        .status
-        .success();
-    let is_amd = run_bash_command("grep svm /proc/cpuinfo", None)?
-        .status
-        .success();
-    let module = if is_intel {
-        "kvm-intel"
-    } else if is_amd {
-        "kvm-amd"
-    } else {
-        bail!("Your system does not have virtualization extensions.");
-    };
-
-    info!("Loading Kernel modules...");
-    let mut load_kernel_module = Command::new("sudo")
-        .args(["modprobe", module])
-        .spawn()
-        .context("Failed to load kernel modules")?;
-    load_kernel_module
-        .wait()
-        .context("Failed to wait for loading kernel modules")?;
+    match arch {
+        HostArch::X86_64 => {
+            let is_intel = run_bash_command("grep vmx /proc/cpuinfo", None)?
+                .status
+                .success();
+            let is_amd = run_bash_command("grep svm /proc/cpuinfo", None)?
+                .status
+                .success();
+            let module = if is_intel {
+                "kvm-intel"
+            } else if is_amd {
+                "kvm-amd"
+            } else {
+                bail!("Your system does not have virtualization extensions.");
+            };
+
+            info!("Loading Kernel modules...");
+            let mut load_kernel_module = Command::new("sudo")
+                .args(["modprobe", module])
+                .spawn()
+                .context("Failed to load kernel modules")?;
+            load_kernel_module
+                .wait()
+                .context("Failed to wait for loading kernel modules")?;
+        }
+        HostArch::Arm64 => {
+            // There's no kvm-intel/kvm-amd split on ARM: virtualization
+            // (EL2) support is either built into the SoC/kernel or it
+            // isn't, and the single `kvm` module covers it.
+            info!("Loading the kvm module...");
+            let mut load_kernel_module = Command::new("sudo")
+                .args(["modprobe", "kvm"])
+                .spawn()
+                .context("Failed to load the kvm kernel module")?;
+            load_kernel_module
+                .wait()
+                .context("Failed to wait for loading the kvm kernel module")?;
+        }
+    }
 
     let username = whoami::username();
     info!("Adding the user to the kvm local group...");
@@ -151,12 +309,18 @@ fn enable_kvm() -> Result<()> {
         .wait()
         .context("Failed to wait for adding the user to the kvm local group")?;
 
-    let is_kvm_enabled = run_bash_command(
-        "[[ -e /dev/kvm ]] && grep '^flags' /proc/cpuinfo | grep -qE 'vmx|svm'",
-        None,
-    )?
-    .status
-    .success();
+    let is_kvm_enabled = match arch {
+        // vmx/svm are x86-only cpuinfo flags; on ARM, the kernel only
+        // exposes /dev/kvm at all once EL2 and CONFIG_KVM are both
+        // satisfied, so its presence is the check.
+        HostArch::X86_64 => run_bash_command(
+            "[[ -e /dev/kvm ]] && grep '^flags' /proc/cpuinfo | grep -qE 'vmx|svm'",
+            None,
+        )?
+        .status
+        .success(),
+        HostArch::Arm64 => Path::new("/dev/kvm").exists(),
+    };
 
     if !is_kvm_enabled {
         bail!("KVM did not enable correctly");
@@ -184,7 +348,10 @@ pub struct ArgsStart {
     arc: Option<String>,
 
     /// for betty.sh. The BOARD to run (e.g. betty-pi-arc). It is required when
-    /// you launch a local VM instance.
+    /// you launch a local VM instance. An arm64 board (e.g. betty-arm64)
+    /// and its --version/--vm_image are forwarded to betty.sh the same way
+    /// as an x86 one; only the host-side `vm setup` (enable_kvm) needs to
+    /// know the host's architecture.
     #[argh(option)]
     board: Option<String>,
 
@@ -205,11 +372,29 @@ pub struct ArgsStart {
     version: Option<String>,
 
     /// for betty.sh. Path to betty VM image to start. It has priority over
-    /// --board and --version (they will be ignored)
+    /// --board and --version (they will be ignored). For --backend crosvm,
+    /// this is the rootfs/disk image passed to `crosvm run --rwdisk` and is
+    /// required.
     #[argh(option)]
     vm_image: Option<String>,
 
-    /// for acloudw. Launch a cloud based VM instance. It is false by default.  
+    /// which backend launches the guest: `betty` (default, shells out to
+    /// betty.sh, requires an internal android checkout) or `crosvm` (runs a
+    /// locally built or prebuilt `crosvm` binary directly, no android
+    /// checkout needed).
+    #[argh(option, default = "VmBackendKind::Betty")]
+    backend: VmBackendKind,
+
+    /// for --backend crosvm. Path to the kernel image to boot. Required
+    /// when using --backend crosvm.
+    #[argh(option)]
+    kernel: Option<String>,
+
+    /// for --backend crosvm. Path to the KVM device node.
+    #[argh(option, default = "String::from(\"/dev/kvm\")")]
+    kvm_device: String,
+
+    /// for acloudw. Launch a cloud based VM instance. It is false by default.
     #[argh(switch)]
     acloud: bool,
 
@@ -237,15 +422,294 @@ fn run_start(args: &ArgsStart) -> Result<()> {
     if args.acloud {
         run_acloudw(args)?;
     } else {
-        run_betty_start(args)?;
-
-        println!("To connect the betty instance, run `lium dut shell --dut localhost:9222`.");
+        let backend: Box<dyn VmBackend> = match args.backend {
+            VmBackendKind::Betty => Box::new(BettyBackend { args }),
+            VmBackendKind::Crosvm => Box::new(CrosvmBackend { args }),
+        };
+        backend.setup()?;
+        backend.start()?;
+
+        println!("To connect the instance, run `lium dut shell --dut localhost:9222`.");
         println!("To push an Android build a betty VM, run `lium arc flash`.");
     }
 
     Ok(())
 }
 
+/// Which backend `vm start` drove, spelled out so `--backend` takes a
+/// readable name on the command line instead of a raw boolean.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum VmBackendKind {
+    Betty,
+    Crosvm,
+}
+impl std::str::FromStr for VmBackendKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "betty" => Ok(Self::Betty),
+            "crosvm" => Ok(Self::Crosvm),
+            _ => bail!("Unknown --backend {s:?}, expected `betty` or `crosvm`"),
+        }
+    }
+}
+
+/// A way to launch the guest for `vm start`, so betty.sh and a native
+/// `crosvm` invocation are interchangeable behind the same `ArgsStart`.
+trait VmBackend {
+    /// Checks the backend's prerequisites (the betty.sh checkout, or the
+    /// `crosvm` binary and KVM device) before attempting to start.
+    fn setup(&self) -> Result<()>;
+    /// Launches the guest and blocks for as long as it's running.
+    fn start(&self) -> Result<()>;
+}
+
+struct BettyBackend<'a> {
+    args: &'a ArgsStart,
+}
+impl VmBackend for BettyBackend<'_> {
+    fn setup(&self) -> Result<()> {
+        find_betty_script(&self.args.arc).map(|_| ())
+    }
+    fn start(&self) -> Result<()> {
+        run_betty_start(self.args)
+    }
+}
+
+struct CrosvmBackend<'a> {
+    args: &'a ArgsStart,
+}
+impl VmBackend for CrosvmBackend<'_> {
+    fn setup(&self) -> Result<()> {
+        let which_crosvm = run_bash_command("which crosvm", None)?;
+        which_crosvm
+            .status
+            .exit_ok()
+            .context("crosvm was not found in PATH; build or install it first")?;
+        if !Path::new(&self.args.kvm_device).exists() {
+            bail!(
+                "{} does not exist; is KVM available on this host?",
+                self.args.kvm_device
+            );
+        }
+        Ok(())
+    }
+    /// Boots `crosvm` directly from the disk/kernel/KVM-device args,
+    /// forwarding the guest's SSH to the same `localhost:9222` betty.sh
+    /// uses so `lium dut shell --dut localhost:9222` works unchanged, then
+    /// waits for the guest to answer SSH before handing control back to
+    /// the (still-running) crosvm process in the foreground.
+    fn start(&self) -> Result<()> {
+        let vm_image = self
+            .args
+            .vm_image
+            .as_deref()
+            .context("--vm-image is required when using --backend crosvm")?;
+        let kernel = self
+            .args
+            .kernel
+            .as_deref()
+            .context("--kernel is required when using --backend crosvm")?;
+
+        info!("Launching crosvm (kernel={kernel}, disk={vm_image})...");
+        let mut child = Command::new("crosvm")
+            .arg("run")
+            .arg("--disable-sandbox")
+            .arg("--rwdisk")
+            .arg(vm_image)
+            .arg("--net")
+            .arg(format!(
+                "host-ip=10.0.2.1,netmask=255.255.255.0,tap-fd=none,ssh-forward={BETTY_SSH_PORT}"
+            ))
+            .arg("--kvm-device")
+            .arg(&self.args.kvm_device)
+            .arg("-p")
+            .arg("root=/dev/vda3")
+            .arg(kernel)
+            .spawn()
+            .context("Failed to spawn crosvm; is it installed and is KVM available?")?;
+
+        SshInfo::new_host_and_port("127.0.0.1", BETTY_SSH_PORT)?
+            .wait_online()
+            .context("The crosvm guest never came up over SSH")?;
+        info!("crosvm guest is reachable over SSH");
+
+        let result = child.wait().context("Failed to wait for crosvm")?;
+        if !result.success() {
+            error!("crosvm exited with {result:?}")
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, FromArgs, PartialEq, Debug)]
+/// boot a matrix of images and run a command in each, reporting pass/fail
+#[argh(subcommand, name = "test")]
+pub struct ArgsTest {
+    /// for betty.sh. Path to the android source checkout. If omitted, current
+    /// directory will be used.
+    #[argh(option)]
+    arc: Option<String>,
+
+    /// for betty.sh. The BOARD to run (e.g. betty-pi-arc).
+    #[argh(option)]
+    board: String,
+
+    /// for betty.sh. Reuse the VM image. It is true by default. If you want to
+    /// disable it, use `--reuse-disk-image false`.
+    #[argh(option, default = "true")]
+    reuse_disk_image: bool,
+
+    /// for betty.sh. Start betty with rootfs verification. It is false by
+    /// default.
+    #[argh(switch)]
+    enable_rootfs_verification: bool,
+
+    /// command to run inside each guest over SSH once it comes up.
+    #[argh(option)]
+    cmd: String,
+
+    /// stop testing the matrix as soon as one target fails. It is false by
+    /// default.
+    #[argh(switch)]
+    fail_fast: bool,
+
+    /// print the summary as JSON instead of a human-readable table.
+    #[argh(switch)]
+    json: bool,
+
+    /// a ChromeOS `--version` (e.g. R72-11268.0.0) or a path to a VM image
+    /// to boot. Repeat to test a matrix of multiple images.
+    #[argh(positional)]
+    targets: Vec<String>,
+}
+
+/// The result of booting a single `vm test` target and running the
+/// configured command in it, mirroring `ProbeReport` in `cmd::dut`.
+#[derive(Debug, Clone, serde::Serialize)]
+struct TargetReport {
+    target: String,
+    passed: bool,
+    detail: String,
+}
+fn target_report(target: &str, result: Result<String>) -> TargetReport {
+    match result {
+        Ok(detail) => TargetReport {
+            target: target.to_string(),
+            passed: true,
+            detail,
+        },
+        Err(e) => TargetReport {
+            target: target.to_string(),
+            passed: false,
+            detail: format!("{e:#}"),
+        },
+    }
+}
+
+fn run_test(args: &ArgsTest) -> Result<()> {
+    if args.targets.is_empty() {
+        bail!("Specify at least one --version or VM image path to test");
+    }
+
+    let mut reports = Vec::new();
+    for target in &args.targets {
+        info!("Testing {target}...");
+        let report = target_report(target, test_one_target(args, target));
+        let passed = report.passed;
+        reports.push(report);
+        if !passed && args.fail_fast {
+            info!("--fail-fast is set, stopping the matrix early");
+            break;
+        }
+    }
+    let all_passed = reports.iter().all(|r| r.passed);
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::json!({"board": args.board, "passed": all_passed, "targets": reports})
+        );
+    } else {
+        for r in &reports {
+            println!(
+                "{}: {:<24} {}",
+                if r.passed { "PASS" } else { "FAIL" },
+                r.target,
+                r.detail
+            );
+        }
+    }
+
+    if !all_passed {
+        bail!("One or more targets failed");
+    }
+    Ok(())
+}
+
+/// Boots `target` (a `--version` or an explicit image path, same
+/// precedence as `run_betty_start`), runs `args.cmd` over SSH once the
+/// guest is reachable, and tears the VM down before returning.
+fn test_one_target(args: &ArgsTest, target: &str) -> Result<String> {
+    let dir = find_betty_script(&args.arc)?;
+    let is_image_path = Path::new(target).exists();
+    let options = betty_image_options(
+        &args.board,
+        args.reuse_disk_image,
+        args.enable_rootfs_verification,
+        (!is_image_path).then_some(target),
+        is_image_path.then_some(target),
+    );
+    let options: Vec<&str> = options.iter().map(String::as_str).collect();
+
+    let started_at = Instant::now();
+    let vm = BettyVm::start(&dir, &options)?;
+    let ssh = vm.ssh()?;
+    ssh.wait_online().context("The VM never came up over SSH")?;
+    let (status, stdout, stderr) = ssh.run_cmd_captured(&[&args.cmd])?;
+    info!("{target} ran `{}` in {:?}", args.cmd, started_at.elapsed());
+    if status != 0 {
+        bail!("`{}` exited with status {status}: {stderr}", args.cmd);
+    }
+    Ok(stdout)
+}
+
+/// A headless `betty.sh start` instance, kept around only long enough to
+/// run one command over SSH. Unlike `run_betty_start` (which hands the
+/// instance to the user to connect to interactively), `vm test` needs to
+/// boot, run, and tear down automatically between matrix targets, so this
+/// spawns betty.sh in the background instead of waiting on it and kills it
+/// on drop, the same shape as `LocalVm` in `lium::vm` for the native
+/// crosvm backend.
+struct BettyVm {
+    child: Child,
+}
+impl BettyVm {
+    fn start(dir: &str, opts: &[&str]) -> Result<Self> {
+        let betty_cmd = format!("./betty.sh start {}", opts.join(" "));
+        info!("Running `{betty_cmd}` in the background...");
+        let child = Command::new("bash")
+            .current_dir(dir)
+            .arg("-c")
+            .arg(betty_cmd)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to execute betty.sh")?;
+        Ok(Self { child })
+    }
+    fn ssh(&self) -> Result<SshInfo> {
+        SshInfo::new_host_and_port("127.0.0.1", BETTY_SSH_PORT)
+    }
+}
+impl Drop for BettyVm {
+    fn drop(&mut self) {
+        info!("Shutting down the betty VM (pid {})...", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
 fn run_acloudw(args: &ArgsStart) -> Result<()> {
     let branch = args
         .branch
@@ -253,17 +717,21 @@ fn run_acloudw(args: &ArgsStart) -> Result<()> {
         .ok_or(anyhow!("--branch option is required when using acloudw"))?;
     let git_branch = format!("git_{branch}");
 
-    // b/314731302 use the Android API to get this programmatically if not specified
-    let build_id = args
-        .build_id
-        .clone()
-        .ok_or(anyhow!("--build-id option is required when using acloudw"))?;
-    let re = regex!(r"^\d+$");
-    if !re.is_match(&build_id) {
-        bail!("--build-id must be a digit.");
-    }
-
     let config = Config::read()?;
+    let target = get_target_name(&config, args.container, &branch)?;
+
+    // b/314731302 resolve the latest build ID via the Android Build API when
+    // --build-id is omitted or explicitly set to "latest".
+    let build_id = match args.build_id.as_deref() {
+        Some(build_id) if build_id != "latest" => {
+            let re = regex!(r"^\d+$");
+            if !re.is_match(build_id) {
+                bail!("--build-id must be a digit.");
+            }
+            build_id.to_string()
+        }
+        _ => resolve_latest_android_build_id(&config, &git_branch, &target)?,
+    };
 
     let cmd_path = config.acloudw_cmd_path().context(
         "Config acloudw_cmd_path is required when using acloudw. For internal users, please \
@@ -273,7 +741,6 @@ fn run_acloudw(args: &ArgsStart) -> Result<()> {
         "Config acloudw_config_path is required when using acloudw. For internal users, please \
          configure path to acloudw config file",
     )?;
-    let target = get_target_name(&config, args.container, &branch)?;
     let cheeps = get_cheeps_image_name(&config, args.container, &branch)?;
     let betty = get_betty_image_name(&config, args.container, &branch)?;
 
@@ -301,6 +768,34 @@ fn run_acloudw(args: &ArgsStart) -> Result<()> {
     run_acloudw_cmd(&options)
 }
 
+/// Queries the Android Build API for the latest build ID of `git_branch`/
+/// `target` and caches the result, so repeated `vm start --acloudw` runs in
+/// the same build don't hit the API again. For internal users, configure
+/// `android_build_api_cmd` to a command that prints the build ID given
+/// `{branch}` and `{target}` placeholders.
+fn resolve_latest_android_build_id(config: &Config, git_branch: &str, target: &str) -> Result<String> {
+    let cmd_template = config.android_build_api_cmd().context(
+        "Config android_build_api_cmd is required to resolve the latest --build-id. For \
+         internal users, please configure a command that queries the Android Build API",
+    )?;
+    let cmd = cmd_template
+        .replace("{branch}", git_branch)
+        .replace("{target}", target);
+    let cache_key = format!("{git_branch}:{target}");
+    let build_id = ANDROID_BUILD_ID_CACHE.get_or_else(&cache_key, &|_| {
+        let output = run_bash_command(&cmd, None)?;
+        let build_id = String::from_utf8(output.stdout)?.trim().to_string();
+        let re = regex!(r"^\d+$");
+        if !re.is_match(&build_id) {
+            bail!("android_build_api_cmd did not print a digit build ID, got: {build_id}");
+        }
+        Ok(build_id)
+    })?;
+    info!("Resolved the latest build for {git_branch}/{target} to build ID {build_id}");
+
+    Ok(build_id)
+}
+
 fn get_target_name(config: &Config, is_container: bool, branch: &str) -> Result<String> {
     let vm_type = if branch.contains("main") {
         "main"
@@ -387,6 +882,41 @@ fn run_acloudw_cmd(opts: &[&str]) -> Result<()> {
     Ok(())
 }
 
+/// Builds the `betty.sh` image-selection/reuse flags shared by `vm start`
+/// and `vm test`: which board to boot, whether to keep or reset the
+/// per-board/version disk image betty.sh caches, rootfs verification, and
+/// the `--release`/`--vm_image` pair (a literal image path takes priority
+/// over a `--version`, mirroring betty.sh's own precedence).
+fn betty_image_options(
+    board: &str,
+    reuse_disk_image: bool,
+    enable_rootfs_verification: bool,
+    version: Option<&str>,
+    vm_image: Option<&str>,
+) -> Vec<String> {
+    let mut options = vec![
+        "--board".to_string(),
+        board.to_string(),
+        "--display".to_string(),
+        "none".to_string(),
+    ];
+
+    if !reuse_disk_image {
+        options.push("--reset_image".to_string());
+    }
+    if enable_rootfs_verification {
+        options.push("--nodisbple_rootfs".to_string());
+    }
+    if let Some(version) = version {
+        options.extend([String::from("--release"), version.to_string()]);
+    }
+    if let Some(vm_image) = vm_image {
+        options.extend([String::from("--vm_image"), vm_image.to_string()]);
+    }
+
+    options
+}
+
 fn run_betty_start(args: &ArgsStart) -> Result<()> {
     let dir = find_betty_script(&args.arc)?;
 
@@ -395,23 +925,17 @@ fn run_betty_start(args: &ArgsStart) -> Result<()> {
         .clone()
         .ok_or(anyhow!("--board option is required when using betty.sh"))?;
 
-    let mut options = vec!["--board", &board, "--display", "none"];
-
-    if !args.reuse_disk_image {
-        options.extend_from_slice(&["--reset_image"]);
-    }
-    if args.enable_rootfs_verification {
-        options.extend_from_slice(&["--nodisbple_rootfs"]);
-    }
-    if let Some(version) = &args.version {
-        options.extend_from_slice(&["--release", version]);
-    }
-    if let Some(vm_image) = &args.vm_image {
-        options.extend_from_slice(&["--vm_image", vm_image]);
-    }
+    let mut options = betty_image_options(
+        &board,
+        args.reuse_disk_image,
+        args.enable_rootfs_verification,
+        args.version.as_deref(),
+        args.vm_image.as_deref(),
+    );
     if let Some(extra_args) = &args.extra_args {
-        options.extend_from_slice(&[extra_args]);
+        options.push(extra_args.clone());
     }
+    let options: Vec<&str> = options.iter().map(String::as_str).collect();
 
     run_betty_cmd(&dir, SubCommand::Start(args.clone()), &options)
 }
@@ -5,23 +5,35 @@
 // https://developers.google.com/open-source/licenses/bsd
 
 use std::collections::HashMap;
+use std::env;
+use std::fs::read_dir;
 use std::fs::read_to_string;
+use std::fs::remove_file;
 use std::fs::write;
+use std::fs::OpenOptions;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use fs2::FileExt;
 use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
+use strum::IntoEnumIterator;
 use strum::ParseError;
 use strum_macros::EnumIter;
 use strum_macros::EnumString;
 use tracing::warn;
 
-use crate::util::cro3_paths::gen_path_in_cro3_dir;
 use crate::util::shell_helpers::run_bash_command;
+use crate::util::xdg_dirs::config_dir_in_lium_dir;
+use crate::util::xdg_dirs::config_path_in_lium_dir;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SshOverride {
@@ -31,6 +43,29 @@ pub struct SshOverride {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
     ssh_options: Vec<String>,
+    /// Comma-separated list passed as `-o Ciphers=...`, for DUTs whose SSH
+    /// server only offers legacy ciphers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    ciphers: Option<String>,
+    /// Comma-separated list passed as `-o KexAlgorithms=...`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    kex_algorithms: Option<String>,
+    /// Comma-separated list passed as `-o MACs=...`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    macs: Option<String>,
+    /// Comma-separated list passed as `-o HostKeyAlgorithms=...`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    host_key_algorithms: Option<String>,
+    /// A bastion/jump host to reach this DUT through, e.g. `user@bastion`
+    /// or `user@bastion:2222`, passed straight to `ssh -J`. Unset by
+    /// default, which reaches the DUT directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    jump_host: Option<String>,
 }
 impl SshOverride {
     pub fn is_match_condition(&self) -> Result<bool> {
@@ -44,7 +79,77 @@ impl SshOverride {
     pub fn ssh_options(&self) -> &Vec<String> {
         &self.ssh_options
     }
+    pub fn ciphers(&self) -> Option<&str> {
+        self.ciphers.as_deref()
+    }
+    pub fn kex_algorithms(&self) -> Option<&str> {
+        self.kex_algorithms.as_deref()
+    }
+    pub fn macs(&self) -> Option<&str> {
+        self.macs.as_deref()
+    }
+    pub fn host_key_algorithms(&self) -> Option<&str> {
+        self.host_key_algorithms.as_deref()
+    }
+    pub fn jump_host(&self) -> Option<&str> {
+        self.jump_host.as_deref()
+    }
+    /// Translates `jump_host` into the `-J jump_host` pair `ssh`/`scp`
+    /// expect, chaining the same `testing_rsa`/`BatchMode` options through
+    /// the jump since `ssh -J` reuses this process's own identity/options
+    /// for the hop rather than the destination's `~/.ssh/config` (which
+    /// `COMMON_SSH_OPTIONS` already opts out of via `-F none`).
+    pub fn jump_ssh_options(&self) -> Vec<String> {
+        match &self.jump_host {
+            Some(jump_host) => vec!["-J".to_string(), jump_host.clone()],
+            None => Vec::new(),
+        }
+    }
+    /// Translates the `ciphers`/`kex_algorithms`/`macs`/`host_key_algorithms`
+    /// fields into the `-o Name=value` pairs `ssh`/`scp` expect, to be
+    /// appended alongside [`Self::ssh_options`].
+    pub fn crypto_ssh_options(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, value) in [
+            ("Ciphers", &self.ciphers),
+            ("KexAlgorithms", &self.kex_algorithms),
+            ("MACs", &self.macs),
+            ("HostKeyAlgorithms", &self.host_key_algorithms),
+        ] {
+            if let Some(value) = value {
+                args.push("-o".to_string());
+                args.push(format!("{name}={value}"));
+            }
+        }
+        args
+    }
+}
+
+/// What to do when [`LogMatchRule::pattern`] matches a line of live
+/// stdout/stderr from a running `tast run` invocation (see
+/// `crate::tast::monitor_and_await_tast_execution`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LogMatchAction {
+    /// Abort the run the moment this pattern is seen, e.g. a kernel panic.
+    AbortImmediately,
+    /// Abort once this pattern has matched more than `count` times within a
+    /// trailing window of `window` lines, e.g. a burst of flaky retries
+    /// that's individually tolerable but fatal in aggregate.
+    AbortAfterBurst { count: usize, window: usize },
+    /// Never abort; just count and report how many times this pattern
+    /// matched once the run finishes.
+    Count,
+}
+
+/// A named live-log assertion rule, keyed by name in
+/// [`Config::tast_log_matchers`] so a user rule can override one of the
+/// built-in defaults (e.g. `network_diagnosis`) by reusing its name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogMatchRule {
+    pub pattern: String,
+    pub action: LogMatchAction,
 }
+
 // When adding a new config parameter, add an item in this enum and
 // struct Config.
 #[derive(Debug, PartialEq, EnumIter, EnumString, strum_macros::Display)]
@@ -62,10 +167,27 @@ pub enum ConfigKey {
     IsInternalAuthValid,
     AcloudwCmdPath,
     AcloudwConfigPath,
+    AndroidBuildApiCmd,
     AndroidTargetForVmType,
     ArcVmCheepsImage,
     ArcVmBettyImageForBranch,
     ArcContainerCheepsImageForBranch,
+    RmaAuthEndpoint,
+    ChrootBackend,
+    ChrootContainerRuntime,
+    ChrootContainerImage,
+    SshBackend,
+    TastLogMatchers,
+    MetricsGitRepo,
+    TastCacheBucket,
+    TastCacheEndpoint,
+    TastCacheAccessKeyId,
+    TastCacheSecretAccessKey,
+    Alias,
+    DefaultBoard,
+    DefaultUseFlags,
+    DefaultServoSerial,
+    MdnsDiscoveryMaxAgeSecs,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -114,6 +236,13 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     acloudw_config_path: Option<String>,
+    /// Command that queries the Android Build API for the latest build ID
+    /// of a `git_<branch>`/`--build-target` pair and prints just the build
+    /// ID. It is set by the internal cro3-installer, mirroring
+    /// `is_internal_auth_valid`/`acloudw_cmd_path`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    android_build_api_cmd: Option<String>,
     /// Key: {vm, container, main}, value: Android lunch target. It is set by
     /// the internal cro3-installer.
     #[serde(skip_serializing_if = "HashMap::is_empty")]
@@ -134,29 +263,617 @@ pub struct Config {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     arc_container_cheeps_image_for_branch: HashMap<String, String>,
+    /// Base URL of an authorization endpoint that can exchange a Cr50
+    /// rma_auth challenge for an unlock authcode, used by `servo rma-open`
+    /// instead of prompting the operator interactively. Unset by default,
+    /// which keeps the existing manual/interactive flow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    rma_auth_endpoint: Option<String>,
+    /// Which `cro3::chroot::ExecBackend` `Chroot::new` picks: "cros-sdk"
+    /// (default) or "container". Overridable per-invocation by a
+    /// `--backend` flag where commands expose one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    chroot_backend: Option<String>,
+    /// Container runtime binary for the "container" chroot backend, e.g.
+    /// "podman" or "docker". Defaults to "podman" if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    chroot_container_runtime: Option<String>,
+    /// Image the "container" chroot backend runs generated scripts in.
+    /// Required to use that backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    chroot_container_image: Option<String>,
+    /// Which transport `SshInfo` uses to talk to a DUT: "openssh" (default,
+    /// shells out to the system `ssh`/`scp`) or "native" (an in-process
+    /// `ssh2`/libssh2 session, for hosts without a working scp).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    ssh_backend: Option<String>,
+    /// How long (in seconds) a DUT seen advertising over mDNS is trusted as
+    /// reachable without a fresh advertisement before `connection_state`
+    /// reports it `Disconnected` again. Defaults to 120s if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    mdns_discovery_max_age_secs: Option<u64>,
+    /// Extra/overriding live-log assertion rules applied on top of
+    /// `monitor_and_await_tast_execution`'s built-in defaults, keyed by a
+    /// user-chosen name (reuse a built-in name, e.g. "network_diagnosis",
+    /// to override it).
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    tast_log_matchers: HashMap<String, LogMatchRule>,
+    /// Git repo (URL or local path) `cro3::metrics::record_metrics` mirrors
+    /// the local Tast metrics JSON-lines store into after every `cro3 tast
+    /// analyze` run. Unset by default, which keeps metrics local-only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    metrics_git_repo: Option<String>,
+    /// S3-compatible bucket `cro3 tast build`'s tastpack cache uploads to
+    /// and downloads from, keyed by the content digest of the bundle. Unset
+    /// by default, which keeps `tast build` always re-emerging.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    tast_cache_bucket: Option<String>,
+    /// Endpoint URL for [`Self::tast_cache_bucket`]'s S3-compatible
+    /// storage, passed to the `aws` CLI as `--endpoint-url`. Unset uses
+    /// AWS S3's own default endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    tast_cache_endpoint: Option<String>,
+    /// Access key ID for [`Self::tast_cache_bucket`], exported as
+    /// `AWS_ACCESS_KEY_ID` around each `aws s3` invocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    tast_cache_access_key_id: Option<String>,
+    /// Secret access key for [`Self::tast_cache_bucket`], exported as
+    /// `AWS_SECRET_ACCESS_KEY` around each `aws s3` invocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    tast_cache_secret_access_key: Option<String>,
+    /// User-defined command aliases, keyed by the alias name with the
+    /// expansion (e.g. `"dut shell"` for an alias named `ssh-dut`) as the
+    /// value, the way Cargo resolves `alias.*` entries. Spliced into argv
+    /// in place of the alias name before argument dispatch by
+    /// `expand_aliases`; a name that collides with a built-in subcommand is
+    /// rejected by `set()` rather than silently shadowing it.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    alias: HashMap<String, String>,
+    /// Default `--board` for `cro3 build` when it's omitted, set by `cro3
+    /// config wizard` or `cro3 config set default_board <board>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    default_board: Option<String>,
+    /// Default `--use-flags` for `cro3 build` when it's omitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    default_use_flags: Option<String>,
+    /// Default servo serial, used when a servo command's `--serial` is
+    /// omitted and exactly one isn't otherwise implied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    default_servo_serial: Option<String>,
+    /// Schema version of this persisted document; absent (old files) means
+    /// 0. `Config::read_profile` upgrades older versions via `MIGRATIONS`
+    /// before deserializing into this struct, then stamps the current
+    /// `CONFIG_VERSION` back on write.
+    #[serde(default)]
+    config_version: u32,
+    /// Name of the profile this config was loaded from/will be written back
+    /// to. Not itself part of the persisted document; see
+    /// [`Config::profile_name`].
+    #[serde(skip)]
+    #[serde(default = "default_profile_name")]
+    profile: String,
+    /// When set, `write()` is a no-op; flipped on by [`Config::transaction`]
+    /// so a batch of `set()`/`clear()` calls writes the file exactly once,
+    /// on [`ConfigTransaction::commit`], instead of once per call. Not
+    /// itself part of the persisted document.
+    #[serde(skip)]
+    #[serde(default)]
+    suppress_write: bool,
+    /// When set, `write()` writes here instead of resolving `profile`'s
+    /// usual path -- set by [`Config::read_checkout_layer`] so `config set
+    /// --local` persists to the checkout layer, not the user profile. Not
+    /// itself part of the persisted document.
+    #[serde(skip)]
+    #[serde(default)]
+    write_override_path: Option<PathBuf>,
 }
 static CONFIG_FILE_NAME: &str = "config.json";
+/// System-wide layer, read-only from `cro3`'s point of view (nothing here
+/// ever writes to it): an organization can ship default `tast_bundles`/
+/// `ssh_overrides` to every machine via this file. Optional -- most
+/// installs won't have one.
+static SYSTEM_CONFIG_PATH: &str = "/etc/cro3/config.json";
+/// Directory/file name of the per-checkout config layer `config set
+/// --local` writes to and `Config::read` discovers by walking up from cwd,
+/// analogous to `repo::find_cros_dir_from_cwd`.
+static CHECKOUT_CONFIG_DIR: &str = ".cro3";
+static CHECKOUT_CONFIG_FILE_NAME: &str = "config.json";
+/// Current on-disk schema version. Bump this and append a migration to
+/// `MIGRATIONS` whenever a persisted field is renamed or restructured,
+/// instead of breaking config files written by older `cro3` builds.
+const CONFIG_VERSION: u32 = 1;
+/// Ordered migrations; entry `i` upgrades `value` from version `i` to
+/// version `i + 1`. Applied in order starting from the file's stored
+/// `config_version` (absent == 0) up to `CONFIG_VERSION`.
+static MIGRATIONS: &[fn(&mut Value)] = &[migrate_v0_to_v1];
+/// v0 -> v1: the old flat, top-level `ssh_options: [String]` (one global
+/// list of `"<host_pattern> <opt>=<value> ..."` entries) is replaced by the
+/// structured, per-host `ssh_overrides` map; and `acloud_cmd_path` (a
+/// naming inconsistency with the `is_internal_auth_valid`/
+/// `acloudw_config_path` siblings it's set alongside by the internal
+/// cro3-installer) is renamed to `acloudw_cmd_path`.
+fn migrate_v0_to_v1(value: &mut Value) {
+    let Some(obj) = value.as_object_mut() else {
+        return;
+    };
+    if let Some(Value::Array(options)) = obj.remove("ssh_options") {
+        let overrides = obj
+            .entry("ssh_overrides")
+            .or_insert_with(|| Value::Object(Default::default()));
+        if let Some(overrides) = overrides.as_object_mut() {
+            for entry in options {
+                let Some(entry) = entry.as_str() else {
+                    continue;
+                };
+                let mut parts = entry.split_whitespace();
+                let Some(host_pattern) = parts.next() else {
+                    continue;
+                };
+                let opts: Vec<Value> = parts.map(|s| Value::String(s.to_string())).collect();
+                overrides
+                    .entry(host_pattern.to_string())
+                    .or_insert_with(|| serde_json::json!({ "ssh_options": opts }));
+            }
+        }
+    }
+    if !obj.contains_key("acloudw_cmd_path") {
+        if let Some(old) = obj.remove("acloud_cmd_path") {
+            obj.insert("acloudw_cmd_path".to_string(), old);
+        }
+    }
+}
+/// Recursively merges `overlay` into `base` for [`Config::read`]'s layered
+/// resolution: object overlays merge key-by-key (so map-shaped fields like
+/// `ssh_overrides`/`tast_log_matchers` accumulate entries across layers
+/// instead of one layer's map wholesale replacing another's), a `null`
+/// overlay leaves `base` untouched (an absent layer contributes nothing),
+/// and any other overlay value replaces `base` outright.
+fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (base @ &mut Value::Object(_), Value::Object(overlay)) => {
+            let base = base.as_object_mut().expect("just matched Value::Object");
+            for (key, overlay_value) in overlay {
+                merge_json(base.entry(key).or_insert(Value::Null), overlay_value);
+            }
+        }
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+/// Applies `CRO3_<KEY>` environment variable overrides (e.g.
+/// `CRO3_SSH_PORT_SEARCH_TIMEOUT`) on top of an already layer-merged config
+/// `Value`, for [`Config::read`]. Iterates every [`ConfigKey`] rather than
+/// the env vars actually set, since there's no other way to enumerate which
+/// `CRO3_*` vars are config overrides. Each override is parsed as JSON first
+/// (so e.g. `CRO3_SSH_PORT_SEARCH_TIMEOUT=60` or
+/// `CRO3_TAST_BUNDLES='["a","b"]'` both work), falling back to a plain JSON
+/// string if it doesn't parse as JSON.
+fn apply_env_overrides(value: &mut Value) {
+    let Some(obj) = value.as_object_mut() else {
+        return;
+    };
+    for key in ConfigKey::iter() {
+        let var_name = format!("CRO3_{}", key.to_string().to_uppercase());
+        if let Ok(raw) = env::var(&var_name) {
+            let parsed =
+                serde_json::from_str(&raw).unwrap_or_else(|_| Value::String(raw.clone()));
+            obj.insert(key.to_string(), parsed);
+        }
+    }
+}
+/// Walks up from the current directory looking for `.cro3/config.json`,
+/// analogous to `repo::find_cros_dir_from_cwd`'s search for a CrOS checkout
+/// root, so a config set with `cro3 config set --local` applies anywhere
+/// inside that checkout. Returns `None` (rather than bailing, unlike
+/// `find_cros_dir_from_cwd`) since an absent checkout layer is the common
+/// case, not an error.
+fn find_checkout_config_path() -> Option<PathBuf> {
+    let mut dir = env::current_dir().ok()?;
+    loop {
+        let candidate = dir.join(CHECKOUT_CONFIG_DIR).join(CHECKOUT_CONFIG_FILE_NAME);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+/// Names of every built-in top-level subcommand (kept in sync by hand with
+/// `cmd::Args`'s variants, since nothing here can depend on the `cmd` module
+/// without inverting the crate's dependency direction). `Config::set` and
+/// `Config::expand_aliases` both consult this so a user-defined alias can
+/// never shadow one.
+const BUILTIN_SUBCOMMANDS: &[&str] = &[
+    "arc",
+    "artifact",
+    "build",
+    "cl",
+    "chroot",
+    "config",
+    "daemon",
+    "deploy",
+    "dut",
+    "firmware",
+    "flash",
+    "metrics",
+    "packages",
+    "plugin",
+    "servo",
+    "setup",
+    "setup-sdk",
+    "sync",
+    "tast",
+    "tunnel",
+    "version",
+    "vm",
+];
+/// Bounds [`Config::expand_aliases`] so a self-referential or cyclic alias
+/// (e.g. `foo = "foo --verbose"`) can't expand forever.
+const MAX_ALIAS_EXPANSION_DEPTH: usize = 8;
+/// Name of the profile used when no `--profile`/`CRO3_PROFILE` override and
+/// no persisted `cro3 config profile use` choice apply.
+const DEFAULT_PROFILE_NAME: &str = "default";
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE_NAME.to_string()
+}
+/// Name of the file that remembers the profile `cro3 config profile use`
+/// last selected, so the choice persists across invocations without the
+/// CRO3_PROFILE env var being set every time.
+static ACTIVE_PROFILE_POINTER_FILE: &str = "active_profile";
+/// Maps a profile name to its config file name, keeping the unnamed
+/// "default" profile's file as plain `config.json` for backward
+/// compatibility with configs written before profiles existed.
+fn profile_config_file_name(profile: &str) -> String {
+    if profile == DEFAULT_PROFILE_NAME {
+        CONFIG_FILE_NAME.to_string()
+    } else {
+        format!("config.{profile}.json")
+    }
+}
 impl Config {
+    /// Resolves the active profile name: an explicit CRO3_PROFILE override
+    /// (set by `main.rs` from `--profile`, mirroring LIUM_FORMAT) takes
+    /// priority, then the profile last selected with `cro3 config profile
+    /// use`, then "default".
+    pub fn active_profile_name() -> String {
+        if let Ok(profile) = env::var("CRO3_PROFILE") {
+            if !profile.is_empty() {
+                return profile;
+            }
+        }
+        if let Ok(path) = config_path_in_lium_dir(ACTIVE_PROFILE_POINTER_FILE) {
+            if let Ok(name) = read_to_string(path) {
+                let name = name.trim();
+                if !name.is_empty() {
+                    return name.to_string();
+                }
+            }
+        }
+        DEFAULT_PROFILE_NAME.to_string()
+    }
+    /// Layered config resolution, modeled on Cargo's config model: merges
+    /// the system-wide layer, the active profile's user layer, and a
+    /// per-checkout layer discovered by walking up from cwd, each
+    /// overriding the keys of the one before it, then applies any
+    /// `CRO3_<KEY>` environment variable overrides (e.g.
+    /// `CRO3_SSH_PORT_SEARCH_TIMEOUT`) on top. All accessors (`tast_
+    /// bundles()`, `ssh_overrides()`, etc.) read from this merged view, so
+    /// a checkout can pin e.g. `tast_bundles` without mutating the user's
+    /// global config.
     pub fn read() -> Result<Self> {
-        let path = gen_path_in_cro3_dir(CONFIG_FILE_NAME)?;
+        let profile = Self::active_profile_name();
+        let mut merged = Self::read_layer_value(Path::new(SYSTEM_CONFIG_PATH))?;
+        let user_config = Self::read_profile(&profile)?;
+        merge_json(&mut merged, serde_json::to_value(&user_config)?);
+        if let Some(path) = find_checkout_config_path() {
+            merge_json(&mut merged, Self::read_layer_value(&path)?);
+        }
+        apply_env_overrides(&mut merged);
+        let mut config: Self = serde_json::from_value(merged)?;
+        config.profile = profile;
+        Ok(config)
+    }
+    /// Reads the checkout-local config layer (`.cro3/config.json`, found by
+    /// walking up from cwd -- mirrors `repo::find_cros_dir_from_cwd`'s
+    /// search), or an empty `Config` pointed at where one would be created
+    /// in cwd if none exists yet. The returned `Config` writes back to that
+    /// path (not the user profile) via [`Config::set`]/[`Config::clear`],
+    /// for `cro3 config set --local`.
+    pub fn read_checkout_layer() -> Result<Self> {
+        let path = find_checkout_config_path().unwrap_or_else(|| {
+            PathBuf::from(CHECKOUT_CONFIG_DIR).join(CHECKOUT_CONFIG_FILE_NAME)
+        });
+        let value = Self::read_layer_value(&path)?;
+        let mut config: Self = if value.is_null() {
+            Self::default()
+        } else {
+            serde_json::from_value(value)?
+        };
+        config.write_override_path = Some(path);
+        Ok(config)
+    }
+    /// Reads the named profile's config, creating it (as an empty default
+    /// config) on first use. If `config.json` is present but isn't valid
+    /// JSON (e.g. a write was interrupted mid-way before atomic rename/lock
+    /// was added, or the file was hand-edited badly), transparently falls
+    /// back to the `.bak` copy `write_to_profile` keeps of the last known
+    /// good contents instead of failing outright.
+    pub fn read_profile(profile: &str) -> Result<Self> {
+        let path = config_path_in_lium_dir(&profile_config_file_name(profile))?;
         let config = read_to_string(&path);
-        match config {
-            Ok(config) => Ok(serde_json::from_str(&config)?),
+        let (mut config, migrated): (Self, bool) = match config {
+            Ok(s) => match serde_json::from_str::<Value>(&s) {
+                Ok(value) => Self::upgrade_value(value)?,
+                Err(e) => {
+                    let backup_path = Self::backup_path(&path);
+                    warn!(
+                        "config file {path:?} is corrupt ({e}); falling back to {backup_path:?}"
+                    );
+                    let backup = read_to_string(&backup_path).with_context(|| {
+                        format!(
+                            "config file {path:?} is corrupt and no backup at {backup_path:?} \
+                             exists"
+                        )
+                    })?;
+                    let value = serde_json::from_str(&backup)
+                        .with_context(|| format!("backup config file {backup_path:?} is also corrupt"))?;
+                    Self::upgrade_value(value)?
+                }
+            },
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 // Just create a default config
                 let config = Self::default();
-                config.write()?;
+                config.write_to_profile(profile)?;
                 warn!("config file created at {:?}", path);
-                Ok(config)
+                (config, false)
             }
-            e => bail!("Failed to create a new config: {:?}", e),
+            Err(e) => bail!("Failed to create a new config: {:?}", e),
+        };
+        if migrated {
+            config.write_to_profile(profile)?;
+        }
+        config.profile = profile.to_string();
+        Ok(config)
+    }
+    /// Upgrades a just-parsed config `Value` through `MIGRATIONS`, then
+    /// deserializes the result into `Self`. Returns whether any migration
+    /// ran, so the caller knows to write the upgraded document back.
+    fn upgrade_value(mut value: Value) -> Result<(Self, bool)> {
+        let migrated = Self::migrate_value(&mut value)?;
+        let config: Self = serde_json::from_value(value)?;
+        Ok((config, migrated))
+    }
+    /// Runs `MIGRATIONS` over `value` in place, starting from its stored
+    /// `config_version` (absent == 0) up to `CONFIG_VERSION`, then stamps
+    /// `CONFIG_VERSION` back on. Refuses to load a `config_version` newer
+    /// than this build's `CONFIG_VERSION` rather than silently dropping
+    /// keys it doesn't understand. Returns whether any migration ran.
+    fn migrate_value(value: &mut Value) -> Result<bool> {
+        let stored_version = value
+            .get("config_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+        if stored_version > CONFIG_VERSION {
+            bail!(
+                "config_version {stored_version} is newer than this cro3 build supports \
+                 ({CONFIG_VERSION}); refusing to downgrade it"
+            );
+        }
+        for migrate in &MIGRATIONS[stored_version as usize..CONFIG_VERSION as usize] {
+            migrate(value);
         }
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("config_version".to_string(), Value::from(CONFIG_VERSION));
+        }
+        Ok(stored_version != CONFIG_VERSION)
+    }
+    /// Reads and migrates a single config layer file into a `Value`
+    /// (without deserializing into `Self`, so [`Config::read`] can merge
+    /// several layers before doing that once). Returns `Value::Null` if
+    /// the file doesn't exist -- an absent layer (e.g. no system-wide
+    /// config) simply contributes nothing to the merge.
+    fn read_layer_value(path: &Path) -> Result<Value> {
+        match read_to_string(path) {
+            Ok(s) => {
+                let mut value: Value = serde_json::from_str(&s)
+                    .with_context(|| format!("failed to parse {path:?}"))?;
+                Self::migrate_value(&mut value)?;
+                Ok(value)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Null),
+            Err(e) => Err(e).with_context(|| format!("failed to read {path:?}")),
+        }
+    }
+    fn backup_path(path: &Path) -> PathBuf {
+        path.with_file_name(format!(
+            "{}.bak",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or(CONFIG_FILE_NAME)
+        ))
+    }
+    fn lock_path(path: &Path) -> PathBuf {
+        path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or(CONFIG_FILE_NAME)
+        ))
     }
     // This is private since write should happen on every updates transparently
     fn write(&self) -> Result<()> {
+        if self.suppress_write {
+            return Ok(());
+        }
+        if let Some(path) = self.write_override_path.clone() {
+            return self.write_to_path(&path);
+        }
+        self.write_to_profile(&self.profile)
+    }
+    /// Crash-safe write to the named profile's usual path; see
+    /// [`Self::write_to_path`] for the actual write mechanics.
+    fn write_to_profile(&self, profile: &str) -> Result<()> {
+        let path = config_path_in_lium_dir(&profile_config_file_name(profile))?;
+        self.write_to_path(&path)
+    }
+    /// Crash-safe write: serializes to a `.tmp-<pid>` sibling, `fsync`s it,
+    /// backs up the previous file to `.bak`, then atomically `rename`s the
+    /// temp file over `path` -- so a crash or panic mid-write never leaves a
+    /// truncated, unparseable config behind (see `read_profile`'s `.bak`
+    /// fallback). An advisory file lock is held around the backup+rename so
+    /// two concurrent `cro3` processes writing the same file can't
+    /// interleave and clobber each other.
+    fn write_to_path(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("failed to create the config directory")?;
+        }
         let s = serde_json::to_string_pretty(&self)?;
-        write(gen_path_in_cro3_dir(CONFIG_FILE_NAME)?, s.into_bytes())
-            .context("failed to write config")
+        let tmp_path = path.with_file_name(format!(
+            "{}.tmp-{}",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or(CONFIG_FILE_NAME),
+            std::process::id()
+        ));
+        write(&tmp_path, s.into_bytes()).context("failed to write the temp config file")?;
+        std::fs::File::open(&tmp_path)
+            .and_then(|f| f.sync_all())
+            .context("failed to sync the temp config file")?;
+        let lock_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(Self::lock_path(&path))
+            .context("failed to open the config lock file")?;
+        lock_file
+            .lock_exclusive()
+            .context("failed to acquire the config file lock")?;
+        let result = (|| -> Result<()> {
+            if path.is_file() {
+                std::fs::copy(&path, Self::backup_path(&path))
+                    .context("failed to back up the previous config file")?;
+            }
+            std::fs::rename(&tmp_path, &path)
+                .context("failed to atomically replace the config file")
+        })();
+        let _ = FileExt::unlock(&lock_file);
+        result
+    }
+    /// Starts a batch of `set()`/`clear()` calls that persist to disk
+    /// exactly once, on [`ConfigTransaction::commit`], instead of once per
+    /// call. If the returned guard is dropped without committing (e.g. an
+    /// error propagated out of the caller before it got there), the
+    /// accumulated in-memory changes are simply discarded -- `config.json`
+    /// was never touched, so there's nothing to roll back.
+    pub fn transaction() -> Result<ConfigTransaction> {
+        let mut config = Self::read()?;
+        config.suppress_write = true;
+        Ok(ConfigTransaction {
+            config,
+            committed: false,
+        })
+    }
+    /// Name of the profile this config was read from (and is written back
+    /// to).
+    pub fn profile_name(&self) -> &str {
+        &self.profile
+    }
+    /// Lists all known profile names, with "default" always included even
+    /// if no profile-specific file has been written yet.
+    pub fn list_profiles() -> Result<Vec<String>> {
+        let dir = config_dir_in_lium_dir()?;
+        let mut profiles = vec![DEFAULT_PROFILE_NAME.to_string()];
+        if dir.is_dir() {
+            for entry in read_dir(&dir).context("failed to list config dir")?.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if let Some(profile) = name
+                    .strip_prefix("config.")
+                    .and_then(|s| s.strip_suffix(".json"))
+                {
+                    profiles.push(profile.to_string());
+                }
+            }
+        }
+        profiles.sort();
+        profiles.dedup();
+        Ok(profiles)
+    }
+    /// Creates a new, empty profile. Fails if one of that name already
+    /// exists.
+    pub fn create_profile(name: &str) -> Result<()> {
+        if name == DEFAULT_PROFILE_NAME {
+            bail!("the {DEFAULT_PROFILE_NAME} profile always exists implicitly");
+        }
+        let path = config_path_in_lium_dir(&profile_config_file_name(name))?;
+        if path.is_file() {
+            bail!("profile {name:?} already exists");
+        }
+        Self::default().write_to_profile(name)
+    }
+    /// Persists `name` as the profile used by future invocations that don't
+    /// override it via `--profile`/CRO3_PROFILE.
+    pub fn use_profile(name: &str) -> Result<()> {
+        if !Self::list_profiles()?.iter().any(|p| p == name) {
+            bail!(
+                "profile {name:?} does not exist; create it first with `cro3 config profile \
+                 create {name}`"
+            );
+        }
+        write(
+            config_path_in_lium_dir(ACTIVE_PROFILE_POINTER_FILE)?,
+            name.as_bytes(),
+        )
+        .context("failed to persist the active profile")
+    }
+    /// Deletes a named profile's config file. The implicit "default"
+    /// profile can't be deleted.
+    pub fn delete_profile(name: &str) -> Result<()> {
+        if name == DEFAULT_PROFILE_NAME {
+            bail!("the {DEFAULT_PROFILE_NAME} profile can't be deleted");
+        }
+        let path = config_path_in_lium_dir(&profile_config_file_name(name))?;
+        if !path.is_file() {
+            bail!("profile {name:?} does not exist");
+        }
+        remove_file(&path).context("failed to delete profile")?;
+        if Self::active_profile_name() == name {
+            // Don't leave the active-profile pointer referring to a profile
+            // that no longer exists.
+            let pointer = config_path_in_lium_dir(ACTIVE_PROFILE_POINTER_FILE)?;
+            let _ = remove_file(pointer);
+        }
+        Ok(())
+    }
+    /// Serializes this profile as a TOML document, for `cro3 config
+    /// export`.
+    pub fn export_to_file(&self, path: &Path) -> Result<()> {
+        // Go through toml::Value rather than serializing `self` directly:
+        // Config interleaves plain fields (Option<String>, ...) with table
+        // fields (HashMap<..>), and a plain struct Serializer can't reorder
+        // those for TOML's "values before tables" rule, while Value (being
+        // fully buffered) can.
+        let value = toml::Value::try_from(self).context("failed to convert config to TOML")?;
+        let s = toml::to_string_pretty(&value).context("failed to serialize config as TOML")?;
+        write(path, s.into_bytes()).context("failed to write export file")
+    }
+    /// Reads a TOML document written by `export_to_file` and persists it as
+    /// `profile`, for `cro3 config import`.
+    pub fn import_from_file(path: &Path, profile: &str) -> Result<Self> {
+        let s = read_to_string(path).context("failed to read import file")?;
+        let mut config: Self = toml::from_str(&s).context("failed to parse TOML config")?;
+        config.profile = profile.to_string();
+        config.write()?;
+        Ok(config)
     }
     pub fn set<K: AsRef<str>>(&mut self, key: &str, values: &[K]) -> Result<()> {
         let k: Result<ConfigKey, ParseError> = ConfigKey::from_str(key);
@@ -201,6 +918,7 @@ impl Config {
                     SshOverride {
                         shell_condition,
                         ssh_options,
+                        ..Default::default()
                     },
                 );
             }
@@ -221,6 +939,12 @@ impl Config {
                 }
                 self.default_ipv6_prefix = Some(values[0].as_ref().parse().unwrap());
             }
+            ConfigKey::MdnsDiscoveryMaxAgeSecs => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.mdns_discovery_max_age_secs = Some(values[0].as_ref().parse().unwrap());
+            }
             ConfigKey::IsInternal => {
                 if values.len() != 1 {
                     bail!("{key} only takes 1 params");
@@ -245,6 +969,12 @@ impl Config {
                 }
                 self.acloudw_config_path = Some(values[0].as_ref().to_string());
             }
+            ConfigKey::AndroidBuildApiCmd => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.android_build_api_cmd = Some(values[0].as_ref().to_string());
+            }
             ConfigKey::AndroidTargetForVmType => {
                 if values.len() != 2 {
                     bail!("{key} takes 2 parameters");
@@ -276,6 +1006,138 @@ impl Config {
                 self.arc_container_cheeps_image_for_branch
                     .insert(branch, target);
             }
+            ConfigKey::RmaAuthEndpoint => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.rma_auth_endpoint = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::ChrootBackend => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.chroot_backend = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::ChrootContainerRuntime => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.chroot_container_runtime = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::ChrootContainerImage => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.chroot_container_image = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::SshBackend => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.ssh_backend = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::TastLogMatchers => {
+                if values.len() < 3 {
+                    bail!(
+                        "{key} takes 3+ parameters: <name> <regex> <abort|count|abort-after-burst> \
+                         [count window]"
+                    );
+                }
+                let name = values[0].as_ref().to_string();
+                let pattern = values[1].as_ref().to_string();
+                Regex::new(&pattern).context("Invalid regex is provided as a log match pattern")?;
+                let action = match values[2].as_ref() {
+                    "abort" => LogMatchAction::AbortImmediately,
+                    "count" => LogMatchAction::Count,
+                    "abort-after-burst" => {
+                        if values.len() != 5 {
+                            bail!(
+                                "{key} abort-after-burst takes <name> <regex> abort-after-burst \
+                                 <count> <window>"
+                            );
+                        }
+                        LogMatchAction::AbortAfterBurst {
+                            count: values[3].as_ref().parse().context("invalid count")?,
+                            window: values[4].as_ref().parse().context("invalid window")?,
+                        }
+                    }
+                    other => bail!(
+                        "Unknown log match action {other:?}, expected abort, count, or \
+                         abort-after-burst"
+                    ),
+                };
+                self.tast_log_matchers
+                    .insert(name, LogMatchRule { pattern, action });
+            }
+            ConfigKey::MetricsGitRepo => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.metrics_git_repo = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::TastCacheBucket => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.tast_cache_bucket = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::TastCacheEndpoint => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.tast_cache_endpoint = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::TastCacheAccessKeyId => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.tast_cache_access_key_id = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::TastCacheSecretAccessKey => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.tast_cache_secret_access_key = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::Alias => {
+                if values.len() < 2 {
+                    bail!("{key} takes 2+ parameters: <name> <expansion...>");
+                }
+                let name = values[0].as_ref().to_string();
+                if BUILTIN_SUBCOMMANDS.contains(&name.as_str()) {
+                    bail!("{name:?} is a built-in subcommand and can't be aliased");
+                }
+                let expansion = values[1..]
+                    .iter()
+                    .map(|s| s.as_ref().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.alias.insert(name, expansion);
+            }
+            ConfigKey::DefaultBoard => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.default_board = Some(values[0].as_ref().to_string());
+            }
+            ConfigKey::DefaultUseFlags => {
+                if values.is_empty() {
+                    bail!("{key} takes 1+ params");
+                }
+                self.default_use_flags = Some(
+                    values
+                        .iter()
+                        .map(|s| s.as_ref().to_string())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+            }
+            ConfigKey::DefaultServoSerial => {
+                if values.len() != 1 {
+                    bail!("{key} only takes 1 params");
+                }
+                self.default_servo_serial = Some(values[0].as_ref().to_string());
+            }
         }
         self.write()
     }
@@ -307,6 +1169,9 @@ impl Config {
             ConfigKey::DefaultIpv6Prefix => {
                 self.default_ipv6_prefix = None;
             }
+            ConfigKey::MdnsDiscoveryMaxAgeSecs => {
+                self.mdns_discovery_max_age_secs = None;
+            }
             ConfigKey::IsInternal => {
                 self.is_internal = None;
             }
@@ -319,6 +1184,9 @@ impl Config {
             ConfigKey::AcloudwConfigPath => {
                 self.acloudw_config_path = None;
             }
+            ConfigKey::AndroidBuildApiCmd => {
+                self.android_build_api_cmd = None;
+            }
             ConfigKey::AndroidTargetForVmType => self.android_target_for_vm_type.clear(),
             ConfigKey::ArcVmCheepsImage => {
                 self.arc_vm_cheeps_image = None;
@@ -327,6 +1195,47 @@ impl Config {
             ConfigKey::ArcContainerCheepsImageForBranch => {
                 self.arc_container_cheeps_image_for_branch.clear()
             }
+            ConfigKey::RmaAuthEndpoint => {
+                self.rma_auth_endpoint = None;
+            }
+            ConfigKey::ChrootBackend => {
+                self.chroot_backend = None;
+            }
+            ConfigKey::ChrootContainerRuntime => {
+                self.chroot_container_runtime = None;
+            }
+            ConfigKey::ChrootContainerImage => {
+                self.chroot_container_image = None;
+            }
+            ConfigKey::SshBackend => {
+                self.ssh_backend = None;
+            }
+            ConfigKey::TastLogMatchers => self.tast_log_matchers.clear(),
+            ConfigKey::MetricsGitRepo => {
+                self.metrics_git_repo = None;
+            }
+            ConfigKey::TastCacheBucket => {
+                self.tast_cache_bucket = None;
+            }
+            ConfigKey::TastCacheEndpoint => {
+                self.tast_cache_endpoint = None;
+            }
+            ConfigKey::TastCacheAccessKeyId => {
+                self.tast_cache_access_key_id = None;
+            }
+            ConfigKey::TastCacheSecretAccessKey => {
+                self.tast_cache_secret_access_key = None;
+            }
+            ConfigKey::Alias => self.alias.clear(),
+            ConfigKey::DefaultBoard => {
+                self.default_board = None;
+            }
+            ConfigKey::DefaultUseFlags => {
+                self.default_use_flags = None;
+            }
+            ConfigKey::DefaultServoSerial => {
+                self.default_servo_serial = None;
+            }
         }
         self.write()?;
         Ok(())
@@ -348,12 +1257,39 @@ impl Config {
     pub fn ssh_overrides(&self) -> &HashMap<String, SshOverride> {
         &self.ssh_overrides
     }
+    pub fn tast_log_matchers(&self) -> &HashMap<String, LogMatchRule> {
+        &self.tast_log_matchers
+    }
+    pub fn metrics_git_repo(&self) -> Option<&str> {
+        self.metrics_git_repo.as_deref()
+    }
+    pub fn tast_cache_bucket(&self) -> Option<String> {
+        self.tast_cache_bucket.clone()
+    }
+    pub fn tast_cache_endpoint(&self) -> Option<String> {
+        self.tast_cache_endpoint.clone()
+    }
+    pub fn tast_cache_access_key_id(&self) -> Option<String> {
+        self.tast_cache_access_key_id.clone()
+    }
+    pub fn tast_cache_secret_access_key(&self) -> Option<String> {
+        self.tast_cache_secret_access_key.clone()
+    }
     pub fn android_manifest_url(&self) -> Option<String> {
         self.android_manifest_url.clone()
     }
     pub fn default_cros_checkout(&self) -> Option<String> {
         self.default_cros_checkout.clone()
     }
+    pub fn default_board(&self) -> Option<String> {
+        self.default_board.clone()
+    }
+    pub fn default_use_flags(&self) -> Option<String> {
+        self.default_use_flags.clone()
+    }
+    pub fn default_servo_serial(&self) -> Option<String> {
+        self.default_servo_serial.clone()
+    }
     pub fn default_cros_reference(&self) -> Option<String> {
         self.default_cros_reference.clone()
     }
@@ -363,6 +1299,9 @@ impl Config {
     pub fn default_ipv6_prefix(&self) -> Option<String> {
         self.default_ipv6_prefix.clone()
     }
+    pub fn mdns_discovery_max_age_secs(&self) -> u64 {
+        self.mdns_discovery_max_age_secs.unwrap_or(120 /* 2 min */)
+    }
     pub fn is_internal(&self) -> bool {
         self.is_internal.unwrap_or(false)
     }
@@ -375,6 +1314,9 @@ impl Config {
     pub fn acloudw_config_path(&self) -> Option<String> {
         self.acloudw_config_path.clone()
     }
+    pub fn android_build_api_cmd(&self) -> Option<String> {
+        self.android_build_api_cmd.clone()
+    }
     pub fn android_target_for_vm_type(&self) -> &HashMap<String, String> {
         &self.android_target_for_vm_type
     }
@@ -387,4 +1329,97 @@ impl Config {
     pub fn arc_container_cheeps_image_for_branch(&self) -> &HashMap<String, String> {
         &self.arc_container_cheeps_image_for_branch
     }
+    pub fn rma_auth_endpoint(&self) -> Option<String> {
+        self.rma_auth_endpoint.clone()
+    }
+    pub fn chroot_backend(&self) -> Option<String> {
+        self.chroot_backend.clone()
+    }
+    pub fn chroot_container_runtime(&self) -> Option<String> {
+        self.chroot_container_runtime.clone()
+    }
+    pub fn chroot_container_image(&self) -> Option<String> {
+        self.chroot_container_image.clone()
+    }
+    /// "openssh" (the default, if unset) or "native".
+    pub fn ssh_backend(&self) -> String {
+        self.ssh_backend.clone().unwrap_or_else(|| "openssh".to_string())
+    }
+    pub fn alias(&self) -> &HashMap<String, String> {
+        &self.alias
+    }
+    /// Splices a configured alias expansion into `argv`'s first element
+    /// (the subcommand name), the way Cargo resolves `alias.*` entries --
+    /// e.g. an alias named `ssh-dut` expanding to `"dut shell"` makes `cro3
+    /// ssh-dut foo.bar` run as `cro3 dut shell foo.bar`. Called from `main`
+    /// before argument dispatch. A name matching a [`BUILTIN_SUBCOMMANDS`]
+    /// entry is never looked up (so it can't be shadowed even if one
+    /// sneaks into the config file by hand), and expansion is bounded by
+    /// `MAX_ALIAS_EXPANSION_DEPTH` to guard against a self-referential or
+    /// cyclic alias looping forever.
+    pub fn expand_aliases(mut argv: Vec<String>) -> Result<Vec<String>> {
+        let Some(first) = argv.first() else {
+            return Ok(argv);
+        };
+        if BUILTIN_SUBCOMMANDS.contains(&first.as_str()) {
+            return Ok(argv);
+        }
+        let config = Self::read()?;
+        let mut depth = 0;
+        loop {
+            let Some(first) = argv.first() else {
+                break;
+            };
+            if BUILTIN_SUBCOMMANDS.contains(&first.as_str()) {
+                break;
+            }
+            let Some(expansion) = config.alias.get(first) else {
+                break;
+            };
+            depth += 1;
+            if depth > MAX_ALIAS_EXPANSION_DEPTH {
+                bail!(
+                    "alias {first:?} expansion exceeded depth {MAX_ALIAS_EXPANSION_DEPTH} \
+                     (cyclic alias?)"
+                );
+            }
+            let expansion_tokens: Vec<String> =
+                expansion.split_whitespace().map(str::to_string).collect();
+            argv.splice(0..1, expansion_tokens);
+        }
+        Ok(argv)
+    }
+}
+
+/// Guard returned by [`Config::transaction`]; derefs to the `Config` being
+/// batch-mutated, and writes it to disk exactly once, on [`Self::commit`].
+pub struct ConfigTransaction {
+    config: Config,
+    committed: bool,
+}
+impl ConfigTransaction {
+    /// Persists the accumulated `set()`/`clear()` calls in a single write.
+    pub fn commit(mut self) -> Result<()> {
+        self.committed = true;
+        self.config.suppress_write = false;
+        self.config.write()
+    }
+}
+impl Deref for ConfigTransaction {
+    type Target = Config;
+    fn deref(&self) -> &Config {
+        &self.config
+    }
+}
+impl DerefMut for ConfigTransaction {
+    fn deref_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+}
+impl Drop for ConfigTransaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            warn!("config transaction dropped without commit(); discarding its changes");
+        }
+    }
 }
@@ -0,0 +1,133 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Runs a top-level host command (`repo sync`, `cros flash`, ...) inside a
+//! Docker/Podman container instead of requiring a local ChromiumOS SDK
+//! chroot. Unlike [`crate::chroot`]'s [`crate::chroot::backend::ExecBackend`]
+//! (which runs commands *inside* an already-entered chroot), this targets
+//! hosts that have no chroot at all: the container image is expected to
+//! already carry whatever tool (`repo`, `cros`, ...) `command` invokes.
+//!
+//! The container is driven by a small shell template materialized once
+//! under `gen_path_in_lium_dir("container/run.sh.tmpl")`, so it can be
+//! edited by hand to add site-specific setup before the command runs.
+
+use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use futures::executor::block_on;
+use futures::stream;
+use futures::FutureExt;
+use futures::StreamExt;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::util::gen_path_in_lium_dir;
+use crate::util::shell_helpers::get_async_lines;
+
+const DEFAULT_TEMPLATE: &str = r#"#!/bin/bash
+# Generated by cro3; edit the copy at ~/.lium/container/run.sh.tmpl to
+# customize (e.g. to add an apt-get or env setup step before the command).
+set -e
+cd {{REPO_PATH}}
+export BOARD={{BOARD}}
+{{COMMAND}}
+"#;
+
+/// Materializes the default template the first time it's needed, then
+/// returns its path. Never overwrites an existing (possibly user-edited)
+/// template.
+fn template_path() -> Result<PathBuf> {
+    let path = gen_path_in_lium_dir("container/run.sh.tmpl")?;
+    if !path.exists() {
+        std::fs::write(&path, DEFAULT_TEMPLATE)
+            .with_context(|| format!("Failed to write the default template to {path:?}"))?;
+    }
+    Ok(path)
+}
+
+fn render_template(repo_path: &str, board: &str, command: &str) -> Result<String> {
+    let template = read_to_string(template_path()?).context("Failed to read the container template")?;
+    Ok(template
+        .replace("{{REPO_PATH}}", repo_path)
+        .replace("{{BOARD}}", board)
+        .replace("{{COMMAND}}", command))
+}
+
+/// Renders [`DEFAULT_TEMPLATE`] (or the user's edited copy of it) with
+/// `command` and runs it inside `image` via the configured container
+/// runtime, bind-mounting `repo_path` read-write at `/repo` and streaming
+/// the container's stdout/stderr through `tracing` as it runs.
+pub fn run_in_container(image: &str, repo_path: &str, board: &str, command: &str) -> Result<()> {
+    let runtime = Config::read()?
+        .chroot_container_runtime()
+        .unwrap_or_else(|| "podman".to_string());
+
+    let script = render_template(repo_path, board, command)?;
+    let script_path = gen_path_in_lium_dir("container/run.sh")?;
+    std::fs::write(&script_path, script)
+        .with_context(|| format!("Failed to write {script_path:?}"))?;
+
+    let home = std::env::var("HOME").unwrap_or_default();
+    let path_env = std::env::var("PATH").unwrap_or_default();
+
+    info!("Running `{command}` for board {board} in {runtime} image {image}...");
+    let mut cmd = async_process::Command::new(&runtime);
+    cmd.arg("run")
+        .arg("--rm")
+        .arg("-i")
+        .arg("-v")
+        .arg(format!("{repo_path}:/repo"))
+        .arg("-v")
+        .arg(format!("{}:/run.sh", script_path_to_str(&script_path)?))
+        .arg("-e")
+        .arg(format!("HOME={home}"))
+        .arg("-e")
+        .arg(format!("PATH={path_env}"))
+        .arg("-w")
+        .arg("/repo")
+        .arg(image)
+        .arg("bash")
+        .arg("/run.sh")
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    block_on(stream_container_output(cmd))
+}
+
+fn script_path_to_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .context("Container script path is not valid UTF-8")
+}
+
+async fn stream_container_output(mut cmd: async_process::Command) -> Result<()> {
+    let mut child = cmd.spawn().context("Failed to launch the container")?;
+    let (stdout, stderr) = get_async_lines(&mut child);
+    let stdout = stdout.context("container stdout was None")?;
+    let stderr = stderr.context("container stderr was None")?;
+    let mut merged = stream::select(stdout.fuse(), stderr.fuse());
+    while let Some(line) = merged.next().await {
+        match line {
+            Ok(line) => info!("{line}"),
+            Err(e) => warn!("Failed to read container output: {e}"),
+        }
+    }
+    let status = child
+        .status()
+        .await
+        .context("Failed to wait for the container")?;
+    status
+        .exit_ok()
+        .with_context(|| anyhow!("Container exited with {:?}", status.code()))
+}
@@ -4,19 +4,43 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+use std::fs::create_dir_all;
+use std::path::PathBuf;
 use std::process::Command;
 
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use regex::Regex;
 use regex_macro::regex;
 
 use crate::cache::KvCache;
 use crate::google_storage;
+use crate::util::xdg_dirs::cache_path_in_lium_dir;
 use crate::util::shell_helpers::run_bash_command;
 
 static VERSION_TO_MILESTONE_CACHE: KvCache<String> = KvCache::new("version_cache");
 
+// TODO #83 create an enum to represent board that can be converted to string
+// (adds some type safety)
+/// Resolves a `LATEST-*` pointer object (e.g. `LATEST-main`, `LATEST-120`)
+/// published alongside a board's image archive into a full
+/// `R<milestone>-<version>` string.
+fn resolve_latest_pointer(
+    board: &str,
+    pointer_name: &str,
+    re_full_cros_version: &Regex,
+) -> Result<String> {
+    let content = google_storage::cat_gs_file(&format!(
+        "gs://chromiumos-image-archive/{board}-release/{pointer_name}"
+    ))
+    .with_context(|| format!("Failed to read {pointer_name} (unknown board or milestone?)"))?;
+    let captures = re_full_cros_version
+        .captures(content.trim())
+        .with_context(|| format!("Unexpected contents of {pointer_name}: {content}"))?;
+    Ok(captures.get(1).context("No match found")?.as_str().to_string())
+}
+
 // TODO #83 create an enum to represent board that can be converted to string
 // (adds some type safety)
 pub fn lookup_full_version(input: &str, board: &str) -> Result<String> {
@@ -24,6 +48,7 @@ pub fn lookup_full_version(input: &str, board: &str) -> Result<String> {
     let re_cros_version_without_milestone = regex!(r"^\d+\.\d+\.\d+$");
     let re_cros_version = regex!(r"/(R\d+\-\d+\.\d+\.\d+)/");
     let re_full_cros_version = regex!(r"(R\d+\-\d+\.\d+\.\d+)");
+    let re_milestone_only = regex!(r"(?i)^(?:latest-)?r(\d+)$");
     if let Some(captures) = re_full_cros_version.captures(input) {
         let captures = captures.get(1).context("No match found")?;
         Ok(captures.as_str().to_string())
@@ -43,6 +68,18 @@ pub fn lookup_full_version(input: &str, board: &str) -> Result<String> {
             let output = output.get(1).context("No match found")?;
             Ok(output.as_str().to_string())
         })
+    } else if input.eq_ignore_ascii_case("latest") || input.eq_ignore_ascii_case("tot") {
+        let cache_key = format!("{board}:LATEST-main");
+        VERSION_TO_MILESTONE_CACHE.get_or_else(&cache_key, &|_| {
+            resolve_latest_pointer(board, "LATEST-main", re_full_cros_version)
+        })
+    } else if let Some(captures) = re_milestone_only.captures(input) {
+        let milestone = &captures[1];
+        let pointer_name = format!("LATEST-{milestone}");
+        let cache_key = format!("{board}:{pointer_name}");
+        VERSION_TO_MILESTONE_CACHE.get_or_else(&cache_key, &|_| {
+            resolve_latest_pointer(board, &pointer_name, re_full_cros_version)
+        })
     } else {
         bail!("Invalid version format: {}", input)
     }
@@ -63,6 +100,91 @@ fi
     }
 }
 
+/// Parses simple shell-style `KEY="value"` lines such as an overlay's
+/// `sdk_version.conf`. Lines that aren't a bare `KEY="value"` assignment
+/// (comments, blanks, anything fancier) are ignored.
+fn parse_shell_kv(contents: &str, key: &str) -> Option<String> {
+    let re_kv = regex!(r#"^([A-Za-z_][A-Za-z0-9_]*)="([^"]*)"$"#);
+    contents.lines().find_map(|line| {
+        let captures = re_kv.captures(line.trim())?;
+        if &captures[1] == key {
+            Some(captures[2].to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Reads the pinned SDK version (`SDK_LATEST_VERSION`) out of the contents
+/// of an overlay's `sdk_version.conf`.
+pub fn read_pinned_sdk_version(sdk_version_conf: &str) -> Result<String> {
+    parse_shell_kv(sdk_version_conf, "SDK_LATEST_VERSION")
+        .context("SDK_LATEST_VERSION not found in sdk_version.conf")
+}
+
+fn sdk_cache_dir(version: &str) -> Result<PathBuf> {
+    let mut path = cache_path_in_lium_dir(&format!("sdk/{version}/.keep"))?;
+    path.pop();
+    Ok(path)
+}
+
+/// Provisions the pinned ChromeOS SDK tarball and `board`'s prebuilt
+/// binpkgs into a cache under `lium_dir()`, without doing a full `repo
+/// sync`. If `version` is not given, it is read from `sdk_version_conf`
+/// (the contents of the overlay's `sdk_version.conf`). Already-cached
+/// artifacts for the resolved version are reused rather than re-downloaded.
+pub fn setup_sdk(sdk_version_conf: &str, board: &str, version: Option<&str>) -> Result<()> {
+    let version = match version {
+        Some(version) => version.to_string(),
+        None => read_pinned_sdk_version(sdk_version_conf)?,
+    };
+
+    let cache_dir = sdk_cache_dir(&version)?;
+    create_dir_all(&cache_dir).context("Failed to create the SDK cache dir")?;
+
+    let sdk_tarball = cache_dir.join(format!("cros-sdk-{version}.tar.xz"));
+    if sdk_tarball.exists() {
+        eprintln!("Using cached SDK tarball: {}", sdk_tarball.display());
+    } else {
+        let listing = google_storage::list_gs_files(&format!(
+            "gs://chromiumos-sdk/cros-sdk-{version}.tar.*"
+        ))
+        .context("Failed to look up the pinned SDK tarball")?;
+        let gs_path = listing
+            .lines()
+            .next()
+            .context("No cros-sdk tarball found for the pinned version")?;
+        google_storage::fetch_gs_file(gs_path, &sdk_tarball)
+            .context("Failed to download the pinned SDK tarball")?;
+    }
+    if !sdk_tarball.exists() {
+        bail!("SDK tarball was not found at {}", sdk_tarball.display());
+    }
+
+    let binpkgs_dir = cache_dir.join(format!("{board}-binpkgs"));
+    create_dir_all(&binpkgs_dir).context("Failed to create the board binpkgs cache dir")?;
+    let marker = binpkgs_dir.join(".synced");
+    if marker.exists() {
+        eprintln!("Using cached {board} prebuilts: {}", binpkgs_dir.display());
+    } else {
+        let listing = google_storage::list_gs_files(&format!(
+            "gs://chromeos-prebuilt/board/{board}/{version}/packages/*"
+        ))
+        .context("Failed to look up the board's prebuilt binpkgs")?;
+        for gs_path in listing.lines() {
+            let file_name = gs_path
+                .rsplit('/')
+                .next()
+                .context("Unexpected gsutil listing entry")?;
+            google_storage::fetch_gs_file(gs_path, &binpkgs_dir.join(file_name))
+                .context("Failed to download a prebuilt binpkg")?;
+        }
+        std::fs::write(&marker, "")?;
+    }
+
+    Ok(())
+}
+
 pub fn setup_cros_repo(repo: &str, version: &str, reference: &Option<String>) -> Result<()> {
     let url = if version == "tot" {
         "https://chrome-internal.googlesource.com/chromeos/manifest-internal"
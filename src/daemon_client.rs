@@ -0,0 +1,168 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Client/manager protocol shared between `lium daemon start` and the CLI
+//! commands that can use it. The daemon holds persistent SSH connections to
+//! known DUTs so that `list`/`info`/`discover` can be served from its
+//! in-memory cache instead of every CLI invocation reconnecting from
+//! scratch.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use retry::retry;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dut::MonitoredDutStatus;
+use crate::util::gen_path_in_lium_dir;
+
+/// Bumped whenever `DaemonRequest`/`DaemonResponse` change shape, so a
+/// stale daemon left running from a previous `cro3` build is detected
+/// cleanly instead of failing to deserialize a request it doesn't know.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+}
+
+pub fn socket_path() -> Result<String> {
+    Ok(gen_path_in_lium_dir("daemon.sock")?
+        .to_string_lossy()
+        .to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DaemonRequest {
+    /// Returns the cached info for a single DUT, refreshing it first if
+    /// it's not already tracked by the daemon.
+    Info { dut: String },
+    /// Returns the cached info for every DUT the daemon currently tracks.
+    List,
+    /// Runs `cmd` on `dut` over the daemon's own (possibly already
+    /// connected) SSH session and returns its captured output.
+    Run { dut: String, cmd: Vec<String> },
+    /// Establishes, or reuses, a forward from a free local port in
+    /// `port_first..=port_last` to `dut`'s SSH port, owned by the daemon.
+    Forward {
+        dut: String,
+        port_first: u16,
+        port_last: u16,
+    },
+    /// Starts monitoring `dut` with a daemon-owned `MonitoredDut` forwarding
+    /// `port` to it, so the forward (and its reconnect loop) outlives the
+    /// CLI invocation that requested it. Replaces any existing monitor for
+    /// the same `dut`.
+    MonitorAdd { dut: String, port: u16 },
+    /// Stops monitoring `dut`, dropping its forward.
+    MonitorRemove { dut: String },
+    /// Returns the DUT ids currently being monitored.
+    MonitorList,
+    /// Returns one status snapshot per currently monitored DUT.
+    MonitorStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DaemonResponse {
+    Info(HashMap<String, String>),
+    List(Vec<HashMap<String, String>>),
+    Run { code: i32, stdout: String, stderr: String },
+    Forward { port: u16 },
+    MonitorAdded,
+    MonitorRemoved,
+    MonitorList(Vec<String>),
+    MonitorStatus(Vec<MonitoredDutStatus>),
+    Error(String),
+}
+
+/// Returns true if a daemon is listening on the well-known socket.
+pub fn is_running() -> bool {
+    socket_path()
+        .ok()
+        .and_then(|p| UnixStream::connect(p).ok())
+        .is_some()
+}
+
+/// Auto-starts the daemon in the background if it isn't already running,
+/// and waits for its socket to become connectable. Callers that want to
+/// share a daemon-owned resource (e.g. a port forward) use this instead of
+/// `is_running()` + falling back to doing the work themselves.
+pub fn ensure_running() -> Result<()> {
+    if is_running() {
+        return Ok(());
+    }
+    let exe = std::env::current_exe()?;
+    Command::new(exe)
+        .args(["daemon", "start"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to auto-start the cro3 daemon")?;
+    retry(retry::delay::Fixed::from_millis(200).take(50), || {
+        if is_running() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    })
+    .or(Err(anyhow!(
+        "Timed out waiting for the auto-started daemon to come up"
+    )))
+}
+
+fn send_line<T: Serialize>(stream: &mut UnixStream, value: &T) -> Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn read_line(stream: &UnixStream) -> Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(line)
+}
+
+/// Sends `req` to the running daemon and waits for its response. Callers
+/// should check `is_running()` first and fall back to direct SSH if it
+/// returns false; this fails outright if the daemon disappeared in
+/// between.
+pub fn query(req: &DaemonRequest) -> Result<DaemonResponse> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path).context("daemon is not running")?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+
+    send_line(
+        &mut stream,
+        &Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+    let their_version = serde_json::from_str::<Hello>(&read_line(&stream)?)
+        .context("daemon sent an invalid handshake")?
+        .version;
+    if their_version != PROTOCOL_VERSION {
+        bail!(
+            "daemon speaks protocol v{their_version}, this cro3 speaks v{PROTOCOL_VERSION}; \
+             restart the daemon (kill it and it will be auto-started again)"
+        );
+    }
+
+    send_line(&mut stream, req)?;
+    serde_json::from_str(&read_line(&stream)?).context("Failed to parse daemon response")
+}
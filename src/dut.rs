@@ -4,16 +4,29 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::env::current_exe;
 use std::ffi::OsStr;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
 use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
 use std::process::Command;
-use std::process::Output;
 use std::process::Stdio;
 use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::anyhow;
 use anyhow::bail;
@@ -22,6 +35,7 @@ use anyhow::Result;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use chrono::Local;
+use dirs::home_dir;
 use futures::executor::block_on;
 use futures::select;
 use futures::stream;
@@ -29,18 +43,32 @@ use futures::StreamExt;
 use lazy_static::lazy_static;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use rand::Rng;
 use rayon::prelude::*;
 use regex::Regex;
-use retry::retry;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 use url::Url;
 
 use crate::cache::KvCache;
 use crate::config::Config;
+use crate::config::SshOverride;
 use crate::cros::ensure_testing_rsa_is_there;
+use crate::daemon_client;
+use crate::ssh_native::NativeSshSession;
+use crate::ssh_native::SshCryptoPrefs;
+use crate::util::gen_path_in_lium_dir;
 use crate::util::shell_helpers::get_async_lines;
+
+/// Schema version for `lium dut discover`'s remote protocol: the JSON shape
+/// of a discovered DUT record and the set of `extra_attr` keys a remote
+/// `lium` is expected to understand. Bump this whenever that shape changes
+/// in a way that would make an older/newer remote produce incompatible
+/// output, so `run_discover --remote` can refuse instead of silently
+/// parsing garbage.
+pub const DISCOVER_PROTOCOL_VERSION: u32 = 1;
 use crate::util::shell_helpers::get_stderr;
 use crate::util::shell_helpers::get_stdout;
 use crate::util::shell_helpers::run_bash_command;
@@ -81,13 +109,200 @@ lazy_static! {
         Regex::new(r"^0x[0-9a-fA-F]+$").unwrap();
 }
 
+/// Upper bound on how many authenticated native sessions `NATIVE_SESSION_POOL`
+/// keeps alive at once. A lab sweeping over hundreds of DUTs with
+/// `ssh_backend = "native"` would otherwise accumulate one open TCP
+/// connection per DUT it has ever touched for the life of the process; once
+/// this is exceeded, inserting a new session evicts the least-recently-used
+/// one instead.
+const MAX_NATIVE_SESSIONS: usize = 32;
+
+lazy_static! {
+    // One authenticated `ssh2::Session` per DUT (keyed by `host_and_port`),
+    // shared across the native `ssh_backend` call sites so probing a DUT
+    // (`get_board`, `get_arch`, ..., `DutInfo::fetch_keys`) reuses a single
+    // connection instead of paying for a handshake on every call. Bounded to
+    // `MAX_NATIVE_SESSIONS` entries, each tracking when it was last handed
+    // out so the pool can evict the least-recently-used one on overflow.
+    static ref NATIVE_SESSION_POOL: Mutex<HashMap<String, (Arc<Mutex<NativeSshSession>>, Instant)>> =
+        Mutex::new(HashMap::new());
+}
+
 pub static SSH_CACHE: KvCache<SshInfo> = KvCache::new("ssh_cache");
 
+/// How reachable a cached DUT was last seen to be, borrowed from Fuchsia
+/// ffx's `TargetConnectionState`. Tracked in-process only (see
+/// `CONNECTION_STATE` below) rather than inside the persisted `SSH_CACHE`
+/// entries themselves, since it's only meaningful for as long as this
+/// process has been observing the DUT and `Instant` (needed for staleness)
+/// isn't something that round-trips through `SSH_CACHE`'s JSON file anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not seen recently enough by any of the ways below to still trust.
+    Disconnected,
+    /// Seen advertising itself over mDNS, but not yet confirmed over SSH.
+    Mdns,
+    /// An SSH command or ping round-tripped successfully.
+    Ssh,
+    /// `reboot()` was just called; SSH is expected to drop momentarily, so
+    /// callers should not treat that as the DUT going `Disconnected`.
+    Rebooting,
+}
+impl ConnectionState {
+    /// How long a state is trusted without being refreshed before
+    /// `connection_state` reports `Disconnected` instead. `Mdns` is
+    /// configurable via `mdns_discovery_max_age_secs`, since a lab's mDNS
+    /// advertisement interval (and how long it's reasonable to assume a DUT
+    /// is still on the link after one) varies by network.
+    fn max_age(self) -> Duration {
+        match self {
+            ConnectionState::Disconnected => Duration::ZERO,
+            ConnectionState::Mdns => Duration::from_secs(
+                Config::read()
+                    .map(|c| c.mdns_discovery_max_age_secs())
+                    .unwrap_or(120),
+            ),
+            ConnectionState::Ssh => Duration::from_secs(30),
+            ConnectionState::Rebooting => Duration::from_secs(300),
+        }
+    }
+}
+
+lazy_static! {
+    // In-process only: last observed `ConnectionState` per DUT, keyed by
+    // `host_and_port`. Not persisted alongside `SSH_CACHE` (see
+    // `ConnectionState`'s doc comment for why).
+    static ref CONNECTION_STATE: Mutex<HashMap<String, (ConnectionState, Instant)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Records that `ssh` was just observed to be in `state`, so that
+/// `connection_state`/`pingable_duts` can report it without re-probing for
+/// as long as `state.max_age()` allows.
+fn note_connection_state(ssh: &SshInfo, state: ConnectionState) {
+    CONNECTION_STATE
+        .lock()
+        .unwrap()
+        .insert(ssh.host_and_port(), (state, Instant::now()));
+}
+/// The freshest `ConnectionState` known for `ssh`, or `Disconnected` if
+/// nothing was recorded or the recorded state has aged past its
+/// `max_age()`.
+pub fn connection_state(ssh: &SshInfo) -> ConnectionState {
+    match CONNECTION_STATE.lock().unwrap().get(&ssh.host_and_port()) {
+        Some((state, last_seen)) if last_seen.elapsed() < state.max_age() => *state,
+        _ => ConnectionState::Disconnected,
+    }
+}
+
+/// The TCP port `arm_reboot_callback` asks the DUT to dial back out on,
+/// forwarded over the reverse tunnel to the listener `reboot()` binds on the
+/// host. Arbitrary but chosen next to `start_ssh_forwarding_background`'s
+/// `4100..=4200` range so the two don't collide.
+const REBOOT_CALLBACK_DUT_PORT: u16 = 4210;
+/// Sentinel the DUT's one-shot writes back once userland is up, so
+/// `wait_for_reboot_callback` doesn't mistake an unrelated connection (or a
+/// stale one from a previous reboot) for the real signal.
+const REBOOT_CALLBACK_TOKEN: &[u8] = b"cro3-booted";
+/// Upper bound on how long `wait_online_with_timeout` waits on the reverse
+/// callback before giving up on it and falling back to polling. The
+/// DUT-side one-shot is an ordinary backgrounded process with no init-script
+/// hook, so it cannot survive the actual shutdown/kexec boundary to phone
+/// home from the *new* boot -- at best it can win a brief race against the
+/// old boot's network going down. Capping the wait independently of the
+/// caller's overall timeout keeps this a strictly-optional fast path: a
+/// caller that doesn't get the callback pays this small fixed cost once,
+/// not a doubling of its whole timeout.
+const REBOOT_CALLBACK_WAIT_CAP: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    // Listener + still-alive reverse-forward ssh process armed by
+    // `reboot()` for the reverse-callback boot signal, keyed by
+    // `host_and_port`, so a later `wait_online()` call in the same process
+    // can pick it up instead of falling back to polling. `reboot()` and
+    // `wait_online()` are commonly called as separate `DutAction`s within
+    // the same `cro3 dut action` invocation (see `do_reboot`/
+    // `do_wait_online` in `cmd/dut.rs`), which is what makes sharing this
+    // in-process map safe to rely on. The forward process is kept alive
+    // (not killed the moment the one-shot is armed) since the DUT's
+    // callback attempts have to tunnel through it; it's only torn down once
+    // `wait_for_reboot_callback` is done with it.
+    static ref REBOOT_CALLBACK_LISTENER: Mutex<HashMap<String, (TcpListener, Child)>> =
+        Mutex::new(HashMap::new());
+}
+
 pub enum PartitionSet {
     Primary,
     Secondary,
 }
 
+/// After this many consecutive failed reconnect attempts, `get_status`
+/// reports the DUT as hard `Down` instead of perpetually "Reconnecting...".
+const MONITOR_MAX_CONSECUTIVE_FAILURES: u32 = 6;
+/// Backoff delay before the first retry; doubled per consecutive failure
+/// (1s, 2s, 4s, ...) up to `MONITOR_MAX_BACKOFF`.
+const MONITOR_BASE_BACKOFF: Duration = Duration::from_secs(1);
+/// Cap on the exponential backoff delay between reconnect attempts.
+const MONITOR_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The reconnect state reported by [`MonitoredDut::status`], mirrored by
+/// both its human `get_status` text and its [`MonitoredDut::status_json`]
+/// structured form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitoredDutState {
+    /// The forward is up and running.
+    Alive,
+    /// The forward dropped and a reconnect is being retried (with backoff).
+    Reconnecting,
+    /// `MONITOR_MAX_CONSECUTIVE_FAILURES` consecutive reconnects have
+    /// failed; still retrying, but no longer considered transient.
+    Down,
+}
+
+/// A snapshot of one [`MonitoredDut`]'s forward, as returned by
+/// [`MonitoredDut::status_json`] for `--format json` consumers (and
+/// round-tripped through the `lium daemon`'s control protocol so a CLI
+/// invocation thin-clienting off it can render either format from the same
+/// data).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitoredDutStatus {
+    pub dut: String,
+    pub forward_addr: Option<String>,
+    pub ip_addr: String,
+    pub state: MonitoredDutState,
+    pub reconnecting: bool,
+    pub consecutive_failures: u32,
+    pub retry_in_secs: Option<u64>,
+}
+impl MonitoredDutStatus {
+    /// The same fixed-width text [`MonitoredDut::get_status`] has always
+    /// produced.
+    pub fn to_line(&self) -> String {
+        match (self.state, self.retry_in_secs) {
+            (MonitoredDutState::Alive, _) => format!(
+                "{:<31}\t{:<15}\t{}",
+                &self.dut,
+                self.forward_addr.clone().unwrap_or_default(),
+                &self.ip_addr
+            ),
+            (MonitoredDutState::Reconnecting, None) => {
+                format!("{:<31}\tReconnecting...", &self.dut)
+            }
+            (MonitoredDutState::Reconnecting, Some(secs)) => format!(
+                "{:<31}\tReconnecting... (failures={}, retry in {secs}s)",
+                &self.dut, self.consecutive_failures
+            ),
+            (MonitoredDutState::Down, secs) => format!(
+                "{:<31}\tDown (failures={}, retry in {}s)",
+                &self.dut,
+                self.consecutive_failures,
+                secs.unwrap_or_default()
+            ),
+        }
+    }
+}
+
 /// MonitoredDut holds connection to a monitoring Dut
 #[derive(Debug)]
 pub struct MonitoredDut {
@@ -96,6 +311,14 @@ pub struct MonitoredDut {
     port: u16,
     child: Option<async_process::Child>,
     reconnecting: bool,
+    /// Consecutive failed reconnect attempts since the last time the
+    /// forward was confirmed alive. Reset to 0 as soon as `get_status` sees
+    /// the forward running again.
+    consecutive_failures: u32,
+    /// Earliest time `reconnect` is allowed to retry; set to `now + backoff`
+    /// after each failure so a down DUT is retried with exponential
+    /// backoff-plus-jitter instead of busy-looping.
+    next_retry_at: Instant,
 }
 impl MonitoredDut {
     pub fn new(dut: &str, port: u16) -> Result<Self> {
@@ -106,42 +329,99 @@ impl MonitoredDut {
             port,
             child: block_on(ssh.start_ssh_forwarding(port)).ok(),
             reconnecting: false,
+            consecutive_failures: 0,
+            next_retry_at: Instant::now(),
         };
         Ok(dut)
     }
     pub fn reconnecting(&self) -> bool {
         self.reconnecting
     }
-    fn reconnect(&mut self) -> Result<String> {
+    /// Exponential backoff (with up to 20% jitter) for the `n`th consecutive
+    /// failure, capped at `MONITOR_MAX_BACKOFF`.
+    fn backoff_for(n: u32) -> Duration {
+        let backoff = MONITOR_BASE_BACKOFF
+            .saturating_mul(1u32.checked_shl(n.saturating_sub(1)).unwrap_or(u32::MAX))
+            .min(MONITOR_MAX_BACKOFF);
+        let jitter_frac: f64 = thread_rng().gen_range(0.0..0.2);
+        backoff + Duration::from_secs_f64(backoff.as_secs_f64() * jitter_frac)
+    }
+    fn reconnect(&mut self) -> MonitoredDutStatus {
+        let now = Instant::now();
+        if now < self.next_retry_at {
+            let remaining = (self.next_retry_at - now).as_secs();
+            return self.status_snapshot(self.state_for_retry(), Some(remaining));
+        }
         let new_child = block_on(self.ssh.start_ssh_forwarding(self.port));
-        if let Err(e) = &new_child {
-            error!("Failed to reconnect: {e:?}");
-        };
-        self.child = new_child.ok();
         self.reconnecting = true;
-        Ok(format!("{:<31}\tReconnecting...", &self.dut))
+        match new_child {
+            // Just (re-)spawned; not yet confirmed alive, so this is still
+            // `Reconnecting` until the next poll's `try_status` succeeds.
+            Ok(child) => {
+                self.child = Some(child);
+                self.status_snapshot(MonitoredDutState::Reconnecting, None)
+            }
+            Err(e) => {
+                self.child = None;
+                self.consecutive_failures += 1;
+                let backoff = Self::backoff_for(self.consecutive_failures);
+                self.next_retry_at = now + backoff;
+                error!("Failed to reconnect (attempt {}): {e:?}", self.consecutive_failures);
+                self.status_snapshot(self.state_for_retry(), Some(backoff.as_secs()))
+            }
+        }
+    }
+    fn state_for_retry(&self) -> MonitoredDutState {
+        if self.consecutive_failures >= MONITOR_MAX_CONSECUTIVE_FAILURES {
+            MonitoredDutState::Down
+        } else {
+            MonitoredDutState::Reconnecting
+        }
+    }
+    /// Builds the [`MonitoredDutStatus`] snapshot for the current fields.
+    fn status_snapshot(&self, state: MonitoredDutState, retry_in_secs: Option<u64>) -> MonitoredDutStatus {
+        MonitoredDutStatus {
+            dut: self.dut.clone(),
+            forward_addr: (state == MonitoredDutState::Alive)
+                .then(|| format!("127.0.0.1:{}", self.port)),
+            ip_addr: self.ssh.host_and_port(),
+            state,
+            reconnecting: self.reconnecting,
+            consecutive_failures: self.consecutive_failures,
+            retry_in_secs,
+        }
     }
     pub fn get_status_header() -> String {
         format!("{:<31}\t{:<15}\t{}", "DUT", "Forward Addr", "IP Addr")
     }
-    pub fn get_status(&mut self) -> Result<String> {
+    /// A snapshot of this DUT's forward for `--format json` consumers. Pure
+    /// wrapper over the same state transitions [`Self::get_status`] uses,
+    /// returned as structured data instead of formatted text.
+    pub fn status_json(&mut self) -> Result<MonitoredDutStatus> {
+        Ok(self.status())
+    }
+    fn status(&mut self) -> MonitoredDutStatus {
         if let Some(child) = &mut self.child {
-            match child.try_status()? {
-                None => {
+            match child.try_status() {
+                Ok(None) => {
                     self.reconnecting = false;
-                    Ok(format!(
-                        "{:<31}\t127.0.0.1:{:<5}\t{}",
-                        &self.dut,
-                        self.port,
-                        &self.ssh.host_and_port()
-                    ))
+                    self.consecutive_failures = 0;
+                    self.next_retry_at = Instant::now();
+                    self.status_snapshot(MonitoredDutState::Alive, None)
+                }
+                Ok(Some(_status)) => self.reconnect(),
+                Err(e) => {
+                    error!("Failed to poll the forwarding child's status: {e:#}");
+                    self.reconnect()
                 }
-                Some(_status) => self.reconnect(),
             }
         } else {
             self.reconnect()
         }
     }
+    pub fn get_status(&mut self) -> Result<String> {
+        Ok(self.status().to_line())
+    }
 }
 
 lazy_static! {
@@ -347,7 +627,11 @@ impl DutInfo {
             })
             .collect()
     }
-    pub fn fetch_keys(ssh: &SshInfo, keys: &Vec<&str>) -> Result<HashMap<String, String>> {
+    /// Run the remote attribute-fetching commands for `keys` and return the
+    /// raw, not-yet-cross-derived per-key results. Shared by [`Self::fetch_keys`]
+    /// (which bails on the first failure) and [`Self::fetch_keys_partial`]
+    /// (which doesn't).
+    fn fetch_raw_values(ssh: &SshInfo, keys: &[&str]) -> Result<HashMap<String, Result<String>>> {
         ensure_testing_rsa_is_there()?;
         // First, list up all the keys to retrieve from a DUT
         let mut keys_from_dut = HashSet::new();
@@ -381,33 +665,127 @@ impl DutInfo {
 
         info!("Fetching info for {:?}...", ssh);
         let result = ssh.run_cmd_stdio(&cmds)?;
-        let values: HashMap<String, Result<String>> = result
+        Ok(result
             .split('\n')
             .zip(keys_from_dut.iter())
             .map(|(line, key)| -> (String, Result<String>) {
                 let value = Self::decode_result_line(line, key);
                 (key.to_string(), value)
             })
-            .collect();
+            .collect())
+    }
+    pub fn fetch_keys(ssh: &SshInfo, keys: &Vec<&str>) -> Result<HashMap<String, String>> {
+        let values = Self::fetch_raw_values(ssh, keys)?;
         Self::parse_values(keys, values)
     }
+    /// Like [`Self::fetch_keys`], but reports a failure on an individual key
+    /// as `Err` in that key's slot instead of aborting the whole fetch, so a
+    /// JSON caller can still see the attributes that did succeed.
+    pub fn fetch_keys_partial(
+        ssh: &SshInfo,
+        keys: &Vec<&str>,
+    ) -> Result<HashMap<String, Result<String, String>>> {
+        let values = Self::fetch_raw_values(ssh, keys)?;
+        Ok(Self::parse_values_partial(keys, values))
+    }
+    /// Like [`Self::parse_values`], but for each key in `keys` returns an
+    /// `Err` for that key alone instead of bailing out of the whole map.
+    fn parse_values_partial(
+        keys: &[&str],
+        mut values: HashMap<String, Result<String>>,
+    ) -> HashMap<String, Result<String, String>> {
+        if keys.contains(&"timestamp") {
+            values.insert("timestamp".to_string(), Ok(Local::now().to_string()));
+        }
+        if keys.contains(&"model") {
+            let model = match values.get("model_from_cros_config") {
+                Some(Ok(model)) => Ok(model.clone()),
+                _ => match values.get("model_from_mosys") {
+                    Some(Ok(model)) => Ok(model.clone()),
+                    _ => Err(anyhow!("Failed to get model")),
+                },
+            };
+            values.insert("model".to_string(), model);
+        }
+        if keys.contains(&"gbb_flags") {
+            let gbb_flags = match values.get("gbb_flags") {
+                Some(Ok(v)) => match RE_GBB_FLAGS.find(v) {
+                    Some(m) => Ok(m.as_str().to_string()),
+                    None => Err(anyhow!(
+                        "gbb_flags should match regex RE_GBB_FLAGS but got {v:?}"
+                    )),
+                },
+                _ => Err(anyhow!("Failed to get gbb_flags")),
+            };
+            values.insert("gbb_flags".to_string(), gbb_flags);
+        }
+        if keys.contains(&"dut_id") {
+            let serial = match values.get("serial") {
+                Some(Ok(serial)) => Some(serial.clone()),
+                _ => match values.get("mac") {
+                    Some(Ok(mac)) => {
+                        let serial = format!("NoSerial{}", mac.replace(':', "").to_lowercase());
+                        values.insert("serial".to_string(), Ok(serial.clone()));
+                        Some(serial)
+                    }
+                    _ => None,
+                },
+            };
+            let dut_id = match (serial, values.get("model")) {
+                (Some(serial), Some(Ok(model))) => Ok(format!("{model}_{serial}")),
+                _ => Err(anyhow!("Failed to get dut_id")),
+            };
+            values.insert("dut_id".to_string(), dut_id);
+        }
+        keys.iter()
+            .map(|&k| {
+                let v = match values.get(k) {
+                    Some(Ok(v)) => Ok(v.clone()),
+                    Some(Err(e)) => Err(format!("{e:#}")),
+                    None => Err(format!("key {k} was not fetched")),
+                };
+                (k.to_string(), v)
+            })
+            .collect()
+    }
 }
 
+/// A DUT reached through a port forward, when the real address isn't
+/// directly reachable (e.g. from inside a chroot). The forward itself is
+/// now owned by the daemon (see `start_ssh_forwarding_background`) and kept
+/// alive for as long as the daemon runs rather than torn down when this
+/// struct is dropped, so concurrent `cro3` invocations can share it.
 pub struct ForwardedDut {
     ssh: SshInfo,
-    forwarding_process: Option<async_process::Child>,
 }
 impl ForwardedDut {
     pub fn ssh(&self) -> &SshInfo {
         &self.ssh
     }
 }
-impl Drop for ForwardedDut {
+
+/// A pre-warmed `ControlMaster=auto` connection to a DUT, held open for as
+/// long as this struct is alive. Any `run_cmd_*`/`scp` call made against the
+/// same DUT while this is in scope reuses the multiplexed socket instead of
+/// paying for a fresh TCP+SSH handshake.
+pub struct ControlMaster {
+    ssh: SshInfo,
+    control_path: String,
+    master_process: async_process::Child,
+}
+impl ControlMaster {
+    pub fn ssh(&self) -> &SshInfo {
+        &self.ssh
+    }
+}
+impl Drop for ControlMaster {
     fn drop(&mut self) {
-        if let Some(forwarding_process) = &mut self.forwarding_process {
-            info!("Sending SIGTERM to the forwarding process {}", forwarding_process.id());
-            nix::sys::signal::kill(nix::unistd::Pid::from_raw(forwarding_process.id() as i32), nix::sys::signal::Signal::SIGTERM).expect("failed to kill");
-        }
+        info!("Sending SIGTERM to the ControlMaster process {}", self.master_process.id());
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(self.master_process.id() as i32), nix::sys::signal::Signal::SIGTERM).expect("failed to kill");
+        // The master socket is normally removed by ssh itself on exit, but
+        // clean up any stale file left behind by a crashed or SIGKILLed
+        // master so the next connection doesn't trip over a dead socket.
+        let _ = std::fs::remove_file(&self.control_path);
     }
 }
 
@@ -423,7 +801,9 @@ impl SshInfo {
     pub fn ping(&self) -> Result<()> {
         let host = &self.host;
         let output = run_bash_command(&format!("ping -c 1 -W 0.5 {host} 1>/dev/null 2>&1"), None)?;
-        output.status.exit_ok().context("Failed to ping")
+        output.status.exit_ok().context("Failed to ping")?;
+        note_connection_state(self, ConnectionState::Ssh);
+        Ok(())
     }
     pub fn new(dut: &str) -> Result<Self> {
         if let Ok(Some(resolved)) = SSH_CACHE.get(dut) {
@@ -487,26 +867,71 @@ impl SshInfo {
     }
     pub fn into_forwarded(&self) -> Result<ForwardedDut> {
         if self.needs_port_forwarding_in_chroot() {
-            let (port, forwarding_process) = self.start_ssh_forwarding_background()?;
+            let port = self.start_ssh_forwarding_background()?;
             let ssh = Self::new_host_and_port("127.0.0.1", port)?;
-            Ok(ForwardedDut {
-                ssh,
-                forwarding_process: Some(forwarding_process),
-            })
+            Ok(ForwardedDut { ssh })
         } else {
-            Ok(ForwardedDut {
-                ssh: self.clone(),
-                forwarding_process: None,
-            })
+            Ok(ForwardedDut { ssh: self.clone() })
         }
     }
+    /// Path to the ControlMaster socket used to multiplex connections to this
+    /// DUT, so repeated `run_cmd_piped`/`get_files`/`send_files`/
+    /// `start_port_forwarding` calls ride a single already-authenticated TCP
+    /// connection instead of paying for a fresh handshake every time.
+    ///
+    /// This would ideally be keyed off the DUT's stable [`KeyInfo::key()`]
+    /// (model+serial) instead of its address, so two cro3 invocations that
+    /// reach the same physical DUT via different IPs still share a master --
+    /// but `KeyInfo` is itself derived from values fetched *through* an SSH
+    /// connection, so there is no key to hash until after the control socket
+    /// this function names has already been used to connect. We key off
+    /// `host_and_port()` instead, which is everything callers have before
+    /// that first connection.
+    ///
+    /// We'd also rather hand ssh an abstract (non-filesystem) unix-domain
+    /// socket the way sccache's server socket does, so stale sockets can
+    /// never collide on disk and nothing needs cleaning up on exit. OpenSSH's
+    /// `ControlPath`, though, always `bind()`s a real filesystem path -- it
+    /// has no notion of Linux's abstract socket namespace -- so there's no
+    /// flag that gets us that. The closest practical equivalent is hashing
+    /// the address into a short, fixed-width name: it keeps the path well
+    /// under the ~104-byte `sockaddr_un` limit regardless of how long the
+    /// DUT's hostname is, and collisions are as unlikely as the hash itself.
+    fn control_path(&self) -> Result<String> {
+        let mut hasher = DefaultHasher::new();
+        self.host_and_port().hash(&mut hasher);
+        let path = gen_path_in_lium_dir(&format!("ssh_control/{:016x}", hasher.finish()))?;
+        Ok(path.to_string_lossy().to_string())
+    }
     fn gen_ssh_options(&self) -> Result<Vec<String>> {
         let mut args: Vec<String> = Vec::from(COMMON_SSH_OPTIONS)
             .iter()
             .map(|s| s.to_string())
             .collect();
+        args.extend(
+            [
+                "-o".to_string(),
+                "ControlMaster=auto".to_string(),
+                "-o".to_string(),
+                format!("ControlPath={}", self.control_path()?),
+                "-o".to_string(),
+                "ControlPersist=10m".to_string(),
+            ]
+            .into_iter(),
+        );
+        for v in self.matching_ssh_overrides()? {
+            args.extend(v.ssh_options().iter().map(|e| e.to_owned()));
+            args.extend(v.crypto_ssh_options());
+            args.extend(v.jump_ssh_options());
+        }
+        Ok(args)
+    }
+    /// `ssh_overrides` entries (see [`Config::ssh_overrides`]) whose host
+    /// regex matches this DUT and whose `shell_condition` (if any) passed.
+    fn matching_ssh_overrides(&self) -> Result<Vec<SshOverride>> {
         let host = &self.host;
         let config = Config::read()?;
+        let mut matched = Vec::new();
         for (k, v) in config.ssh_overrides() {
             if !Regex::new(k)
                 .context("Failed to compile regex for ssh overrides")?
@@ -515,9 +940,9 @@ impl SshInfo {
             {
                 continue;
             }
-            args.extend(v.ssh_options().iter().map(|e| e.to_owned()));
+            matched.push(v.clone());
         }
-        Ok(args)
+        Ok(matched)
     }
 
     fn gen_ssh_args(&self, optional_args: Option<&[&str]>) -> Result<Vec<String>> {
@@ -587,6 +1012,63 @@ impl SshInfo {
 
         Ok(args)
     }
+    /// Whether `ssh_backend` config is set to the in-process `ssh2`
+    /// transport instead of shelling out to the system `ssh`/`scp`.
+    fn uses_native_backend() -> bool {
+        Config::read()
+            .map(|c| c.ssh_backend() == "native")
+            .unwrap_or(false)
+    }
+    /// Returns this DUT's pooled native ssh2 session for the `ssh_backend =
+    /// "native"` code paths, connecting and caching one on first use instead
+    /// of paying for a fresh TCP+crypto handshake on every `run_cmd_*`/
+    /// `get_*` call. This is what lets `DutInfo::from_ssh`'s chain of probes
+    /// (and the parallel DUTs in `fetch_dut_info_in_parallel`) reuse a
+    /// connection per DUT rather than forking an `ssh` process each.
+    fn native_session(&self) -> Result<Arc<Mutex<NativeSshSession>>> {
+        let key = self.host_and_port();
+        if let Some((session, last_used)) = NATIVE_SESSION_POOL.lock().unwrap().get_mut(&key) {
+            if session.lock().unwrap().send_keepalive().is_ok() {
+                *last_used = Instant::now();
+                return Ok(session.clone());
+            }
+            // The pooled session no longer responds (DUT rebooted, link
+            // dropped); fall through and reconnect from scratch below.
+            self.evict_native_session();
+        }
+        let testing_rsa = home_dir()
+            .context("Failed to determine home dir")?
+            .join(".ssh/testing_rsa");
+        let crypto = SshCryptoPrefs::from_overrides(&self.matching_ssh_overrides()?);
+        let session = Arc::new(Mutex::new(NativeSshSession::connect(
+            &key,
+            &testing_rsa,
+            &crypto,
+        )?));
+        let mut pool = NATIVE_SESSION_POOL.lock().unwrap();
+        if pool.len() >= MAX_NATIVE_SESSIONS && !pool.contains_key(&key) {
+            if let Some(lru_key) = pool
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(k, _)| k.clone())
+            {
+                pool.remove(&lru_key);
+            }
+        }
+        // If another thread raced us and already inserted a session for this
+        // DUT, keep that one instead of the one we just opened.
+        Ok(pool
+            .entry(key)
+            .or_insert((session, Instant::now()))
+            .0
+            .clone())
+    }
+    /// Drops this DUT's pooled native session, if any, so the next native
+    /// call reconnects from scratch instead of reusing one that may have
+    /// gone stale (DUT rebooted, link dropped).
+    fn evict_native_session(&self) {
+        NATIVE_SESSION_POOL.lock().unwrap().remove(&self.host_and_port());
+    }
     pub fn scp_get_cmd(&self, files: &[String], dest: Option<&String>) -> Result<Command> {
         let mut cmd = Command::new("scp");
         cmd.args(self.gen_scp_get_args(files, dest)?);
@@ -617,30 +1099,56 @@ impl SshInfo {
         &self,
         arg: &[T],
     ) -> Result<()> {
-        let mut ssh = self.ssh_cmd(None)?;
-        ssh.args(arg);
-        let cmd = ssh.spawn()?;
-        let result = cmd.wait_with_output()?;
-        result.status.exit_ok().context(anyhow!(
-            "run_cmd_piped failed with {:?}. cmd = {:?}",
-            result.status.code(),
-            arg
-        ))
-    }
-    fn run_cmd_captured(&self, cmd: &str) -> Result<Output> {
-        let mut ssh = self.ssh_cmd(None)?;
-        ssh.arg(cmd).stdout(Stdio::piped()).stderr(Stdio::piped());
-        let cmd = ssh.spawn()?;
-        let output = cmd
-            .wait_with_output()
-            .context("wait_with_output failed in run_cmd_captured")?;
-        if output.status.success() {
-            Ok(output)
+        let result: Result<()> = if Self::uses_native_backend() {
+            let cmd: Vec<&str> = arg.iter().map(|s| AsRef::<str>::as_ref(s)).collect();
+            self.native_session()?
+                .lock()
+                .unwrap()
+                .run_cmd_piped(&cmd.join(" "))
+        } else {
+            let mut ssh = self.ssh_cmd(None)?;
+            ssh.args(arg);
+            let cmd = ssh.spawn()?;
+            let result = cmd.wait_with_output()?;
+            result.status.exit_ok().context(anyhow!(
+                "run_cmd_piped failed with {:?}. cmd = {:?}",
+                result.status.code(),
+                arg
+            ))
+        };
+        if result.is_ok() {
+            note_connection_state(self, ConnectionState::Ssh);
+        }
+        result
+    }
+    /// Run `cmd` on the DUT and return its exit code, stdout and stderr
+    /// separately, without failing on a non-zero exit status. This lets
+    /// callers branch on the real exit code (e.g. `switch_partition_set`)
+    /// instead of string-matching piped output.
+    pub fn run_cmd_captured(&self, args: &[&str]) -> Result<(i32, String, String)> {
+        let result = if Self::uses_native_backend() {
+            self.native_session()?
+                .lock()
+                .unwrap()
+                .run_cmd_captured(&args.join(" "))
         } else {
-            let stdout = get_stdout(&output);
-            let stderr = get_stderr(&output);
-            bail!("run_cmd_captured failed: {} {}", stdout, stderr)
+            let mut ssh = self.ssh_cmd(None)?;
+            ssh.args(args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let child = ssh.spawn()?;
+            let output = child
+                .wait_with_output()
+                .context("wait_with_output failed in run_cmd_captured")?;
+            let code = output.status.code().unwrap_or(-1);
+            Ok((code, get_stdout(&output), get_stderr(&output)))
+        };
+        // The round-trip itself succeeding (regardless of the remote
+        // command's exit code) is what tells us the DUT is SSH-reachable.
+        if result.is_ok() {
+            note_connection_state(self, ConnectionState::Ssh);
         }
+        result
     }
     pub fn open_ssh(&self) -> Result<()> {
         let cmd = self.ssh_cmd(None)?.spawn()?;
@@ -651,6 +1159,46 @@ impl SshInfo {
             exit_status.code()
         )))
     }
+    /// Fork a `ControlMaster=auto` connection into the background with `ssh
+    /// -f` and return once it is established, so it outlives this process.
+    /// `ControlPersist` keeps it around for subsequent commands to reuse;
+    /// ssh removes the socket itself once that persist window expires.
+    pub fn prewarm_control_master(&self) -> Result<()> {
+        let status = self
+            .ssh_cmd(Some(&["-M", "-N", "-f"]))?
+            .stdin(Stdio::null())
+            .status()
+            .context("Failed to spawn the ControlMaster process")?;
+        status
+            .exit_ok()
+            .context("Failed to establish the ControlMaster connection")
+    }
+    /// Like [`Self::prewarm_control_master`], but keeps the master
+    /// connection in this process instead of forking it away, so the
+    /// returned `ControlMaster` can tear it down (and clean up its socket)
+    /// as soon as it is dropped.
+    pub fn connect_persistent(&self) -> Result<ControlMaster> {
+        let control_path = self.control_path()?;
+        let master_process = self
+            .ssh_cmd_async(Some(&["-M", "-N"]))?
+            .kill_on_drop(false)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to spawn the ControlMaster process")?;
+        for _ in 0..50 {
+            if Path::new(&control_path).exists() {
+                return Ok(ControlMaster {
+                    ssh: self.clone(),
+                    control_path,
+                    master_process,
+                });
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        bail!("Timed out waiting for the ControlMaster socket to appear at {control_path}")
+    }
 
     pub fn start_port_forwarding(
         &self,
@@ -729,56 +1277,84 @@ impl SshInfo {
 
         bail!("Could not find a port available for forwarding")
     }
-    /// Keep forwarding in background.
-    /// The execution will be blocked until the first attemp succeeds, and the
-    /// return value represents which port is used for this forwarding, or an
-    /// error. Forwarding port on this side will be automatically determined by
-    /// start_ssh_forwarding, and the same port will be used for reconnecting
-    /// while this cro3 instance is running.
-    fn start_ssh_forwarding_background_in_range(&self, port_range: RangeInclusive<u16>) -> Result<(u16, async_process::Child)> {
-        let port_file = tempfile::NamedTempFile::new()?;
-        let port_file_path = port_file.into_temp_path();
-        let child = async_process::Command::new(current_exe()?)
-            .args(&[
-                "dut",
-                "forward",
-                "--dut",
-                &self.host_and_port(),
-                "--port-file",
-                &port_file_path.as_os_str().to_string_lossy(),
-                "--port-first",
-                &format!("{}", port_range.start()),
-                "--port-last",
-                &format!("{}", port_range.end()),
-            ])
-            .kill_on_drop(false)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        let port = retry(retry::delay::Fixed::from_millis(1000).take(60), || -> Result<u16> {
-            info!("setting up a port forwarding...");
-            let port = std::fs::read_to_string(&port_file_path)?;
-            let port: u16 = port.trim().parse()?;
-            Result::Ok(port)
-        }).or(Err(anyhow!("Failed to establish the port forwarding")))?;
-        Ok((port, child))
-    }
-    pub fn start_ssh_forwarding_background(&self) -> Result<(u16, async_process::Child)> {
+    /// Asks the DUT daemon (auto-starting it if it isn't already running) to
+    /// establish, or reuse, a forward from a free local port in `port_range`
+    /// to this DUT's SSH port. The forward is owned and kept alive by the
+    /// daemon itself rather than by this call's process, so concurrent
+    /// `cro3` invocations asking for the same DUT share it instead of each
+    /// spawning and racing their own.
+    fn start_ssh_forwarding_background_in_range(&self, port_range: RangeInclusive<u16>) -> Result<u16> {
+        daemon_client::ensure_running()?;
+        match daemon_client::query(&daemon_client::DaemonRequest::Forward {
+            dut: self.host_and_port(),
+            port_first: *port_range.start(),
+            port_last: *port_range.end(),
+        })? {
+            daemon_client::DaemonResponse::Forward { port } => Ok(port),
+            daemon_client::DaemonResponse::Error(e) => bail!("daemon failed to set up forwarding: {e}"),
+            other => bail!("unexpected daemon response to a Forward request: {other:?}"),
+        }
+    }
+    pub fn start_ssh_forwarding_background(&self) -> Result<u16> {
         self.start_ssh_forwarding_background_in_range(4100..=4200)
     }
+    /// Run `cmd` on the DUT and return its stdout, failing on a non-zero exit
+    /// status. Built on top of `run_cmd_captured` (rather than shelling out
+    /// directly) so that native-backend calls -- and the pooled session it
+    /// uses -- cover this, the method `get_board`/`get_arch`/`get_rootdev`/
+    /// `DutInfo::fetch_keys` all funnel through.
     pub fn run_cmd_stdio(&self, cmd: &str) -> Result<String> {
-        let output = self.run_cmd_captured(cmd)?;
-        if output.status.success() {
-            Ok(get_stdout(&output))
+        let (code, stdout, stderr) = self.run_cmd_captured(&[cmd])?;
+        if code == 0 {
+            Ok(stdout)
         } else {
-            Err(anyhow!(
-                "run_cmd_stdio failed: {} {}",
-                get_stderr(&output),
-                get_stdout(&output)
-            ))
+            Err(anyhow!("run_cmd_stdio failed: {} {}", stderr, stdout))
         }
     }
+    /// Runs `cmd` on the DUT, invoking `on_line` for each line of stdout/
+    /// stderr as it arrives, instead of buffering the whole output like
+    /// `run_cmd_captured` does. Useful for long-running operations
+    /// (autologin, image flashing, test runs) where a caller wants to show
+    /// live progress, or wants to implement a timeout by simply giving up on
+    /// reading further lines instead of waiting for the command to exit.
+    pub fn run_cmd_streamed<F: FnMut(StreamedLine)>(
+        &self,
+        cmd: &str,
+        mut on_line: F,
+    ) -> Result<i32> {
+        if Self::uses_native_backend() {
+            return self
+                .native_session()?
+                .lock()
+                .unwrap()
+                .run_cmd_streamed(cmd, &mut on_line);
+        }
+        block_on(async {
+            let mut child = self
+                .ssh_cmd_async(None)?
+                .arg(cmd)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context("Failed to spawn the ssh child")?;
+            let (stdout, stderr) = get_async_lines(&mut child);
+            let stdout = stdout
+                .context("ssh stdout was None")?
+                .map(|l| l.map(StreamedLine::Stdout));
+            let stderr = stderr
+                .context("ssh stderr was None")?
+                .map(|l| l.map(StreamedLine::Stderr));
+            let mut merged = stream::select(stdout, stderr);
+            while let Some(line) = merged.next().await {
+                on_line(line.context("Failed to read a line from the ssh child")?);
+            }
+            let status = child
+                .status()
+                .await
+                .context("Failed to wait for the ssh child")?;
+            Ok(status.code().unwrap_or(-1))
+        })
+    }
     pub fn run_autologin(&self) -> Result<()> {
         self.run_cmd_piped(&["/usr/local/autotest/bin/autologin.py", "-a", "-d"])
     }
@@ -844,6 +1420,13 @@ impl SshInfo {
         ))
     }
     pub fn get_files(&self, files: &[String], dest: Option<&String>) -> Result<()> {
+        if Self::uses_native_backend() {
+            return self
+                .native_session()?
+                .lock()
+                .unwrap()
+                .get_files(files, dest.map(|s| s.as_str()));
+        }
         let mut cmd = self.scp_get_cmd(files, dest)?;
         let chd = cmd.stderr(Stdio::piped()).spawn()?;
         let result = chd.wait_with_output()?;
@@ -856,6 +1439,13 @@ stderr:
         ))
     }
     pub fn send_files(&self, files: &[String], dest: Option<&String>) -> Result<()> {
+        if Self::uses_native_backend() {
+            return self
+                .native_session()?
+                .lock()
+                .unwrap()
+                .send_files(files, dest.map(|s| s.as_str()));
+        }
         let mut cmd = self.scp_send_cmd(files, dest)?;
         let chd = cmd.stderr(Stdio::piped()).spawn()?;
         let result = chd.wait_with_output()?;
@@ -865,6 +1455,38 @@ stderr:
             stderr
         ))
     }
+    /// Makes sure the remote host has a copy of the current `lium` binary
+    /// cached at a versioned path, uploading it only if missing, and
+    /// returns the remote path to exec. This avoids re-uploading the
+    /// executable (and racing with concurrent discoveries) on every
+    /// `--remote` invocation.
+    pub fn ensure_remote_lium(&self, local_exe: &str) -> Result<String> {
+        const VERSION: &str = env!("CARGO_PKG_VERSION");
+        let remote_dir = "~/.cache/lium/bin";
+        let remote_path = format!("{remote_dir}/lium-{VERSION}");
+        self.run_cmd_stdio(&format!("mkdir -p {remote_dir}"))?;
+        let already_cached = self
+            .run_cmd_stdio(&format!(
+                "test -x {remote_path} && echo yes || echo no"
+            ))
+            .map(|s| s.trim() == "yes")
+            .unwrap_or(false);
+        if already_cached {
+            info!("Remote already has lium v{VERSION} cached at {remote_path}");
+            return Ok(remote_path);
+        }
+        info!("Uploading lium v{VERSION} to {remote_path} on the remote...");
+        self.send_files(&[local_exe.to_string()], Some(&remote_dir.to_string()))?;
+        let uploaded_name = Path::new(local_exe)
+            .file_name()
+            .context("Failed to get the executable file name")?
+            .to_string_lossy()
+            .to_string();
+        self.run_cmd_stdio(&format!(
+            "mv {remote_dir}/{uploaded_name} {remote_path} && chmod +x {remote_path}"
+        ))?;
+        Ok(remote_path)
+    }
     pub fn switch_partition_set(&self, target: PartitionSet) -> Result<()> {
         let rootdev = self.get_rootdev()?;
         let rootdisk = self.get_rootdisk()?;
@@ -891,19 +1513,510 @@ stderr:
                 format!("cgpt prioritize -P2 -i {other_kern} {rootdisk}")
             }
         };
-        self.run_cmd_piped(&[cmd])
+        let (code, stdout, stderr) = self.run_cmd_captured(&[&cmd])?;
+        if code != 0 {
+            bail!("cgpt prioritize exited with code {code}: stdout={stdout} stderr={stderr}");
+        }
+        Ok(())
+    }
+    /// Arms the reverse-callback boot signal `wait_online` prefers over
+    /// polling: binds an ephemeral listener on the host, forwards
+    /// `REBOOT_CALLBACK_DUT_PORT` on the DUT back to it over a dedicated
+    /// reverse SSH connection (`-R`, mirroring `start_port_forwarding`'s
+    /// `-L`), and drops a one-shot on the DUT that dials through that
+    /// forward and writes `REBOOT_CALLBACK_TOKEN` once userland is up.
+    ///
+    /// The forwarding connection has to stay alive for the one-shot's dial
+    /// attempts to have anywhere to connect to, so it's handed back to the
+    /// caller alongside the listener rather than killed here -- it must
+    /// outlive the `reboot; exit` this is arming for, and is only torn down
+    /// once `wait_for_reboot_callback` is done with it (see
+    /// `REBOOT_CALLBACK_WAIT_CAP`'s doc comment for why that can't be
+    /// relied on to fire after the DUT actually comes back up).
+    ///
+    /// Returns `Ok(None)` instead of erroring if any step fails (no route
+    /// back to the host, DUT doesn't have `/dev/tcp`, etc.), so `reboot()`
+    /// can fall back to `wait_online`'s plain polling.
+    fn arm_reboot_callback(&self) -> Option<(TcpListener, Child)> {
+        let listener = match TcpListener::bind("127.0.0.1:0") {
+            Ok(listener) => listener,
+            Err(e) => {
+                info!("Could not bind a reboot-callback listener: {e:#}");
+                return None;
+            }
+        };
+        let local_port = match listener.local_addr() {
+            Ok(addr) => addr.port(),
+            Err(e) => {
+                info!("Could not read back the reboot-callback listener's port: {e:#}");
+                return None;
+            }
+        };
+        let forward = self.ssh_cmd_async(Some(&[
+            "-R",
+            &format!("{REBOOT_CALLBACK_DUT_PORT}:127.0.0.1:{local_port}"),
+            "-N",
+            "-o",
+            "ExitOnForwardFailure yes",
+        ]));
+        let mut forward = match forward.and_then(|mut cmd| {
+            cmd.kill_on_drop(true)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .context("failed to spawn the reverse-forward ssh process")
+        }) {
+            Ok(child) => child,
+            Err(e) => {
+                info!("Could not set up the reboot-callback reverse forward: {e:#}");
+                return None;
+            }
+        };
+        // Give the forward a moment to finish its handshake before we arm
+        // the one-shot that depends on it already being up.
+        std::thread::sleep(Duration::from_millis(500));
+        let script = format!(
+            "(for i in $(seq 1 60); do \
+               if exec 3<>/dev/tcp/127.0.0.1/{REBOOT_CALLBACK_DUT_PORT}; then \
+                 echo -n {token} >&3; \
+                 break; \
+               fi; \
+               sleep 1; \
+             done) >/dev/null 2>&1 &",
+            token = String::from_utf8_lossy(REBOOT_CALLBACK_TOKEN),
+        );
+        if let Err(e) = self.run_cmd_piped(&["sh", "-c", &script]) {
+            info!("Could not arm the reboot callback on the DUT: {e:#}");
+            let _ = forward.kill();
+            return None;
+        }
+        Some((listener, forward))
     }
     pub fn reboot(&self) -> Result<()> {
-        self.run_cmd_piped(&["reboot; exit"])
+        if let Some(armed) = self.arm_reboot_callback() {
+            REBOOT_CALLBACK_LISTENER
+                .lock()
+                .unwrap()
+                .insert(self.host_and_port(), armed);
+        }
+        let result = self.run_cmd_piped(&["reboot; exit"]);
+        // The pooled native session (if any) is about to be severed by the
+        // reboot; drop it now so the next native call reconnects instead of
+        // trying to use a dead ssh2::Session.
+        self.evict_native_session();
+        if result.is_ok() {
+            // Overrides the `Ssh` state `run_cmd_piped` just recorded: SSH
+            // dropping right after this is expected, not a sign the DUT
+            // went away.
+            note_connection_state(self, ConnectionState::Rebooting);
+        }
+        result
+    }
+    /// Reads a value that is regenerated every boot, so it can be compared
+    /// across a reboot to prove that the machine actually went down and came
+    /// back, instead of SSH merely staying reachable throughout shutdown.
+    pub fn boot_id(&self) -> Result<String> {
+        self.run_cmd_stdio("cat /proc/sys/kernel/random/boot_id")
+            .map(|s| s.trim().to_string())
     }
     pub fn wait_online(&self) -> Result<()> {
-        retry(retry::delay::Fixed::from_millis(1000), || {
-            self.run_cmd_piped(&["echo ok"])
-        })
-        .or(Err(anyhow!("Timed out while waiting for DUT to be online")))
+        self.wait_online_with_timeout(Duration::from_secs(300))
+    }
+    /// Waits for the DUT to come back online, preferring the
+    /// reverse-callback signal a preceding `reboot()` call may have armed
+    /// (see `arm_reboot_callback`) over polling: that signal only fires once
+    /// the *new* boot's userland is up, so it can't latch onto a pre-reboot
+    /// sshd that hasn't gone down yet. This is a strictly-optional,
+    /// best-effort fast path -- the wait on it is capped at
+    /// `REBOOT_CALLBACK_WAIT_CAP` regardless of `timeout`, so a caller that
+    /// never gets the callback still falls back to the polling loop below
+    /// with most of its timeout budget intact, instead of burning the whole
+    /// thing waiting on a signal that may structurally be unable to fire.
+    fn wait_online_with_timeout(&self, timeout: Duration) -> Result<()> {
+        let armed = REBOOT_CALLBACK_LISTENER
+            .lock()
+            .unwrap()
+            .remove(&self.host_and_port());
+        if let Some((listener, mut forward)) = armed {
+            let callback_timeout = timeout.min(REBOOT_CALLBACK_WAIT_CAP);
+            let callback_result = self.wait_for_reboot_callback(&listener, callback_timeout);
+            let _ = forward.kill();
+            if callback_result.is_ok() {
+                return Ok(());
+            }
+            info!("Reboot callback didn't fire in time; falling back to polling SSH");
+        }
+        self.poll_until_online(timeout)
+    }
+    /// Blocks on `listener.accept()` until a connection arrives carrying
+    /// `REBOOT_CALLBACK_TOKEN` or `timeout` elapses, then does a single
+    /// `echo ok` round-trip to confirm SSH itself (not just the one-shot) is
+    /// up before declaring success.
+    fn wait_for_reboot_callback(&self, listener: &TcpListener, timeout: Duration) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            match listener.accept() {
+                Ok((mut stream, _)) => {
+                    let mut buf = Vec::new();
+                    if stream.read_to_end(&mut buf).is_ok() && buf == REBOOT_CALLBACK_TOKEN {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("failed to accept on the reboot-callback listener"),
+            }
+            if std::time::Instant::now() >= deadline {
+                bail!("Timed out while waiting for the reboot callback");
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        self.run_cmd_piped(&["echo ok"])
+    }
+    /// Polls SSH with exponential backoff (1s growing to a 10s cap) until a
+    /// connection succeeds or `timeout` elapses.
+    fn poll_until_online(&self, timeout: Duration) -> Result<()> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut delay = Duration::from_secs(1);
+        loop {
+            if self.run_cmd_piped(&["echo ok"]).is_ok() {
+                return Ok(());
+            }
+            if std::time::Instant::now() >= deadline {
+                bail!("Timed out while waiting for DUT to be online");
+            }
+            std::thread::sleep(delay);
+            delay = std::cmp::min(delay * 2, Duration::from_secs(10));
+        }
+    }
+    /// Waits for the DUT to come back online after a reboot, and only
+    /// declares success once SSH is reachable AND `boot_id()` differs from
+    /// `boot_id_before_reboot`. An unchanged boot_id means the reboot never
+    /// actually took effect (e.g. sshd stayed up through a failed reboot),
+    /// so this keeps retrying until `timeout` elapses rather than returning
+    /// a false positive.
+    pub fn wait_for_reboot(&self, boot_id_before_reboot: &str, timeout: Duration) -> Result<()> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut delay = Duration::from_secs(1);
+        loop {
+            if let Ok(boot_id) = self.boot_id() {
+                if boot_id != boot_id_before_reboot {
+                    return Ok(());
+                }
+                info!("DUT is reachable but boot_id is unchanged, still waiting for reboot...");
+            }
+            if std::time::Instant::now() >= deadline {
+                bail!("Timed out while waiting for the DUT to reboot");
+            }
+            std::thread::sleep(delay);
+            delay = std::cmp::min(delay * 2, Duration::from_secs(10));
+        }
+    }
+    /// Reboots the DUT and waits for it to come back, preferring the
+    /// phone-home callback `wait_online` arms over polling, and only
+    /// declaring success once `boot_id()` has actually changed -- not just
+    /// once SSH is reachable again. Returns a [`WaitForBootError`] that
+    /// distinguishes *why* it didn't come back, so callers can react
+    /// differently (retry a plain timeout, but flag a broken image on
+    /// `UptimeDidNotReset`) instead of pattern-matching an opaque error
+    /// string.
+    pub fn reboot_and_wait(&self, timeout: Duration) -> Result<(), WaitForBootError> {
+        let boot_id_before = self.boot_id().ok();
+        self.reboot().map_err(WaitForBootError::RebootFailed)?;
+        let deadline = std::time::Instant::now() + timeout;
+        if self.wait_online_with_timeout(timeout).is_err() {
+            // Distinguish "never came back" from "came back, but SSH itself
+            // is failing" with one more direct TCP connect to the SSH port:
+            // if that succeeds, the DUT is up and listening, so whatever
+            // `wait_online` hit was an auth/protocol failure, not the DUT
+            // being down.
+            let ssh_port_reachable = self
+                .host_and_port()
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .map(|addr| TcpStream::connect_timeout(&addr, Duration::from_secs(3)).is_ok())
+                .unwrap_or(false);
+            return Err(if ssh_port_reachable {
+                WaitForBootError::SshAuthFailed(anyhow!(
+                    "the SSH port is reachable but authentication/round-trip to it kept failing"
+                ))
+            } else {
+                WaitForBootError::TimedOut
+            });
+        }
+        if let Some(boot_id_before) = &boot_id_before {
+            let remaining = deadline
+                .saturating_duration_since(std::time::Instant::now())
+                .max(Duration::from_secs(1));
+            if self.wait_for_reboot(boot_id_before, remaining).is_err() {
+                return Err(WaitForBootError::UptimeDidNotReset);
+            }
+        }
+        Ok(())
+    }
+    /// A readiness transition reported by [`SshInfo::wait_until_ready`], so a
+    /// caller can log "waiting for DUT..." the same way
+    /// [`MonitoredDut::reconnect`] logs "Reconnecting...", without
+    /// re-implementing the polling here.
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        self.wait_until_ready_with_progress(timeout, |_event| {})
+    }
+    /// Like [`Self::wait_until_ready`], but calls `on_event` on every
+    /// transition (not on every poll) with the new state.
+    ///
+    /// Each iteration first attempts a bare TCP connect to `port` -- cheap
+    /// enough to immediately catch "connection refused"/"timed out" while
+    /// the DUT is down or mid-reboot -- and only runs a real command over
+    /// SSH once that succeeds. Both steps, and the address resolution before
+    /// them, are treated as "not ready yet" on failure rather than as fatal,
+    /// so this tolerates the DUT's address disappearing and reappearing
+    /// (reboot, link-local IPv6 re-deriving) instead of racing the
+    /// still-open pre-reboot connection.
+    pub fn wait_until_ready_with_progress(
+        &self,
+        timeout: Duration,
+        mut on_event: impl FnMut(ReadinessEvent),
+    ) -> Result<()> {
+        const READY_TOKEN: &str = "cro3-dut-is-ready";
+        let deadline = std::time::Instant::now() + timeout;
+        let mut delay = Duration::from_secs(1);
+        let mut last_event = None;
+        loop {
+            let event = match self
+                .host_and_port()
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+            {
+                Some(addr) => match TcpStream::connect_timeout(&addr, Duration::from_secs(2)) {
+                    Ok(_) => match self.run_cmd_captured(&[&format!("echo {READY_TOKEN}")]) {
+                        Ok((0, stdout, _)) if stdout.trim() == READY_TOKEN => ReadinessEvent::Ready,
+                        _ => ReadinessEvent::PortOpen,
+                    },
+                    Err(_) => ReadinessEvent::Unreachable,
+                },
+                None => ReadinessEvent::Unreachable,
+            };
+            if last_event != Some(event) {
+                on_event(event);
+                last_event = Some(event);
+            }
+            if event == ReadinessEvent::Ready {
+                return Ok(());
+            }
+            if std::time::Instant::now() >= deadline {
+                bail!(match event {
+                    ReadinessEvent::Unreachable => format!(
+                        "Timed out waiting for {self:?} to become ready: never reachable \
+                         (TCP connect to the SSH port never succeeded)"
+                    ),
+                    ReadinessEvent::PortOpen => format!(
+                        "Timed out waiting for {self:?} to become ready: reachable but the \
+                         readiness command never succeeded over SSH"
+                    ),
+                    ReadinessEvent::Ready => unreachable!("handled above"),
+                });
+            }
+            std::thread::sleep(delay);
+            delay = std::cmp::min(delay * 2, Duration::from_secs(10));
+        }
+    }
+    /// Starts tailing `source`'s logs from this DUT on a background thread
+    /// that never gives up: whenever the stream drops (transient SSH
+    /// hiccup, or the DUT rebooting out from under it) it calls
+    /// `wait_online` and reconnects, replaying the last `backlog_lines` of
+    /// history and deduping against what was already delivered. Every line
+    /// is tagged with the DUT's `KeyInfo::key()` (falling back to its
+    /// address if that can't be determined) and a receive timestamp, sent
+    /// to the returned channel, and -- if `sink` is given -- appended to
+    /// that file. Like [`Self::arm_reboot_callback`], failures along the
+    /// way are logged and retried rather than propagated, since the point
+    /// of this is to keep watching for as long as something is listening.
+    pub fn stream_logs(
+        &self,
+        source: LogSource,
+        backlog_lines: u32,
+        sink: Option<PathBuf>,
+    ) -> mpsc::Receiver<LogLine> {
+        let ssh = self.clone();
+        let dut_key = DutInfo::new(&ssh.host_and_port())
+            .map(|info| info.id().to_string())
+            .unwrap_or_else(|_| ssh.host_and_port());
+        let (tx, rx) = mpsc::channel();
+        let tail_cmd = source.tail_cmd(backlog_lines);
+        std::thread::spawn(move || {
+            let mut buffer = LogBuffer::new((backlog_lines as usize).max(1));
+            let mut sink = sink.and_then(|path| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| warn!("Failed to open log sink {path:?}: {e:#}"))
+                    .ok()
+            });
+            loop {
+                info!("[{dut_key}] Streaming {source:?} logs...");
+                let result = block_on(async {
+                    let mut child = ssh
+                        .ssh_cmd_async(None)?
+                        .arg(&tail_cmd)
+                        .stdout(Stdio::piped())
+                        .spawn()
+                        .context("Failed to start log stream")?;
+                    let (stdout, _stderr) = get_async_lines(&mut child);
+                    let mut stdout = stdout.context("log stream stdout was None")?;
+                    while let Some(line) = stdout.next().await {
+                        let text = line.context("Failed to read a log line")?;
+                        if buffer.seen(&text) {
+                            continue;
+                        }
+                        buffer.push(text.clone());
+                        let line = LogLine {
+                            dut_key: dut_key.clone(),
+                            timestamp: Local::now(),
+                            source,
+                            text,
+                        };
+                        if let Some(sink) = &mut sink {
+                            let _ = writeln!(sink, "[{}] {}: {}", line.timestamp, line.dut_key, line.text);
+                        }
+                        if tx.send(line).is_err() {
+                            // The receiver was dropped; no one is listening
+                            // anymore, so stop tailing entirely.
+                            return Result::<bool>::Ok(false);
+                        }
+                    }
+                    child.status().await.context("log stream child process failed")?;
+                    Result::<bool>::Ok(true)
+                });
+                match result {
+                    Ok(false) => return,
+                    Ok(true) => info!("[{dut_key}] Log stream ended, reconnecting..."),
+                    Err(e) => warn!("[{dut_key}] Log stream disconnected ({e:#}), reconnecting..."),
+                }
+                if let Err(e) = ssh.wait_online() {
+                    warn!("[{dut_key}] DUT didn't come back online ({e:#}); retrying the log stream anyway");
+                }
+            }
+        });
+        rx
     }
 }
 
+/// Why [`SshInfo::reboot_and_wait`] gave up, so callers can react
+/// differently instead of pattern-matching an opaque error string.
+#[derive(Debug)]
+pub enum WaitForBootError {
+    /// The reboot command itself could not be issued.
+    RebootFailed(anyhow::Error),
+    /// Neither the phone-home callback nor polling saw the DUT come back
+    /// online within the timeout.
+    TimedOut,
+    /// The DUT is reachable over the network again, but SSH
+    /// authentication/round-tripping to it keeps failing (e.g. a host key
+    /// or image mismatch after the reboot).
+    SshAuthFailed(anyhow::Error),
+    /// SSH came back up, but `boot_id()` never changed: the reboot never
+    /// actually took effect (sshd survived a failed/aborted reboot).
+    UptimeDidNotReset,
+}
+impl std::fmt::Display for WaitForBootError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WaitForBootError::RebootFailed(e) => write!(f, "failed to issue the reboot: {e:#}"),
+            WaitForBootError::TimedOut => {
+                write!(f, "timed out waiting for the DUT to come back online")
+            }
+            WaitForBootError::SshAuthFailed(e) => write!(
+                f,
+                "DUT is reachable but SSH authentication/round-trip failed: {e:#}"
+            ),
+            WaitForBootError::UptimeDidNotReset => write!(
+                f,
+                "DUT is reachable over SSH but boot_id never changed; the reboot did not take effect"
+            ),
+        }
+    }
+}
+impl std::error::Error for WaitForBootError {}
+
+/// One line of output from [`SshInfo::run_cmd_streamed`], tagged by which
+/// stream it came from.
+#[derive(Debug, Clone)]
+pub enum StreamedLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Which on-DUT log [`SshInfo::stream_logs`] tails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSource {
+    /// `/var/log/messages`, the general CrOS syslog.
+    Messages,
+    /// The live kernel ring buffer.
+    Dmesg,
+    /// ARC container/VM logs, via `android-sh`.
+    Logcat,
+}
+impl LogSource {
+    fn tail_cmd(&self, backlog_lines: u32) -> String {
+        match self {
+            LogSource::Messages => format!("tail -n {backlog_lines} -F /var/log/messages"),
+            LogSource::Dmesg => format!("dmesg -w | tail -n {backlog_lines}"),
+            LogSource::Logcat => format!("android-sh -c 'logcat' 2>/dev/null | tail -n {backlog_lines}"),
+        }
+    }
+}
+
+/// A single line captured by [`SshInfo::stream_logs`], tagged with the DUT
+/// it came from and when it was received.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub dut_key: String,
+    pub timestamp: chrono::DateTime<Local>,
+    pub source: LogSource,
+    pub text: String,
+}
+
+/// Bounded window of the most recently streamed log lines, used to dedupe
+/// the replayed tail when [`SshInfo::stream_logs`] reconnects after a
+/// dropped SSH session or DUT reboot.
+struct LogBuffer {
+    lines: std::collections::VecDeque<String>,
+    cap: usize,
+}
+impl LogBuffer {
+    fn new(cap: usize) -> Self {
+        Self {
+            lines: std::collections::VecDeque::with_capacity(cap),
+            cap,
+        }
+    }
+    fn seen(&self, line: &str) -> bool {
+        self.lines.iter().any(|l| l == line)
+    }
+    fn push(&mut self, line: String) {
+        if self.lines.len() == self.cap {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+/// A state reported by [`SshInfo::wait_until_ready_with_progress`] as it
+/// polls a DUT back to readiness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadinessEvent {
+    /// The address couldn't be resolved, or the TCP port refused/timed out.
+    Unreachable,
+    /// TCP connected, but the command round-trip over SSH hasn't succeeded.
+    PortOpen,
+    /// The command round-tripped successfully; the DUT is ready.
+    Ready,
+}
+
 /// KeyInfo holds values that can identify a physical DUT uniquely
 #[derive(Clone, Debug)]
 pub struct KeyInfo {
@@ -929,14 +2042,17 @@ impl KeyInfo {
     }
 }
 
+/// DUTs in `SSH_CACHE` that are reachable, trusting a cached-fresh
+/// `ConnectionState` (see `ConnectionState::max_age`) instead of re-pinging
+/// entries we already know are alive.
 pub fn pingable_duts() -> Result<Vec<SshInfo>> {
     Ok(SSH_CACHE
         .entries()
         .context(anyhow!("SSH_CACHE is not initialized yet"))?
-        .iter()
-        .flat_map(|it| {
-            let ssh = it.1;
-            ssh.ping().and(Ok(ssh.clone()))
+        .into_values()
+        .flat_map(|ssh| match connection_state(&ssh) {
+            ConnectionState::Disconnected => ssh.ping().and(Ok(ssh)),
+            _ => Ok(ssh),
         })
         .collect())
 }
@@ -945,21 +2061,40 @@ pub fn fetch_dut_info_in_parallel(
     addrs: &Vec<String>,
     extra_attr: &[String],
 ) -> Result<Vec<DutInfo>> {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(std::cmp::min(16, addrs.len()))
-        .build_global()
-        .context("Failed to set thread count")?;
+    // A locally-built pool instead of `build_global()`: the global pool can
+    // only be built once per process, so a second discovery pass (e.g. a
+    // `cro3 dut discover --method both` run, or a daemon that discovers more
+    // than once) would panic/fail on a repeat call.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(std::cmp::min(16, addrs.len().max(1)))
+        .build()
+        .context("Failed to build a discovery thread pool")?;
+    pool.install(|| fetch_dut_info_in_parallel_inner(addrs, extra_attr))
+}
+
+fn fetch_dut_info_in_parallel_inner(
+    addrs: &Vec<String>,
+    extra_attr: &[String],
+) -> Result<Vec<DutInfo>> {
     Ok(addrs
         .par_iter()
         .flat_map(|addr| -> Result<DutInfo> {
-            let addr = &format!("[{}]", addr);
+            let bracketed = &format!("[{}]", addr);
             // Since we are listing the DUTs on the same network
             // so assume that port 22 is open for ssh
-            let ssh = SshInfo::new_host_and_port(addr, 22).context("failed to create SshInfo")?;
+            let ssh =
+                SshInfo::new_host_and_port(bracketed, 22).context("failed to create SshInfo")?;
             let dut = block_on(DutInfo::from_ssh(&ssh, extra_attr));
             match &dut {
                 Ok(_) => {
-                    info!("{} is a DUT :)", addr)
+                    info!("{} is a DUT :)", addr);
+                    // `from_ssh` already cached this under the derived
+                    // dut_id; also cache it under the raw address
+                    // (including a link-local `%zone`, if any) so it can be
+                    // addressed directly without a fresh discovery pass.
+                    if let Err(e) = SSH_CACHE.set(addr, ssh.clone()) {
+                        info!("Failed to cache {addr} in SSH_CACHE: {e:#}");
+                    }
                 }
                 Err(e) => {
                     info!("{} is not a DUT...(ToT) : {:#}", addr, e)
@@ -998,7 +2133,64 @@ pub fn discover_local_nodes(iface: Option<String>) -> Result<Vec<String>> {
     Ok(addrs)
 }
 
+/// Detects DUTs on the same network by reading the kernel's IPv6 neighbor
+/// table (`ip neigh`) instead of actively pinging it, complementing
+/// [`discover_local_nodes`]'s `ping6` sweep with addresses the kernel has
+/// already learned about (e.g. from recent traffic) but that a 3-round sweep
+/// may have missed.
+pub fn discover_arp_neighbors(iface: Option<String>) -> Result<Vec<String>> {
+    ensure_testing_rsa_is_there()?;
+    info!("Detecting DUTs via the neighbor table...");
+    let iface = iface
+        .ok_or(())
+        .or_else(|_| -> Result<String, anyhow::Error> {
+            let r = run_bash_command(CMD_GET_DEFAULT_IFACE, None)
+                .context("failed to determine interface to scan from ip route")?;
+            r.status.exit_ok()?;
+            Ok(get_stdout(&r).trim().to_string())
+        })
+        .context("Failed to determine interface to scan")?;
+    info!("Using {iface} to read neighbors...");
+    let output = run_bash_command(
+        &format!(
+            "ip neigh show dev {iface} | grep -E 'REACHABLE|STALE|DELAY' | cut -d ' ' -f 1 | \
+             sort | uniq"
+        ),
+        None,
+    )?;
+    let stdout = get_stdout(&output);
+    let addrs = stdout
+        .split('\n')
+        .map(str::to_string)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<String>>();
+    Ok(addrs)
+}
+
+/// Seeds `SSH_CACHE` with `addr` under `dut_id`, without connecting to it --
+/// for a `dut_id` known ahead of time from something like an mDNS TXT record
+/// (see [`crate::mdns::MdnsDut::dut_id`]), so callers racing the eventual SSH
+/// probe already have an entry to look up.
+pub fn seed_ssh_cache(addr: &str, dut_id: &str) -> Result<()> {
+    let ssh = SshInfo::new(addr).context("failed to create SshInfo")?;
+    note_connection_state(&ssh, ConnectionState::Mdns);
+    SSH_CACHE.set(dut_id, ssh)
+}
 pub fn register_dut(dut: &str) -> Result<DutInfo> {
+    register_dut_with_hint(dut, None)
+}
+/// Like [`register_dut`], but if `dut_id_hint` is known ahead of time,
+/// seeds `SSH_CACHE` with it via [`seed_ssh_cache`] before the SSH probe
+/// that discovers the DUT's real id even starts. The probe's result still
+/// wins: if it disagrees with the hint (stale advertisement, address
+/// reassigned to a different DUT), the cache ends up keyed by the real id,
+/// not the hint.
+pub fn register_dut_with_hint(dut: &str, dut_id_hint: Option<&str>) -> Result<DutInfo> {
+    if let Some(hint) = dut_id_hint {
+        if let Err(e) = seed_ssh_cache(dut, hint) {
+            info!("Failed to pre-cache {dut} as {hint}: {e:#}");
+        }
+    }
     info!("Checking DutInfo of {dut:?}...");
     let info = DutInfo::new(dut)?;
     let id = info.id();
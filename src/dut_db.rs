@@ -0,0 +1,102 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! A persistent inventory of DUTs seen over time, keyed on the stable
+//! `dut_id` (model + serial) rather than the network address, so that a
+//! DUT moving to a new IP is recorded as an address change instead of
+//! silently dropped as happened with the old `AddressReused` handling.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::Local;
+use rusqlite::params;
+use rusqlite::Connection;
+
+use crate::dut::DutInfo;
+use crate::util::xdg_dirs::data_path_in_lium_dir;
+
+/// One row of DUT inventory history: a snapshot of the address a `dut_id`
+/// was reachable at, and when it was first/last observed there.
+#[derive(Debug, Clone)]
+pub struct DutHistoryEntry {
+    pub dut_id: String,
+    pub address: String,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+pub struct DutDb {
+    conn: Connection,
+}
+impl DutDb {
+    pub fn open() -> Result<Self> {
+        let path = data_path_in_lium_dir("dut_inventory.sqlite3")
+            .context("Failed to generate a path for the DUT inventory DB")?;
+        let conn = Connection::open(path).context("Failed to open DUT inventory DB")?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS dut_history (
+                dut_id TEXT NOT NULL,
+                address TEXT NOT NULL,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                PRIMARY KEY (dut_id, address)
+            )",
+            [],
+        )
+        .context("Failed to create dut_history table")?;
+        Ok(Self { conn })
+    }
+    /// Records a `DutInfo` snapshot, keyed on its stable `dut_id`. If the
+    /// same `dut_id` was previously seen at a different address, that
+    /// relation is kept as a separate row rather than overwriting it, so
+    /// address-reuse history is queryable later.
+    pub fn record(&self, info: &DutInfo) -> Result<()> {
+        let now = Local::now().to_rfc3339();
+        let dut_id = info.id();
+        let address = info.ssh().host_and_port();
+        self.conn
+            .execute(
+                "INSERT INTO dut_history (dut_id, address, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?3)
+                 ON CONFLICT(dut_id, address) DO UPDATE SET last_seen = ?3",
+                params![dut_id, address, now],
+            )
+            .context("Failed to record DUT history entry")?;
+        Ok(())
+    }
+    /// Returns every address ever seen for `dut_id`, most-recently-seen
+    /// first.
+    pub fn history(&self, dut_id: &str) -> Result<Vec<DutHistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT dut_id, address, first_seen, last_seen FROM dut_history
+             WHERE dut_id = ?1 ORDER BY last_seen DESC",
+        )?;
+        let rows = stmt
+            .query_map(params![dut_id], |row| {
+                Ok(DutHistoryEntry {
+                    dut_id: row.get(0)?,
+                    address: row.get(1)?,
+                    first_seen: row.get(2)?,
+                    last_seen: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+    /// Returns the most recent `last_seen` timestamp for every known
+    /// `dut_id`, for use by `lium dut list --since`.
+    pub fn last_seen_all(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT dut_id, MAX(last_seen) FROM dut_history GROUP BY dut_id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+        Ok(rows)
+    }
+}
@@ -1,5 +1,7 @@
+use std::path::Path;
 use std::process::Command;
 
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 
@@ -15,3 +17,57 @@ pub fn list_gs_files(pattern: &str) -> Result<String> {
         .trim()
         .to_string())
 }
+
+/// Reads the contents of a single small `gs://...` object, e.g. a version
+/// pointer file, via `gsutil.py cat`.
+pub fn cat_gs_file(gs_path: &str) -> Result<String> {
+    let cmd = format!("gsutil.py cat {}", gs_path.trim());
+    println!("{:?}", cmd);
+    let output = Command::new("bash").arg("-c").arg(cmd).output().context(
+        "Failed to execute gsutil cat (maybe you need depot_tools and/or `gsutil.py config` with \
+         'chromeos-swarming' project)",
+    )?;
+    if !output.status.success() {
+        bail!(
+            "gsutil cat {gs_path} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads the metadata (size, md5, generation, ...) of a single `gs://...`
+/// object via `gsutil.py stat`, as free-form text in gsutil's own
+/// `Key:   Value` layout.
+pub fn stat_gs_file(gs_path: &str) -> Result<String> {
+    let cmd = format!("gsutil.py stat {}", gs_path.trim());
+    println!("{:?}", cmd);
+    let output = Command::new("bash").arg("-c").arg(cmd).output().context(
+        "Failed to execute gsutil stat (maybe you need depot_tools and/or `gsutil.py config` \
+         with 'chromeos-swarming' project)",
+    )?;
+    if !output.status.success() {
+        bail!(
+            "gsutil stat {gs_path} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Downloads a single `gs://...` object to `dest` via `gsutil.py cp`.
+pub fn fetch_gs_file(gs_path: &str, dest: &Path) -> Result<()> {
+    let cmd = format!("gsutil.py cp {} {}", gs_path.trim(), dest.display());
+    println!("{:?}", cmd);
+    let output = Command::new("bash").arg("-c").arg(cmd).output().context(
+        "Failed to execute gsutil cp (maybe you need depot_tools and/or `gsutil.py config` with \
+         'chromeos-swarming' project)",
+    )?;
+    if !output.status.success() {
+        bail!(
+            "gsutil cp {gs_path} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
@@ -14,15 +14,34 @@
 
 pub mod abtest;
 pub mod arc;
+pub mod artifact;
 pub mod bluebench;
 pub mod cache;
 pub mod chroot;
 pub mod config;
+pub mod container;
 pub mod cros;
+pub mod daemon_client;
 pub mod dut;
+pub mod dut_db;
 pub mod google_storage;
+pub mod linux;
+pub mod lsp_proxy;
+pub mod mdns;
+pub mod metrics;
 pub mod parser;
+pub mod patch_sync;
+pub mod plugin;
+pub mod rand_util;
 pub mod repo;
+pub mod s3_storage;
 pub mod servo;
+pub mod servo_daemon;
+pub mod shell;
+pub mod ssh_native;
+pub mod stats;
 pub mod tast;
+pub mod trace_profiler;
 pub mod util;
+pub mod verity;
+pub mod vm;
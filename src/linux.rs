@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -61,3 +63,75 @@ pub fn cmdline_to_mitigations(s: &str) -> Result<String> {
         .join(" ");
     Ok(s.to_string())
 }
+
+/// A single token-level difference between two [`cmdline_to_mitigations`]
+/// outputs, keyed by the option name (the part before `=`, or the whole
+/// token for a bare flag like `noinitrd`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdlineTokenDiff {
+    /// Present in B but not in A.
+    Added(String),
+    /// Present in A but not in B.
+    Removed(String),
+    /// Present in both, but with a different value, e.g. `mitigations=off`
+    /// vs `mitigations=auto`.
+    Changed { key: String, from: String, to: String },
+}
+impl std::fmt::Display for CmdlineTokenDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added(token) => write!(f, "+{token}"),
+            Self::Removed(token) => write!(f, "-{token}"),
+            Self::Changed { key, from, to } => write!(f, "~{key}: {from} -> {to}"),
+        }
+    }
+}
+
+/// Splits a `cmdline_to_mitigations`-filtered string into `key=value`
+/// tokens (bare flags get an empty value), keyed by `key` so later tokens
+/// with the same key (shouldn't normally happen) override earlier ones.
+fn tokenize_mitigations(s: &str) -> BTreeMap<String, String> {
+    s.split(' ')
+        .filter(|t| !t.is_empty())
+        .map(|token| match token.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (token.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Diffs two arms' [`cmdline_to_mitigations`] outputs token by token: a
+/// token present in only one arm is `Added`/`Removed`, a token present in
+/// both with the same key but a different value (e.g. `mitigations=off`
+/// vs `mitigations=auto`) is `Changed`. Returns an empty `Vec` when both
+/// arms booted with identical mitigation-relevant args.
+pub fn diff_mitigations(a: &str, b: &str) -> Vec<CmdlineTokenDiff> {
+    let a = tokenize_mitigations(a);
+    let b = tokenize_mitigations(b);
+    let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| match (a.get(key), b.get(key)) {
+            (Some(av), Some(bv)) if av != bv => Some(CmdlineTokenDiff::Changed {
+                key: key.clone(),
+                from: reassemble(key, av),
+                to: reassemble(key, bv),
+            }),
+            (Some(_), Some(_)) => None,
+            (Some(av), None) => Some(CmdlineTokenDiff::Removed(reassemble(key, av))),
+            (None, Some(bv)) => Some(CmdlineTokenDiff::Added(reassemble(key, bv))),
+            (None, None) => unreachable!("key came from one of the two maps"),
+        })
+        .collect()
+}
+
+/// Reassembles a `key`/`value` pair back into the original token form
+/// (`key=value`, or just `key` for a bare flag).
+fn reassemble(key: &str, value: &str) -> String {
+    if value.is_empty() {
+        key.to_string()
+    } else {
+        format!("{key}={value}")
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2026 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Bridges a local editor's Language Server Protocol (LSP) client to a
+//! language server (`clangd`, `rust-analyzer`, ...) running on a DUT over
+//! SSH, rewriting `file://` URIs in each JSON-RPC message between the
+//! host's checkout path and the DUT's on-device path. This lets an editor
+//! navigate/get diagnostics for code as it exists on the target, which is
+//! valuable when the DUT's source tree differs from the host checkout.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::process::Child;
+use std::process::Stdio;
+use std::thread;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::dut::SshInfo;
+
+/// Reads one `Content-Length`-framed JSON-RPC message, per the LSP base
+/// protocol. Returns `Ok(None)` once the stream is exhausted.
+fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<String>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse()
+                    .context("Malformed Content-Length header")?,
+            );
+        }
+    }
+    let content_length = content_length.context("LSP message had no Content-Length header")?;
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(
+        String::from_utf8(body).context("LSP message body was not valid UTF-8")?,
+    ))
+}
+
+/// Writes `body` as a `Content-Length`-framed JSON-RPC message.
+fn write_message<W: Write>(writer: &mut W, body: &str) -> Result<()> {
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Rewrites every `file://{from_root}...` URI found anywhere in `body`
+/// (notably `rootUri`/workspace paths and `textDocument.uri`) to
+/// `file://{to_root}...`, leaving everything else untouched. Falls back to
+/// the original text if `body` doesn't parse as JSON, so a malformed or
+/// non-JSON-RPC message is still forwarded rather than dropped.
+fn rewrite_uris(body: &str, from_root: &str, to_root: &str) -> String {
+    let Ok(mut value) = serde_json::from_str::<Value>(body) else {
+        return body.to_string();
+    };
+    let from_uri = format!("file://{from_root}");
+    let to_uri = format!("file://{to_root}");
+    rewrite_value(&mut value, &from_uri, &to_uri);
+    serde_json::to_string(&value).unwrap_or_else(|_| body.to_string())
+}
+
+fn rewrite_value(value: &mut Value, from_uri: &str, to_uri: &str) {
+    match value {
+        Value::String(s) => {
+            if let Some(rest) = s.strip_prefix(from_uri) {
+                *s = format!("{to_uri}{rest}");
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_value(item, from_uri, to_uri);
+            }
+        }
+        Value::Object(map) => {
+            for v in map.values_mut() {
+                rewrite_value(v, from_uri, to_uri);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Spawns `lsp_cmd` on `ssh`'s DUT and bridges it to this process's own
+/// stdin/stdout, rewriting `local_root` <-> `remote_root` in every
+/// JSON-RPC message so an editor pointed at `local_root` can drive a
+/// language server that only knows about `remote_root`. Blocks until the
+/// remote language server exits.
+pub fn run_proxy(ssh: &SshInfo, lsp_cmd: &str, local_root: &str, remote_root: &str) -> Result<()> {
+    let mut child: Child = ssh
+        .ssh_cmd(None)?
+        .arg(lsp_cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to start the remote language server over SSH")?;
+    let mut child_stdin = child
+        .stdin
+        .take()
+        .context("remote language server stdin was None")?;
+    let mut child_stdout = BufReader::new(
+        child
+            .stdout
+            .take()
+            .context("remote language server stdout was None")?,
+    );
+    let to_remote_local_root = local_root.to_string();
+    let to_remote_remote_root = remote_root.to_string();
+
+    // Host editor -> remote language server: rewrite local_root to remote_root.
+    let to_remote = thread::spawn(move || -> Result<()> {
+        let mut stdin = BufReader::new(std::io::stdin());
+        while let Some(body) = read_message(&mut stdin)? {
+            let rewritten = rewrite_uris(&body, &to_remote_local_root, &to_remote_remote_root);
+            write_message(&mut child_stdin, &rewritten)?;
+        }
+        Ok(())
+    });
+
+    // Remote language server -> host editor: rewrite remote_root to local_root.
+    let mut stdout = std::io::stdout();
+    while let Some(body) = read_message(&mut child_stdout)? {
+        let rewritten = rewrite_uris(&body, remote_root, local_root);
+        write_message(&mut stdout, &rewritten)?;
+    }
+
+    match to_remote.join() {
+        Ok(result) => result?,
+        Err(_) => bail!("the host-to-remote relay thread panicked"),
+    }
+    let status = child
+        .wait()
+        .context("Failed to wait for the remote language server")?;
+    if !status.success() {
+        bail!("remote language server exited with {status:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_uris_rewrites_nested_uris() {
+        let body = serde_json::json!({
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {"uri": "file:///home/user/chromiumos/src/foo.cc"},
+                "rootUri": "file:///home/user/chromiumos",
+            },
+        })
+        .to_string();
+        let rewritten = rewrite_uris(&body, "/home/user/chromiumos", "/mnt/host/source/src");
+        let value: Value = serde_json::from_str(&rewritten).unwrap();
+        assert_eq!(
+            value["params"]["textDocument"]["uri"],
+            "file:///mnt/host/source/src/foo.cc"
+        );
+        assert_eq!(value["params"]["rootUri"], "file:///mnt/host/source/src");
+    }
+
+    #[test]
+    fn rewrite_uris_leaves_unmatched_uris_alone() {
+        let body = serde_json::json!({"uri": "file:///somewhere/else/foo.cc"}).to_string();
+        let rewritten = rewrite_uris(&body, "/home/user/chromiumos", "/mnt/host/source/src");
+        assert_eq!(rewritten, serde_json::to_string(&serde_json::from_str::<Value>(&body).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn rewrite_uris_passes_through_non_json() {
+        let body = "not json";
+        assert_eq!(rewrite_uris(body, "/a", "/b"), body);
+    }
+
+    #[test]
+    fn message_framing_round_trips() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, r#"{"jsonrpc":"2.0"}"#).unwrap();
+        let mut reader = BufReader::new(&buf[..]);
+        let message = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(message, r#"{"jsonrpc":"2.0"}"#);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
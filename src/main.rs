@@ -22,7 +22,7 @@ extern crate lazy_static;
 mod cmd;
 
 fn main() -> ExitCode {
-    let args: cmd::TopLevel = argh::from_env();
+    let args: cmd::TopLevel = from_env_with_aliases();
 
     let command_line_log_level = args.verbosity.as_ref().map(|s| {
         LevelFilter::from_str(s)
@@ -41,9 +41,21 @@ fn main() -> ExitCode {
         .with_thread_ids(true)
         .with_level(true)
         .with_writer(std::io::stderr);
+    // `trace_handle` is kept separately from the layer itself so we can
+    // flush it after `cmd::run` below: once installed as the global
+    // subscriber the layer is never dropped, so a `Drop` impl on it alone
+    // wouldn't reliably flush the trace file.
+    let (trace_layer, trace_handle) = match &args.trace_output {
+        Some(path) => {
+            let (layer, handle) = lium::trace_profiler::TraceProfilerLayer::new(path.clone());
+            (Some(layer), Some(handle))
+        }
+        None => (None, None),
+    };
     tracing_subscriber::registry()
         .with(tracing_subscriber)
         .with(cro3_logging_env_filter)
+        .with(trace_layer)
         .init();
 
     let args_log = &std::env::args().skip(1).collect::<Vec<_>>();
@@ -56,10 +68,70 @@ fn main() -> ExitCode {
         );
     }
 
-    if let Err(e) = cmd::run(&args) {
-        error!("{e:#}");
+    // Make the requested output format visible to subcommands that don't
+    // receive `TopLevel` directly (e.g. deep in `cmd::dut`), so a failure
+    // partway through a JSON-format invocation can still report as JSON.
+    std::env::set_var("LIUM_FORMAT", &args.format);
+
+    // Make an explicit --profile override visible to cro3::config::Config::
+    // read(), mirroring LIUM_FORMAT above, since Config::read() is called
+    // from deep inside individual subcommands rather than from here.
+    if let Some(profile) = &args.profile {
+        std::env::set_var("CRO3_PROFILE", profile);
+    }
+
+    let format = if args.json_requested() {
+        lium::shell::OutputFormat::Json
+    } else {
+        lium::shell::OutputFormat::Human
+    };
+    let verbosity = if args.quiet {
+        lium::shell::Verbosity::Quiet
+    } else {
+        lium::shell::Verbosity::Normal
+    };
+    lium::shell::Shell::install(format, verbosity);
+
+    let result = cmd::run(&args);
+
+    if let Some(trace_handle) = &trace_handle {
+        if let Err(e) = trace_handle.flush() {
+            error!("Failed to write trace output: {e:#}");
+        }
+    }
+
+    if let Err(e) = result {
+        if lium::shell::Shell::lock().format() == lium::shell::OutputFormat::Json {
+            let envelope = serde_json::json!({"error": format!("{e:#}")});
+            println!("{envelope}");
+        } else {
+            error!("{e:#}");
+        }
         ExitCode::FAILURE
     } else {
         ExitCode::SUCCESS
     }
 }
+
+/// Like `argh::from_env`, but first splices any configured `cro3 config set
+/// alias <name> <expansion...>` expansion into argv's subcommand position
+/// (see `lium::config::Config::expand_aliases`), so e.g. an alias named
+/// `ssh-dut` can shorten `cro3 dut shell`. Mirrors `argh::from_env`'s own
+/// help/error-printing and exit-code behavior since we can no longer call
+/// it directly once argv needs to be rewritten first.
+fn from_env_with_aliases() -> cmd::TopLevel {
+    let strings: Vec<String> = std::env::args().collect();
+    let cmd_name = strings.first().map(|s| s.as_str()).unwrap_or("");
+    let argv = lium::config::Config::expand_aliases(strings[1..].to_vec()).unwrap_or_else(|e| {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    });
+    let arg_refs: Vec<&str> = argv.iter().map(String::as_str).collect();
+    argh::FromArgs::from_args(&[cmd_name], &arg_refs).unwrap_or_else(|early_exit| {
+        println!("{}", early_exit.output);
+        std::process::exit(match early_exit.status {
+            Ok(()) => 0,
+            Err(()) => 1,
+        })
+    })
+}
@@ -0,0 +1,91 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! mDNS / DNS-SD based DUT discovery, as an alternative to sweeping an
+//! interface's subnet with ping6. Finds devices advertising the ChromeOS/
+//! Android test service over multicast DNS, including link-local IPv6
+//! addresses a routing-table sweep would miss.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use mdns_sd::ServiceDaemon;
+use mdns_sd::ServiceEvent;
+use tracing::info;
+
+/// Service type advertised by ChromeOS/Android test devices on the lab
+/// network.
+const DUT_SERVICE_TYPE: &str = "_cros-dut._tcp.local.";
+
+/// A DUT found via mDNS, with whatever TXT-record attributes it advertised
+/// (e.g. `model`, `board`) alongside its reachable addresses.
+#[derive(Debug, Clone)]
+pub struct MdnsDut {
+    pub addresses: Vec<String>,
+    pub txt: Vec<(String, String)>,
+}
+impl MdnsDut {
+    fn txt_value(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn model(&self) -> Option<&str> {
+        self.txt_value("model")
+    }
+    pub fn serial(&self) -> Option<&str> {
+        self.txt_value("serial")
+    }
+    /// The `dut_id` this DUT would be assigned after a full SSH probe (see
+    /// `KeyInfo::from_raw_dut_info`), derived straight from advertised TXT
+    /// records -- `None` if the responder didn't advertise both `model` and
+    /// `serial`. Lets callers seed `SSH_CACHE` with an id before connecting.
+    pub fn dut_id(&self) -> Option<String> {
+        Some(format!("{}_{}", self.model()?, self.serial()?))
+    }
+}
+
+/// Listens for PTR/A/AAAA/SRV/TXT responses to `DUT_SERVICE_TYPE` for up to
+/// `timeout`, and returns every responder found.
+pub fn discover(timeout: Duration) -> Result<Vec<MdnsDut>> {
+    let daemon = ServiceDaemon::new().context("Failed to start mDNS daemon")?;
+    let receiver = daemon
+        .browse(DUT_SERVICE_TYPE)
+        .context("Failed to browse for DUT service")?;
+    let deadline = std::time::Instant::now() + timeout;
+    let mut duts = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(std::time::Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                info!("mDNS: found {} at {:?}", info.get_fullname(), info.get_addresses());
+                duts.push(MdnsDut {
+                    addresses: info.get_addresses().iter().map(|a| a.to_string()).collect(),
+                    txt: info
+                        .get_properties()
+                        .iter()
+                        .map(|p| (p.key().to_string(), p.val_str().to_string()))
+                        .collect(),
+                });
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(duts)
+}
+
+/// Convenience wrapper returning just the candidate addresses, in the same
+/// shape `discover_local_nodes` (ping6-sweep based) produces, so both
+/// sources can feed `fetch_dut_info_in_parallel` identically.
+pub fn discover_addrs(timeout: Duration) -> Result<Vec<String>> {
+    Ok(discover(timeout)?
+        .into_iter()
+        .flat_map(|d| d.addresses)
+        .collect())
+}
@@ -0,0 +1,152 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Persists each `cro3 tast analyze` comparison as a line in a JSON-lines
+//! time series, so a slow regression across many runs can be spotted even
+//! though a single A/B diff only ever compares two points. Optionally
+//! mirrors the local store into a dedicated metrics git repo (the same
+//! append-commit-push shape as rust-analyzer's `xtask metrics`), so the
+//! history survives across machines/CI runs instead of living only in one
+//! developer's `~/.lium` data dir.
+
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::Config;
+use crate::tast::TastAnalyzerOutput;
+use crate::util::xdg_dirs::data_path_in_lium_dir;
+
+/// One metric's A/B comparison from a single `cro3 tast analyze` run, as one
+/// line of the `metrics.jsonl` time series.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricRecord {
+    pub metric_key: String,
+    pub timestamp: u64,
+    pub board: Option<String>,
+    pub build: Option<String>,
+    pub mean: f64,
+    pub stddev: f64,
+    pub p: f64,
+    pub change_percent: f64,
+}
+
+fn metrics_store_path() -> Result<PathBuf> {
+    data_path_in_lium_dir("metrics.jsonl")
+}
+
+/// Appends one [`MetricRecord`] per entry in `outputs` to the local
+/// JSON-lines store, then (if `Config::metrics_git_repo` is set) mirrors the
+/// updated store into that git repo.
+pub fn record_metrics(
+    outputs: &[TastAnalyzerOutput],
+    board: Option<&str>,
+    build: Option<&str>,
+    timestamp: u64,
+) -> Result<()> {
+    let path = metrics_store_path()?;
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open the metrics store at {path:?}"))?;
+    for o in outputs {
+        let record = MetricRecord {
+            metric_key: o.key.clone(),
+            timestamp,
+            board: board.map(str::to_string),
+            build: build.map(str::to_string),
+            mean: o.stats_b.mean,
+            stddev: o.stats_b.stddev,
+            p: o.analysis.p,
+            change_percent: o.analysis.change_percent,
+        };
+        writeln!(f, "{}", serde_json::to_string(&record)?)?;
+    }
+    if let Some(repo) = Config::read()?.metrics_git_repo() {
+        sync_metrics_repo(repo, &path)?;
+    }
+    Ok(())
+}
+
+/// Reads every [`MetricRecord`] for `metric_key` back out of the local
+/// JSON-lines store, oldest first, for `cro3 metrics history`.
+pub fn read_metric_history(metric_key: &str) -> Result<Vec<MetricRecord>> {
+    let path = metrics_store_path()?;
+    let f = match std::fs::File::open(&path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Failed to open the metrics store at {path:?}")),
+    };
+    let mut history = Vec::new();
+    for line in BufReader::new(f).lines() {
+        let record: MetricRecord = serde_json::from_str(&line?)?;
+        if record.metric_key == metric_key {
+            history.push(record);
+        }
+    }
+    Ok(history)
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .with_context(|| format!("Failed to run git {args:?} in {dir:?}"))?;
+    if !status.success() {
+        bail!("git {args:?} in {dir:?} failed with {status}");
+    }
+    Ok(())
+}
+
+/// Clones `repo` into cro3's data dir on first use, copies the local
+/// JSON-lines store over `metrics.jsonl` in that checkout, and commits +
+/// pushes the update -- mirroring rust-analyzer's xtask metrics publishing
+/// flow, but with `git` shelled out to directly instead of a library.
+fn sync_metrics_repo(repo: &str, local_store: &Path) -> Result<()> {
+    let checkout = data_path_in_lium_dir("metrics-repo/.cro3-metrics-repo-marker")?
+        .parent()
+        .context("metrics-repo checkout path unexpectedly has no parent dir")?
+        .to_path_buf();
+    if !checkout.join(".git").is_dir() {
+        std::fs::create_dir_all(&checkout)?;
+        info!("Cloning metrics repo {repo} into {checkout:?}");
+        run_git(
+            checkout.parent().context("metrics-repo checkout has no parent")?,
+            &["clone", repo, &checkout.to_string_lossy()],
+        )?;
+    } else {
+        run_git(&checkout, &["pull", "--ff-only"])?;
+    }
+    std::fs::copy(local_store, checkout.join("metrics.jsonl"))
+        .context("Failed to copy the metrics store into the metrics repo checkout")?;
+    run_git(&checkout, &["add", "metrics.jsonl"])?;
+    // Nothing changed since the last sync; avoid an empty commit.
+    if Command::new("git")
+        .args(["diff", "--cached", "--quiet"])
+        .current_dir(&checkout)
+        .status()
+        .context("failed to run git diff")?
+        .success()
+    {
+        return Ok(());
+    }
+    run_git(&checkout, &["commit", "-m", "Update cro3 Tast metrics"])?;
+    run_git(&checkout, &["push"])?;
+    Ok(())
+}
@@ -11,13 +11,63 @@
 //! https://source.chromium.org/chromium/chromiumos/platform2/+/main:vm_tools/crostini_client/lsb_release.rs;drc=41a92137d3e795ad6a51c5dec90dfa142af8c7c3
 
 use std::collections::BTreeMap;
+use std::env;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
 use std::result::Result;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 const CHROMEOS_RELEASE_TRACK_KEY: &str = "CHROMEOS_RELEASE_TRACK";
 
+/// Keys holding a dotted `major.minor.bugfix` ChromeOS version string, in
+/// the priority order [`LsbRelease::version`] reads them in, matching
+/// Chromium's `kLinuxStandardBaseVersionKeys`.
+const CHROMEOS_VERSION_KEYS: &[&str] = &[
+    "CHROMEOS_RELEASE_VERSION",
+    "GOOGLE_RELEASE",
+    "DISTRIB_RELEASE",
+];
+const CHROMEOS_RELEASE_CHROME_MILESTONE_KEY: &str = "CHROMEOS_RELEASE_CHROME_MILESTONE";
+const CHROMEOS_RELEASE_BUILD_NUMBER_KEY: &str = "CHROMEOS_RELEASE_BUILD_NUMBER";
+const CHROMEOS_RELEASE_NAME_KEY: &str = "CHROMEOS_RELEASE_NAME";
+
+/// Known values of `CHROMEOS_RELEASE_NAME` on a genuine ChromeOS/Chromium OS
+/// image, per Chromium's `ChromeOSVersionInfo`.
+const CHROMEOS_RELEASE_NAMES: &[&str] = &["Chrome OS", "Chromium OS", "ChromeOS"];
+
+/// A structured `major.minor.bugfix` ChromeOS platform version, e.g.
+/// `11438.0.0` parsed out of `11438.0.0-rc1`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ChromeOsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub bugfix: u32,
+}
+
+impl FromStr for ChromeOsVersion {
+    type Err = LsbReleaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, '.');
+        let parse_component = |s: &str| -> Result<u32, Self::Err> {
+            // Drop a trailing `-rc1`-style suffix, only relevant (and only
+            // ever present) on the last component.
+            let s = s.split('-').next().unwrap_or(s);
+            s.parse().map_err(|_| LsbReleaseError::ParseError {
+                row: 0,
+                message: "version component is not a number",
+            })
+        };
+        let major = parts.next().map(parse_component).transpose()?.unwrap_or(0);
+        let minor = parts.next().map(parse_component).transpose()?.unwrap_or(0);
+        let bugfix = parts.next().map(parse_component).transpose()?.unwrap_or(0);
+        Ok(ChromeOsVersion { major, minor, bugfix })
+    }
+}
+
 /// An error generated while gathering release information.
 #[derive(Debug)]
 pub enum LsbReleaseError {
@@ -39,6 +89,40 @@ impl Error for LsbReleaseError {}
 /// A result from gathering resource information.
 pub type LsbReleaseResult<T> = Result<T, LsbReleaseError>;
 
+const LSB_RELEASE_ENV_VAR: &str = "LSB_RELEASE";
+const LSB_RELEASE_DEFAULT_PATH: &str = "/etc/lsb-release";
+
+/// An error generated while loading release information from its usual
+/// sources, via [`LsbRelease::load`] or [`LsbRelease::load_from_path`].
+#[derive(Debug)]
+pub enum LsbReleaseLoadError {
+    /// Neither the `LSB_RELEASE` environment variable nor an lsb-release
+    /// file were available.
+    NoSource,
+    Io(std::io::Error),
+    Parse(LsbReleaseError),
+}
+
+impl Display for LsbReleaseLoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LsbReleaseLoadError::NoSource => {
+                write!(f, "no lsb-release source found (no $LSB_RELEASE, no file)")
+            }
+            LsbReleaseLoadError::Io(e) => write!(f, "failed to read lsb-release: {e}"),
+            LsbReleaseLoadError::Parse(e) => write!(f, "failed to parse lsb-release: {e}"),
+        }
+    }
+}
+
+impl Error for LsbReleaseLoadError {}
+
+impl From<LsbReleaseError> for LsbReleaseLoadError {
+    fn from(e: LsbReleaseError) -> Self {
+        LsbReleaseLoadError::Parse(e)
+    }
+}
+
 /// Release information typically gathered from the environment or from
 /// `/etc/lsb-release`.
 #[derive(Debug)]
@@ -52,12 +136,95 @@ impl LsbRelease {
         self.info.get(k.as_ref()).map(|s| s.as_str())
     }
 
+    /// Sets (or overwrites) a key, e.g. to stamp `CHROMEOS_RELEASE_TRACK`,
+    /// `CHROMEOS_AUSERVER`, or `CHROMEOS_DEVSERVER` into a local image the
+    /// way `cros_set_lsb_release.py` does. Backed by a map, so the
+    /// duplicate-key rejection [`FromStr`] enforces on parse can't apply
+    /// here: setting an existing key just replaces its value.
+    pub fn set<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.info.insert(key.into(), value.into());
+    }
+
+    /// Removes a key, returning its previous value if it was present.
+    pub fn remove<K: AsRef<str>>(&mut self, key: K) -> Option<String> {
+        self.info.remove(key.as_ref())
+    }
+
     /// Gets the type of release channel this release information corresponds
     /// to, or none if this
     /// information was not indicated.
     pub fn release_channel(&self) -> Option<ReleaseChannel> {
         self.get(CHROMEOS_RELEASE_TRACK_KEY).map(|c| c.into())
     }
+
+    /// Gets the structured platform version, reading the first of
+    /// `CHROMEOS_RELEASE_VERSION`, `GOOGLE_RELEASE`, `DISTRIB_RELEASE` that's
+    /// present, or none if none of them are.
+    pub fn version(&self) -> Option<ChromeOsVersion> {
+        CHROMEOS_VERSION_KEYS
+            .iter()
+            .find_map(|k| self.get(k))
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// Gets `CHROMEOS_RELEASE_CHROME_MILESTONE` parsed as a number, or none
+    /// if unavailable or not a valid number.
+    pub fn milestone(&self) -> Option<u32> {
+        self.get(CHROMEOS_RELEASE_CHROME_MILESTONE_KEY)
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// Gets `CHROMEOS_RELEASE_BUILD_NUMBER` parsed as a number, or none if
+    /// unavailable or not a valid number.
+    pub fn build_number(&self) -> Option<u32> {
+        self.get(CHROMEOS_RELEASE_BUILD_NUMBER_KEY)
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// Whether this is a genuine ChromeOS/Chromium OS image, following
+    /// Chromium's `ChromeOSVersionInfo` logic: `CHROMEOS_RELEASE_NAME` must
+    /// match a known release name, and at least one of the version keys
+    /// must be present and parse to a nonzero version. A plain Linux box
+    /// with a hand-written lsb-release fails this even if it sets
+    /// `CHROMEOS_RELEASE_NAME` by hand, as long as it has no version.
+    pub fn is_running_on_chromeos(&self) -> bool {
+        let known_name = self
+            .get(CHROMEOS_RELEASE_NAME_KEY)
+            .is_some_and(|name| CHROMEOS_RELEASE_NAMES.contains(&name));
+        let nonzero_version = self
+            .version()
+            .is_some_and(|v| v.major != 0 || v.minor != 0 || v.bugfix != 0);
+        known_name && nonzero_version
+    }
+
+    /// Loads release information the way Chromium's `sys_info_chromeos`
+    /// does: from the `LSB_RELEASE` environment variable if set (used in
+    /// non-device/test contexts), otherwise from `/etc/lsb-release`. The
+    /// result is cached behind a `OnceLock`, so the file is read and parsed
+    /// at most once per process.
+    pub fn load() -> Result<&'static LsbRelease, &'static LsbReleaseLoadError> {
+        static CACHE: OnceLock<Result<LsbRelease, LsbReleaseLoadError>> = OnceLock::new();
+        CACHE
+            .get_or_init(|| match env::var(LSB_RELEASE_ENV_VAR) {
+                Ok(s) => Ok(s.parse::<LsbRelease>()?),
+                Err(_) => Self::load_from_path(Path::new(LSB_RELEASE_DEFAULT_PATH)),
+            })
+            .as_ref()
+    }
+
+    /// Reads and parses lsb-release content from `path`, uncached. An
+    /// overridable entry point for tests; [`Self::load`] is the normal
+    /// entry point and is the one that caches its result.
+    pub fn load_from_path(path: &Path) -> Result<LsbRelease, LsbReleaseLoadError> {
+        let s = fs::read_to_string(path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                LsbReleaseLoadError::NoSource
+            } else {
+                LsbReleaseLoadError::Io(e)
+            }
+        })?;
+        Ok(s.parse()?)
+    }
 }
 
 impl FromStr for LsbRelease {
@@ -94,6 +261,17 @@ impl FromStr for LsbRelease {
     }
 }
 
+impl Display for LsbRelease {
+    /// Emits `KEY=VALUE` lines in sorted key order (the map's own
+    /// iteration order), so the output round-trips through `FromStr`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (key, value) in &self.info {
+            writeln!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
 /// A channel of OS releases. Channels are distinguished by their relative
 /// stability and frequency of release.
 #[derive(PartialEq, Eq, Debug)]
@@ -110,6 +288,52 @@ pub enum ReleaseChannel<'a> {
     Other(&'a str),
 }
 
+impl<'a> ReleaseChannel<'a> {
+    /// The short channel name Chromium uses, e.g. in `chrome://version`
+    /// (`Stable` has no suffix). `Other` has no defined short name, so its
+    /// raw `lsb-release` value is returned instead.
+    pub fn display_name(&self) -> &'a str {
+        match self {
+            ReleaseChannel::Stable => "",
+            ReleaseChannel::Beta => "beta",
+            ReleaseChannel::Dev => "dev",
+            ReleaseChannel::Canary => "canary",
+            ReleaseChannel::Ltc => "ltc",
+            ReleaseChannel::Lts => "lts",
+            ReleaseChannel::Other(s) => s,
+        }
+    }
+
+    /// Stability rank backing [`Ord`]/[`PartialOrd`]: lower is less stable.
+    /// `Other` has no defined stability; it's pinned below every known
+    /// channel (i.e. treated as the least stable) and compares equal to any
+    /// other `Other`, regardless of its inner string, so it's effectively
+    /// outside the real total order this is meant to express.
+    fn stability_rank(&self) -> u8 {
+        match self {
+            ReleaseChannel::Other(_) => 0,
+            ReleaseChannel::Canary => 1,
+            ReleaseChannel::Dev => 2,
+            ReleaseChannel::Beta => 3,
+            ReleaseChannel::Stable => 4,
+            ReleaseChannel::Ltc => 5,
+            ReleaseChannel::Lts => 6,
+        }
+    }
+}
+
+impl<'a> PartialOrd for ReleaseChannel<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for ReleaseChannel<'a> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.stability_rank().cmp(&other.stability_rank())
+    }
+}
+
 impl<'a> From<&'a str> for ReleaseChannel<'a> {
     fn from(s: &str) -> ReleaseChannel {
         use self::ReleaseChannel::*;
@@ -207,4 +431,147 @@ mod tests {
             .unwrap();
         assert_eq!(lsb_release.release_channel(), Some(ReleaseChannel::Lts));
     }
+
+    #[test]
+    fn version() {
+        let lsb_release = LSB_RELEASE.parse::<LsbRelease>().unwrap();
+        assert_eq!(
+            lsb_release.version(),
+            Some(ChromeOsVersion {
+                major: 11438,
+                minor: 0,
+                bugfix: 0,
+            })
+        );
+        assert_eq!(lsb_release.milestone(), Some(73));
+        assert_eq!(lsb_release.build_number(), Some(11438));
+    }
+
+    #[test]
+    fn version_falls_back_through_priority_keys() {
+        let lsb_release = "GOOGLE_RELEASE=15.3.7"
+            .parse::<LsbRelease>()
+            .unwrap();
+        assert_eq!(
+            lsb_release.version(),
+            Some(ChromeOsVersion {
+                major: 15,
+                minor: 3,
+                bugfix: 7,
+            })
+        );
+        let lsb_release = "DISTRIB_RELEASE=16"
+            .parse::<LsbRelease>()
+            .unwrap();
+        assert_eq!(
+            lsb_release.version(),
+            Some(ChromeOsVersion {
+                major: 16,
+                minor: 0,
+                bugfix: 0,
+            })
+        );
+        let lsb_release = "DEVICETYPE=CHROMEBOOK".parse::<LsbRelease>().unwrap();
+        assert_eq!(lsb_release.version(), None);
+        assert_eq!(lsb_release.milestone(), None);
+        assert_eq!(lsb_release.build_number(), None);
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let mut lsb_release = "A=1".parse::<LsbRelease>().unwrap();
+        lsb_release.set("A", "2");
+        lsb_release.set("B", "3");
+        assert_eq!(lsb_release.get("A"), Some("2"));
+        assert_eq!(lsb_release.get("B"), Some("3"));
+        assert_eq!(lsb_release.remove("A"), Some("2".to_string()));
+        assert_eq!(lsb_release.get("A"), None);
+        assert_eq!(lsb_release.remove("A"), None);
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str_in_sorted_order() {
+        let mut lsb_release = LsbRelease {
+            info: BTreeMap::new(),
+        };
+        lsb_release.set("CHROMEOS_RELEASE_TRACK", "beta-channel");
+        lsb_release.set("CHROMEOS_AUSERVER", "http://example.com");
+        let text = lsb_release.to_string();
+        assert_eq!(
+            text,
+            "CHROMEOS_AUSERVER=http://example.com\nCHROMEOS_RELEASE_TRACK=beta-channel\n"
+        );
+        let round_tripped = text.parse::<LsbRelease>().unwrap();
+        assert_eq!(round_tripped.get("CHROMEOS_RELEASE_TRACK"), Some("beta-channel"));
+        assert_eq!(
+            round_tripped.get("CHROMEOS_AUSERVER"),
+            Some("http://example.com")
+        );
+    }
+
+    #[test]
+    fn release_channel_display_name() {
+        assert_eq!(ReleaseChannel::Stable.display_name(), "");
+        assert_eq!(ReleaseChannel::Beta.display_name(), "beta");
+        assert_eq!(ReleaseChannel::Dev.display_name(), "dev");
+        assert_eq!(ReleaseChannel::Canary.display_name(), "canary");
+        assert_eq!(ReleaseChannel::Ltc.display_name(), "ltc");
+        assert_eq!(ReleaseChannel::Lts.display_name(), "lts");
+        assert_eq!(
+            ReleaseChannel::Other("testimage-channel").display_name(),
+            "testimage-channel"
+        );
+    }
+
+    #[test]
+    fn release_channel_stability_order() {
+        assert!(ReleaseChannel::Canary < ReleaseChannel::Dev);
+        assert!(ReleaseChannel::Dev < ReleaseChannel::Beta);
+        assert!(ReleaseChannel::Beta < ReleaseChannel::Stable);
+        assert!(ReleaseChannel::Stable < ReleaseChannel::Ltc);
+        assert!(ReleaseChannel::Ltc < ReleaseChannel::Lts);
+        assert!(ReleaseChannel::Other("testimage-channel") < ReleaseChannel::Canary);
+        assert_eq!(
+            ReleaseChannel::Other("a").cmp(&ReleaseChannel::Other("b")),
+            std::cmp::Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn is_running_on_chromeos() {
+        let lsb_release = LSB_RELEASE.parse::<LsbRelease>().unwrap();
+        assert!(lsb_release.is_running_on_chromeos());
+
+        let lsb_release = "CHROMEOS_RELEASE_NAME=ChromeOS\nCHROMEOS_RELEASE_VERSION=15.0.0"
+            .parse::<LsbRelease>()
+            .unwrap();
+        assert!(lsb_release.is_running_on_chromeos());
+
+        let lsb_release = "CHROMEOS_RELEASE_NAME=Chrome OS\nCHROMEOS_RELEASE_VERSION=0.0.0"
+            .parse::<LsbRelease>()
+            .unwrap();
+        assert!(!lsb_release.is_running_on_chromeos());
+
+        let lsb_release = "CHROMEOS_RELEASE_NAME=Ubuntu\nCHROMEOS_RELEASE_VERSION=15.0.0"
+            .parse::<LsbRelease>()
+            .unwrap();
+        assert!(!lsb_release.is_running_on_chromeos());
+
+        let lsb_release = "DEVICETYPE=CHROMEBOOK".parse::<LsbRelease>().unwrap();
+        assert!(!lsb_release.is_running_on_chromeos());
+    }
+
+    #[test]
+    fn load_from_path_reads_and_parses_the_file() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut f, LSB_RELEASE.as_bytes()).unwrap();
+        let lsb_release = LsbRelease::load_from_path(f.path()).unwrap();
+        assert_eq!(lsb_release.milestone(), Some(73));
+    }
+
+    #[test]
+    fn load_from_path_missing_file_is_no_source() {
+        let err = LsbRelease::load_from_path(Path::new("/nonexistent/lsb-release")).unwrap_err();
+        assert!(matches!(err, LsbReleaseLoadError::NoSource));
+    }
 }
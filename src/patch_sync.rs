@@ -0,0 +1,345 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Mirrors local downstream patches between a ChromeOS checkout
+//! (`default_cros_checkout`) and an Android/ARC checkout (the one
+//! `crate::arc::setup_arc_repo` sets up from `android_manifest_url`), for
+//! situations where the same cherry-pick needs to land in both trees (e.g.
+//! a kernel fix backported to both the CrOS kernel and the upstream ARC
+//! kernel it tracks). Each checkout keeps a `PATCHES.json` manifest; syncing
+//! diffs the two manifests by content hash and copies across whatever's
+//! missing on either side, with the git-touching steps wrapped in a
+//! [`GitSyncTransaction`] so a failure partway through never leaves either
+//! tree on a half-applied branch.
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::info;
+use tracing::warn;
+
+/// Name of the manifest file kept at the root of each synced checkout.
+const MANIFEST_FILE_NAME: &str = "PATCHES.json";
+/// Branch [`GitSyncTransaction::begin`] creates to stage a sync's changes,
+/// so they never land directly on whatever branch was checked out before.
+const SYNC_BRANCH_NAME: &str = "cro3-patch-sync";
+
+/// One downstream patch tracked in a [`PatchManifest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchEntry {
+    /// Path to the patch file, relative to the checkout root.
+    pub path: String,
+    /// SHA-256 of the patch file's content, used to detect whether the
+    /// same patch already exists on the other side (possibly at a
+    /// different path) and to detect content drift when the paths match
+    /// but the hashes don't.
+    pub sha256: String,
+    /// Platform tags this patch applies to (e.g. `"arc-r"`, `"arc-t"`); an
+    /// empty list means "applies everywhere".
+    #[serde(default)]
+    pub platforms: Vec<String>,
+}
+impl PatchEntry {
+    /// Whether this patch should be considered when syncing for
+    /// `platform`: true if it has no platform tags at all, or `platform`
+    /// is one of them.
+    fn applies_to(&self, platform: &str) -> bool {
+        self.platforms.is_empty() || self.platforms.iter().any(|p| p == platform)
+    }
+}
+
+/// The `PATCHES.json` manifest kept at the root of a synced checkout.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatchManifest {
+    pub patches: Vec<PatchEntry>,
+}
+impl PatchManifest {
+    /// Reads `<checkout>/PATCHES.json`, or an empty manifest if it doesn't
+    /// exist yet (a checkout that's never been synced before).
+    pub fn read(checkout: &Path) -> Result<Self> {
+        let path = checkout.join(MANIFEST_FILE_NAME);
+        match read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s)
+                .with_context(|| format!("failed to parse {path:?}")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {path:?}")),
+        }
+    }
+    /// Writes this manifest back to `<checkout>/PATCHES.json`, sorted by
+    /// path so repeated syncs produce a minimal diff.
+    pub fn write(&self, checkout: &Path) -> Result<()> {
+        let mut sorted = self.clone();
+        sorted.patches.sort_by(|a, b| a.path.cmp(&b.path));
+        let s = serde_json::to_string_pretty(&sorted)?;
+        write(checkout.join(MANIFEST_FILE_NAME), s.into_bytes())
+            .context("failed to write PATCHES.json")
+    }
+}
+
+/// A path present with diverging content on both sides of a sync -- reported
+/// rather than resolved, since overwriting either side could silently drop
+/// someone's in-progress fix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConflict {
+    pub path: String,
+    pub sha256_a: String,
+    pub sha256_b: String,
+}
+
+/// What `sync_patches` did, for the caller to report to the user.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    /// Patches copied from `side_a` into `side_b`.
+    pub copied_to_b: Vec<String>,
+    /// Patches copied from `side_b` into `side_a`.
+    pub copied_to_a: Vec<String>,
+    /// Paths present on both sides with diverging content; left untouched.
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// Computes `sha256sum`'s hex digest of `path`'s content.
+fn sha256_file(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .with_context(|| format!("failed to run sha256sum on {path:?}"))?;
+    if !output.status.success() {
+        bail!("sha256sum {path:?} exited with {}", output.status);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .with_context(|| format!("sha256sum produced no output for {path:?}"))
+}
+
+/// Scope guard around the git-touching part of a sync: stashes the
+/// checkout's current state on [`Self::begin`] and checks out a fresh
+/// [`SYNC_BRANCH_NAME`] branch, then -- unless [`Self::commit`] was called
+/// -- restores the original ref and pops the stash back on drop, so a
+/// failure partway through `sync_patches` never leaves the checkout on a
+/// half-built branch or missing the user's prior uncommitted work.
+pub struct GitSyncTransaction {
+    checkout: PathBuf,
+    original_ref: String,
+    stashed: bool,
+    committed: bool,
+}
+impl GitSyncTransaction {
+    pub fn begin(checkout: &Path) -> Result<Self> {
+        let original_ref = git_stdout(checkout, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+        let stash_before = git_stdout(checkout, &["stash", "list"])?;
+        run_git(checkout, &["stash", "push", "--include-untracked", "-m", "cro3 patch_sync"])?;
+        let stash_after = git_stdout(checkout, &["stash", "list"])?;
+        let stashed = stash_after != stash_before;
+        run_git(checkout, &["checkout", "-B", SYNC_BRANCH_NAME])?;
+        Ok(Self {
+            checkout: checkout.to_path_buf(),
+            original_ref,
+            stashed,
+            committed: false,
+        })
+    }
+    /// Stages and commits every change made to `paths` since `begin()`.
+    pub fn commit_paths(&self, paths: &[PathBuf], message: &str) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["add"];
+        let path_strs: Vec<String> = paths.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        args.extend(path_strs.iter().map(String::as_str));
+        run_git(&self.checkout, &args)?;
+        run_git(&self.checkout, &["commit", "-m", message])
+    }
+    /// Marks the sync as having succeeded, so `Drop` leaves the new branch
+    /// and its commit(s) in place instead of reverting them.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+impl Drop for GitSyncTransaction {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        warn!(
+            "patch_sync transaction in {:?} dropped without commit(); reverting to {:?}",
+            self.checkout, self.original_ref
+        );
+        if let Err(e) = run_git(&self.checkout, &["checkout", &self.original_ref]) {
+            warn!("failed to check out {:?} back in {:?}: {e:#}", self.original_ref, self.checkout);
+            return;
+        }
+        let _ = run_git(&self.checkout, &["branch", "-D", SYNC_BRANCH_NAME]);
+        if self.stashed {
+            if let Err(e) = run_git(&self.checkout, &["stash", "pop"]) {
+                warn!("failed to restore stashed changes in {:?}: {e:#}", self.checkout);
+            }
+        }
+    }
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .with_context(|| format!("failed to run git {args:?} in {dir:?}"))?;
+    if !status.success() {
+        bail!("git {args:?} in {dir:?} failed with {status}");
+    }
+    Ok(())
+}
+
+fn git_stdout(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("failed to run git {args:?} in {dir:?}"))?;
+    if !output.status.success() {
+        bail!("git {args:?} in {dir:?} failed with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Syncs downstream patches between `side_a` and `side_b` (a ChromeOS
+/// checkout and an ARC/Android checkout, in either order), for `platform`.
+///
+/// Builds the set difference of patch content hashes between the two
+/// `PATCHES.json` manifests (skipping entries whose `platforms` tags
+/// exclude `platform`), copies whatever's missing on each side into the
+/// other's checkout root, commits the copies (wrapped in a
+/// [`GitSyncTransaction`] per side so a failure partway through reverts
+/// cleanly), and rewrites both manifests. A path present on both sides with
+/// a different hash is reported as a [`SyncConflict`] instead of being
+/// overwritten.
+pub fn sync_patches(side_a: &Path, side_b: &Path, platform: &str) -> Result<SyncReport> {
+    let manifest_a = PatchManifest::read(side_a)?;
+    let manifest_b = PatchManifest::read(side_b)?;
+
+    let applicable = |m: &PatchManifest| -> Vec<PatchEntry> {
+        m.patches.iter().filter(|p| p.applies_to(platform)).cloned().collect()
+    };
+    let entries_a = applicable(&manifest_a);
+    let entries_b = applicable(&manifest_b);
+
+    let by_path_a: HashMap<&str, &PatchEntry> =
+        entries_a.iter().map(|p| (p.path.as_str(), p)).collect();
+    let by_path_b: HashMap<&str, &PatchEntry> =
+        entries_b.iter().map(|p| (p.path.as_str(), p)).collect();
+    let hashes_a: std::collections::HashSet<&str> =
+        entries_a.iter().map(|p| p.sha256.as_str()).collect();
+    let hashes_b: std::collections::HashSet<&str> =
+        entries_b.iter().map(|p| p.sha256.as_str()).collect();
+
+    let mut conflicts = Vec::new();
+    for (path, a) in &by_path_a {
+        if let Some(b) = by_path_b.get(path) {
+            if a.sha256 != b.sha256 {
+                conflicts.push(SyncConflict {
+                    path: path.to_string(),
+                    sha256_a: a.sha256.clone(),
+                    sha256_b: b.sha256.clone(),
+                });
+            }
+        }
+    }
+    let conflicting_paths: std::collections::HashSet<&str> =
+        conflicts.iter().map(|c| c.path.as_str()).collect();
+
+    let missing_on_b: Vec<&PatchEntry> = entries_a
+        .iter()
+        .filter(|p| !conflicting_paths.contains(p.path.as_str()) && !hashes_b.contains(p.sha256.as_str()))
+        .collect();
+    let missing_on_a: Vec<&PatchEntry> = entries_b
+        .iter()
+        .filter(|p| !conflicting_paths.contains(p.path.as_str()) && !hashes_a.contains(p.sha256.as_str()))
+        .collect();
+
+    let mut report = SyncReport {
+        conflicts,
+        ..Default::default()
+    };
+
+    if !missing_on_b.is_empty() {
+        let tx = GitSyncTransaction::begin(side_b)?;
+        let mut new_manifest = manifest_b.clone();
+        let mut touched = Vec::new();
+        for entry in &missing_on_b {
+            let dest = side_b.join(&entry.path);
+            copy_patch_file(side_a, side_b, &entry.path)?;
+            new_manifest.patches.push((*entry).clone());
+            touched.push(dest);
+            report.copied_to_b.push(entry.path.clone());
+        }
+        new_manifest.write(side_b)?;
+        touched.push(side_b.join(MANIFEST_FILE_NAME));
+        tx.commit_paths(&touched, &format!("cro3 patch_sync: sync {} patch(es) from the other side", missing_on_b.len()))?;
+        tx.commit();
+        info!("Synced {} patch(es) into {:?}", missing_on_b.len(), side_b);
+    }
+    if !missing_on_a.is_empty() {
+        let tx = GitSyncTransaction::begin(side_a)?;
+        let mut new_manifest = manifest_a.clone();
+        let mut touched = Vec::new();
+        for entry in &missing_on_a {
+            let dest = side_a.join(&entry.path);
+            copy_patch_file(side_b, side_a, &entry.path)?;
+            new_manifest.patches.push((*entry).clone());
+            touched.push(dest);
+            report.copied_to_a.push(entry.path.clone());
+        }
+        new_manifest.write(side_a)?;
+        touched.push(side_a.join(MANIFEST_FILE_NAME));
+        tx.commit_paths(&touched, &format!("cro3 patch_sync: sync {} patch(es) from the other side", missing_on_a.len()))?;
+        tx.commit();
+        info!("Synced {} patch(es) into {:?}", missing_on_a.len(), side_a);
+    }
+    if !report.conflicts.is_empty() {
+        warn!(
+            "patch_sync found {} path(s) with diverging content; left untouched: {:?}",
+            report.conflicts.len(),
+            report.conflicts.iter().map(|c| &c.path).collect::<Vec<_>>()
+        );
+    }
+    Ok(report)
+}
+
+/// Copies `relative_path` from `src_checkout` into `dest_checkout`,
+/// creating any missing parent directories.
+fn copy_patch_file(src_checkout: &Path, dest_checkout: &Path, relative_path: &str) -> Result<()> {
+    let src = src_checkout.join(relative_path);
+    let dest = dest_checkout.join(relative_path);
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {parent:?}"))?;
+    }
+    std::fs::copy(&src, &dest)
+        .with_context(|| format!("failed to copy {src:?} to {dest:?}"))?;
+    Ok(())
+}
+
+/// Re-hashes every entry of `<checkout>/PATCHES.json`'s patch files and
+/// updates any whose content has changed since the manifest was last
+/// written, for `cro3 patch-sync add`/a pre-sync refresh.
+pub fn rehash_manifest(checkout: &Path) -> Result<PatchManifest> {
+    let mut manifest = PatchManifest::read(checkout)?;
+    for entry in &mut manifest.patches {
+        entry.sha256 = sha256_file(&checkout.join(&entry.path))?;
+    }
+    Ok(manifest)
+}
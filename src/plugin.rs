@@ -0,0 +1,349 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Lets users drop executables into `~/.lium/plugins/` and have them
+//! surface as subcommands of `lium plugin <name>`, without needing to be
+//! compiled into this binary (new built-in subcommands still require the
+//! `pub mod` wiring in `lib.rs`/`cmd.rs`). A plugin speaks a small
+//! newline-delimited JSON-RPC protocol over its stdin/stdout: on startup
+//! `lium` sends it a `config` request and expects back its argh-equivalent
+//! command signature (used for `lium plugin --list`), and when invoked it
+//! sends a `run` request carrying the parsed args plus a slice of the
+//! current environment. This mirrors the stdin/stdout JSON-RPC plugin
+//! model used by shells that load external command binaries.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+use tracing::warn;
+use wait_timeout::ChildExt;
+
+use crate::cache::CacheKeyInputs;
+use crate::cache::KvCache;
+use crate::cache::Memoized;
+use crate::util::shell_helpers::spawn_output_reader_threads;
+use crate::util::shell_helpers::OutputLine;
+use crate::util::xdg_dirs::data_path_in_lium_dir;
+
+const JSONRPC_VERSION: &str = "2.0";
+/// How long a plugin gets to answer the `config` handshake before it's
+/// considered unresponsive and killed.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a plugin gets to finish a `run` invocation before it's killed --
+/// the same safety net `ProcessBuilder` gives other subprocesses.
+const RUN_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Serialize)]
+struct JsonRpcRequest {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: Value,
+}
+impl JsonRpcRequest {
+    fn new(method: &'static str, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            method,
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcResponse {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// One positional argument in a plugin's command signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginPositional {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// One `--option` in a plugin's command signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginOption {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub has_value: bool,
+}
+
+/// The argh-equivalent signature a plugin reports in its `config`
+/// response: enough to print help text and, eventually, validate args
+/// before they're forwarded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginSignature {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub options: Vec<PluginOption>,
+    #[serde(default)]
+    pub positionals: Vec<PluginPositional>,
+}
+
+/// A plugin executable discovered under [`plugins_dir`], together with the
+/// signature it reported over its `config` handshake.
+pub struct Plugin {
+    pub path: PathBuf,
+    pub signature: PluginSignature,
+}
+
+/// Discovered plugin signatures, keyed by plugin path and invalidated
+/// whenever the executable's mtime changes, so a normal `lium` invocation
+/// doesn't have to re-handshake with every plugin on every run.
+static PLUGIN_SIGNATURE_CACHE: KvCache<Memoized<PluginSignature>> =
+    KvCache::new("plugin_signatures");
+
+/// The directory `lium` scans for plugin executables.
+pub fn plugins_dir() -> Result<PathBuf> {
+    let mut path = data_path_in_lium_dir("plugins/.keep")?;
+    path.pop();
+    Ok(path)
+}
+
+fn is_executable(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        return std::fs::metadata(path)
+            .map(|meta| meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false);
+    }
+    #[cfg(not(unix))]
+    true
+}
+
+/// Scans [`plugins_dir`] for executables and queries (or reuses a cached)
+/// signature for each, skipping -- with a warning, rather than failing
+/// discovery outright -- any that fail the handshake.
+pub fn discover_plugins() -> Result<Vec<Plugin>> {
+    let dir = plugins_dir()?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read the plugins dir"),
+    };
+    let mut plugins = Vec::new();
+    for entry in entries {
+        let path = entry.context("Failed to read a plugins dir entry")?.path();
+        if !is_executable(&path) {
+            continue;
+        }
+        match signature_for(&path) {
+            Ok(signature) => plugins.push(Plugin { path, signature }),
+            Err(e) => warn!("Skipping plugin {path:?}: {e:#}"),
+        }
+    }
+    plugins.sort_by(|a, b| a.signature.name.cmp(&b.signature.name));
+    Ok(plugins)
+}
+
+/// Looks up `name` among the discovered plugins.
+pub fn find_plugin(name: &str) -> Result<Plugin> {
+    discover_plugins()?
+        .into_iter()
+        .find(|plugin| plugin.signature.name == name)
+        .with_context(|| format!("No plugin named {name:?} in {:?}", plugins_dir()))
+}
+
+fn signature_for(path: &Path) -> Result<PluginSignature> {
+    let key = path.to_string_lossy().to_string();
+    let inputs = CacheKeyInputs::new(&key).input_file(path);
+    PLUGIN_SIGNATURE_CACHE.get_or_compute(&key, &inputs, None, || query_signature(path))
+}
+
+/// Spawns `path`, sends it a `config` handshake request, and parses the
+/// [`PluginSignature`] out of its response -- killing the plugin if it
+/// doesn't answer within [`HANDSHAKE_TIMEOUT`].
+fn query_signature(path: &Path) -> Result<PluginSignature> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to spawn plugin {path:?}"))?;
+
+    write_request(&mut child, &JsonRpcRequest::new("config", Value::Array(Vec::new())))?;
+
+    let deadline = std::time::Instant::now() + HANDSHAKE_TIMEOUT;
+    let stdout = child.stdout.take().context("plugin stdout was not piped")?;
+    let (response_snd, response_rcv) = std::sync::mpsc::channel();
+    // A plain blocking read_line() has no deadline of its own -- run it on
+    // its own thread and bound how long we wait on it instead, so a plugin
+    // that hangs without closing its stdout (alive, fd still open) can't
+    // block here forever with HANDSHAKE_TIMEOUT never getting a chance to
+    // fire.
+    std::thread::spawn(move || {
+        let mut response_line = String::new();
+        let result = std::io::BufReader::new(stdout)
+            .read_line(&mut response_line)
+            .map(|_| response_line);
+        let _ = response_snd.send(result);
+    });
+    let response_line = response_rcv
+        .recv_timeout(deadline.saturating_duration_since(std::time::Instant::now()))
+        .ok()
+        .transpose()
+        .with_context(|| format!("Failed to read {path:?}'s config response"))?;
+
+    kill_if_still_running(
+        &mut child,
+        deadline.saturating_duration_since(std::time::Instant::now()),
+    )
+    .with_context(|| format!("Plugin {path:?} didn't answer the config handshake in time"))?;
+
+    let response_line = response_line
+        .with_context(|| format!("Plugin {path:?} didn't answer the config handshake in time"))?;
+    parse_response(path, &response_line)
+}
+
+fn write_request(child: &mut Child, request: &JsonRpcRequest) -> Result<()> {
+    let mut line = serde_json::to_string(request).context("Failed to encode a plugin request")?;
+    line.push('\n');
+    child
+        .stdin
+        .take()
+        .context("plugin stdin was not piped")?
+        .write_all(line.as_bytes())
+        .context("Failed to write to the plugin's stdin")
+}
+
+/// Waits (up to `timeout`) for `child` to exit on its own, killing and
+/// reaping it otherwise -- the same kill-on-expiry approach `ProcessBuilder`
+/// uses for other subprocesses.
+fn kill_if_still_running(child: &mut Child, timeout: Duration) -> Result<()> {
+    match child
+        .wait_timeout(timeout)
+        .context("Failed to wait on the plugin")?
+    {
+        Some(_) => Ok(()),
+        None => {
+            child
+                .kill()
+                .context("Failed to kill an unresponsive plugin")?;
+            child.wait().context("Failed to reap a killed plugin")?;
+            bail!("timed out after {timeout:?}")
+        }
+    }
+}
+
+fn parse_response(path: &Path, line: &str) -> Result<PluginSignature> {
+    let response: JsonRpcResponse = serde_json::from_str(line.trim())
+        .with_context(|| format!("Plugin {path:?} sent an invalid JSON-RPC response: {line:?}"))?;
+    if let Some(error) = response.error {
+        bail!("Plugin {path:?} returned an error: {error}");
+    }
+    let result = response.result.with_context(|| {
+        format!("Plugin {path:?}'s response had neither a result nor an error")
+    })?;
+    serde_json::from_value(result).with_context(|| {
+        format!("Plugin {path:?}'s config response didn't match the expected signature")
+    })
+}
+
+/// Invokes `plugin` with `args`, passing `env` through as part of the `run`
+/// request. Stdout is streamed back live (the same per-stream forwarding
+/// `launch_command_with_stdout_label` uses), stderr is logged via tracing,
+/// and the plugin is killed if it runs past [`RUN_TIMEOUT`].
+pub fn run_plugin(
+    plugin: &Plugin,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+) -> Result<ExitStatus> {
+    let mut child = Command::new(&plugin.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to spawn plugin {:?}", plugin.path))?;
+
+    let params = serde_json::json!({ "args": args, "env": env });
+    write_request(&mut child, &JsonRpcRequest::new("run", params))?;
+
+    let stdout_iter = child
+        .stdout
+        .take()
+        .map(|s| std::io::BufReader::new(s).lines())
+        .into_iter()
+        .flatten();
+    let stderr_iter = child
+        .stderr
+        .take()
+        .map(|s| std::io::BufReader::new(s).lines())
+        .into_iter()
+        .flatten();
+    // `log = false`: we forward stdout/stderr ourselves below (stdout to
+    // this process's stdout, stderr via tracing) instead of letting the
+    // reader threads log stdout lines too.
+    let (merged_snd, merged_rcv) = std::sync::mpsc::sync_channel(1);
+    let (stdout_join, stderr_join) =
+        spawn_output_reader_threads(stdout_iter, stderr_iter, merged_snd, false);
+    // A plain blocking recv() here would have no deadline of its own, so a
+    // plugin that hangs without closing its stdout/stderr (alive, fds still
+    // open) would block forever and RUN_TIMEOUT would never get a chance to
+    // fire. Poll with a short timeout instead and check the overall deadline
+    // on each wakeup.
+    let deadline = std::time::Instant::now() + RUN_TIMEOUT;
+    loop {
+        match merged_rcv.recv_timeout(Duration::from_millis(200)) {
+            Ok(OutputLine::Stdout(line)) => println!("{line}"),
+            Ok(OutputLine::Stderr(line)) => info!("stderr: {line}"),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                if std::time::Instant::now() >= deadline {
+                    break;
+                }
+            }
+        }
+    }
+
+    let status = match child
+        .wait_timeout(deadline.saturating_duration_since(std::time::Instant::now()))
+        .context("Failed to wait on the plugin")?
+    {
+        Some(status) => status,
+        None => {
+            child.kill().context("Failed to kill a stuck plugin")?;
+            child.wait().context("Failed to reap a killed plugin")?
+        }
+    };
+    info!("Plugin {} finished with {status}", plugin.signature.name);
+
+    stdout_join
+        .join()
+        .map_err(|e| anyhow::anyhow!("could not join plugin stdout reader thread: {e:?}"))?;
+    stderr_join
+        .join()
+        .map_err(|e| anyhow::anyhow!("could not join plugin stderr reader thread: {e:?}"))?;
+
+    Ok(status)
+}
@@ -0,0 +1,37 @@
+// Copyright 2026 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Small, dependency-free deterministic PRNG helpers shared by the A/B test
+//! schedulers (`abtest`'s `build_schedule`, `cmd::abtest`'s work-item
+//! shuffle) so a given `--seed` always reproduces the same interleaving
+//! regardless of the `rand` crate's (unspecified) internal algorithm.
+
+/// Splitmix64.
+pub struct SplitMix64(pub u64);
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform value in `[0, bound)`.
+    fn gen_range(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Seeded Fisher-Yates shuffle: walks from the last element down to the
+/// second, swapping element `i` with a random `j` in `[0, i]`. Reusing the
+/// same seed always reproduces the same permutation.
+pub fn seeded_fisher_yates_shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64(seed);
+    for i in (1..items.len()).rev() {
+        let j = rng.gen_range(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Thin wrapper around the `aws` CLI for talking to an S3-compatible
+//! bucket, mirroring [`crate::google_storage`]'s `gsutil.py`-shelling
+//! convention but pointed at an arbitrary `--endpoint-url` so the same code
+//! works against AWS S3 itself or any compatible object store.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Where an S3-compatible cache lives and how to authenticate against it.
+#[derive(Debug, Clone)]
+pub struct S3Bucket {
+    pub bucket: String,
+    pub endpoint: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+impl S3Bucket {
+    fn run(&self, args: &[&str]) -> Result<std::process::Output> {
+        let mut cmd = Command::new("aws");
+        cmd.arg("s3");
+        cmd.args(args);
+        if let Some(endpoint) = &self.endpoint {
+            cmd.args(["--endpoint-url", endpoint]);
+        }
+        if let Some(key) = &self.access_key_id {
+            cmd.env("AWS_ACCESS_KEY_ID", key);
+        }
+        if let Some(secret) = &self.secret_access_key {
+            cmd.env("AWS_SECRET_ACCESS_KEY", secret);
+        }
+        cmd.output().context("Failed to execute the `aws` CLI")
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("s3://{}/{key}", self.bucket)
+    }
+
+    /// Whether an object is already present under `key`, via `aws s3 ls`.
+    pub fn exists(&self, key: &str) -> Result<bool> {
+        let output = self.run(&["ls", &self.object_url(key)])?;
+        Ok(output.status.success() && !output.stdout.is_empty())
+    }
+
+    /// Downloads the object at `key` to `dest`.
+    pub fn download(&self, key: &str, dest: &Path) -> Result<()> {
+        let url = self.object_url(key);
+        let dest_str = dest.to_string_lossy().to_string();
+        let output = self.run(&["cp", &url, &dest_str])?;
+        if !output.status.success() {
+            bail!(
+                "aws s3 cp {url} {} failed: {}",
+                dest.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+
+    /// Uploads `src` to the object at `key`.
+    pub fn upload(&self, src: &Path, key: &str) -> Result<()> {
+        let url = self.object_url(key);
+        let src_str = src.to_string_lossy().to_string();
+        let output = self.run(&["cp", &src_str, &url])?;
+        if !output.status.success() {
+            bail!(
+                "aws s3 cp {} {url} failed: {}",
+                src.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+}
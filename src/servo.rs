@@ -4,6 +4,7 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+use crate::cache::KvCache;
 use crate::chroot::Chroot;
 use crate::config::Config;
 use crate::util::get_async_lines;
@@ -11,11 +12,17 @@ use crate::util::get_stderr;
 use crate::util::get_stdout;
 use crate::util::has_root_privilege;
 use crate::util::run_bash_command;
+use crate::util::run_bash_command_async;
 use crate::util::run_lium_with_sudo;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use async_io::Timer;
 use async_process::Child;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Local;
 use core::str::FromStr;
 use futures::executor::block_on;
 use futures::select;
@@ -32,14 +39,49 @@ use retry::retry;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs;
+use std::io::Write;
 use std::iter::FromIterator;
 use std::path::Path;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
+use std::time::Instant;
+
+/// Minimal CRC-32 (IEEE 802.3 polynomial) implementation used to verify a
+/// flashed image against the device's reported checksum, without pulling in
+/// an extra crate for a single use.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Result of `LocalServo::open_console` in capture mode: every line seen
+/// (most recent last) and whether the `until` regex matched before the
+/// timeout elapsed.
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleCapture {
+    pub lines: Vec<String>,
+    pub matched: bool,
+}
 
 lazy_static! {
     static ref RE_MAC_ADDR: Regex =
@@ -47,6 +89,8 @@ lazy_static! {
     static ref RE_EC_VERSION: Regex = Regex::new(r"RO:\s*(?P<version>.*)\n").unwrap();
     static ref RE_GBB_FLAGS: Regex = Regex::new(r"^flags: 0x(?P<flags>[0-9a-fA-F]+)$").unwrap();
     static ref RE_USB_SYSFS_PATH_FUNC: Regex = Regex::new(r"\.[0-9]+$").unwrap();
+    static ref RE_SERVOD_PS_SERIAL: Regex = Regex::new(r"-s\s+(?P<serial>\S+)").unwrap();
+    static ref RE_SERVOD_PS_PORT: Regex = Regex::new(r"-p\s+(?P<port>[0-9]+)").unwrap();
 }
 #[cfg(test)]
 mod tests {
@@ -69,6 +113,80 @@ mod tests {
     }
 }
 
+/// Outcome of [`LocalServo::read_ipv6_addr_verified`]: whether the
+/// synthesized EUI-64 address is just a computed guess, or an address
+/// that responded to a reachability check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6AddrStatus {
+    /// Computed from the servo's MAC and the configured prefix, but not
+    /// (yet) confirmed reachable.
+    Unverified(String),
+    /// Confirmed reachable, either the EUI-64 guess itself or an address
+    /// recovered from the neighbor table by matching the servo's MAC.
+    Confirmed(String),
+}
+impl Ipv6AddrStatus {
+    /// Returns the address regardless of verification status.
+    pub fn addr(&self) -> &str {
+        match self {
+            Ipv6AddrStatus::Unverified(addr) | Ipv6AddrStatus::Confirmed(addr) => addr,
+        }
+    }
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self, Ipv6AddrStatus::Confirmed(_))
+    }
+}
+
+lazy_static! {
+    // e.g. "3: eth0    inet6 2001:db8::1/64 scope global"
+    static ref RE_IP_ADDR_SHOW: Regex =
+        Regex::new(r"^\d+:\s*(?P<iface>\S+)\s+inet6\s+(?P<addr>[0-9a-fA-F:]+)/\d+").unwrap();
+    // e.g. "2001:db8::aabb:ccff:fedd:eeff dev eth0 lladdr aa:bb:cc:dd:ee:ff REACHABLE"
+    static ref RE_IP_NEIGH_SHOW: Regex =
+        Regex::new(r"^(?P<addr>[0-9a-fA-F:]+)\s+dev\s+\S+\s+lladdr\s+(?P<lladdr>[0-9a-fA-F:]+)")
+            .unwrap();
+}
+
+/// Number of leading `:`-separated groups to compare when matching a host
+/// interface address against `default_ipv6_prefix`, since the prefix may be
+/// given with or without a trailing `:`.
+fn prefix_group_count(prefix: &str) -> usize {
+    prefix.trim_end_matches(':').split(':').count()
+}
+
+/// Finds the host network interface that has an address on
+/// `default_ipv6_prefix`, by matching the leading address groups.
+fn find_host_iface_for_prefix(prefix: &str) -> Result<Option<String>> {
+    let ngroups = prefix_group_count(prefix);
+    let output = run_bash_command("ip -o -6 addr show", None)?;
+    let stdout = get_stdout(&output);
+    for line in stdout.lines() {
+        if let Some(c) = RE_IP_ADDR_SHOW.captures(line) {
+            let addr_groups: Vec<&str> = c["addr"].split(':').collect();
+            let prefix_groups: Vec<&str> = prefix.trim_end_matches(':').split(':').collect();
+            if addr_groups.len() >= ngroups && addr_groups[..ngroups] == prefix_groups[..] {
+                return Ok(Some(c["iface"].to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Scans `iface`'s IPv6 neighbor table for an entry whose link-layer
+/// address matches `mac_addr`, returning its IPv6 address if found.
+fn find_neighbor_by_lladdr(iface: &str, mac_addr: &str) -> Result<Option<String>> {
+    let output = run_bash_command(&format!("ip -6 neigh show dev {iface}"), None)?;
+    let stdout = get_stdout(&output);
+    for line in stdout.lines() {
+        if let Some(c) = RE_IP_NEIGH_SHOW.captures(line) {
+            if c["lladdr"].eq_ignore_ascii_case(mac_addr) {
+                return Ok(Some(c["addr"].to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
 fn get_usb_sysfs_path_stem(path: &str) -> String {
     RE_USB_SYSFS_PATH_FUNC.replace(path, "").to_string()
 }
@@ -150,6 +268,7 @@ fn discover_slow() -> Result<Vec<LocalServo>> {
         let mac_addr = s.read_mac_addr().ok();
         let ec_version = s.read_ec_version().ok();
         s.cached_info = Some(CachedServoInfo {
+            usb_sysfs_path: s.usb_sysfs_path.clone(),
             mac_addr,
             ec_version,
         })
@@ -157,6 +276,34 @@ fn discover_slow() -> Result<Vec<LocalServo>> {
     Ok(servos)
 }
 
+/// Persistent, on-disk version of `cached_info`, keyed by servo serial, so
+/// repeated `servo list` calls don't have to re-probe every console. A
+/// cache entry is invalidated (re-probed) when the device's
+/// `usb_sysfs_path` no longer matches, since that means it was re-plugged.
+pub static SERVO_CACHE: KvCache<CachedServoInfo> = KvCache::new("servo_cache");
+
+fn discover_cached() -> Result<Vec<LocalServo>> {
+    let mut servos = discover()?;
+    for s in servos.iter_mut() {
+        let cached = SERVO_CACHE.get(&s.serial)?;
+        let cached = cached.filter(|c| c.usb_sysfs_path == s.usb_sysfs_path);
+        s.cached_info = Some(match cached {
+            Some(cached) => cached,
+            None => {
+                eprintln!("Checking {} (cache miss)", s.serial);
+                let info = CachedServoInfo {
+                    usb_sysfs_path: s.usb_sysfs_path.clone(),
+                    mac_addr: s.read_mac_addr().ok(),
+                    ec_version: s.read_ec_version().ok(),
+                };
+                SERVO_CACHE.set(&s.serial, info.clone())?;
+                info
+            }
+        });
+    }
+    Ok(servos)
+}
+
 pub fn reset_devices(serials: &Vec<String>) -> Result<()> {
     let servo_info = discover()?;
     let servo_info: Vec<LocalServo> = if !serials.is_empty() {
@@ -192,6 +339,11 @@ impl ServoList {
             devices: discover_slow()?,
         })
     }
+    pub fn discover_cached() -> Result<Self> {
+        Ok(Self {
+            devices: discover_cached()?,
+        })
+    }
     pub fn find_by_serial(&self, serial: &str) -> Result<&LocalServo> {
         self.devices
             .iter()
@@ -214,6 +366,8 @@ impl Display for ServoList {
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct CachedServoInfo {
+    #[serde(default)]
+    usb_sysfs_path: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     mac_addr: Option<String>,
@@ -221,7 +375,15 @@ pub struct CachedServoInfo {
     #[serde(default)]
     ec_version: Option<String>,
 }
-impl CachedServoInfo {}
+impl CachedServoInfo {
+    pub fn new(usb_sysfs_path: String, mac_addr: Option<String>, ec_version: Option<String>) -> Self {
+        Self {
+            usb_sysfs_path,
+            mac_addr,
+            ec_version,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct LocalServo {
@@ -268,9 +430,216 @@ impl LocalServo {
             .context(anyhow!("servo command failed: {}", get_stderr(&output)))?;
         Ok(get_stdout(&output))
     }
+    /// Opens `tty_type`'s console asynchronously, reusing the
+    /// `get_async_lines`/`select!` machinery also used by `start_servod`, so
+    /// the caller gets a live stream instead of `run_cmd`'s one-shot
+    /// echo/response. Every received line is teed to `log_file` (if given)
+    /// with a timestamp prefix.
+    ///
+    /// If `until` is `None`, this runs interactively: console output goes
+    /// to stdout and stdin keystrokes are forwarded to the console, until
+    /// the underlying process exits. If `until` is `Some`, this instead
+    /// captures silently and returns as soon as a line matches the regex or
+    /// `timeout` elapses, without touching stdin/stdout.
+    pub fn open_console(
+        &self,
+        tty_type: &str,
+        log_file: Option<&Path>,
+        until: Option<&Regex>,
+        timeout: Duration,
+    ) -> Result<ConsoleCapture> {
+        let tty_path = self.tty_path(tty_type)?;
+        run_bash_command(&format!("stty -F {tty_path} 115200 raw -echo"), None)
+            .context("Failed to configure the console tty")?;
+        let mut child = run_bash_command_async(&format!("exec cat {tty_path}"), None)
+            .context("Failed to attach to the console tty")?;
+        let (stdout, _stderr) = get_async_lines(&mut child);
+        let mut stdout = stdout.context("console stdout was None")?;
+        let mut log_file = log_file
+            .map(fs::File::create)
+            .transpose()
+            .context("Failed to create the console log file")?;
+        let mut lines = Vec::new();
+        let matched = block_on(async {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let mut next_line = stdout.next().fuse();
+                let mut timed_out = Timer::at(deadline).fuse();
+                select! {
+                    line = next_line => {
+                        let Some(line) = line else { return false };
+                        let line = line.unwrap_or_default();
+                        if let Some(log_file) = &mut log_file {
+                            let _ = writeln!(log_file, "[{}] {line}", Local::now());
+                        }
+                        if until.is_none() {
+                            println!("{line}");
+                        }
+                        let is_match = until.map(|re| re.is_match(&line)).unwrap_or(false);
+                        lines.push(line);
+                        if is_match {
+                            return true;
+                        }
+                    }
+                    _ = timed_out => {
+                        return false;
+                    }
+                }
+            }
+        });
+        let _ = child.kill();
+        Ok(ConsoleCapture { lines, matched })
+    }
+    /// Flashes `image_path` to the EC/AP reachable from `tty_type`'s
+    /// console, transferring it in `block_size` chunks with per-block
+    /// acknowledgement instead of a single long-running `flashrom`
+    /// invocation. A background keepalive task pokes the console
+    /// periodically so the session doesn't time out during a long
+    /// transfer, and each failed block is retried individually rather than
+    /// restarting the whole transfer. Returns once a post-flash CRC
+    /// readback confirms the image matches.
+    pub fn flash_firmware(
+        &self,
+        tty_type: &str,
+        image_path: &Path,
+        block_size: usize,
+    ) -> Result<()> {
+        let image = fs::read(image_path)
+            .with_context(|| format!("Failed to read firmware image {image_path:?}"))?;
+        let total_bytes = image.len();
+        info!(
+            "Opening a flash session on {tty_type} ({total_bytes} bytes, {block_size}-byte blocks)..."
+        );
+        self.run_cmd(tty_type, "flash_open")
+            .context("Failed to open a flash session")?;
+
+        let keepalive_stop = Arc::new(AtomicBool::new(false));
+        let keepalive_handle = {
+            let servo = self.clone();
+            let tty_type = tty_type.to_string();
+            let stop = keepalive_stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_secs(5));
+                    if !stop.load(Ordering::Relaxed) {
+                        // Tester-present keepalive; errors are expected once
+                        // the session is closed, so ignore them.
+                        let _ = servo.run_cmd(&tty_type, "flash_keepalive");
+                    }
+                }
+            })
+        };
+        let transfer_result = (|| -> Result<()> {
+            for (i, block) in image.chunks(block_size.max(1)).enumerate() {
+                retry(delay::Fixed::from_millis(500).take(3), || {
+                    self.write_flash_block(tty_type, i, block)
+                })
+                .map_err(|e| anyhow!("Failed to write flash block {i} after retries: {e:?}"))?;
+                info!(
+                    "Flashed {}/{total_bytes} bytes",
+                    ((i + 1) * block_size).min(total_bytes)
+                );
+            }
+            Ok(())
+        })();
+        keepalive_stop.store(true, Ordering::Relaxed);
+        let _ = keepalive_handle.join();
+        if let Err(e) = transfer_result {
+            // Best-effort: close the flash session before propagating the
+            // transfer error, so a failed flash doesn't leave the device's
+            // flash session open and requiring a manual power cycle /
+            // `flash_close` before the next attempt can succeed.
+            let _ = self.run_cmd(tty_type, "flash_close");
+            return Err(e);
+        }
+
+        self.run_cmd(tty_type, "flash_close")
+            .context("Failed to close the flash session")?;
+        let expected_crc = format!("{:08x}", crc32(&image));
+        let reported_crc = self
+            .run_cmd(tty_type, "flash_crc32")
+            .context("Failed to read back the flashed image's CRC")?
+            .trim()
+            .to_lowercase();
+        if reported_crc != expected_crc {
+            bail!(
+                "Post-flash verification failed: device reports CRC {reported_crc}, expected \
+                 {expected_crc}"
+            );
+        }
+        info!("Flash verified ({total_bytes} bytes, crc32={expected_crc})");
+        Ok(())
+    }
+    /// Opens a persistent, bidirectional console session on `tty_type`,
+    /// with the tty configured raw at `baud` so the remote side sees
+    /// keystrokes as-typed, analogous to a remote `--shell`. Unlike
+    /// [`Self::run_cmd`] this spawns `socat` exactly once for the whole
+    /// session instead of once per command; the session ends when
+    /// `escape_byte` is typed (consumed by socat's `escape` option) or on
+    /// Ctrl-C (SIGINT, handled by socat's own default teardown), whichever
+    /// comes first. If `log_file` is given, the whole session (everything
+    /// the user typed and everything the console echoed back) is recorded
+    /// there via `script`.
+    pub fn open_interactive_shell(
+        &self,
+        tty_type: &str,
+        log_file: Option<&Path>,
+        baud: u32,
+        escape_byte: u8,
+    ) -> Result<()> {
+        let tty_path = self.tty_list.get(tty_type).cloned().with_context(|| {
+            format!(
+                "tty type {tty_type:?} not found for servo {}; available types: {}",
+                self.serial,
+                self.tty_list.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        run_bash_command(&format!("stty -F {tty_path} {baud} raw -echo"), None)
+            .context("Failed to configure the console tty")?;
+        eprintln!(
+            "Opening interactive {tty_type} console on {tty_path} at {baud} baud. Press the \
+             configured escape key (byte 0x{escape_byte:02x}) or Ctrl-C to leave."
+        );
+        let socat_cmd = format!(
+            "exec socat -,raw,echo=0,escape=0x{escape_byte:02x} \
+             {tty_path},raw,echo=0,crtscts=1,b{baud}"
+        );
+        let status = match log_file {
+            Some(path) => process::Command::new("script")
+                .args(["-qc", &socat_cmd])
+                .arg(path)
+                .status(),
+            None => process::Command::new("bash").arg("-c").arg(&socat_cmd).status(),
+        }
+        .context("Failed to launch the interactive console session")?;
+        if !status.success() {
+            bail!("Interactive console session on {tty_path} exited with {status}");
+        }
+        Ok(())
+    }
+    fn write_flash_block(&self, tty_type: &str, index: usize, block: &[u8]) -> Result<()> {
+        let encoded = STANDARD.encode(block);
+        let response = self.run_cmd(tty_type, &format!("flash_write {index} {encoded}"))?;
+        if !response.trim().starts_with("OK") {
+            bail!("Block {index} was not acknowledged: {response}");
+        }
+        Ok(())
+    }
     pub fn usb_sysfs_path(&self) -> &str {
         &self.usb_sysfs_path
     }
+    /// MAC address from the last `--slow`/`--cached` discovery, if any ran;
+    /// `None` for a plain `discover()` (which doesn't probe it) or a servo
+    /// that doesn't expose one.
+    pub fn cached_mac_addr(&self) -> Option<&str> {
+        self.cached_info.as_ref()?.mac_addr.as_deref()
+    }
+    /// EC version from the last `--slow`/`--cached` discovery, if any ran;
+    /// `None` for a plain `discover()` (which doesn't probe it) or a servo
+    /// that doesn't expose one.
+    pub fn cached_ec_version(&self) -> Option<&str> {
+        self.cached_info.as_ref()?.ec_version.as_deref()
+    }
     pub fn reset(&self) -> Result<()> {
         if has_root_privilege()? {
             eprintln!("Resetting servo device: {}", self.serial);
@@ -306,7 +675,12 @@ impl LocalServo {
             .context("failed to launch servod")
     }
     pub fn start_servod(&self, chroot: &Chroot) -> Result<ServodConnection> {
-        block_on(async {
+        // A stale SERVOD_STATE entry for this serial (left by a crashed
+        // `cro3` run) shouldn't block this fresh launch -- the port scan
+        // below doesn't consult SERVOD_STATE at all, it only matters for
+        // `servo status`/`servo stop` staying accurate.
+        reconcile_servod_state()?;
+        let (servod, port) = block_on(async {
             eprintln!("Starting servod...");
             let mut ports = (9000..9099).into_iter().collect::<Vec<u16>>();
             let mut rng = thread_rng();
@@ -336,7 +710,7 @@ impl LocalServo {
                                     let line = line?;
                                     eprintln!("{}", line);
                                     if line.contains("Listening on localhost port") {
-                                        return Result::Ok(servod);
+                                        return Result::Ok((servod, port));
                                     }
                                 } else {
                     return Err(anyhow!("servod failed unexpectedly"));
@@ -347,6 +721,9 @@ impl LocalServo {
             }
             return Err(anyhow!("servod failed unexpectedly"));
         })?;
+        if let Some(pid) = servod.id() {
+            SERVOD_STATE.set(&self.serial, ServodRecord { pid, port })?;
+        }
         ServodConnection::from_serial(&self.serial)
     }
     pub fn is_cr50(&self) -> bool {
@@ -401,12 +778,8 @@ impl LocalServo {
         MacAddr8::from_str(&self.read_mac_addr()?)
             .context("Failed to convert MAC address string to MacAddr8")
     }
-    pub fn read_ipv6_addr(&self) -> Result<String> {
+    fn compute_eui64_ipv6_addr(&self, prefix: &str) -> Result<String> {
         let mac_addr = self.read_mac_addr6()?;
-        let config = Config::read()?;
-        let prefix = config
-            .default_ipv6_prefix()
-            .context("Config default_ipv6_prefix is needed")?;
         let mac_addr = mac_addr.as_bytes();
         let mut eui64_bytes = [0; 8];
         eui64_bytes.copy_from_slice(
@@ -423,6 +796,39 @@ impl LocalServo {
                 .to_lowercase()
         ))
     }
+    pub fn read_ipv6_addr(&self) -> Result<String> {
+        let config = Config::read()?;
+        let prefix = config
+            .default_ipv6_prefix()
+            .context("Config default_ipv6_prefix is needed")?;
+        self.compute_eui64_ipv6_addr(&prefix)
+    }
+    /// Like [`LocalServo::read_ipv6_addr`], but confirms the synthesized
+    /// Modified-EUI-64 address is actually reachable rather than assuming
+    /// it. Finds the host interface on `default_ipv6_prefix`, pings the
+    /// computed address, and if that fails, falls back to scanning that
+    /// interface's neighbor table for an entry whose link-layer address
+    /// matches the servo's MAC. This catches DUTs that use IPv6 privacy
+    /// addresses or otherwise didn't pick up an EUI-64 address.
+    pub fn read_ipv6_addr_verified(&self) -> Result<Ipv6AddrStatus> {
+        let config = Config::read()?;
+        let prefix = config
+            .default_ipv6_prefix()
+            .context("Config default_ipv6_prefix is needed")?;
+        let guess = self.compute_eui64_ipv6_addr(&prefix)?;
+        let host = guess.trim_start_matches('[').trim_end_matches(']');
+        let ping = run_bash_command(&format!("ping -c 1 -W 0.5 {host} 1>/dev/null 2>&1"), None)?;
+        if ping.status.success() {
+            return Ok(Ipv6AddrStatus::Confirmed(guess));
+        }
+        if let Some(iface) = find_host_iface_for_prefix(&prefix)? {
+            let mac_addr = self.read_mac_addr()?;
+            if let Some(addr) = find_neighbor_by_lladdr(&iface, &mac_addr)? {
+                return Ok(Ipv6AddrStatus::Confirmed(format!("[{addr}]")));
+            }
+        }
+        Ok(Ipv6AddrStatus::Unverified(guess))
+    }
     pub fn read_gbb_flags(&self, repo: &str) -> Result<u64> {
         if !self.is_cr50() {
             return get_cr50_attached_to_servo(self)?.read_gbb_flags(repo);
@@ -446,6 +852,46 @@ impl LocalServo {
             .context("Invalid output of futility: {flags}")?["flags"];
         u64::from_str_radix(flags, 16).context("Failed to convert value: {flags}")
     }
+    /// Writes `flags` back through the same Cr50/flashrom/futility path
+    /// [`Self::read_gbb_flags`] reads through: read the current GBB image,
+    /// patch just its flags word with `futility gbb --set`, then flash the
+    /// patched image back.
+    pub fn write_gbb_flags(&self, repo: &str, flags: u64) -> Result<()> {
+        if !self.is_cr50() {
+            return get_cr50_attached_to_servo(self)?.write_gbb_flags(repo, flags);
+        }
+        let chroot = Chroot::new(repo)?;
+        eprintln!("Reading gbb flags via Cr50...");
+        chroot.exec_in_chroot(&[
+            "sudo",
+            "flashrom",
+            "-p",
+            &format!("raiden_debug_spi:target=AP,serial={}", self.serial),
+            "-r",
+            "-i",
+            "GBB:/tmp/gbb.bin",
+        ])?;
+        eprintln!("Writing gbb flags...");
+        chroot.exec_in_chroot(&[
+            "sudo",
+            "futility",
+            "gbb",
+            "-s",
+            &format!("--flags={flags:#x}"),
+            "/tmp/gbb.bin",
+        ])?;
+        eprintln!("Flashing gbb flags back via Cr50...");
+        chroot.exec_in_chroot(&[
+            "sudo",
+            "flashrom",
+            "-p",
+            &format!("raiden_debug_spi:target=AP,serial={}", self.serial),
+            "-w",
+            "-i",
+            "GBB:/tmp/gbb.bin",
+        ])?;
+        Ok(())
+    }
 }
 impl Display for LocalServo {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -457,6 +903,153 @@ impl Display for LocalServo {
     }
 }
 
+/// One servod instance `start_servod` launched, tracked in [`SERVOD_STATE`]
+/// by serial so `cro3 servo status`/`cro3 servo stop` can report on (and
+/// clean up) servod processes without re-scraping `ps ax` for every query,
+/// and so a servod left behind by a crashed `cro3` run is still visible.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServodRecord {
+    pub pid: u32,
+    pub port: u16,
+}
+
+/// Persistent serial -> [`ServodRecord`] map under `~/.cro3`, written by
+/// `start_servod` and consulted/reaped by `reconcile_servod_state`,
+/// `reap_orphaned_servod`, and `cro3 servo status`/`stop`.
+pub static SERVOD_STATE: KvCache<ServodRecord> = KvCache::new("servod_state");
+
+/// Persistent serial -> arbitrary key/value map under `~/.cro3`, for
+/// anything `cro3 servo set`/`get` writes that isn't a live hardware
+/// attribute -- e.g. a preferred `tty_type`, a `board` override, or a
+/// `servod_port` -- so a user only has to specify it once per Servo.
+pub static SERVO_USER_CONFIG: KvCache<HashMap<String, String>> = KvCache::new("servo_user_config");
+
+/// Reads one key out of `serial`'s entry in [`SERVO_USER_CONFIG`].
+pub fn servo_user_config_get(serial: &str, key: &str) -> Result<Option<String>> {
+    Ok(SERVO_USER_CONFIG
+        .get(serial)?
+        .and_then(|config| config.get(key).cloned()))
+}
+
+/// Writes one key into `serial`'s entry in [`SERVO_USER_CONFIG`], creating
+/// the entry if this is the first key stored for that serial.
+pub fn servo_user_config_set(serial: &str, key: &str, value: &str) -> Result<()> {
+    let mut config = SERVO_USER_CONFIG.get(serial)?.unwrap_or_default();
+    config.insert(key.to_string(), value.to_string());
+    SERVO_USER_CONFIG.set(serial, config)
+}
+
+/// Removes one key from `serial`'s entry in [`SERVO_USER_CONFIG`], or the
+/// whole entry if `key` is `None`.
+pub fn servo_user_config_clear(serial: &str, key: Option<&str>) -> Result<()> {
+    match key {
+        Some(key) => {
+            if let Some(mut config) = SERVO_USER_CONFIG.get(serial)? {
+                config.remove(key);
+                SERVO_USER_CONFIG.set(serial, config)?;
+            }
+            Ok(())
+        }
+        None => SERVO_USER_CONFIG.remove(serial),
+    }
+}
+
+/// Whether `pid` still refers to a live process.
+fn is_process_alive(pid: u32) -> bool {
+    Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// Drops any [`SERVOD_STATE`] entry whose recorded pid is no longer alive,
+/// so a stale record left by a crashed `cro3` run doesn't make `servo
+/// status` misreport a DUT as currently served.
+pub fn reconcile_servod_state() -> Result<()> {
+    for (serial, record) in SERVOD_STATE.entries()? {
+        if !is_process_alive(record.pid) {
+            SERVOD_STATE.remove(&serial)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists every running `servod` process as `(pid, serial, port)`, parsed
+/// out of `ps ax`, regardless of whether [`SERVOD_STATE`] knows about it.
+fn list_running_servod() -> Result<Vec<(u32, String, u16)>> {
+    let output = run_bash_command("ps ax | grep /servod | grep -v grep", None)?;
+    Ok(get_stdout(&output)
+        .lines()
+        .filter_map(|line| {
+            let pid = line.split_whitespace().next()?.parse::<u32>().ok()?;
+            let serial = RE_SERVOD_PS_SERIAL.captures(line)?["serial"].to_string();
+            let port = RE_SERVOD_PS_PORT.captures(line)?["port"].parse::<u16>().ok()?;
+            Some((pid, serial, port))
+        })
+        .collect())
+}
+
+/// Kills any running `servod` process not accounted for by
+/// [`SERVOD_STATE`] (an orphan/zombie left behind by a crashed `cro3` run,
+/// or a `servod` started outside `cro3` entirely), and returns the serials
+/// it reaped.
+pub fn reap_orphaned_servod() -> Result<Vec<String>> {
+    reconcile_servod_state()?;
+    let tracked = SERVOD_STATE.entries()?;
+    let mut reaped = Vec::new();
+    for (pid, serial, _port) in list_running_servod()? {
+        let is_tracked = tracked.get(&serial).map(|r| r.pid == pid).unwrap_or(false);
+        if !is_tracked {
+            let _ = run_bash_command(&format!("sudo kill -9 {pid}"), None);
+            reaped.push(serial);
+        }
+    }
+    Ok(reaped)
+}
+
+/// Terminates the tracked servod for `serial`, if any, and forgets it.
+pub fn stop_servod(serial: &str) -> Result<()> {
+    if let Some(record) = SERVOD_STATE.get(serial)? {
+        if is_process_alive(record.pid) {
+            run_bash_command(&format!("sudo kill {}", record.pid), None)?;
+        }
+        SERVOD_STATE.remove(serial)?;
+    }
+    Ok(())
+}
+
+/// Terminates every tracked servod and returns the serials stopped.
+pub fn stop_all_servod() -> Result<Vec<String>> {
+    let serials: Vec<String> = SERVOD_STATE.entries()?.into_keys().collect();
+    for serial in &serials {
+        stop_servod(serial)?;
+    }
+    Ok(serials)
+}
+
+/// One [`SERVOD_STATE`] entry as reported by `cro3 servo status`: whether
+/// the pid it was recorded with is still alive.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServodStatusEntry {
+    pub serial: String,
+    pub pid: u32,
+    pub port: u16,
+    pub alive: bool,
+}
+
+/// Lists every tracked servod, live or dead, sorted by serial.
+pub fn servod_status() -> Result<Vec<ServodStatusEntry>> {
+    let mut entries: Vec<ServodStatusEntry> = SERVOD_STATE
+        .entries()?
+        .into_iter()
+        .map(|(serial, record)| ServodStatusEntry {
+            alive: is_process_alive(record.pid),
+            serial,
+            pid: record.pid,
+            port: record.port,
+        })
+        .collect();
+    entries.sort_by(|a, b| a.serial.cmp(&b.serial));
+    Ok(entries)
+}
+
 pub struct ServodConnection {
     serial: String,
     host: String,
@@ -505,6 +1098,7 @@ impl ServodConnection {
 #[test]
 fn local_servo_info_in_json() {
     let cached_info = CachedServoInfo{
+        usb_sysfs_path: "/sys/bus/usb/devices/1-2.3".to_string(),
         mac_addr: Some("00:00:5e:00:53:01".to_string()),
         ec_version: None
     };
@@ -529,6 +1123,7 @@ fn local_servo_info_in_json() {
     "Servo EC Shell": "/dev/ttyUSB0"
   },
   "cached_info": {
+    "usb_sysfs_path": "/sys/bus/usb/devices/1-2.3",
     "mac_addr": "00:00:5e:00:53:01"
   }
 }"#);
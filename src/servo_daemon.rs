@@ -0,0 +1,113 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Client/server protocol for `cro3 servo daemon`, the persistent servod
+//! manager. Mirrors [`crate::daemon_client`]'s DUT-daemon protocol, but
+//! keyed by servo serial and proxying `dut_control`/console-shell requests
+//! instead of DUT SSH ones, so concurrent `servo control`/`servo shell`
+//! invocations on the same host stop fighting each other over
+//! `pkill -f servod`.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::util::gen_path_in_lium_dir;
+
+/// Bumped whenever `ServodDaemonRequest`/`ServodDaemonResponse` change
+/// shape, so a stale daemon left running from a previous `cro3` build is
+/// detected cleanly instead of failing to deserialize a request it
+/// doesn't know.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+}
+
+pub fn socket_path() -> Result<String> {
+    Ok(gen_path_in_lium_dir("servod_daemon.sock")?
+        .to_string_lossy()
+        .to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServodDaemonRequest {
+    /// Runs `dut_control` with `args` against `serial`'s servod, starting
+    /// (or restarting) it first if it isn't already alive.
+    DutControl { serial: String, args: Vec<String> },
+    /// Runs a single command over `serial`'s `tty_type` console.
+    Shell {
+        serial: String,
+        tty_type: String,
+        cmd: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServodDaemonResponse {
+    Output(String),
+    Error(String),
+}
+
+/// Returns true if a servod daemon is listening on the well-known socket.
+pub fn is_running() -> bool {
+    socket_path()
+        .ok()
+        .and_then(|p| UnixStream::connect(p).ok())
+        .is_some()
+}
+
+fn send_line<T: Serialize>(stream: &mut UnixStream, value: &T) -> Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn read_line(stream: &UnixStream) -> Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(line)
+}
+
+/// Sends `req` to the running servod daemon and waits for its response.
+/// Callers should check `is_running()` first (or call `ensure_running()`)
+/// and fall back to driving servod themselves if it returns false; this
+/// fails outright if the daemon disappeared in between.
+pub fn query(req: &ServodDaemonRequest) -> Result<ServodDaemonResponse> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path).context("servod daemon is not running")?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+
+    send_line(
+        &mut stream,
+        &Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+    let their_version = serde_json::from_str::<Hello>(&read_line(&stream)?)
+        .context("servod daemon sent an invalid handshake")?
+        .version;
+    if their_version != PROTOCOL_VERSION {
+        bail!(
+            "servod daemon speaks protocol v{their_version}, this cro3 speaks \
+             v{PROTOCOL_VERSION}; kill it (`pkill -f 'servo daemon'`) and it will be \
+             auto-started again"
+        );
+    }
+
+    send_line(&mut stream, req)?;
+    serde_json::from_str(&read_line(&stream)?).context("Failed to parse servod daemon response")
+}
@@ -0,0 +1,132 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! A process-wide output sink, installed once from `TopLevel`'s `--json`/
+//! `--quiet` flags before any subcommand runs. Subcommand code should
+//! prefer the [`sh_println!`]/[`sh_warn!`]/[`sh_err!`] macros over
+//! `println!`/`eprintln!` so `--quiet` and `--json` are honored the same
+//! way everywhere instead of each subcommand hand-rolling it.
+
+use std::io::stderr;
+use std::io::stdout;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+/// How much non-essential output a command should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// What shape a command's output should take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+/// The process-wide output state: the active format/verbosity, plus the
+/// stdout/stderr handles the `sh_*!` macros write through.
+pub struct Shell {
+    format: OutputFormat,
+    verbosity: Verbosity,
+    stdout: Box<dyn Write + Send>,
+    stderr: Box<dyn Write + Send>,
+}
+impl Shell {
+    fn new() -> Self {
+        Self {
+            format: OutputFormat::Human,
+            verbosity: Verbosity::Normal,
+            stdout: Box::new(stdout()),
+            stderr: Box::new(stderr()),
+        }
+    }
+
+    fn global() -> &'static Mutex<Shell> {
+        static SHELL: OnceLock<Mutex<Shell>> = OnceLock::new();
+        SHELL.get_or_init(|| Mutex::new(Shell::new()))
+    }
+
+    /// Installs the process-wide format/verbosity. Called once from `main`
+    /// with `TopLevel`'s `--json`/`--quiet` flags, before any subcommand's
+    /// `run()`.
+    pub fn install(format: OutputFormat, verbosity: Verbosity) {
+        let mut shell = Self::global().lock().unwrap();
+        shell.format = format;
+        shell.verbosity = verbosity;
+    }
+
+    /// Locks the process-wide shell for [`sh_println!`]/[`sh_warn!`]/
+    /// [`sh_err!`] (and direct callers like [`Shell::print_envelope`]) to
+    /// write through.
+    pub fn lock() -> MutexGuard<'static, Shell> {
+        Self::global().lock().unwrap()
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+    pub fn stdout(&mut self) -> &mut (dyn Write + Send) {
+        &mut *self.stdout
+    }
+    pub fn stderr(&mut self) -> &mut (dyn Write + Send) {
+        &mut *self.stderr
+    }
+
+    /// Wraps `data` in the `{ "command", "ok", "data" }` envelope every
+    /// command's [`OutputFormat::Json`] output shares, and writes it to
+    /// stdout as a single line.
+    pub fn print_envelope<T: Serialize>(&mut self, command: &str, ok: bool, data: &T) {
+        let envelope = serde_json::json!({ "command": command, "ok": ok, "data": data });
+        let _ = writeln!(self.stdout(), "{envelope}");
+    }
+}
+
+/// Prints to stdout unless [`Verbosity::Quiet`] is active. Prefer this over
+/// `println!` in subcommand code so `--quiet` is honored uniformly.
+#[macro_export]
+macro_rules! sh_println {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let mut shell = $crate::shell::Shell::lock();
+        if shell.verbosity() != $crate::shell::Verbosity::Quiet {
+            let _ = writeln!(shell.stdout(), $($arg)*);
+        }
+    }};
+}
+
+/// Prints a warning to stderr unless [`Verbosity::Quiet`] is active.
+#[macro_export]
+macro_rules! sh_warn {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let mut shell = $crate::shell::Shell::lock();
+        if shell.verbosity() != $crate::shell::Verbosity::Quiet {
+            let _ = writeln!(shell.stderr(), $($arg)*);
+        }
+    }};
+}
+
+/// Prints an error to stderr. Unlike [`sh_println!`]/[`sh_warn!`], errors
+/// are never suppressed by `--quiet`.
+#[macro_export]
+macro_rules! sh_err {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let mut shell = $crate::shell::Shell::lock();
+        let _ = writeln!(shell.stderr(), $($arg)*);
+    }};
+}
@@ -0,0 +1,458 @@
+// Copyright 2024 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! An in-process alternative to shelling out to the system `ssh`/`scp` for
+//! talking to a DUT, backed by the `ssh2` (libssh2) crate. Selected by
+//! setting the `ssh_backend` config key to `"native"` (see
+//! [`crate::config::Config::ssh_backend`]); the default `"openssh"` backend
+//! keeps spawning `ssh`/`scp` as it always has.
+//!
+//! This exists for hosts that don't have a working system `ssh`/`scp` (or
+//! whose installed OpenSSH version/config disagrees with the `-F none`,
+//! `BatchMode`, `StrictHostKeyChecking=no` flags `SshInfo` passes), at the
+//! cost of not sharing the `ControlMaster` connection multiplexing the
+//! openssh backend gets for free.
+//!
+//! `SshInfo` keeps one [`NativeSshSession`] pooled per DUT (see its
+//! `NATIVE_SESSION_POOL`) so repeated `run_cmd_*`/`get_files`/`send_files`
+//! calls reuse the handshake instead of reconnecting each time.
+//!
+//! [`NativeSshSession::start_port_forward`] is the native equivalent of `ssh
+//! -L`, built on libssh2's `channel_direct_tcpip`. It is not yet plumbed
+//! into `SshInfo::start_ssh_forwarding`/`MonitoredDut`, both of which are
+//! built around polling and killing an `async_process::Child`; forwarding
+//! through those call sites still always shells out to `ssh -L` regardless
+//! of `ssh_backend`, since wrapping a [`NativeForward`] to look like a
+//! `Child` isn't practical. Callers that only need the forward itself (not
+//! the rest of `SshInfo`'s process-based plumbing) can use it directly.
+
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use ssh2::MethodType;
+use ssh2::Session;
+use tracing::error;
+
+use crate::config::SshOverride;
+
+/// User `SshInfo` always connects as, matching the openssh backend.
+const SSH_USER: &str = "root";
+
+/// The native-backend equivalent of the `Ciphers`/`KexAlgorithms`/`MACs`/
+/// `HostKeyAlgorithms` `-o` flags the openssh backend derives from matching
+/// `ssh_overrides` entries (see [`crate::config::SshOverride`]).
+#[derive(Debug, Clone, Default)]
+pub struct SshCryptoPrefs {
+    ciphers: Option<String>,
+    kex_algorithms: Option<String>,
+    macs: Option<String>,
+    host_key_algorithms: Option<String>,
+}
+impl SshCryptoPrefs {
+    /// Merges the crypto fields of every matching override, with later
+    /// entries in `overrides` winning when more than one sets the same
+    /// field.
+    pub fn from_overrides(overrides: &[SshOverride]) -> Self {
+        let mut prefs = Self::default();
+        for o in overrides {
+            if let Some(v) = o.ciphers() {
+                prefs.ciphers = Some(v.to_string());
+            }
+            if let Some(v) = o.kex_algorithms() {
+                prefs.kex_algorithms = Some(v.to_string());
+            }
+            if let Some(v) = o.macs() {
+                prefs.macs = Some(v.to_string());
+            }
+            if let Some(v) = o.host_key_algorithms() {
+                prefs.host_key_algorithms = Some(v.to_string());
+            }
+        }
+        prefs
+    }
+    fn apply(&self, session: &mut Session) -> Result<()> {
+        if let Some(v) = &self.ciphers {
+            session
+                .method_pref(MethodType::CryptCs, v)
+                .context("Failed to set preferred ciphers (client to server)")?;
+            session
+                .method_pref(MethodType::CryptSc, v)
+                .context("Failed to set preferred ciphers (server to client)")?;
+        }
+        if let Some(v) = &self.kex_algorithms {
+            session
+                .method_pref(MethodType::Kex, v)
+                .context("Failed to set preferred kex algorithms")?;
+        }
+        if let Some(v) = &self.macs {
+            session
+                .method_pref(MethodType::MacCs, v)
+                .context("Failed to set preferred MACs (client to server)")?;
+            session
+                .method_pref(MethodType::MacSc, v)
+                .context("Failed to set preferred MACs (server to client)")?;
+        }
+        if let Some(v) = &self.host_key_algorithms {
+            session
+                .method_pref(MethodType::HostKey, v)
+                .context("Failed to set preferred host key algorithms")?;
+        }
+        Ok(())
+    }
+}
+
+/// How often (in seconds) a pooled [`NativeSshSession`] asks libssh2 to send
+/// a keepalive message, matching this crate's other long-lived-connection
+/// timeouts of the same order of magnitude.
+const KEEPALIVE_INTERVAL_SECS: u32 = 30;
+
+/// Drains complete (newline-terminated) lines out of `buf`, passing each to
+/// `on_line` and leaving any trailing partial line buffered for the next
+/// call. Used by `NativeSshSession::run_cmd_streamed` to turn raw chunks
+/// read off the channel into the same line-at-a-time granularity the
+/// openssh-backend path gets from `futures::io::BufReader::lines`.
+fn flush_lines(buf: &mut Vec<u8>, on_line: &mut dyn FnMut(String)) {
+    while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = buf.drain(..=pos).collect();
+        on_line(String::from_utf8_lossy(&line[..line.len() - 1]).into_owned());
+    }
+}
+
+/// A session to one DUT, authenticated with the CrOS `testing_rsa` dev key.
+/// Pooled in `dut::NATIVE_SESSION_POOL` and reused across commands/transfers
+/// rather than reconnecting each time.
+pub struct NativeSshSession {
+    session: Session,
+}
+impl NativeSshSession {
+    /// Opens a TCP connection to `host_and_port`, completes the SSH
+    /// handshake, and authenticates with `testing_rsa` -- the native
+    /// equivalent of `ssh -F none -i ~/.ssh/testing_rsa -o BatchMode=yes -o
+    /// StrictHostKeyChecking=no`. `crypto` carries any per-host
+    /// ciphers/kex/MACs/host-key-algorithm overrides to apply before the
+    /// handshake, for DUTs whose SSH server only offers legacy algorithms.
+    pub fn connect(host_and_port: &str, testing_rsa_path: &Path, crypto: &SshCryptoPrefs) -> Result<Self> {
+        let tcp = TcpStream::connect(host_and_port)
+            .with_context(|| format!("Failed to open a TCP connection to {host_and_port}"))?;
+        let mut session = Session::new().context("Failed to create an ssh2 session")?;
+        session.set_tcp_stream(tcp);
+        crypto.apply(&mut session)?;
+        session.handshake().context("SSH handshake failed")?;
+        session
+            .userauth_pubkey_file(SSH_USER, None, testing_rsa_path, None)
+            .with_context(|| format!("testing_rsa authentication failed for {host_and_port}"))?;
+        if !session.authenticated() {
+            bail!("testing_rsa authentication failed for {host_and_port}");
+        }
+        // We, not libssh2, decide when a keepalive is actually sent (see
+        // `send_keepalive`); this just configures the interval libssh2
+        // reports back via `keepalive_send`'s return value.
+        session.set_keepalive(true, KEEPALIVE_INTERVAL_SECS);
+        Ok(Self { session })
+    }
+
+    /// Sends a keepalive message if `KEEPALIVE_INTERVAL_SECS` have elapsed
+    /// since the last one, returning an error if the connection no longer
+    /// responds. `dut::native_session` calls this before handing out a
+    /// pooled session so a DUT that silently dropped off the network gets
+    /// evicted and reconnected instead of failing the next `exec`/SFTP call
+    /// with a confusing low-level error.
+    pub fn send_keepalive(&self) -> Result<()> {
+        self.session
+            .keepalive_send()
+            .context("SSH keepalive failed; the connection is no longer alive")?;
+        Ok(())
+    }
+
+    /// Runs `cmd` and returns its exit code, stdout and stderr, mirroring
+    /// `SshInfo::run_cmd_captured`'s semantics (does not fail on a non-zero
+    /// exit status).
+    pub fn run_cmd_captured(&self, cmd: &str) -> Result<(i32, String, String)> {
+        let mut channel = self
+            .session
+            .channel_session()
+            .context("Failed to open an ssh channel")?;
+        channel
+            .exec(cmd)
+            .with_context(|| format!("Failed to exec {cmd:?}"))?;
+        let mut stdout = String::new();
+        channel
+            .read_to_string(&mut stdout)
+            .context("Failed to read remote stdout")?;
+        let mut stderr = String::new();
+        channel
+            .stderr()
+            .read_to_string(&mut stderr)
+            .context("Failed to read remote stderr")?;
+        channel.wait_close().context("Failed to close the channel")?;
+        let code = channel.exit_status().context("Failed to read exit status")?;
+        Ok((code, stdout, stderr))
+    }
+
+    /// Runs `cmd`, invoking `on_line` for each line of stdout/stderr as it
+    /// arrives rather than buffering the whole output, mirroring
+    /// `SshInfo::run_cmd_streamed`. Puts the session in non-blocking mode
+    /// for the duration of the command so stdout and stderr can be
+    /// interleaved as they arrive instead of only being readable once the
+    /// other side closes.
+    pub fn run_cmd_streamed(
+        &self,
+        cmd: &str,
+        on_line: &mut dyn FnMut(crate::dut::StreamedLine),
+    ) -> Result<i32> {
+        let mut channel = self
+            .session
+            .channel_session()
+            .context("Failed to open an ssh channel")?;
+        channel
+            .exec(cmd)
+            .with_context(|| format!("Failed to exec {cmd:?}"))?;
+        self.session.set_blocking(false);
+        let result = (|| -> Result<i32> {
+            let mut stdout_buf = Vec::new();
+            let mut stderr_buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            loop {
+                let mut made_progress = false;
+                match channel.read(&mut chunk) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        stdout_buf.extend_from_slice(&chunk[..n]);
+                        made_progress = true;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e).context("Failed to read remote stdout"),
+                }
+                match channel.stderr().read(&mut chunk) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        stderr_buf.extend_from_slice(&chunk[..n]);
+                        made_progress = true;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e).context("Failed to read remote stderr"),
+                }
+                flush_lines(&mut stdout_buf, &mut |line| {
+                    on_line(crate::dut::StreamedLine::Stdout(line))
+                });
+                flush_lines(&mut stderr_buf, &mut |line| {
+                    on_line(crate::dut::StreamedLine::Stderr(line))
+                });
+                if channel.eof() {
+                    break;
+                }
+                if !made_progress {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            if !stdout_buf.is_empty() {
+                on_line(crate::dut::StreamedLine::Stdout(
+                    String::from_utf8_lossy(&stdout_buf).into_owned(),
+                ));
+            }
+            if !stderr_buf.is_empty() {
+                on_line(crate::dut::StreamedLine::Stderr(
+                    String::from_utf8_lossy(&stderr_buf).into_owned(),
+                ));
+            }
+            channel.wait_close().context("Failed to close the channel")?;
+            channel.exit_status().context("Failed to read exit status")
+        })();
+        self.session.set_blocking(true);
+        result
+    }
+
+    /// Runs `cmd`, passing its stdout/stderr through to this process's own
+    /// stdout/stderr as it runs, mirroring `SshInfo::run_cmd_piped`.
+    pub fn run_cmd_piped(&self, cmd: &str) -> Result<()> {
+        let (code, stdout, stderr) = self.run_cmd_captured(cmd)?;
+        print!("{stdout}");
+        eprint!("{stderr}");
+        if code != 0 {
+            bail!("run_cmd_piped failed with {code}. cmd = {cmd:?}");
+        }
+        Ok(())
+    }
+
+    /// Downloads `files` from the DUT into `dest` (or the current directory)
+    /// via SFTP. Each entry in `files` must be a single remote file, not a
+    /// directory -- unlike `scp -r`, this does not recurse.
+    pub fn get_files(&self, files: &[String], dest: Option<&str>) -> Result<()> {
+        let sftp = self.session.sftp().context("Failed to start SFTP")?;
+        let dest_dir = dest.unwrap_or(".");
+        for remote_path in files {
+            let mut remote_file = sftp
+                .open(Path::new(remote_path))
+                .with_context(|| format!("Failed to open remote file {remote_path}"))?;
+            let file_name = Path::new(remote_path)
+                .file_name()
+                .context("Remote path has no file name")?;
+            let mut local_file = File::create(Path::new(dest_dir).join(file_name))
+                .with_context(|| format!("Failed to create a local file for {remote_path}"))?;
+            std::io::copy(&mut remote_file, &mut local_file)
+                .with_context(|| format!("Failed to download {remote_path}"))?;
+        }
+        Ok(())
+    }
+
+    /// Uploads `files` to `dest` (or `~/`) on the DUT via SFTP. Each entry
+    /// in `files` must be a single local file, not a directory.
+    pub fn send_files(&self, files: &[String], dest: Option<&str>) -> Result<()> {
+        let sftp = self.session.sftp().context("Failed to start SFTP")?;
+        let dest_dir = dest.unwrap_or("~/");
+        for local_path in files {
+            let mut local_file = File::open(local_path)
+                .with_context(|| format!("Failed to open local file {local_path}"))?;
+            let mut contents = Vec::new();
+            local_file
+                .read_to_end(&mut contents)
+                .with_context(|| format!("Failed to read {local_path}"))?;
+            let file_name = Path::new(local_path)
+                .file_name()
+                .context("Local path has no file name")?;
+            let remote_path = format!(
+                "{}/{}",
+                dest_dir.trim_end_matches('/'),
+                file_name
+                    .to_str()
+                    .context("Local file name is not valid UTF-8")?
+            );
+            let mut remote_file = sftp
+                .create(Path::new(&remote_path))
+                .with_context(|| format!("Failed to create remote file {remote_path}"))?;
+            remote_file
+                .write_all(&contents)
+                .with_context(|| format!("Failed to upload {local_path}"))?;
+        }
+        Ok(())
+    }
+
+    /// Forwards local connections on `local_port` to `dut_port` on the DUT,
+    /// the native-backend equivalent of `ssh -L local_port:127.0.0.1:dut_port`.
+    /// Accepts and serves connections on a background thread until the
+    /// returned [`NativeForward`] is dropped. Only one local connection is
+    /// served at a time -- a `ssh2::Session` can't safely have more than one
+    /// channel pumped concurrently without its own locking, which `session`
+    /// (shared with every other native call for this DUT) already provides.
+    pub fn start_port_forward(
+        session: Arc<Mutex<Self>>,
+        local_port: u16,
+        dut_port: u16,
+    ) -> Result<NativeForward> {
+        let listener = TcpListener::bind(("127.0.0.1", local_port))
+            .with_context(|| format!("Failed to bind local port {local_port}"))?;
+        listener
+            .set_nonblocking(true)
+            .context("Failed to set the forward listener non-blocking")?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+        let thread = thread::spawn(move || {
+            while !stop_thread.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _addr)) => {
+                        if let Err(e) = Self::pump_forward(&session, stream, dut_port) {
+                            error!("Native port forward connection on {local_port} failed: {e:#}");
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => {
+                        error!("Native port forward on {local_port} stopped accepting: {e}");
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(NativeForward {
+            local_port,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Relays bytes between `stream` and a fresh `direct-tcpip` channel to
+    /// `dut_port` until either side closes, holding `session`'s lock for the
+    /// connection's whole lifetime (see `start_port_forward`'s doc comment).
+    fn pump_forward(session: &Arc<Mutex<Self>>, mut stream: TcpStream, dut_port: u16) -> Result<()> {
+        stream
+            .set_nonblocking(true)
+            .context("Failed to set the forwarded connection non-blocking")?;
+        let session = session.lock().unwrap();
+        let mut channel = session
+            .session
+            .channel_direct_tcpip("127.0.0.1", dut_port, None)
+            .context("Failed to open a direct-tcpip channel")?;
+        let mut local_buf = [0u8; 8192];
+        let mut remote_buf = [0u8; 8192];
+        loop {
+            let mut made_progress = false;
+            match stream.read(&mut local_buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    channel.write_all(&local_buf[..n])?;
+                    made_progress = true;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("Failed to read from the forwarded connection"),
+            }
+            match channel.read(&mut remote_buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    stream.write_all(&remote_buf[..n])?;
+                    made_progress = true;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("Failed to read from the direct-tcpip channel"),
+            }
+            if channel.eof() {
+                break;
+            }
+            if !made_progress {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = channel.close();
+        Ok(())
+    }
+}
+
+/// A running [`NativeSshSession::start_port_forward`] forward. Dropping this
+/// stops accepting new local connections and joins the background thread,
+/// mirroring how `async_process::Child::kill_on_drop` tears down the
+/// openssh-backend forward.
+pub struct NativeForward {
+    local_port: u16,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+impl NativeForward {
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+}
+impl Drop for NativeForward {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2026 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Small, dependency-free statistical building blocks shared by every
+//! A/B-style comparison in the codebase (tast's `compare_abtest_results`,
+//! bluebench's regression detection, ...), so the Welch's t-test math only
+//! has to be gotten right once.
+
+pub fn mean_var(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, var)
+}
+
+/// Abramowitz and Stegun formula 7.1.26, good to ~1e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let (a1, a2, a3, a4, a5, p) = (
+        0.254829592,
+        -0.284496736,
+        1.421413741,
+        -1.453152027,
+        1.061405429,
+        0.3275911,
+    );
+    let t = 1.0 / (1.0 + p * x);
+    let y = 1.0 - (((((a5 * t + a4) * t) + a3) * t + a2) * t + a1) * t * (-x * x).exp();
+    sign * y
+}
+
+pub fn standard_normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Lanczos approximation of `ln(gamma(x))`.
+fn log_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEF: [f64; 9] = [
+        0.99999999999980993,
+        676.5203681218851,
+        -1259.1392167224028,
+        771.32342877765313,
+        -176.61502916214059,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.9843695780195716e-6,
+        1.5056327351493116e-7,
+    ];
+    if x < 0.5 {
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - log_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let mut a = COEF[0];
+    for (i, c) in COEF.iter().enumerate().skip(1) {
+        a += c / (x + i as f64);
+    }
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+/// Continued fraction for the regularized incomplete beta function
+/// (Numerical Recipes' `betacf`, Lentz's algorithm).
+fn incomplete_beta_cf(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITER: usize = 200;
+    const EPS: f64 = 3e-9;
+    const FPMIN: f64 = 1e-300;
+    let qab = a + b;
+    let qap = a + 1.0;
+    let qam = a - 1.0;
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < FPMIN {
+        d = FPMIN;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..=MAX_ITER {
+        let m_f = m as f64;
+        let m2 = 2.0 * m_f;
+        let aa = m_f * (b - m_f) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m_f) * (qab + m_f) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let bt =
+        (log_gamma(a + b) - log_gamma(a) - log_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        bt * incomplete_beta_cf(x, a, b) / a
+    } else {
+        1.0 - bt * incomplete_beta_cf(1.0 - x, b, a) / b
+    }
+}
+
+/// Two-sided p-value of Student's t distribution with `df` degrees of
+/// freedom at statistic `t`.
+fn student_t_two_sided_p(t: f64, df: f64) -> f64 {
+    let x = df / (df + t * t);
+    incomplete_beta(x, df / 2.0, 0.5).clamp(0.0, 1.0)
+}
+
+/// Critical two-sided t-value for `df` degrees of freedom and significance
+/// level `alpha`, found by bisection since the Student-t quantile has no
+/// closed form.
+fn student_t_critical(df: f64, alpha: f64) -> f64 {
+    let (mut lo, mut hi) = (0.0, 1000.0);
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if student_t_two_sided_p(mid, df) > alpha {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+/// Welch's two-sample t-test (unequal variances assumed). Returns
+/// `(t_statistic, degrees_of_freedom, two_sided_p_value)`.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> (f64, f64, f64) {
+    let (mean_a, var_a) = mean_var(a);
+    let (mean_b, var_b) = mean_var(b);
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let se_a = var_a / n_a;
+    let se_b = var_b / n_b;
+    let t = (mean_a - mean_b) / (se_a + se_b).sqrt();
+    let df = (se_a + se_b).powi(2) / (se_a.powi(2) / (n_a - 1.0) + se_b.powi(2) / (n_b - 1.0));
+    (t, df, student_t_two_sided_p(t, df))
+}
+
+/// Cohen's d effect size using the pooled standard deviation.
+pub fn cohens_d(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, var_a) = mean_var(a);
+    let (mean_b, var_b) = mean_var(b);
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let pooled_sd = (((n_a - 1.0) * var_a + (n_b - 1.0) * var_b) / (n_a + n_b - 2.0)).sqrt();
+    if pooled_sd == 0.0 {
+        0.0
+    } else {
+        (mean_a - mean_b) / pooled_sd
+    }
+}
+
+/// Two-sided Welch confidence interval for the mean difference (A - B) at
+/// significance level `alpha`. Returns `(diff, ci_low, ci_high)`.
+pub fn welch_mean_diff_ci(a: &[f64], b: &[f64], df: f64, alpha: f64) -> (f64, f64, f64) {
+    let (mean_a, var_a) = mean_var(a);
+    let (mean_b, var_b) = mean_var(b);
+    let se = (var_a / a.len() as f64 + var_b / b.len() as f64).sqrt();
+    let t_crit = student_t_critical(df, alpha);
+    let diff = mean_a - mean_b;
+    (diff, diff - t_crit * se, diff + t_crit * se)
+}
+
+/// Rank-sum (Mann-Whitney U) test with average ranks for ties, plus the
+/// normal-approximation two-sided p-value (with tie correction).
+///
+/// Returns `(u_statistic, p_value)`.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let n1 = a.len() as f64;
+    let n2 = b.len() as f64;
+    let mut pooled: Vec<(f64, usize)> = a
+        .iter()
+        .map(|v| (*v, 0usize))
+        .chain(b.iter().map(|v| (*v, 1usize)))
+        .collect();
+    pooled.sort_by(|l, r| l.0.partial_cmp(&r.0).unwrap());
+
+    let mut ranks = vec![0f64; pooled.len()];
+    let mut tie_term = 0f64;
+    let mut i = 0;
+    while i < pooled.len() {
+        let mut j = i;
+        while j + 1 < pooled.len() && pooled[j + 1].0 == pooled[i].0 {
+            j += 1;
+        }
+        // Ranks are 1-indexed; ties share the average rank of the run.
+        let avg_rank = ((i + 1) + (j + 1)) as f64 / 2.0;
+        for r in ranks.iter_mut().take(j + 1).skip(i) {
+            *r = avg_rank;
+        }
+        let t = (j - i + 1) as f64;
+        tie_term += t * t * t - t;
+        i = j + 1;
+    }
+
+    let r1: f64 = pooled
+        .iter()
+        .zip(ranks.iter())
+        .filter(|((_, group), _)| *group == 0)
+        .map(|(_, rank)| *rank)
+        .sum();
+    let u1 = r1 - n1 * (n1 + 1.0) / 2.0;
+    let u2 = n1 * n2 - u1;
+    let u = u1.min(u2);
+
+    let n = n1 + n2;
+    let mu = n1 * n2 / 2.0;
+    let sigma = (n1 * n2 * (n + 1.0) / 12.0 - n1 * n2 * tie_term / (12.0 * n * (n - 1.0))).sqrt();
+    let p = if sigma == 0.0 {
+        1.0
+    } else {
+        // Continuity correction of 0.5 toward the mean.
+        let z = ((u - mu).abs() - 0.5).max(0.0) / sigma;
+        2.0 * (1.0 - standard_normal_cdf(z))
+    };
+    (u, p.clamp(0.0, 1.0))
+}
+
+#[test]
+fn welch_t_test_detects_a_shifted_mean() {
+    let a = vec![10.0, 11.0, 9.0, 10.5, 9.5, 10.2, 9.8, 10.1, 9.9, 10.0];
+    let b = vec![15.0, 16.0, 14.0, 15.5, 14.5, 15.2, 14.8, 15.1, 14.9, 15.0];
+    let (t, df, p) = welch_t_test(&a, &b);
+    assert!(t < 0.0);
+    assert!(df > 0.0);
+    assert!(p < 0.01);
+}
@@ -30,6 +30,8 @@ use futures::stream;
 use futures::StreamExt;
 use glob::Pattern;
 use once_cell::sync::Lazy;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
 use rayon::prelude::*;
 use regex::Regex;
 use serde::Deserialize;
@@ -45,6 +47,11 @@ use crate::config::Config;
 use crate::cros::ensure_testing_rsa_is_there;
 use crate::dut::SshInfo;
 use crate::repo::get_cros_dir;
+use crate::stats::cohens_d;
+use crate::stats::mann_whitney_u;
+use crate::stats::mean_var;
+use crate::stats::welch_mean_diff_ci;
+use crate::stats::welch_t_test;
 use crate::util::shell_helpers::get_async_lines;
 use crate::util::shell_helpers::run_bash_command_async;
 
@@ -81,27 +88,95 @@ impl TastTestExecutionType {
 pub static TEST_CACHE: KvCache<Vec<String>> = KvCache::new("tast_cache");
 pub static DEFAULT_BUNDLE: &str = "cros";
 
-pub fn print_cached_tests_in_bundle(filter: &Pattern, bundle: &str) -> Result<()> {
-    if let Ok(Some(tests)) = TEST_CACHE.get(bundle) {
-        for t in &tests {
-            if filter.matches(t) {
-                println!("{t}");
-            }
+/// One run's raw samples for a single bluebench experiment, persisted under
+/// a caller-chosen `label` so a later `cro3 tast analyze --baseline <label>`
+/// can diff its current run against this one with the same significance
+/// machinery as an ordinary A/B comparison, instead of only ever comparing
+/// two arms collected in the same batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub label: String,
+    pub timestamp: u64,
+    pub values: Vec<f64>,
+}
+pub static BASELINE_STORE: KvCache<BaselineEntry> = KvCache::new("tast_baseline");
+
+/// Key a [`BaselineEntry`] under: `experiment_key` is the same
+/// `experiment_name/model` string [`crate::cmd`]'s `parse_bluebench_results`
+/// already uses to bucket A/B arms, so a baseline never gets diffed against
+/// the wrong experiment/model pair.
+fn baseline_key(experiment_key: &str, label: &str) -> String {
+    format!("{experiment_key}/{label}")
+}
+
+/// Persists `values` (this run's per-invocation `converged_mean_mean`
+/// samples) as baseline `label` for `experiment_key`, overwriting whatever
+/// was previously stored under that label.
+pub fn save_baseline(experiment_key: &str, label: &str, values: Vec<f64>, timestamp: u64) -> Result<()> {
+    BASELINE_STORE.set(
+        &baseline_key(experiment_key, label),
+        BaselineEntry {
+            label: label.to_string(),
+            timestamp,
+            values,
+        },
+    )
+}
+
+/// Reads back the baseline stored under `label` for `experiment_key`, if
+/// any.
+pub fn load_baseline(experiment_key: &str, label: &str) -> Result<Option<BaselineEntry>> {
+    BASELINE_STORE.get(&baseline_key(experiment_key, label))
+}
+
+/// Which class of Tast bundle `tast run` should execute: a bundle that runs
+/// on the DUT itself (the default), or a remote bundle that runs on the host
+/// and drives the DUT -- and any `-companiondut`/servo it needs -- over the
+/// network, e.g. for firmware tests that need servo to reboot the board.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TastBundleType {
+    Local,
+    Remote,
+}
+impl std::str::FromStr for TastBundleType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "local" => Ok(Self::Local),
+            "remote" => Ok(Self::Remote),
+            _ => bail!("Unknown --bundle {s:?}, expected `local` or `remote`"),
         }
-        return Ok(());
+    }
+}
+
+/// Returns the cached test names in `bundle` matching `filter`, without
+/// printing them, so callers (e.g. `cro3 tast list --json`) can decide how
+/// to present the list themselves.
+pub fn collect_cached_tests_in_bundle(filter: &Pattern, bundle: &str) -> Result<Vec<String>> {
+    if let Ok(Some(tests)) = TEST_CACHE.get(bundle) {
+        return Ok(tests.into_iter().filter(|t| filter.matches(t)).collect());
     }
     bail!("No cache found")
 }
 
-pub fn print_cached_tests(filter: &Pattern, bundles: &Vec<&str>) -> Result<()> {
+/// Same as [`collect_cached_tests_in_bundle`], across all of `bundles`.
+pub fn collect_cached_tests(filter: &Pattern, bundles: &Vec<&str>) -> Result<Vec<String>> {
     // Ensure all bundles are cached.
     for b in bundles {
         if TEST_CACHE.get(b)?.is_none() {
             bail!("No cache found for {b}.");
         }
     }
+    let mut tests = Vec::new();
     for b in bundles {
-        print_cached_tests_in_bundle(filter, b)?
+        tests.extend(collect_cached_tests_in_bundle(filter, b)?);
+    }
+    Ok(tests)
+}
+
+pub fn print_cached_tests(filter: &Pattern, bundles: &Vec<&str>) -> Result<()> {
+    for t in collect_cached_tests(filter, bundles)? {
+        println!("{t}");
     }
     Ok(())
 }
@@ -146,13 +221,108 @@ fn bundle_has_test(bundle: &str, filter: &Pattern) -> bool {
     false
 }
 
+/// The one rule `monitor_and_await_tast_execution` applied before live log
+/// matching became configurable via `Config::tast_log_matchers`, kept as the
+/// always-on default (named so a user rule of the same name overrides it)
+/// so existing behavior doesn't regress for users who configure nothing.
+fn default_log_match_rules() -> HashMap<String, crate::config::LogMatchRule> {
+    HashMap::from([(
+        "network_diagnosis".to_string(),
+        crate::config::LogMatchRule {
+            pattern: "Running network diagnosis".to_string(),
+            action: crate::config::LogMatchAction::AbortAfterBurst {
+                count: 5,
+                window: 100,
+            },
+        },
+    )])
+}
+
+/// A [`crate::config::LogMatchRule`] compiled into a live regex, plus the
+/// running state `observe` needs to evaluate its action against a stream of
+/// lines one at a time.
+struct LogMatcher {
+    name: String,
+    regex: Regex,
+    action: crate::config::LogMatchAction,
+    burst_count: usize,
+    total_count: usize,
+}
+impl LogMatcher {
+    fn from_rule(name: String, rule: crate::config::LogMatchRule) -> Result<Self> {
+        let regex = Regex::new(&rule.pattern)
+            .with_context(|| format!("invalid regex in log match rule {name:?}"))?;
+        Ok(Self {
+            name,
+            regex,
+            action: rule.action,
+            burst_count: 0,
+            total_count: 0,
+        })
+    }
+    /// Feeds one more line (the `num_lines`'th seen so far) to this rule.
+    /// Returns `Some(reason)` once it decides the run should be aborted.
+    fn observe(&mut self, line: &str, num_lines: usize) -> Option<String> {
+        if !self.regex.is_match(line) {
+            return None;
+        }
+        self.total_count += 1;
+        match self.action {
+            crate::config::LogMatchAction::AbortImmediately => {
+                Some(format!("log match rule {:?} fired on: {line}", self.name))
+            }
+            crate::config::LogMatchAction::AbortAfterBurst { count, window } => {
+                self.burst_count += 1;
+                if window > 0 && num_lines % window == 0 {
+                    self.burst_count = 0;
+                }
+                if self.burst_count > count {
+                    Some(format!(
+                        "log match rule {:?} fired {} times within the last {window} lines",
+                        self.name, self.burst_count
+                    ))
+                } else {
+                    None
+                }
+            }
+            crate::config::LogMatchAction::Count => None,
+        }
+    }
+}
+
+fn report_log_matchers(matchers: &[LogMatcher]) {
+    for m in matchers {
+        if m.total_count > 0 {
+            info!("log match rule {:?} matched {} time(s)", m.name, m.total_count);
+        }
+    }
+}
+
+/// Streams `child`'s merged stdout/stderr, printing every line and
+/// evaluating it against a configurable set of live log matchers: the
+/// built-in [`default_log_match_rules`] plus/overridden by whatever the
+/// active `Config::tast_log_matchers` adds, so fatal patterns (kernel
+/// panics, OOM, DUT disconnect, ...) can terminate the run early without
+/// being hardcoded here. Reports which rules fired (if any) before
+/// returning.
 pub async fn monitor_and_await_tast_execution(mut child: Child) -> Result<()> {
     let (so, se) = get_async_lines(&mut child);
     let so = so.context(anyhow!("ssh_stdout was None"))?;
     let se = se.context(anyhow!("ssh_stderr was None"))?;
     let mut merged_stream = stream::select(se.fuse(), so.fuse());
+
+    let mut rules = default_log_match_rules();
+    if let Ok(config) = Config::read() {
+        for (name, rule) in config.tast_log_matchers() {
+            rules.insert(name.clone(), rule.clone());
+        }
+    }
+    let mut matchers: Vec<LogMatcher> = rules
+        .into_iter()
+        .map(|(name, rule)| LogMatcher::from_rule(name, rule))
+        .collect::<Result<Vec<_>>>()?;
+
     let mut num_lines = 0;
-    let mut num_network_diagnosis = 0;
     loop {
         let mut merged_stream = merged_stream.next();
         select! {
@@ -160,15 +330,17 @@ pub async fn monitor_and_await_tast_execution(mut child: Child) -> Result<()> {
                 if let Some(Ok(line)) = line {
                     // Using eprintln!() instead of info!() to reduce the headers
                     eprintln!("{line}");
-                    if line.contains("Running network diagnosis") {
-                        num_network_diagnosis += 1;
-                    }
                     num_lines += 1;
-                    if num_lines % 100 == 0 {
-                        num_network_diagnosis = 0;
+                    let mut abort_reason = None;
+                    for matcher in matchers.iter_mut() {
+                        if let Some(reason) = matcher.observe(&line, num_lines) {
+                            abort_reason = Some(reason);
+                            break;
+                        }
                     }
-                    if num_network_diagnosis > 5 {
-                        bail!("network diagnosi burst detected. terminating the test...");
+                    if let Some(reason) = abort_reason {
+                        report_log_matchers(&matchers);
+                        bail!("{reason}; terminating the test...");
                     }
                 }
             }
@@ -180,33 +352,61 @@ pub async fn monitor_and_await_tast_execution(mut child: Child) -> Result<()> {
             }
         }
     }
+    report_log_matchers(&matchers);
     Ok(())
 }
 
+/// Builds the `-companiondut=...`/`-var=servo=...` flags a remote bundle
+/// needs to reach the DUT's companion hardware, so a local bundle's command
+/// line is unaffected and a remote bundle's is extended in one place.
+fn remote_bundle_options(
+    bundle_type: TastBundleType,
+    companion_dut: Option<&str>,
+    servo: Option<&str>,
+) -> String {
+    if bundle_type != TastBundleType::Remote {
+        return String::new();
+    }
+    let mut opts = String::new();
+    if let Some(companion_dut) = companion_dut {
+        opts.push_str(&format!(" -companiondut={companion_dut}"));
+    }
+    if let Some(servo) = servo {
+        opts.push_str(&format!(" -var=servo={servo}"));
+    }
+    opts
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run_test_with_bundle(
     bundle: &str,
     filter: &Pattern,
     tast: &TastTestExecutionType,
     port: u16,
     opt: Option<&str>,
+    bundle_type: TastBundleType,
+    companion_dut: Option<&str>,
+    servo: Option<&str>,
 ) -> Result<()> {
+    let remote_opts = remote_bundle_options(bundle_type, companion_dut, servo);
     match tast {
         TastTestExecutionType::Chroot(chroot) => {
-            chroot.run_bash_script_in_chroot(
-                "tast_run_cmd",
-                &format!(
-                    "tast run -installbuilddeps -buildbundle={bundle} {} 127.0.0.1:{port} {filter}",
-                    opt.unwrap_or("")
-                ),
-                None,
-            )?;
+            let output = chroot.run_in_chroot_async(&format!(
+                "tast run -installbuilddeps -buildbundle={bundle} {}{remote_opts} \
+                 127.0.0.1:{port} {filter}",
+                opt.unwrap_or("")
+            ))?;
+            block_on(monitor_and_await_tast_execution(output))?;
         }
         TastTestExecutionType::TastPack(path) => {
             let mut path = path.clone();
             path.push("run_tast.sh");
             let path = path.as_os_str().to_string_lossy();
             let output = run_bash_command_async(
-                &format!("{path} {} 127.0.0.1:{port} {filter}", opt.unwrap_or("")),
+                &format!(
+                    "{path} {}{remote_opts} 127.0.0.1:{port} {filter}",
+                    opt.unwrap_or("")
+                ),
                 None,
             )?;
             block_on(monitor_and_await_tast_execution(output))?;
@@ -215,11 +415,15 @@ pub fn run_test_with_bundle(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_tast_test(
     ssh: &SshInfo,
     tast: &TastTestExecutionType,
     test_query: &str,
     tast_options: Option<&str>,
+    bundle_type: TastBundleType,
+    companion_dut: Option<&str>,
+    servo: Option<&str>,
 ) -> Result<()> {
     ensure_testing_rsa_is_there()?;
     let ssh = ssh.into_forwarded()?;
@@ -236,24 +440,37 @@ pub fn run_tast_test(
     for b in bundles {
         if bundle_has_test(b, &filter) {
             matched = true;
-            run_test_with_bundle(b, &filter, tast, ssh.port(), tast_options)?
+            run_test_with_bundle(
+                b,
+                &filter,
+                tast,
+                ssh.port(),
+                tast_options,
+                bundle_type,
+                companion_dut,
+                servo,
+            )?
         }
     }
 
     if !matched {
         warn!("{test_query} did not match any cached tests. Run it with default bundle.");
-        run_test_with_bundle(DEFAULT_BUNDLE, &filter, tast, ssh.port(), tast_options)?
+        run_test_with_bundle(
+            DEFAULT_BUNDLE,
+            &filter,
+            tast,
+            ssh.port(),
+            tast_options,
+            bundle_type,
+            companion_dut,
+            servo,
+        )?
     }
 
     Ok(())
 }
 
-pub fn collect_results(
-    cros: Option<&str>,
-    results_dir: Option<&str>,
-    start: Option<&str>,
-    end: Option<&str>,
-) -> Result<Vec<TastResultMetadata>> {
+fn resolve_results_dir(cros: Option<&str>, results_dir: Option<&str>) -> Result<PathBuf> {
     let results_dir = match (&cros, &results_dir) {
         (Some(cros), None) => {
             let cros = Path::new(cros);
@@ -270,7 +487,15 @@ pub fn collect_results(
     if !results_dir.is_dir() {
         bail!("{results_dir:?} is not a dir");
     }
-    let mut results: Vec<PathBuf> = read_dir(&results_dir)?
+    Ok(results_dir)
+}
+
+fn invocation_dirs_in_range(
+    results_dir: &Path,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let mut results: Vec<PathBuf> = read_dir(results_dir)?
         .flatten()
         .map(|e| e.path().to_path_buf())
         .collect();
@@ -295,7 +520,11 @@ pub fn collect_results(
         .cloned()
         .collect();
     info!("{} test invocations in the specified range", results.len());
-    let results: Vec<TastResultMetadata> = results
+    Ok(results)
+}
+
+fn parse_invocation_dirs(dirs: &[PathBuf]) -> Vec<TastResultMetadata> {
+    let results: Vec<TastResultMetadata> = dirs
         .par_iter()
         .flat_map(|p| -> Result<Vec<TastResultMetadata>, ()> {
             let invocation = TastInvocationMetadata::from_path(p).map_err(|e| {
@@ -337,7 +566,42 @@ pub fn collect_results(
         .collect();
     eprintln!();
     info!("{} test invocations are succeeded", results.len());
-    Ok(results)
+    results
+}
+
+pub fn collect_results(
+    cros: Option<&str>,
+    results_dir: Option<&str>,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Vec<TastResultMetadata>> {
+    let results_dir = resolve_results_dir(cros, results_dir)?;
+    let dirs = invocation_dirs_in_range(&results_dir, start, end)?;
+    Ok(parse_invocation_dirs(&dirs))
+}
+
+/// Incremental counterpart to [`collect_results`], for a `--watch` loop
+/// polling a `results-dir` that's still being filled in by a long-running
+/// overnight A/B campaign. Re-scans the directory on every call, but only
+/// parses invocation directories not already in `seen` -- `seen` is grown
+/// in place with every directory name this call returns, so the caller
+/// can keep calling this across a poll loop and only ever pay parsing cost
+/// for freshly-completed invocations.
+pub fn collect_new_results(
+    cros: Option<&str>,
+    results_dir: Option<&str>,
+    start: Option<&str>,
+    end: Option<&str>,
+    seen: &mut HashSet<PathBuf>,
+) -> Result<Vec<TastResultMetadata>> {
+    let results_dir = resolve_results_dir(cros, results_dir)?;
+    let dirs = invocation_dirs_in_range(&results_dir, start, end)?;
+    let new_dirs: Vec<PathBuf> = dirs.into_iter().filter(|d| seen.insert(d.clone())).collect();
+    if new_dirs.is_empty() {
+        return Ok(Vec::new());
+    }
+    info!("{} new test invocations to ingest", new_dirs.len());
+    Ok(parse_invocation_dirs(&new_dirs))
 }
 
 /// Subset of /tmp/tast/results/*/results.json
@@ -458,7 +722,7 @@ pub fn kernel_cmdline_masked_in_results(
     Ok(HashMap::new())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TastResultMetadata {
     pub invocation: TastInvocationMetadata,
     pub result_json_item: TastResultsJsonItem,
@@ -472,6 +736,20 @@ pub struct TastResultsChartJsonItem {
     value: Option<f64>,
     values: Option<Vec<f64>>,
 }
+impl TastResultsChartJsonItem {
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+    pub fn values(&self) -> Option<&[f64]> {
+        self.values.as_deref()
+    }
+    pub fn units(&self) -> &str {
+        &self.units
+    }
+    pub fn improvement_direction(&self) -> &str {
+        &self.improvement_direction
+    }
+}
 pub type TastResultsChartJson = HashMap<String, HashMap<String, TastResultsChartJsonItem>>;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -601,6 +879,152 @@ pub fn save_result_metadata_json(
     Ok(())
 }
 
+/// Companion to [`save_result_metadata_json`]: writes the same `results` as
+/// a JUnit-style `<testsuites>` XML document (one `<testsuite>` per model,
+/// or per OS release for results without a model) so CI systems can ingest
+/// a cro3 Tast run the same way they do any other test report, without
+/// having to post-process `parsed_results.json` themselves.
+pub fn save_results_junit_xml(results: &[&TastResultMetadata], prefix: Option<&str>) -> Result<()> {
+    let path = if let Some(prefix) = prefix {
+        format!("{prefix}_results.xml")
+    } else {
+        "results.xml".to_string()
+    };
+    let path = Path::new("out").join(path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut f = std::fs::File::create(path)?;
+    f.write_all(results_to_junit_xml(results).as_bytes())?;
+    Ok(())
+}
+
+fn junit_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn results_to_junit_xml(results: &[&TastResultMetadata]) -> String {
+    let mut by_suite: HashMap<String, Vec<&TastResultMetadata>> = HashMap::new();
+    for r in results {
+        let suite = r
+            .invocation
+            .model()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| r.invocation.os_release().to_string());
+        by_suite.entry(suite).or_default().push(r);
+    }
+    let mut suite_names: Vec<&String> = by_suite.keys().collect();
+    suite_names.sort();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for suite_name in suite_names {
+        let cases = &by_suite[suite_name];
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\">\n",
+            junit_escape(suite_name),
+            cases.len()
+        ));
+        for r in cases {
+            let name = junit_escape(&r.result_json_item.name);
+            xml.push_str(&format!("    <testcase name=\"{name}\" classname=\"{name}\">\n"));
+            for e in r.result_json_item.errors.iter().flatten() {
+                xml.push_str(&format!(
+                    "      <failure message=\"{}\" time=\"{}\"/>\n",
+                    junit_escape(&e.reason),
+                    junit_escape(&e.time)
+                ));
+            }
+            if let Some(chart) = &r.results_chart_json {
+                let properties = chart_to_junit_properties(chart);
+                if !properties.is_empty() {
+                    xml.push_str("      <properties>\n");
+                    xml.push_str(&properties);
+                    xml.push_str("      </properties>\n");
+                }
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn chart_to_junit_properties(chart: &TastResultsChartJson) -> String {
+    let mut metric_names: Vec<&String> = chart.keys().collect();
+    metric_names.sort();
+    let mut properties = String::new();
+    for metric_name in metric_names {
+        let mut variants: Vec<&String> = chart[metric_name].keys().collect();
+        variants.sort();
+        for variant in variants {
+            let item = &chart[metric_name][variant];
+            let name = junit_escape(&format!("{metric_name}.{variant}"));
+            if let Some(v) = item.value() {
+                properties.push_str(&format!("        <property name=\"{name}\" value=\"{v}\"/>\n"));
+            }
+            if let Some(vs) = item.values() {
+                let joined = vs.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+                properties.push_str(&format!("        <property name=\"{name}\" value=\"{joined}\"/>\n"));
+            }
+        }
+    }
+    properties
+}
+
+#[test]
+fn junit_xml_reports_failures_and_chart_properties() {
+    let invocation = TastInvocationMetadata {
+        path: PathBuf::from("/tmp/results/20240101_000000"),
+        os_release: "R120-15662.0.0".to_string(),
+        model: Some("coral".to_string()),
+        kernel_cmdline: String::new(),
+        kernel_cmdline_masked: String::new(),
+        abtest_metadata: None,
+        bluebench_result: None,
+    };
+    let mut chart = TastResultsChartJson::new();
+    chart.insert(
+        "TabOpenLatency".to_string(),
+        HashMap::from([(
+            "summary".to_string(),
+            serde_json::from_str::<TastResultsChartJsonItem>(
+                r#"{"units": "ms", "improvement_direction": "down", "value": 108.65, "values": null}"#,
+            )
+            .unwrap(),
+        )]),
+    );
+    let passing = TastResultMetadata {
+        invocation: invocation.clone(),
+        result_json_item: TastResultsJsonItem {
+            name: "perf.TabOpenLatency".to_string(),
+            errors: None,
+        },
+        results_chart_json: Some(chart),
+    };
+    let failing = TastResultMetadata {
+        invocation,
+        result_json_item: TastResultsJsonItem {
+            name: "meta.LocalFail".to_string(),
+            errors: Some(vec![TastResultsJsonError {
+                time: "2024-01-01T00:00:01Z".to_string(),
+                reason: "something broke".to_string(),
+            }]),
+        },
+        results_chart_json: None,
+    };
+    let xml = results_to_junit_xml(&[&passing, &failing]);
+    assert!(xml.contains("<testsuite name=\"coral\" tests=\"2\">"));
+    assert!(xml.contains("<testcase name=\"perf.TabOpenLatency\""));
+    assert!(xml.contains("<property name=\"TabOpenLatency.summary\" value=\"108.65\"/>"));
+    assert!(xml.contains("<testcase name=\"meta.LocalFail\""));
+    assert!(xml.contains("<failure message=\"something broke\" time=\"2024-01-01T00:00:01Z\"/>"));
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 struct TastAnalyzerScalarResult {
     units: String,
@@ -625,44 +1049,100 @@ impl TastAnalyzerInputJson {
         f.write_all(&serde_json::to_string(&self)?.into_bytes())?;
         Ok(())
     }
-    pub fn from_results(results: &[&TastResultMetadata]) -> Result<Self> {
-        let mut data = Self::default();
-        for r in results {
-            let abtest_metadata = r
-                .invocation
-                .abtest_metadata
-                .as_ref()
-                .context("abtest_metadata should be populated")?;
-            let tast_test = &abtest_metadata.runner.tast_test;
-            let r = r
-                .invocation
-                .bluebench_result
-                .as_ref()
-                .context("bluebench_result is empty")?;
-            let value = r.converged_mean_mean;
-            let v = TastAnalyzerScalarResult {
-                units: "milliseconds".to_string(),
-                improvement_direction: "down".to_string(),
-                value,
-            };
+    /// `run_id` is derived from the bluebench result's own timestamp/HWID
+    /// when present, since that's what earlier-saved input JSONs already key
+    /// on; otherwise it falls back to the invocation's model and the
+    /// `YYYYMMDD-hhmmss` results directory name `collect_results` already
+    /// sorts and range-filters on.
+    fn run_id(r: &TastResultMetadata) -> Result<String> {
+        if let Some(b) = r.invocation.bluebench_result.as_ref() {
             let ts = chrono::DateTime::parse_from_rfc3339(
-                r.metadata
+                b.metadata
                     .test_start_timestamp
                     .split(' ')
                     .next()
                     .context("failed to get test start timestamp")?,
             )
             .context("failed to parse test start timestamp")?;
-            let hwid = &r.metadata.hwid;
+            return Ok(format!("{}/{ts}", b.metadata.hwid));
+        }
+        let model = r.invocation.model.as_deref().unwrap_or("UNKNOWN_MODEL");
+        let timestamp = r
+            .invocation
+            .path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or("UNKNOWN_TIMESTAMP");
+        Ok(format!("{model}/{timestamp}"))
+    }
+
+    /// Walks every metric in `r.results_chart_json` (falling back to the
+    /// single `bluebench_result.converged_mean_mean` for invocations that
+    /// predate per-metric chart JSON) and inserts one
+    /// `TastAnalyzerResultJsonKey`/`TastAnalyzerScalarResult` entry per
+    /// metric, using that metric's own `units`/`improvement_direction` and
+    /// reducing its `values` vector to its mean when no scalar `value` is
+    /// present.
+    fn insert_result(&mut self, r: &TastResultMetadata, tast_test: &str) -> Result<()> {
+        let run_id = Self::run_id(r)?;
+        let Some(chart) = &r.results_chart_json else {
+            let b = r
+                .invocation
+                .bluebench_result
+                .as_ref()
+                .context("neither results_chart_json nor bluebench_result is populated")?;
             let k = TastAnalyzerResultJsonKey {
-                run_id: format!("{hwid}/{ts}"),
-                test_name: tast_test.clone(),
+                run_id,
+                test_name: tast_test.to_string(),
                 metric_name: "TabOpenLatency".to_string(),
                 variant: String::default(),
             };
-            let k = serde_json::to_string(&k)?;
-            let v = serde_json::to_string(&v)?;
-            data.0.insert(k, v);
+            let v = TastAnalyzerScalarResult {
+                units: "milliseconds".to_string(),
+                improvement_direction: "down".to_string(),
+                value: b.converged_mean_mean,
+            };
+            self.0
+                .insert(serde_json::to_string(&k)?, serde_json::to_string(&v)?);
+            return Ok(());
+        };
+        for (metric_name, variants) in chart {
+            for (variant, item) in variants {
+                let value = if let Some(v) = item.value() {
+                    v
+                } else if let Some(vs) = item.values().filter(|vs| !vs.is_empty()) {
+                    mean_var(vs).0
+                } else {
+                    continue;
+                };
+                let k = TastAnalyzerResultJsonKey {
+                    run_id: run_id.clone(),
+                    test_name: tast_test.to_string(),
+                    metric_name: metric_name.clone(),
+                    variant: variant.clone(),
+                };
+                let v = TastAnalyzerScalarResult {
+                    units: item.units().to_string(),
+                    improvement_direction: item.improvement_direction().to_string(),
+                    value,
+                };
+                self.0
+                    .insert(serde_json::to_string(&k)?, serde_json::to_string(&v)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_results(results: &[&TastResultMetadata]) -> Result<Self> {
+        let mut data = Self::default();
+        for r in results {
+            let abtest_metadata = r
+                .invocation
+                .abtest_metadata
+                .as_ref()
+                .context("abtest_metadata should be populated")?;
+            let tast_test = abtest_metadata.runner.tast_test.clone();
+            data.insert_result(r, &tast_test)?;
         }
         Ok(data)
     }
@@ -676,9 +1156,56 @@ pub struct TastAnalyzerOutputAnalysisLine {
     pub cnt_a: usize,
     pub cnt_b: usize,
     pub change_percent: f64,
+    /// Hodges-Lehmann estimate of the A/B location shift: the median of all
+    /// pairwise differences `b_j - a_i`. More robust to outliers than
+    /// `change_percent`'s mean-based formula. `NaN` when unavailable (e.g.
+    /// reconstructed from [`Self::from_legacy_text`], which has no samples to
+    /// compute it from).
+    pub hodges_lehmann: f64,
+    /// Whether `p < 0.05`, i.e. the Mann-Whitney test rejects the null
+    /// hypothesis that A and B are drawn from the same distribution.
+    pub significant: bool,
 }
 impl TastAnalyzerOutputAnalysisLine {
-    pub fn from(stats: &str) -> Result<Self> {
+    /// Computes the A/B verdict natively from the raw sample values, using
+    /// the same two-sided Mann-Whitney U test (with tie-corrected normal
+    /// approximation) as [`compare_abtest_results`], instead of scraping a
+    /// `U=..., p=..., dir=..., n=(a,b), %change=...` line out of the
+    /// external `tast_analyzer` Python tool's stdout.
+    pub fn compute(a: &[f64], b: &[f64]) -> Result<Self> {
+        if a.is_empty() || b.is_empty() {
+            bail!("Both samples must be non-empty (got {} and {})", a.len(), b.len());
+        }
+        let (u, p) = mann_whitney_u(a, b);
+        let (mean_a, _) = mean_var(a);
+        let (mean_b, _) = mean_var(b);
+        if mean_a == 0.0 {
+            bail!("Sample A's mean is zero, can't compute %change");
+        }
+        let dir = if median(b) >= median(a) { "up" } else { "down" };
+        let mut pairwise_diffs: Vec<f64> = Vec::with_capacity(a.len() * b.len());
+        for &av in a {
+            for &bv in b {
+                pairwise_diffs.push(bv - av);
+            }
+        }
+        Ok(Self {
+            u,
+            p,
+            dir: dir.to_string(),
+            cnt_a: a.len(),
+            cnt_b: b.len(),
+            change_percent: (mean_b - mean_a) / mean_a * 100.0,
+            hodges_lehmann: median(&pairwise_diffs),
+            significant: p < 0.05,
+        })
+    }
+
+    /// Fallback for legacy saved logs that only have the external
+    /// `tast_analyzer` tool's text output (not the raw sample values
+    /// [`Self::compute`] needs), re-parsing its `U=..., p=..., dir=...,
+    /// n=(a,b), %change=...` line.
+    pub fn from_legacy_text(stats: &str) -> Result<Self> {
         static RE_ANALYSIS: Lazy<Regex> = Lazy::new(|| {
             Regex::new(r"U=(?<u>[0-9.]+), p=(?<p>.*), dir=(?<dir>.*), n=\((?<cnt_a>.*), (?<cnt_b>.*)\), %change=(?<change_percent>[0-9-.]+)").unwrap()
         });
@@ -686,14 +1213,14 @@ impl TastAnalyzerOutputAnalysisLine {
         let u = stats.name("u").context("u is missing")?.as_str();
         let u = u.parse().context("failed to parse U")?;
         let p = stats.name("p").context("p is missing")?.as_str();
-        let p = p.parse().context("failed to parse U")?;
+        let p = p.parse().context("failed to parse p")?;
         let dir = stats
             .name("dir")
             .context("dir is missing")?
             .as_str()
             .to_string();
         let cnt_a = stats.name("cnt_a").context("cnt_a is missing")?.as_str();
-        let cnt_a = cnt_a.parse().context("failed to parse U")?;
+        let cnt_a = cnt_a.parse().context("failed to parse cnt_a")?;
         let cnt_b = stats.name("cnt_b").context("cnt_b is missing")?.as_str();
         let cnt_b = cnt_b.parse()?;
         let change_percent = stats
@@ -708,10 +1235,77 @@ impl TastAnalyzerOutputAnalysisLine {
             cnt_a,
             cnt_b,
             change_percent,
+            hodges_lehmann: f64::NAN,
+            significant: p < 0.05,
         })
     }
 }
 
+/// A metric's unit, parsed from the free-form string `tast` results report
+/// it in, with a known conversion factor to its own base unit (seconds for
+/// time, bytes for size). Lets [`TastAnalyzerOutput::compute`] normalize
+/// e.g. a `milliseconds` A arm against a `seconds` B arm instead of
+/// computing a nonsensical `%change` across mismatched units.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum Unit {
+    Milliseconds,
+    Seconds,
+    Bytes,
+    Mebibytes,
+    Percent,
+    Count,
+    /// Free-form unit the parser doesn't recognize; only compatible with
+    /// an identical unknown string (checked by the caller, not here).
+    Unknown,
+}
+impl Unit {
+    pub fn parse(unit: &str) -> Self {
+        match unit.to_ascii_lowercase().as_str() {
+            "ms" | "millisecond" | "milliseconds" => Self::Milliseconds,
+            "s" | "sec" | "second" | "seconds" => Self::Seconds,
+            "b" | "byte" | "bytes" => Self::Bytes,
+            "mib" | "mebibyte" | "mebibytes" => Self::Mebibytes,
+            "%" | "percent" => Self::Percent,
+            "count" | "n" => Self::Count,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Factor to convert a value in `self` to this unit family's base unit
+    /// (seconds for time, bytes for size; percent/count/unknown are their
+    /// own base).
+    fn factor_to_base(self) -> f64 {
+        match self {
+            Self::Milliseconds => 1e-3,
+            Self::Seconds => 1.0,
+            Self::Bytes => 1.0,
+            Self::Mebibytes => (1024 * 1024) as f64,
+            Self::Percent | Self::Count | Self::Unknown => 1.0,
+        }
+    }
+
+    /// Whether `self` and `other` measure the same kind of quantity and so
+    /// can be converted to a shared base unit for comparison.
+    fn is_compatible_with(self, other: Self) -> bool {
+        use Unit::*;
+        matches!(
+            (self, other),
+            (Milliseconds | Seconds, Milliseconds | Seconds) | (Bytes | Mebibytes, Bytes | Mebibytes)
+        ) || self == other
+    }
+
+    /// Name of the base unit both sides are normalized to before printing.
+    fn base_name(self) -> &'static str {
+        match self {
+            Self::Milliseconds | Self::Seconds => "seconds",
+            Self::Bytes | Self::Mebibytes => "bytes",
+            Self::Percent => "percent",
+            Self::Count => "count",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct TastAnalyzerOutputStatsLine {
     pub mean: f64,
@@ -721,28 +1315,20 @@ pub struct TastAnalyzerOutputStatsLine {
     pub max: f64,
 }
 impl TastAnalyzerOutputStatsLine {
-    pub fn from(stats: &str) -> Result<Self> {
-        static RE_STATS: Lazy<Regex> = Lazy::new(|| {
-            // mean=108.65 milliseconds, std=3.33, min=98.31, max=113.07
-            Regex::new(r"mean=(?<mean>[0-9.]+) (?<unit>[^,]+), std=(?<stddev>[0-9.]+), min=(?<min>[0-9.]+), max=(?<max>.*)").unwrap()
-        });
-        let stats = RE_STATS.captures(stats).context("No stat line match")?;
-        let mean = stats.name("mean").context("mean is missing")?.as_str();
-        let mean = mean.parse().context("failed to parse mean")?;
-        let unit = stats
-            .name("unit")
-            .context("unit is missing")?
-            .as_str()
-            .to_string();
-        let stddev = stats.name("stddev").context("stddev is missing")?.as_str();
-        let stddev = stddev.parse().context("failed to parse mean")?;
-        let min = stats.name("min").context("min is missing")?.as_str();
-        let min = min.parse().context("failed to parse mean")?;
-        let max = stats.name("max").context("max is missing")?.as_str();
-        let max = max.parse().context("failed to parse mean")?;
+    /// Computes mean/stddev/min/max natively from the raw sample `values`,
+    /// instead of parsing them back out of a `mean=..., std=..., min=...,
+    /// max=...` line.
+    pub fn compute(values: &[f64], unit: &str) -> Result<Self> {
+        if values.is_empty() {
+            bail!("Can't summarize an empty sample");
+        }
+        let (mean, var) = mean_var(values);
+        let stddev = if values.len() < 2 { 0.0 } else { var.sqrt() };
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
         Ok(Self {
             mean,
-            unit,
+            unit: unit.to_string(),
             stddev,
             min,
             max,
@@ -758,98 +1344,429 @@ pub struct TastAnalyzerOutput {
     pub stats_b: TastAnalyzerOutputStatsLine,
 }
 impl TastAnalyzerOutput {
-    pub fn from(output: &str) -> Result<Vec<Self>> {
-        let mut results = Vec::new();
-        let output: Vec<&str> = output
-            .split('\n')
-            .filter(|s| s.starts_with("  ") | s.ends_with(':'))
-            .map(|s| s.trim())
+    /// Builds the full A/B comparison for one metric natively from its raw
+    /// sample values in each arm. `unit_a`/`unit_b` are parsed via [`Unit`]
+    /// and must be compatible (e.g. `milliseconds` vs `seconds` is fine,
+    /// `milliseconds` vs `bytes` is not); both sample vectors are converted
+    /// to their shared base unit before the A/B stats and `%change` are
+    /// computed, so differently-unit-ed arms compare correctly instead of
+    /// silently assuming they already match.
+    pub fn compute(key: String, unit_a: &str, unit_b: &str, a: &[f64], b: &[f64]) -> Result<Self> {
+        let (ua, ub) = (Unit::parse(unit_a), Unit::parse(unit_b));
+        let compatible = if ua == Unit::Unknown && ub == Unit::Unknown {
+            // Neither side matched a known unit family; only treat them as
+            // the same metric if the raw strings agree.
+            unit_a.eq_ignore_ascii_case(unit_b)
+        } else {
+            ua.is_compatible_with(ub)
+        };
+        if !compatible {
+            bail!("Incommensurable units for {key}: A is {unit_a:?}, B is {unit_b:?}");
+        }
+        let base_unit = ua.base_name();
+        let a: Vec<f64> = a.iter().map(|v| v * ua.factor_to_base()).collect();
+        let b: Vec<f64> = b.iter().map(|v| v * ub.factor_to_base()).collect();
+        Ok(Self {
+            key,
+            analysis: TastAnalyzerOutputAnalysisLine::compute(&a, &b)?,
+            stats_a: TastAnalyzerOutputStatsLine::compute(&a, base_unit)?,
+            stats_b: TastAnalyzerOutputStatsLine::compute(&b, base_unit)?,
+        })
+    }
+
+    /// 95% bootstrap confidence interval for `%change` (`self.analysis.
+    /// change_percent`'s own formula, resampled), given the same raw `a`/`b`
+    /// samples `compute` was built from. Returns `(ci_low, ci_high)`.
+    pub fn change_percent_ci(a: &[f64], b: &[f64], iterations: usize) -> (f64, f64) {
+        let mut rng = thread_rng();
+        let mut changes: Vec<f64> = (0..iterations)
+            .map(|_| {
+                let resample_a: Vec<f64> =
+                    (0..a.len()).map(|_| *a.choose(&mut rng).unwrap()).collect();
+                let resample_b: Vec<f64> =
+                    (0..b.len()).map(|_| *b.choose(&mut rng).unwrap()).collect();
+                let (mean_a, _) = mean_var(&resample_a);
+                let (mean_b, _) = mean_var(&resample_b);
+                (mean_b - mean_a) / mean_a * 100.0
+            })
             .collect();
-        for e in output.chunks(4) {
-            if let (Some(key), Some(analysis), Some(a), Some(b)) =
-                (e.first(), e.get(1), e.get(2), e.get(3))
-            {
-                let key = key.to_string();
-                let analysis = TastAnalyzerOutputAnalysisLine::from(analysis)?;
-                let stats_a = TastAnalyzerOutputStatsLine::from(a)?;
-                let stats_b = TastAnalyzerOutputStatsLine::from(b)?;
-                results.push(TastAnalyzerOutput {
-                    key,
-                    analysis,
-                    stats_a,
-                    stats_b,
-                })
-            }
+        changes.sort_by(|l, r| l.partial_cmp(r).unwrap());
+        let pct = |p: f64| -> f64 {
+            let idx = ((changes.len() - 1) as f64 * p).round() as usize;
+            changes[idx]
+        };
+        (pct(0.025), pct(0.975))
+    }
+
+    /// Fails only when the entire 95% bootstrap CI for `%change` lies on the
+    /// worse side of `max_regress_pct` (same sign convention as `%change`
+    /// itself, e.g. `5.0` allows up to a 5% increase, `-5.0` allows up to a
+    /// 5% decrease), so a single noisy sample can't flip CI on its own.
+    pub fn check_regression(&self, a: &[f64], b: &[f64], max_regress_pct: f64) -> Result<()> {
+        let (ci_low, ci_high) = Self::change_percent_ci(a, b, 10000);
+        let regressed = if max_regress_pct >= 0.0 {
+            ci_low > max_regress_pct
+        } else {
+            ci_high < max_regress_pct
+        };
+        if regressed {
+            bail!(
+                "{} regressed by {:+.2}% (95% CI [{:+.2}%, {:+.2}%], threshold {:+.2}%)",
+                self.key,
+                self.analysis.change_percent,
+                ci_low,
+                ci_high,
+                max_regress_pct
+            );
         }
-        Ok(results)
+        Ok(())
     }
 }
 impl Display for TastAnalyzerOutput {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "  {:>+6.2}% change with p={:.6} on {}:\n  {:12.3} => {:12.3} [{}], N=({:3}, {:3})", 
+        write!(f, "  {:>+6.2}% change with p={:.6}{} on {}:\n  {:12.3} => {:12.3} [{}], N=({:3}, {:3}), Hodges-Lehmann shift={:.3}",
             self.analysis.change_percent,
-            self.analysis.p, 
-            self.key, 
-            self.stats_a.mean, 
+            self.analysis.p,
+            if self.analysis.significant { " (significant)" } else { "" },
+            self.key,
+            self.stats_a.mean,
             self.stats_b.mean,
-            self.stats_a.unit, 
-            self.analysis.cnt_a, 
-            self.analysis.cnt_b)
+            self.stats_a.unit,
+            self.analysis.cnt_a,
+            self.analysis.cnt_b,
+            self.analysis.hodges_lehmann)
+    }
+}
+
+/// One metric's entry in an [`html_report`] artifact: the computed A/B
+/// verdict, plus the raw per-run sample values if the caller still has
+/// them (lets the report draw a box plot instead of falling back to a
+/// mean+stddev error bar).
+pub struct TastAnalyzerReportEntry {
+    pub output: TastAnalyzerOutput,
+    pub samples_a: Vec<f64>,
+    pub samples_b: Vec<f64>,
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders `entries` as a single self-contained HTML file (Plotly.js loaded
+/// from its CDN): one chart per metric, a box plot of the A/B distributions
+/// when raw samples were supplied, otherwise a mean bar with a `stddev`
+/// error bar. Each chart is annotated with `%change`/`p` and colored green
+/// when the metric improved, red when it regressed, gray when the
+/// difference isn't significant (assumes lower is better, as elsewhere in
+/// this module), so results from a `tast analyze` run can be shared as a
+/// standalone artifact instead of re-reading console text.
+pub fn html_report(entries: &[TastAnalyzerReportEntry]) -> String {
+    let mut charts = String::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let o = &entry.output;
+        let color = if !o.analysis.significant {
+            "gray"
+        } else if o.analysis.change_percent < 0.0 {
+            "seagreen"
+        } else {
+            "crimson"
+        };
+        let title = escape_html(&format!(
+            "{} ({:+.2}% change, p={:.4})",
+            o.key, o.analysis.change_percent, o.analysis.p
+        ));
+        let data = if !entry.samples_a.is_empty() && !entry.samples_b.is_empty() {
+            format!(
+                "[{{type: 'box', y: {}, name: 'A', marker: {{color: '{color}'}}}}, \
+                 {{type: 'box', y: {}, name: 'B', marker: {{color: '{color}'}}}}]",
+                serde_json::to_string(&entry.samples_a).unwrap_or_default(),
+                serde_json::to_string(&entry.samples_b).unwrap_or_default(),
+            )
+        } else {
+            format!(
+                "[{{type: 'bar', x: ['A', 'B'], y: [{}, {}], error_y: {{type: 'data', array: [{}, {}]}}, marker: {{color: '{color}'}}}}]",
+                o.stats_a.mean, o.stats_b.mean, o.stats_a.stddev, o.stats_b.stddev,
+            )
+        };
+        charts.push_str(&format!(
+            "<h3>{title}</h3><div id=\"plot-{i}\"></div><script>Plotly.newPlot('plot-{i}', {data}, {{margin: {{t: 10}}}});</script>\n"
+        ));
     }
+    format!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <title>tast analyze report</title>\
+         <script src=\"https://cdn.plot.ly/plotly-2.27.0.min.js\"></script></head>\
+         <body>{charts}</body></html>"
+    )
+}
+
+/// Renders [`html_report`] and writes it to `path`.
+pub fn write_html_report(entries: &[TastAnalyzerReportEntry], path: &Path) -> Result<()> {
+    let mut f = File::create(path)?;
+    f.write_all(html_report(entries).as_bytes())?;
+    Ok(())
+}
+
+/// One experiment's outcome for `cro3 tast analyze --report`: the A/B
+/// descriptions alongside the computed stats, plus a pass/fail verdict CI
+/// can gate on directly instead of re-deriving it from `analysis.p`/
+/// `change_percent` itself. As elsewhere in this module (see
+/// [`html_report`]), lower is assumed to be better, so a metric "regresses"
+/// when it's both significant and it got bigger.
+#[derive(Debug, Clone, Serialize)]
+pub struct TastCiReportEntry {
+    pub key: String,
+    pub variant_a: String,
+    pub variant_b: String,
+    pub output: TastAnalyzerOutput,
+    pub regressed: bool,
+}
+
+/// Serializes `entries` as a single JSON document for `cro3 tast analyze
+/// --format json`.
+pub fn to_json_report(entries: &[TastCiReportEntry]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(entries)?)
+}
+
+/// Serializes `entries` into a JUnit-style `<testsuites>` XML document for
+/// `cro3 tast analyze --format junit`, one `<testcase>` per experiment, with
+/// a `<failure>` on every `regressed` entry so existing CI result viewers
+/// render it the same as any other failing test.
+pub fn to_junit_report(entries: &[TastCiReportEntry]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!("<testsuites tests=\"{}\">\n", entries.len()));
+    out.push_str("  <testsuite name=\"cro3.tast.analyze\">\n");
+    for e in entries {
+        out.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"0\">\n",
+            junit_escape(&e.key),
+            junit_escape(&e.key),
+        ));
+        if e.regressed {
+            out.push_str(&format!(
+                "      <failure message=\"regression detected\">{:+.2}% change, p={:.4}, \
+                 Hodges-Lehmann shift={:.3}</failure>\n",
+                e.output.analysis.change_percent, e.output.analysis.p, e.output.analysis.hodges_lehmann
+            ));
+        }
+        out.push_str("    </testcase>\n");
+    }
+    out.push_str("  </testsuite>\n");
+    out.push_str("</testsuites>\n");
+    out
 }
 
 #[test]
-fn tast_analyzer_one_output_can_be_parsed() {
-    let stdout = r#"
-1 metrics, 1 better, 0 worse
-0 GOT WORSE FROM A.json to B.json
-
-1 GOT BETTER FROM A.json to B.json
-perf.TabOpenLatencyPerf.TabOpenLatency.:
-  U=3540.0, p=0.000000, dir=down, n=(59, 60), %change=-17.94
-  mean=108.65 milliseconds, std=3.33, min=98.31, max=113.07
-  mean=89.16 milliseconds, std=2.48, min=82.91, max=93.48
-"#;
-    let actual = TastAnalyzerOutput::from(stdout).unwrap();
-    assert_eq!(actual.len(), 1);
-    let actual = actual[0].clone();
-    let expected = TastAnalyzerOutput {
-        key: "perf.TabOpenLatencyPerf.TabOpenLatency.:".to_string(),
-        analysis: TastAnalyzerOutputAnalysisLine {
-            u: 3540.0,
-            p: 0.0,
-            dir: "down".to_string(),
-            cnt_a: 59,
-            cnt_b: 60,
-            change_percent: -17.94,
-        },
-        stats_a: TastAnalyzerOutputStatsLine {
-            mean: 108.65,
-            unit: "milliseconds".to_string(),
-            stddev: 3.33,
-            min: 98.31,
-            max: 113.07,
-        },
-        stats_b: TastAnalyzerOutputStatsLine {
-            mean: 89.16,
-            unit: "milliseconds".to_string(),
-            stddev: 2.48,
-            min: 82.91,
-            max: 93.48,
-        },
-    };
+fn tast_analyzer_output_computed_natively_from_samples() {
+    let a: Vec<f64> = vec![
+        100.0, 101.0, 102.0, 103.0, 104.0, 105.0, 106.0, 107.0, 108.0, 109.0, 110.0, 111.0,
+    ];
+    let b: Vec<f64> = vec![
+        88.0, 89.0, 90.0, 91.0, 92.0, 93.0, 94.0, 95.0, 96.0, 97.0, 98.0, 99.0,
+    ];
+    let actual =
+        TastAnalyzerOutput::compute("perf.TabOpenLatencyPerf.TabOpenLatency.".to_string(), "milliseconds", "milliseconds", &a, &b)
+            .unwrap();
     println!("{actual}");
-    assert_eq!(actual, expected);
+    assert_eq!(actual.analysis.cnt_a, a.len());
+    assert_eq!(actual.analysis.cnt_b, b.len());
+    assert_eq!(actual.analysis.dir, "down");
+    assert!(actual.analysis.change_percent < 0.0);
+    assert!(actual.analysis.p < 0.01);
+    assert_eq!(actual.stats_a.unit, "milliseconds");
+    assert!(actual.analysis.significant);
+    // B is consistently 12 below A, so the median pairwise difference should
+    // land exactly on that shift.
+    assert_eq!(actual.analysis.hodges_lehmann, -12.0);
+}
+
+#[test]
+fn tast_analyzer_output_analysis_line_not_significant_for_identical_samples() {
+    let a = vec![1.0, 2.0, 3.0, 4.0];
+    let b = vec![1.0, 2.0, 3.0, 4.0];
+    let actual = TastAnalyzerOutputAnalysisLine::compute(&a, &b).unwrap();
+    assert!(!actual.significant);
+    assert_eq!(actual.hodges_lehmann, 0.0);
+}
+
+#[test]
+fn tast_analyzer_output_compute_rejects_an_empty_sample() {
+    assert!(TastAnalyzerOutput::compute("m".to_string(), "ms", "ms", &[], &[1.0]).is_err());
+}
+
+#[test]
+fn tast_analyzer_output_compute_normalizes_compatible_units() {
+    let a = vec![1.0, 1.0, 1.0, 1.0];
+    let b = vec![2000.0, 2000.0, 2000.0, 2000.0];
+    // A is 1 second, B is 2000 milliseconds -- both 2 seconds apart from
+    // A's 1 second, once normalized to the shared base unit.
+    let actual =
+        TastAnalyzerOutput::compute("m".to_string(), "s", "ms", &a, &b).unwrap();
+    assert_eq!(actual.stats_a.unit, "seconds");
+    assert_eq!(actual.stats_a.mean, 1.0);
+    assert_eq!(actual.stats_b.mean, 2.0);
+    assert_eq!(actual.analysis.change_percent, 100.0);
+}
+
+#[test]
+fn tast_analyzer_output_compute_rejects_incommensurable_units() {
+    assert!(TastAnalyzerOutput::compute("m".to_string(), "ms", "bytes", &[1.0], &[1.0]).is_err());
+}
+
+#[test]
+fn check_regression_fails_only_when_the_whole_ci_is_beyond_the_threshold() {
+    let a: Vec<f64> = vec![
+        100.0, 101.0, 102.0, 103.0, 104.0, 105.0, 106.0, 107.0, 108.0, 109.0, 110.0, 111.0,
+    ];
+    let b: Vec<f64> = vec![
+        88.0, 89.0, 90.0, 91.0, 92.0, 93.0, 94.0, 95.0, 96.0, 97.0, 98.0, 99.0,
+    ];
+    let actual =
+        TastAnalyzerOutput::compute("perf.TabOpenLatencyPerf.TabOpenLatency.".to_string(), "milliseconds", "milliseconds", &a, &b)
+            .unwrap();
+    // b is clearly ~10% below a, so a "no more than a 1% increase" gate
+    // should pass (the regression is an improvement, not an increase)...
+    assert!(actual.check_regression(&a, &b, 1.0).is_ok());
+    // ...while a "no more than a 1% decrease" gate should fail.
+    assert!(actual.check_regression(&a, &b, -1.0).is_err());
+}
+
+#[test]
+fn tast_analyzer_output_analysis_line_parses_legacy_text() {
+    let line = "U=12.0, p=0.0041, dir=down, n=(12, 12), %change=-10.6";
+    let actual = TastAnalyzerOutputAnalysisLine::from_legacy_text(line).unwrap();
+    assert_eq!(actual.u, 12.0);
+    assert_eq!(actual.dir, "down");
+    assert_eq!(actual.cnt_a, 12);
+    assert_eq!(actual.cnt_b, 12);
+    assert_eq!(actual.change_percent, -10.6);
+    assert!(actual.significant);
+    assert!(actual.hodges_lehmann.is_nan());
+}
+
+/// Per-metric verdict from comparing `ExperimentConfig::A` vs `::B` tast
+/// results, in the same `U`/`p`/`n`/`%change` vocabulary as
+/// [`TastAnalyzerOutputAnalysisLine`], but computed natively (no
+/// `tast_analyzer` subprocess involved) via Welch's t-test, with a
+/// Mann-Whitney U fallback for metrics that may not be normally
+/// distributed (e.g. latencies).
+#[derive(Debug, Clone, Serialize)]
+pub struct AbtestMetricVerdict {
+    pub metric: String,
+    pub n_a: usize,
+    pub n_b: usize,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub welch_t: f64,
+    pub welch_df: f64,
+    pub welch_p: f64,
+    pub cohens_d: f64,
+    pub mean_diff_ci_low: f64,
+    pub mean_diff_ci_high: f64,
+    pub mann_whitney_u: f64,
+    pub mann_whitney_p: f64,
+    /// True if either test's p-value is below `alpha`.
+    pub significant: bool,
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|l, r| l.partial_cmp(r).unwrap());
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Groups `results` by their `ExperimentRunMetadata::config` (A/B) and, for
+/// every numeric metric in `results_chart_json`, averages repeated `run`s
+/// within the same `(iteration, cluster, group)` unit before comparing the
+/// arms, so clustered variance from retries isn't mistaken for independent
+/// samples. Results lacking `abtest_metadata` or `results_chart_json` are
+/// skipped; metrics with fewer than 2 unit-means per arm are skipped too
+/// since Welch's test and the bootstrap both need at least that much.
+pub fn compare_abtest_results(results: &[TastResultMetadata], alpha: f64) -> Vec<AbtestMetricVerdict> {
+    use crate::abtest::ExperimentConfig;
+    type UnitKey = (ExperimentConfig, usize, usize, usize);
+    let mut by_metric: HashMap<String, HashMap<UnitKey, Vec<f64>>> = HashMap::new();
+    for r in results {
+        let Some(meta) = r.invocation.abtest_metadata() else {
+            continue;
+        };
+        let Some(chart) = &r.results_chart_json else {
+            continue;
+        };
+        let key: UnitKey = (meta.config, meta.iteration(), meta.cluster(), meta.group());
+        for (metric_name, variants) in chart {
+            let bucket = by_metric.entry(metric_name.clone()).or_default();
+            for item in variants.values() {
+                let samples = bucket.entry(key).or_default();
+                if let Some(v) = item.value() {
+                    samples.push(v);
+                }
+                if let Some(vs) = item.values() {
+                    samples.extend(vs.iter().copied());
+                }
+            }
+        }
+    }
+
+    let mut verdicts = Vec::new();
+    for (metric, by_unit) in &by_metric {
+        let mut a_unit_means = Vec::new();
+        let mut b_unit_means = Vec::new();
+        for (key, samples) in by_unit {
+            if samples.is_empty() {
+                continue;
+            }
+            let (config, _, _, _) = *key;
+            let unit_mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            match config {
+                ExperimentConfig::A => a_unit_means.push(unit_mean),
+                ExperimentConfig::B => b_unit_means.push(unit_mean),
+            }
+        }
+        if a_unit_means.len() < 2 || b_unit_means.len() < 2 {
+            continue;
+        }
+        let (mean_a, _) = mean_var(&a_unit_means);
+        let (mean_b, _) = mean_var(&b_unit_means);
+        let (welch_t, welch_df, welch_p) = welch_t_test(&a_unit_means, &b_unit_means);
+        let (_, mean_diff_ci_low, mean_diff_ci_high) =
+            welch_mean_diff_ci(&a_unit_means, &b_unit_means, welch_df, alpha);
+        let (mann_whitney_u, mann_whitney_p) = mann_whitney_u(&a_unit_means, &b_unit_means);
+        verdicts.push(AbtestMetricVerdict {
+            metric: metric.clone(),
+            n_a: a_unit_means.len(),
+            n_b: b_unit_means.len(),
+            mean_a,
+            mean_b,
+            welch_t,
+            welch_df,
+            welch_p,
+            cohens_d: cohens_d(&a_unit_means, &b_unit_means),
+            mean_diff_ci_low,
+            mean_diff_ci_high,
+            mann_whitney_u,
+            mann_whitney_p,
+            significant: welch_p < alpha || mann_whitney_p < alpha,
+        });
+    }
+    verdicts.sort_by(|l, r| l.metric.cmp(&r.metric));
+    verdicts
 }
 
 #[test]
-fn tast_analyzer_zero_output_can_be_parsed() {
-    let stdout = r#"
-0 metrics, 0 better, 0 worse
-0 GOT WORSE FROM experiment_20240619_164907_892671708_kled_A.json to experiment_20240619_164907_892671708_kled_B.json
-
-0 GOT BETTER FROM experiment_20240619_164907_892671708_kled_A.json to experiment_20240619_164907_892671708_kled_B.json
-"#;
-    let actual = TastAnalyzerOutput::from(stdout).unwrap();
-    assert_eq!(actual.len(), 0);
+fn mann_whitney_u_is_symmetric_in_u_for_disjoint_samples() {
+    let a = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+    let b = vec![10.0, 11.0, 12.0, 13.0, 14.0];
+    let (u, p) = mann_whitney_u(&a, &b);
+    assert_eq!(u, 0.0);
+    assert!(p < 0.01);
 }
@@ -0,0 +1,158 @@
+// Copyright 2023 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! A `tracing_subscriber::Layer` that records every `#[tracing::instrument]`ed
+//! span's timing as Chrome Trace Event Format JSON, so a slow `build`/`sync`/
+//! `deploy` run can be opened in chrome://tracing or Perfetto to see where
+//! cro3's own orchestration time goes, as opposed to the underlying
+//! emerge/rsync/ssh calls it shells out to.
+
+use std::fs::File;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::span;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// A single Chrome Trace Event Format "complete" (`ph: "X"`) event, covering
+/// a span from when it was created to when it was closed.
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: String,
+    ph: &'static str,
+    pid: u32,
+    tid: u64,
+    ts: u64,
+    dur: u64,
+    args: Map<String, Value>,
+}
+
+/// Per-span bookkeeping, stashed in the span's extensions between
+/// `on_new_span` and `on_close`.
+struct SpanStart {
+    at: Instant,
+    args: Map<String, Value>,
+}
+
+/// Records a span's fields as a JSON object, using each field's `Debug`
+/// output since `tracing` doesn't otherwise expose a uniform typed value.
+struct JsonFieldVisitor(Map<String, Value>);
+impl Visit for JsonFieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), Value::String(format!("{value:?}")));
+    }
+}
+
+fn current_thread_id() -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::thread::current().id().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Captures span timings as Chrome Trace Event Format JSON. The recorded
+/// events live behind an `Arc` shared with a [`TraceProfilerHandle`], since
+/// once this layer is installed as the process's global subscriber it's
+/// effectively `'static` and never dropped -- so the handle, not a `Drop`
+/// impl on the layer itself, is what the caller uses to flush the file
+/// before exit.
+pub struct TraceProfilerLayer {
+    start: Instant,
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+/// Flushes a [`TraceProfilerLayer`]'s recorded events to `path` as a single
+/// top-level JSON array, either explicitly via [`Self::flush`] or on drop.
+pub struct TraceProfilerHandle {
+    path: String,
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl TraceProfilerLayer {
+    /// Builds a layer ready to install into a `tracing_subscriber::registry`,
+    /// plus the handle used to flush what it records to `path`.
+    pub fn new(path: impl Into<String>) -> (Self, TraceProfilerHandle) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        (
+            Self {
+                start: Instant::now(),
+                events: events.clone(),
+            },
+            TraceProfilerHandle {
+                path: path.into(),
+                events,
+            },
+        )
+    }
+}
+
+impl TraceProfilerHandle {
+    /// Serializes the recorded events as a JSON array and writes them to
+    /// `path`.
+    pub fn flush(&self) -> Result<()> {
+        let events = self.events.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*events)
+            .context("Failed to serialize the trace events")?;
+        File::create(&self.path)
+            .with_context(|| format!("Failed to create the trace output file {}", self.path))?
+            .write_all(json.as_bytes())
+            .context("Failed to write the trace output file")
+    }
+}
+
+impl Drop for TraceProfilerHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Failed to write trace output to {}: {e:#}", self.path);
+        }
+    }
+}
+
+impl<S> Layer<S> for TraceProfilerLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut visitor = JsonFieldVisitor(Map::new());
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanStart {
+            at: Instant::now(),
+            args: visitor.0,
+        });
+    }
+
+    fn on_close(&self, id: span::Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(SpanStart { at, args }) = span.extensions_mut().remove::<SpanStart>() else {
+            return;
+        };
+        let metadata = span.metadata();
+        self.events.lock().unwrap().push(TraceEvent {
+            name: metadata.name().to_string(),
+            cat: metadata.target().to_string(),
+            ph: "X",
+            pid: 1,
+            tid: current_thread_id(),
+            ts: at.duration_since(self.start).as_micros() as u64,
+            dur: at.elapsed().as_micros() as u64,
+            args,
+        });
+    }
+}
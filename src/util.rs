@@ -17,6 +17,7 @@ use anyhow::Result;
 use dirs::home_dir;
 
 pub mod shell_helpers;
+pub mod xdg_dirs;
 
 pub fn has_root_privilege() -> Result<bool> {
     let output = shell_helpers::run_bash_command("id -u", None)?;
@@ -44,6 +45,18 @@ pub fn run_lium_with_sudo(args: &[&str]) -> Result<()> {
     ))
 }
 
+/// The output format requested via the top-level `--format` flag, threaded
+/// down to subcommands via the `LIUM_FORMAT` env var since most subcommand
+/// entry points don't receive `TopLevel` directly. Defaults to `"human"`.
+pub fn output_format() -> String {
+    std::env::var("LIUM_FORMAT").unwrap_or_else(|_| "human".to_string())
+}
+
+/// Returns true if the top-level `--format json` flag was passed.
+pub fn is_json_format() -> bool {
+    output_format() == "json"
+}
+
 pub fn lium_dir() -> Result<String> {
     gen_path_in_lium_dir(".keep").and_then(|mut path| {
         path.pop();
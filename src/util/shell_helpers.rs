@@ -1,8 +1,9 @@
 use std::io::BufRead;
-use std::io::Read;
 use std::iter::Iterator;
 use std::process::Command;
 use std::process::Output;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use anyhow::anyhow;
@@ -17,8 +18,6 @@ use async_process::Stdio;
 use futures::io::BufReader;
 use futures::io::Lines;
 use futures::AsyncBufReadExt;
-use itertools::EitherOrBoth;
-use itertools::Itertools;
 use tracing::info;
 use tracing::trace;
 use wait_timeout::ChildExt;
@@ -45,16 +44,16 @@ pub fn get_async_lines(
 }
 
 pub fn run_bash_command(cmd: &str, dir: Option<&str>) -> Result<Output> {
-    let mut c = Command::new("bash");
-    let c = if let Some(dir) = dir {
-        c.current_dir(dir)
-    } else {
-        &mut c
-    };
-    c.arg("-c")
-        .arg(cmd)
-        .output()
-        .context("Failed to execute cmd")
+    let mut builder = ProcessBuilder::bash_script(cmd);
+    if let Some(dir) = dir {
+        builder = builder.current_dir(dir);
+    }
+    let result = builder.run()?;
+    Ok(Output {
+        status: result.status,
+        stdout: result.stdout,
+        stderr: result.stderr,
+    })
 }
 
 pub fn run_bash_command_with_timeout(
@@ -62,39 +61,285 @@ pub fn run_bash_command_with_timeout(
     dir: Option<&str>,
     timeout: Duration,
 ) -> Result<String> {
-    let mut cmd = Command::new("bash");
-    let cmd = if let Some(dir) = dir {
-        cmd.current_dir(dir)
-    } else {
-        &mut cmd
-    };
-    let mut child = cmd
-        .arg("-c")
-        .arg(script)
-        .stdout(Stdio::piped())
-        .spawn()
-        .context(anyhow!("Failed to spawn command"))?;
-    let status = match child
-        .wait_timeout(timeout)
-        .context(anyhow!("Failed to wait on command"))?
-    {
-        Some(status) => status,
-        None => {
-            child.kill().context("Failed to kill")?;
-            child.wait().context("Failed to wait after kill")?;
-            bail!("Command timeout: {script}");
+    let mut builder = ProcessBuilder::bash_script(script).timeout(timeout);
+    if let Some(dir) = dir {
+        builder = builder.current_dir(dir);
+    }
+    let result = builder.run()?;
+    result.check_status(&format!("Command: {script}"))?;
+    Ok(String::from_utf8_lossy(&result.stdout).to_string())
+}
+
+/// How a [`ProcessBuilder`] handles the child's stdout/stderr.
+enum ProcessOutputMode {
+    /// Buffer stdout/stderr and return them in [`ProcessResult`] without
+    /// logging anything as the process runs.
+    Capture,
+    /// Forward each line to `tracing` the instant it's produced (stderr
+    /// prefixed, same as [`launch_command_with_stdout_label_and_process`]),
+    /// in addition to still buffering it into [`ProcessResult`].
+    Stream { label: Option<String> },
+}
+
+/// The outcome of a [`ProcessBuilder::run`]: the raw exit status plus
+/// whatever stdout/stderr was captured.
+pub struct ProcessResult {
+    pub status: std::process::ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl ProcessResult {
+    pub fn stdout_string(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).trim().to_string()
+    }
+
+    pub fn stderr_string(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).trim().to_string()
+    }
+
+    /// Turns a non-zero exit into an error, distinguishing termination by
+    /// signal from a normal non-zero exit code -- detail that's lost the
+    /// moment a caller only checks `status.success()`.
+    pub fn check_status(&self, what: &str) -> Result<()> {
+        if self.status.success() {
+            return Ok(());
         }
-    };
-    if status.success() {
-        let mut stdout = String::new();
-        child
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = self.status.signal() {
+                bail!(
+                    "{what} was killed by signal {signal}: {}",
+                    self.stderr_string()
+                );
+            }
+        }
+        bail!(
+            "{what} exited with code {}: {}",
+            self.status
+                .code()
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            self.stderr_string()
+        );
+    }
+}
+
+/// Builds and runs a subprocess, composing capabilities that used to be
+/// scattered across the `run_bash_command*` family: an optional kill-on-expiry
+/// timeout, env control, a buffered-vs-live-streamed output mode, and a
+/// fixed-delay retry policy.
+pub struct ProcessBuilder {
+    program: String,
+    args: Vec<String>,
+    current_dir: Option<String>,
+    envs: Vec<(String, String)>,
+    env_removes: Vec<String>,
+    timeout: Option<Duration>,
+    mode: ProcessOutputMode,
+    retries: usize,
+    retry_delay: Duration,
+}
+
+impl ProcessBuilder {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            current_dir: None,
+            envs: Vec::new(),
+            env_removes: Vec::new(),
+            timeout: None,
+            mode: ProcessOutputMode::Capture,
+            retries: 0,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Shorthand for `ProcessBuilder::new("bash").arg("-c").arg(script)`.
+    pub fn bash_script(script: impl Into<String>) -> Self {
+        Self::new("bash").arg("-c").arg(script)
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, args: I) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<String>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.env_removes.push(key.into());
+        self
+    }
+
+    /// Kills (and reaps) the child if it hasn't exited by `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Forwards output to `tracing` live instead of only returning it
+    /// buffered once the process exits. `label` overrides the program name
+    /// in the "Launching subprocess ..." log line.
+    pub fn stream(mut self, label: Option<String>) -> Self {
+        self.mode = ProcessOutputMode::Stream { label };
+        self
+    }
+
+    /// Retries up to `retries` additional times (so `retries + 1` attempts
+    /// total), waiting `delay` between attempts.
+    pub fn retry(mut self, retries: usize, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    fn description(&self) -> String {
+        match &self.mode {
+            ProcessOutputMode::Stream { label: Some(label) } => label.clone(),
+            _ => self.program.clone(),
+        }
+    }
+
+    fn build_command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
+        for (k, v) in &self.envs {
+            cmd.env(k, v);
+        }
+        for k in &self.env_removes {
+            cmd.env_remove(k);
+        }
+        cmd
+    }
+
+    fn run_once(&self) -> Result<ProcessResult> {
+        let mut cmd = self.build_command();
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let description = self.description();
+        info!("Launching subprocess {description}...");
+        let mut child = cmd
+            .spawn()
+            .context(format!("Failed to execute {description}"))?;
+
+        let stdout_iter = child
             .stdout
-            .context("stdout was null")?
-            .read_to_string(&mut stdout)
-            .context("read_to_string failed")?;
-        Ok(stdout)
-    } else {
-        bail!("Command returned {status:?}: {script}")
+            .take()
+            .map(|s| std::io::BufReader::new(s).lines())
+            .into_iter()
+            .flatten();
+        let stderr_iter = child
+            .stderr
+            .take()
+            .map(|s| std::io::BufReader::new(s).lines())
+            .into_iter()
+            .flatten();
+
+        let log = matches!(self.mode, ProcessOutputMode::Stream { .. });
+        let (merged_snd, merged_rcv) = std::sync::mpsc::sync_channel(1);
+        let (stdout_join, stderr_join) =
+            spawn_output_reader_threads(stdout_iter, stderr_iter, merged_snd, log);
+
+        // Drain the merged channel on its own thread, rather than inline
+        // here, so a hung child can't deadlock against `timeout`: the
+        // reader threads share a size-1 buffer, and nothing else drains it
+        // while we're below in `wait_timeout`.
+        let stdout_buf = Arc::new(Mutex::new(Vec::new()));
+        let stderr_buf = Arc::new(Mutex::new(Vec::new()));
+        let drain_join = {
+            let stdout_buf = Arc::clone(&stdout_buf);
+            let stderr_buf = Arc::clone(&stderr_buf);
+            std::thread::spawn(move || {
+                for line in merged_rcv.into_iter() {
+                    match line {
+                        OutputLine::Stdout(l) => {
+                            let mut buf = stdout_buf.lock().unwrap();
+                            buf.extend_from_slice(l.as_bytes());
+                            buf.push(b'\n');
+                        }
+                        OutputLine::Stderr(l) => {
+                            let mut buf = stderr_buf.lock().unwrap();
+                            buf.extend_from_slice(l.as_bytes());
+                            buf.push(b'\n');
+                        }
+                    }
+                }
+            })
+        };
+
+        let status = if let Some(timeout) = self.timeout {
+            match child
+                .wait_timeout(timeout)
+                .context("Failed to wait on command")?
+            {
+                Some(status) => status,
+                None => {
+                    child.kill().context("Failed to kill")?;
+                    child.wait().context("Failed to wait after kill")?
+                }
+            }
+        } else {
+            child.wait().context("Failed to wait for command")?
+        };
+        info!("Subprocess {description} finished with exit code {status}");
+
+        stdout_join
+            .join()
+            .map_err(|e| anyhow!("could not join stdout reader thread: {e:?}"))?;
+        stderr_join
+            .join()
+            .map_err(|e| anyhow!("could not join stderr reader thread: {e:?}"))?;
+        drain_join
+            .join()
+            .map_err(|e| anyhow!("could not join output drain thread: {e:?}"))?;
+
+        Ok(ProcessResult {
+            status,
+            stdout: Arc::try_unwrap(stdout_buf)
+                .map_err(|_| anyhow!("stdout buffer still shared after threads joined"))?
+                .into_inner()
+                .unwrap(),
+            stderr: Arc::try_unwrap(stderr_buf)
+                .map_err(|_| anyhow!("stderr buffer still shared after threads joined"))?
+                .into_inner()
+                .unwrap(),
+        })
+    }
+
+    /// Spawns the process (retrying per [`Self::retry`] if the spawn/wait
+    /// itself fails) and waits for it to finish. Note this only retries on
+    /// an `Err` from `run_once` (e.g. spawn failure); a non-zero exit is
+    /// still a successful `run()` with a failing `status` -- call
+    /// [`ProcessResult::check_status`] if that should be an error too.
+    pub fn run(self) -> Result<ProcessResult> {
+        if self.retries == 0 {
+            return self.run_once();
+        }
+        let description = self.description();
+        retry::retry(
+            retry::delay::Fixed::from_millis(self.retry_delay.as_millis() as u64)
+                .take(self.retries),
+            || self.run_once(),
+        )
+        .map_err(|e| anyhow!("{description} failed after retries: {e}"))
     }
 }
 
@@ -172,99 +417,103 @@ where
         .into_iter()
         .flatten();
 
-    // Create channels to copy the stdout and stderr to.
-    let (stdout_snd, stdout_rcv) = std::sync::mpsc::sync_channel(1);
-    let (stderr_snd, stderr_rcv) = std::sync::mpsc::sync_channel(1);
-    let join = spawn_output_reader_thread(stdout_iter, stderr_iter, stdout_snd, stderr_snd);
+    // Each pipe gets its own reader thread, tagging every line with which
+    // stream it came from before sending it into a single shared channel.
+    // Both threads hold a clone of the same sender, so the channel (and the
+    // receiver's iterator) only closes once both have hit EOF on their pipe.
+    let (merged_snd, merged_rcv) = std::sync::mpsc::sync_channel(1);
+    let (stdout_join, stderr_join) =
+        spawn_output_reader_threads(stdout_iter, stderr_iter, merged_snd, true);
 
-    // Read the recieving ends of the channels and pass them to the process
+    // Read the recieving end of the channel and pass it to the process
     // function's input.
     if let Some(process) = process {
         // create a tracing span for the process function.
         let _process_span = tracing::trace_span!("process stdout/err").entered();
 
-        process(CommandOutputReciever::new(
-            stdout_rcv.into_iter(),
-            stderr_rcv.into_iter(),
-        ))?;
+        process(CommandOutputReciever::new(merged_rcv.into_iter()))?;
+    } else {
+        // Nobody is consuming the output, so drain it ourselves: the reader
+        // threads share a size-1 buffer and would otherwise block forever
+        // waiting for a consumer that doesn't exist.
+        for _ in merged_rcv.into_iter() {}
     }
 
-    // Wait for the process to finish, then wait for the thread to finish reading
-    // stdout/err.
+    // Wait for the process to finish, then wait for both threads to finish
+    // reading stdout/err.
     let r = child.wait()?;
     info!("Subprocess {executable} finished with exit code {r}");
 
-    join.join()
-        .map_err(|e| anyhow!("could not join stdout/err logging and copy thread: {e:?}"))?;
-    trace!("stdout/err logging and copy thread joined");
+    stdout_join
+        .join()
+        .map_err(|e| anyhow!("could not join stdout logging and copy thread: {e:?}"))?;
+    stderr_join
+        .join()
+        .map_err(|e| anyhow!("could not join stderr logging and copy thread: {e:?}"))?;
+    trace!("stdout/err logging and copy threads joined");
 
     Ok(r)
 }
 
-/// This function creates a thread that reads the stdout and stderr of a sub
-/// command and logs them, forwarding a copy to the channels.
-///
-/// This is a little complex because in order to continue with a fixed size
-/// channel, once one is exhausted it needs to be closed. This is why instead of
-/// a single for loop over the iterator, there is a while let until one of
-/// stdout or stderr is exhausted, then the corresponding channel is closed and
-/// the other is drained.
-fn spawn_output_reader_thread<IOut, IErr>(
+/// A line of subprocess output tagged with the pipe it came from, so a
+/// single merged channel can carry both streams without forcing them back
+/// into lockstep.
+pub(crate) enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Spawns one reader thread per pipe, each owning its own `BufReader::lines()`
+/// loop, so a line is forwarded (and logged) the instant it appears instead
+/// of waiting for a matching line on the other stream. Both threads send into
+/// clones of the same `merged_snd`, tagging stdout vs stderr so logging still
+/// prefixes stderr; each thread simply drops its sender clone when its pipe
+/// hits EOF, so a stream that finishes early never blocks the other. `log`
+/// controls whether lines are also forwarded to `tracing` as they arrive
+/// (`ProcessBuilder`'s `Capture` mode wants the lines without the logging).
+pub(crate) fn spawn_output_reader_threads<IOut, IErr>(
     stdout_iter: IOut,
     stderr_iter: IErr,
-    stdout_snd: std::sync::mpsc::SyncSender<String>,
-    stderr_snd: std::sync::mpsc::SyncSender<String>,
-) -> std::thread::JoinHandle<()>
+    merged_snd: std::sync::mpsc::SyncSender<OutputLine>,
+    log: bool,
+) -> (std::thread::JoinHandle<()>, std::thread::JoinHandle<()>)
 where
     IOut: Iterator<Item = std::io::Result<String>> + Send + 'static,
     IErr: Iterator<Item = std::io::Result<String>> + Send + 'static,
 {
-    std::thread::spawn(move || {
-        let _stdout_stderr_output_reader_span =
-            tracing::trace_span!("subprocess output reader").entered();
-
-        // Iterate until one of stdout or stderr is exhausted.
-        let mut cmd_outputs_iter = stdout_iter.zip_longest(stderr_iter);
-        let mut curr = cmd_outputs_iter.next();
-        while let Some(EitherOrBoth::Both(stdout, stderr)) = curr {
-            let stdout = stdout.unwrap();
-            let stderr = stderr.unwrap();
-            info!("{}", stdout.clone());
-            stdout_snd.send(stdout).unwrap();
-
-            info!("stderr: {}", stderr.clone());
-            stderr_snd.send(stderr).unwrap();
-
-            curr = cmd_outputs_iter.next();
+    let stdout_snd = merged_snd.clone();
+    let stdout_join = std::thread::spawn(move || {
+        let _span = tracing::trace_span!("subprocess stdout reader").entered();
+        for stdout in stdout_iter {
+            let Ok(stdout) = stdout else { break };
+            if log {
+                info!("{}", stdout.clone());
+            }
+            if stdout_snd.send(OutputLine::Stdout(stdout)).is_err() {
+                break;
+            }
         }
-
-        // Close the corresponding channel, and drain the other iterator.
-        match curr {
-            Some(EitherOrBoth::Left(_)) => {
-                drop(stderr_snd);
-                while let Some(EitherOrBoth::Left(stdout)) = cmd_outputs_iter.next() {
-                    let stdout = stdout.unwrap();
-                    info!("{}", stdout.clone());
-                    stdout_snd.send(stdout).unwrap();
-                }
+    });
+
+    let stderr_snd = merged_snd;
+    let stderr_join = std::thread::spawn(move || {
+        let _span = tracing::trace_span!("subprocess stderr reader").entered();
+        for stderr in stderr_iter {
+            let Ok(stderr) = stderr else { break };
+            if log {
+                info!("stderr: {}", stderr.clone());
             }
-            Some(EitherOrBoth::Right(_)) => {
-                drop(stdout_snd);
-                while let Some(EitherOrBoth::Right(stderr)) = cmd_outputs_iter.next() {
-                    let stderr = stderr.unwrap();
-                    info!("stderr: {}", stderr.clone());
-                    stderr_snd.send(stderr).unwrap();
-                }
+            if stderr_snd.send(OutputLine::Stderr(stderr)).is_err() {
+                break;
             }
-            Some(EitherOrBoth::Both(_, _)) => panic!("somehow a stdout or stderr came back alive!"),
-            None => (), // We're done.
         }
-    })
+    });
+
+    (stdout_join, stderr_join)
 }
 
 pub struct CommandOutputReciever {
-    stdout_iter: std::sync::mpsc::IntoIter<String>,
-    stderr_iter: std::sync::mpsc::IntoIter<String>,
+    lines: std::sync::mpsc::IntoIter<OutputLine>,
 }
 
 pub struct CommandOutputStdOutReciever {
@@ -276,14 +525,8 @@ pub struct CommandOutputStdErrReciever {
 }
 
 impl CommandOutputReciever {
-    fn new(
-        stdout_iter: std::sync::mpsc::IntoIter<String>,
-        stderr_iter: std::sync::mpsc::IntoIter<String>,
-    ) -> Self {
-        Self {
-            stdout_iter,
-            stderr_iter,
-        }
+    pub(crate) fn new(lines: std::sync::mpsc::IntoIter<OutputLine>) -> Self {
+        Self { lines }
     }
 
     pub fn stdout_only(self) -> CommandOutputStdOutReciever {
@@ -303,13 +546,10 @@ impl Iterator for CommandOutputReciever {
     type Item = (Option<String>, Option<String>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let stdout = self.stdout_iter.next();
-        let stderr = self.stderr_iter.next();
-        if stdout.is_some() || stderr.is_some() {
-            return Some((stdout, stderr));
+        match self.lines.next()? {
+            OutputLine::Stdout(line) => Some((Some(line), None)),
+            OutputLine::Stderr(line) => Some((None, Some(line))),
         }
-
-        None
     }
 }
 
@@ -317,15 +557,16 @@ impl Iterator for CommandOutputStdOutReciever {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (stdout, _) = self.command_output_reciever.next()?;
-
-        if stdout.is_none() {
-            // Exhaust the iterator and return None.
-            for _ in self.command_output_reciever.by_ref() {}
-            return None;
+        // A `None` here just means the merged stream handed us a stderr
+        // line, not that stdout is exhausted (stdout may still have more to
+        // come long after stderr finishes) -- so keep pulling instead of
+        // stopping at the first stderr line.
+        loop {
+            let (stdout, _) = self.command_output_reciever.next()?;
+            if let Some(stdout) = stdout {
+                return Some(stdout);
+            }
         }
-
-        stdout
     }
 }
 
@@ -333,14 +574,11 @@ impl Iterator for CommandOutputStdErrReciever {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (_, stderr) = self.command_output_reciever.next()?;
-
-        if stderr.is_none() {
-            // Exhaust the iterator and return None.
-            for _ in self.command_output_reciever.by_ref() {}
-            return None;
+        loop {
+            let (_, stderr) = self.command_output_reciever.next()?;
+            if let Some(stderr) = stderr {
+                return Some(stderr);
+            }
         }
-
-        stderr
     }
 }
@@ -0,0 +1,86 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Directory-layout helpers that split what used to be a single flat
+//! `~/.lium` into config, cache, and data roots, so large regenerable
+//! artifacts (downloaded images, SDK tarballs) don't share a tier with
+//! small persisted files (config, credentials). Honors
+//! `XDG_CONFIG_HOME`/`XDG_CACHE_HOME`/`XDG_DATA_HOME` (via the
+//! `directories` crate) and a `CRO3_HOME` override that pins all three
+//! tiers under one directory. Falls back to `~/.lium` for existing
+//! installs when neither is available.
+
+use std::env;
+use std::fs::create_dir_all;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use directories::ProjectDirs;
+
+use crate::util::gen_path_in_lium_dir;
+
+fn ensure_parent_dir(path: &Path) -> Result<()> {
+    let mut dir = path.to_path_buf();
+    dir.pop();
+    if let Err(e) = create_dir_all(&dir) {
+        if e.kind() != ErrorKind::AlreadyExists {
+            return Err(e).context("Failed to create a dir");
+        }
+    }
+    Ok(())
+}
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "cro3")
+}
+
+/// Resolves `name` under the given tier, honoring (in order) a `CRO3_HOME`
+/// override, the platform/XDG directory for this tier, and finally the
+/// legacy flat `~/.lium` layout.
+fn gen_path_in_tier(name: &str, pick_tier_dir: impl Fn(&ProjectDirs) -> &Path) -> Result<PathBuf> {
+    let base = if let Ok(cro3_home) = env::var("CRO3_HOME") {
+        Some(PathBuf::from(cro3_home))
+    } else {
+        project_dirs().map(|dirs| pick_tier_dir(&dirs).to_path_buf())
+    };
+    let path = match base {
+        Some(base) => base.join(name),
+        // Neither CRO3_HOME nor a home dir could be determined; keep existing
+        // installs working rather than failing outright.
+        None => return gen_path_in_lium_dir(name),
+    };
+    ensure_parent_dir(&path)?;
+    Ok(path)
+}
+
+/// Small, persisted config/credentials, e.g. `config.json`, the SSH key.
+pub fn config_path_in_lium_dir(name: &str) -> Result<PathBuf> {
+    gen_path_in_tier(name, |dirs| dirs.config_dir())
+}
+
+/// The directory `config_path_in_lium_dir` resolves names under, so
+/// callers can enumerate files in it (e.g. to discover named config
+/// profiles) instead of just resolving one name at a time.
+pub fn config_dir_in_lium_dir() -> Result<PathBuf> {
+    let marker = config_path_in_lium_dir("config.json")?;
+    Ok(marker
+        .parent()
+        .context("config path unexpectedly has no parent dir")?
+        .to_path_buf())
+}
+
+/// Large, regenerable cache, e.g. downloaded images and SDK tarballs.
+pub fn cache_path_in_lium_dir(name: &str) -> Result<PathBuf> {
+    gen_path_in_tier(name, |dirs| dirs.cache_dir())
+}
+
+/// Persisted but non-config data, e.g. the DUT inventory database.
+pub fn data_path_in_lium_dir(name: &str) -> Result<PathBuf> {
+    gen_path_in_tier(name, |dirs| dirs.data_dir())
+}
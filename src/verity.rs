@@ -0,0 +1,277 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Regenerates a disk image's rootfs dm-verity hash tree with custom
+//! verity parameters (salt, hash algorithm, data block size) and patches
+//! the matching kernel partition's `dm=` cmdline argument, the way
+//! `build_kernel_image.sh` does after a rootfs change.
+//!
+//! This targets a locally-built or recovery disk image (the `ROOT-A` /
+//! `KERN-A` GPT partitions in a raw `.bin`), not a DUT's live rootfs.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use regex::Regex;
+use tempfile::NamedTempFile;
+
+// Fixed CrOS GPT partition numbers (see common.sh's PARTITION_NUM_* constants).
+const PARTITION_NUM_KERN_A: u32 = 2;
+const PARTITION_NUM_ROOT_A: u32 = 3;
+const SECTOR_SIZE: u64 = 512;
+
+// Well-known dev-signing keys shipped on test/dev images, used to repack
+// KERN-A after patching its cmdline.
+const DEV_KEYBLOCK: &str = "/usr/share/vboot/devkeys/kernel.keyblock";
+const DEV_DATA_KEY: &str = "/usr/share/vboot/devkeys/kernel_data_key.vbprivk";
+
+/// Tunable dm-verity parameters for regenerating a rootfs's hash tree,
+/// mirroring the `--verity_*` flags `build_kernel_image.sh` accepts.
+#[derive(Debug, Clone)]
+pub struct VerityParams {
+    pub salt: Option<String>,
+    pub hash_alg: String,
+    pub data_block_size: u32,
+}
+impl Default for VerityParams {
+    fn default() -> Self {
+        Self {
+            salt: None,
+            hash_alg: "sha256".to_string(),
+            data_block_size: 4096,
+        }
+    }
+}
+
+/// Output of `veritysetup format`: the root hash digest and the salt
+/// actually used (veritysetup picks a random one when not pinned).
+#[derive(Debug, Clone)]
+pub struct VerityHashTree {
+    pub root_hexdigest: String,
+    pub salt: String,
+}
+
+fn cgpt_show(image: &Path, field_flag: &str, partition_num: u32) -> Result<u64> {
+    let output = Command::new("cgpt")
+        .arg("show")
+        .arg(field_flag)
+        .arg("-i")
+        .arg(partition_num.to_string())
+        .arg(image)
+        .output()
+        .context("Failed to run cgpt show")?;
+    if !output.status.success() {
+        bail!(
+            "cgpt show failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("Unexpected cgpt show output")
+}
+
+/// Returns a GPT partition's (byte offset, byte size) within `image`.
+fn partition_byte_range(image: &Path, partition_num: u32) -> Result<(u64, u64)> {
+    let start_sector = cgpt_show(image, "-b", partition_num)?;
+    let sector_count = cgpt_show(image, "-s", partition_num)?;
+    Ok((start_sector * SECTOR_SIZE, sector_count * SECTOR_SIZE))
+}
+
+fn dd_extract(image: &Path, offset: u64, size: u64, dest: &Path) -> Result<()> {
+    let status = Command::new("dd")
+        .arg(format!("if={}", image.display()))
+        .arg(format!("of={}", dest.display()))
+        .arg(format!("bs={SECTOR_SIZE}"))
+        .arg(format!("skip={}", offset / SECTOR_SIZE))
+        .arg(format!("count={}", size / SECTOR_SIZE))
+        .arg("conv=notrunc")
+        .status()
+        .context("Failed to run dd (extract)")?;
+    if !status.success() {
+        bail!("dd failed to extract {} bytes at offset {offset}", size);
+    }
+    Ok(())
+}
+
+fn dd_write_back(src: &Path, image: &Path, offset: u64) -> Result<()> {
+    let status = Command::new("dd")
+        .arg(format!("if={}", src.display()))
+        .arg(format!("of={}", image.display()))
+        .arg(format!("bs={SECTOR_SIZE}"))
+        .arg(format!("seek={}", offset / SECTOR_SIZE))
+        .arg("conv=notrunc")
+        .status()
+        .context("Failed to run dd (write back)")?;
+    if !status.success() {
+        bail!("dd failed to write back to offset {offset}");
+    }
+    Ok(())
+}
+
+fn parse_veritysetup_output(stdout: &str) -> Result<VerityHashTree> {
+    let re_root = Regex::new(r"(?i)Root hash:\s*([0-9a-f]+)")?;
+    let re_salt = Regex::new(r"(?i)Salt:\s*([0-9a-f]+)")?;
+    let root_hexdigest = re_root
+        .captures(stdout)
+        .context("veritysetup output did not contain a root hash")?[1]
+        .to_string();
+    let salt = re_salt
+        .captures(stdout)
+        .context("veritysetup output did not contain a salt")?[1]
+        .to_string();
+    Ok(VerityHashTree {
+        root_hexdigest,
+        salt,
+    })
+}
+
+/// Runs `veritysetup format` against `rootfs_image`, writing the hash
+/// tree to `hash_tree_image` and returning the resulting root hash/salt.
+fn format_hash_tree(
+    rootfs_image: &Path,
+    hash_tree_image: &Path,
+    params: &VerityParams,
+) -> Result<VerityHashTree> {
+    let mut cmd = Command::new("veritysetup");
+    cmd.arg("format")
+        .arg(rootfs_image)
+        .arg(hash_tree_image)
+        .arg("--hash")
+        .arg(&params.hash_alg)
+        .arg("--data-block-size")
+        .arg(params.data_block_size.to_string())
+        .arg("--hash-block-size")
+        .arg(params.data_block_size.to_string());
+    if let Some(salt) = &params.salt {
+        cmd.arg("--salt").arg(salt);
+    }
+    let output = cmd.output().context("Failed to run veritysetup format")?;
+    if !output.status.success() {
+        bail!(
+            "veritysetup format failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    parse_veritysetup_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Patches the `dm="..."` table in a kernel command line with a
+/// freshly-generated hash tree's `alg=`, `root_hexdigest=`, and `salt=`
+/// tokens, leaving every other verity target argument (device refs,
+/// sector counts, ...) untouched.
+pub fn patch_cmdline_dm_params(
+    cmdline: &str,
+    hash_tree: &VerityHashTree,
+    params: &VerityParams,
+) -> Result<String> {
+    let re_dm = Regex::new(r#"dm="([^"]*)""#)?;
+    let table = re_dm
+        .captures(cmdline)
+        .context("cmdline has no dm= argument to patch")?[1]
+        .to_string();
+
+    let table = Regex::new(r"alg=\S+")?
+        .replace(&table, format!("alg={}", params.hash_alg))
+        .to_string();
+    let table = Regex::new(r"root_hexdigest=\S+")?
+        .replace(&table, format!("root_hexdigest={}", hash_tree.root_hexdigest))
+        .to_string();
+    let table = Regex::new(r"salt=\S+")?
+        .replace(&table, format!("salt={}", hash_tree.salt))
+        .to_string();
+
+    Ok(re_dm
+        .replace(cmdline, format!("dm=\"{table}\"").as_str())
+        .to_string())
+}
+
+/// Extracts KERN-A's current config (the text `vbutil_kernel --verify`
+/// reports under `Config:`), which includes the `dm=` cmdline to patch.
+fn extract_kernel_config(kern_image: &Path) -> Result<String> {
+    let output = Command::new("futility")
+        .args(["vbutil_kernel", "--verify"])
+        .arg(kern_image)
+        .arg("--verbose")
+        .output()
+        .context("Failed to run futility vbutil_kernel --verify")?;
+    if !output.status.success() {
+        bail!(
+            "futility vbutil_kernel --verify failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    stdout
+        .split("Config:")
+        .nth(1)
+        .map(|s| s.trim().to_string())
+        .context("futility vbutil_kernel --verify output had no Config: section")
+}
+
+/// Re-signs KERN-A with `config` as its new cmdline, using the same
+/// well-known dev keys test/dev images are already signed with.
+fn repack_kernel(kern_image: &Path, config: &str) -> Result<()> {
+    let mut config_file = NamedTempFile::new().context("Failed to create a config tempfile")?;
+    std::io::Write::write_all(&mut config_file, config.as_bytes())
+        .context("Failed to write the patched kernel config")?;
+    let repacked = NamedTempFile::new().context("Failed to create a repacked-kernel tempfile")?;
+    let status = Command::new("futility")
+        .arg("vbutil_kernel")
+        .arg("--repack")
+        .arg(repacked.path())
+        .arg("--keyblock")
+        .arg(DEV_KEYBLOCK)
+        .arg("--signprivate")
+        .arg(DEV_DATA_KEY)
+        .arg("--oldblob")
+        .arg(kern_image)
+        .arg("--config")
+        .arg(config_file.path())
+        .status()
+        .context("Failed to run futility vbutil_kernel --repack")?;
+    if !status.success() {
+        bail!("futility vbutil_kernel --repack failed");
+    }
+    std::fs::copy(repacked.path(), kern_image).context("Failed to copy the repacked kernel back")?;
+    Ok(())
+}
+
+/// Recomputes `image`'s rootfs (ROOT-A) verity hash tree with `params`
+/// and patches KERN-A's `dm=` cmdline to match, in place.
+pub fn regenerate_image_verity(image: &Path, params: &VerityParams) -> Result<VerityHashTree> {
+    let (root_offset, root_size) = partition_byte_range(image, PARTITION_NUM_ROOT_A)?;
+    let (kern_offset, kern_size) = partition_byte_range(image, PARTITION_NUM_KERN_A)?;
+
+    let rootfs = NamedTempFile::new().context("Failed to create a rootfs tempfile")?;
+    dd_extract(image, root_offset, root_size, rootfs.path())?;
+
+    let hash_tree_file = NamedTempFile::new().context("Failed to create a hash-tree tempfile")?;
+    let hash_tree = format_hash_tree(rootfs.path(), hash_tree_file.path(), params)?;
+
+    // The hash tree is appended after the filesystem, in the padding
+    // `build_image.sh` already reserves at the end of ROOT-A.
+    let hash_tree_len = std::fs::metadata(hash_tree_file.path())
+        .context("Failed to stat the generated hash tree")?
+        .len();
+    if hash_tree_len > root_size {
+        bail!("Generated hash tree ({hash_tree_len} bytes) does not fit in ROOT-A");
+    }
+    dd_write_back(hash_tree_file.path(), image, root_offset + root_size - hash_tree_len)?;
+
+    let kern_image = NamedTempFile::new().context("Failed to create a kernel tempfile")?;
+    dd_extract(image, kern_offset, kern_size, kern_image.path())?;
+    let config = extract_kernel_config(kern_image.path())?;
+    let patched_config = patch_cmdline_dm_params(&config, &hash_tree, params)?;
+    repack_kernel(kern_image.path(), &patched_config)?;
+    dd_write_back(kern_image.path(), image, kern_offset)?;
+
+    Ok(hash_tree)
+}
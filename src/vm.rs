@@ -0,0 +1,88 @@
+// Copyright 2023 The ChromiumOS Authors
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! A locally-launched crosvm guest, for a hermetic deploy-and-test loop
+//! with no physical board in hand. Modeled after how crosvm boot tests
+//! launch a guest, poll for SSH readiness, and clean up symlinks/processes
+//! afterward: [`LocalVm::launch`] spawns the guest and blocks until SSH is
+//! reachable, and the guest process is killed when the returned handle is
+//! dropped so callers don't need to remember to tear it down.
+
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::info;
+
+use crate::dut::SshInfo;
+
+pub struct LocalVm {
+    child: Child,
+    ssh_port: u16,
+}
+impl LocalVm {
+    /// Launches `crosvm` with `kernel` and `rootfs` (either freshly built
+    /// artifacts or a prebuilt test image), forwards the guest's SSH port
+    /// to a free localhost port, and waits for the guest to reach a
+    /// bootable SSH state before returning.
+    pub fn launch(kernel: &Path, rootfs: &Path) -> Result<Self> {
+        if !kernel.exists() {
+            bail!("kernel image not found: {}", kernel.display());
+        }
+        if !rootfs.exists() {
+            bail!("rootfs image not found: {}", rootfs.display());
+        }
+        let ssh_port = pick_free_port()?;
+        info!(
+            "Launching crosvm (kernel={}, rootfs={}), forwarding guest SSH to 127.0.0.1:{ssh_port}",
+            kernel.display(),
+            rootfs.display()
+        );
+        let child = Command::new("crosvm")
+            .arg("run")
+            .arg("--disable-sandbox")
+            .arg("--rwdisk")
+            .arg(rootfs)
+            .arg("--net")
+            .arg(format!("host-ip=10.0.2.1,netmask=255.255.255.0,tap-fd=none,ssh-forward={ssh_port}"))
+            .arg("-p")
+            .arg("root=/dev/vda3")
+            .arg(kernel)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to spawn crosvm; is it installed and is KVM available?")?;
+
+        let vm = Self { child, ssh_port };
+        vm.ssh()?
+            .wait_online()
+            .context("The VM guest never came up over SSH")?;
+        Ok(vm)
+    }
+    /// Returns an `SshInfo` already pointed at this guest's forwarded SSH
+    /// port, so existing `cros deploy`/`update_kernel.sh` flows that go
+    /// through `SshInfo`/`into_forwarded()` work unchanged.
+    pub fn ssh(&self) -> Result<SshInfo> {
+        SshInfo::new_host_and_port("127.0.0.1", self.ssh_port)
+    }
+}
+impl Drop for LocalVm {
+    fn drop(&mut self) {
+        info!("Shutting down the crosvm guest (pid {})...", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn pick_free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Failed to bind a free port")?;
+    Ok(listener.local_addr()?.port())
+}